@@ -0,0 +1,4 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS username VARCHAR(32);
+
+-- Usernames are unique regardless of case; NULLs (no username) do not conflict
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_username_lower ON users(LOWER(username));
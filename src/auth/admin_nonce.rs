@@ -0,0 +1,155 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Serialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::cache::NonceStore;
+
+/// Request header carrying the nonce on mutating admin requests
+pub const ADMIN_NONCE_HEADER: &str = "X-Admin-Nonce";
+
+/// Seconds an unused nonce stays valid
+pub const ADMIN_NONCE_TTL_SECONDS: u64 = 60;
+
+/// Nonce response
+#[derive(Debug, Serialize)]
+pub struct AdminNonceResponse {
+    pub nonce: String,
+    /// Seconds until the nonce expires
+    pub expires_in: u64,
+}
+
+/// Admin nonce error types
+#[derive(Debug, Error)]
+pub enum NonceError {
+    #[error("Missing admin nonce")]
+    Missing,
+
+    #[error("Invalid, expired or already used admin nonce")]
+    Invalid,
+
+    #[error("Cache error: {0}")]
+    CacheError(#[from] redis::RedisError),
+}
+
+/// Issue a single-use nonce to an admin
+///
+/// # Arguments
+/// * `store` - Nonce storage (Redis in production)
+/// * `user_id` - Admin the nonce is issued to; only they can use it
+/// * `ttl_seconds` - How long the nonce stays valid
+///
+/// # Returns
+/// * `Result<AdminNonceResponse, redis::RedisError>` - The nonce and its lifetime
+pub async fn issue_nonce(
+    store: &dyn NonceStore,
+    user_id: &Uuid,
+    ttl_seconds: u64,
+) -> Result<AdminNonceResponse, redis::RedisError> {
+    let nonce = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+    store
+        .store_nonce(&nonce, &user_id.to_string(), ttl_seconds)
+        .await?;
+
+    Ok(AdminNonceResponse {
+        nonce,
+        expires_in: ttl_seconds,
+    })
+}
+
+/// Consume the nonce sent with a mutating admin request
+///
+/// The nonce is removed whether or not it matches, so a captured request
+/// cannot be replayed and a guessed nonce burns the real one.
+///
+/// # Arguments
+/// * `store` - Nonce storage (Redis in production)
+/// * `user_id` - Admin making the request
+/// * `nonce` - Value of the `X-Admin-Nonce` header, if sent
+///
+/// # Returns
+/// * `Result<(), NonceError>` - Ok if the nonce was issued to `user_id` and unused
+pub async fn consume_nonce(
+    store: &dyn NonceStore,
+    user_id: &Uuid,
+    nonce: Option<&str>,
+) -> Result<(), NonceError> {
+    let nonce = nonce
+        .map(str::trim)
+        .filter(|nonce| !nonce.is_empty())
+        .ok_or(NonceError::Missing)?;
+
+    match store.take_nonce(nonce).await? {
+        Some(owner) if owner == user_id.to_string() => Ok(()),
+        _ => Err(NonceError::Invalid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory::MemoryStore;
+
+    #[tokio::test]
+    async fn test_nonce_allows_one_mutation() {
+        let store = MemoryStore::new();
+        let admin = Uuid::new_v4();
+
+        let issued = issue_nonce(&store, &admin, ADMIN_NONCE_TTL_SECONDS)
+            .await
+            .unwrap();
+        assert_eq!(issued.expires_in, ADMIN_NONCE_TTL_SECONDS);
+
+        assert!(consume_nonce(&store, &admin, Some(&issued.nonce))
+            .await
+            .is_ok());
+
+        // Replaying the captured request fails
+        assert!(matches!(
+            consume_nonce(&store, &admin, Some(&issued.nonce)).await,
+            Err(NonceError::Invalid)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nonce_rejections() {
+        let store = MemoryStore::new();
+        let admin = Uuid::new_v4();
+
+        assert!(matches!(
+            consume_nonce(&store, &admin, None).await,
+            Err(NonceError::Missing)
+        ));
+        assert!(matches!(
+            consume_nonce(&store, &admin, Some("made-up")).await,
+            Err(NonceError::Invalid)
+        ));
+
+        // Expired
+        let issued = issue_nonce(&store, &admin, 0).await.unwrap();
+        assert!(matches!(
+            consume_nonce(&store, &admin, Some(&issued.nonce)).await,
+            Err(NonceError::Invalid)
+        ));
+
+        // Issued to another admin
+        let issued = issue_nonce(&store, &Uuid::new_v4(), ADMIN_NONCE_TTL_SECONDS)
+            .await
+            .unwrap();
+        assert!(matches!(
+            consume_nonce(&store, &admin, Some(&issued.nonce)).await,
+            Err(NonceError::Invalid)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nonces_are_unique() {
+        let store = MemoryStore::new();
+        let admin = Uuid::new_v4();
+
+        let first = issue_nonce(&store, &admin, 60).await.unwrap();
+        let second = issue_nonce(&store, &admin, 60).await.unwrap();
+        assert_ne!(first.nonce, second.nonce);
+        assert_eq!(first.nonce.len(), 43);
+    }
+}
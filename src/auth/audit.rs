@@ -0,0 +1,65 @@
+use serde::Serialize;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// How urgently an audit event needs attention
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSeverity {
+    Info,
+    /// Likely compromise; alert on these
+    High,
+}
+
+/// Security-relevant event about one user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditEvent {
+    pub event: &'static str,
+    pub severity: AuditSeverity,
+    pub user_id: Uuid,
+    pub client_ip: Option<IpAddr>,
+}
+
+/// Destination for audit events
+pub trait AuditLog: Send + Sync {
+    fn record(&self, event: AuditEvent);
+
+    /// Events kept about a user, oldest first, for data-subject exports
+    fn events_for(&self, user_id: &Uuid) -> Vec<AuditEvent>;
+
+    /// Remove every event kept about a user
+    ///
+    /// # Returns
+    /// * `usize` - Number of events removed
+    fn redact(&self, user_id: &Uuid) -> usize;
+}
+
+/// Audit events as JSON lines on the `audit` log target
+///
+/// High-severity events are logged at error level so they reach the
+/// alerting pipeline with the default filter.
+pub struct LogAudit;
+
+impl AuditLog for LogAudit {
+    fn record(&self, event: AuditEvent) {
+        let level = match event.severity {
+            AuditSeverity::Info => log::Level::Info,
+            AuditSeverity::High => log::Level::Error,
+        };
+
+        match serde_json::to_string(&event) {
+            Ok(line) => log::log!(target: "audit", level, "{}", line),
+            Err(e) => log::error!(target: "audit", "Audit event encode error: {}", e),
+        }
+    }
+
+    /// Lines already written belong to the log pipeline; none are kept here
+    fn events_for(&self, _user_id: &Uuid) -> Vec<AuditEvent> {
+        Vec::new()
+    }
+
+    /// Nothing to remove here; redact the user's lines in the log pipeline
+    fn redact(&self, _user_id: &Uuid) -> usize {
+        0
+    }
+}
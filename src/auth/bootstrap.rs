@@ -0,0 +1,178 @@
+use thiserror::Error;
+
+use crate::auth::PasswordManager;
+use crate::db::user::{CreateUser, UserError, ROLE_ADMIN};
+use crate::db::UserStore;
+
+/// What `ensure_admin` had to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootstrapOutcome {
+    /// No account existed; a new admin was created
+    Created,
+    /// The account existed without the admin role, had the configured
+    /// password and was promoted
+    Promoted,
+    /// The account was already an admin
+    Existing,
+}
+
+/// Admin bootstrap error types
+#[derive(Debug, Error)]
+pub enum BootstrapError {
+    #[error("Password validation failed: {0}")]
+    PasswordValidationFailed(String),
+
+    #[error("Account {0} exists without the admin role and its password is not admin.password")]
+    NotAdmin(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+}
+
+impl From<UserError> for BootstrapError {
+    fn from(e: UserError) -> Self {
+        BootstrapError::DatabaseError(e.to_string())
+    }
+}
+
+/// Make sure an admin account exists for `email`
+///
+/// Safe to run on every startup: an existing admin is left as it is. An
+/// existing account without the admin role is only promoted when its
+/// password is the configured one; otherwise whoever registered that email
+/// first would become admin, so startup fails instead.
+///
+/// # Arguments
+/// * `users` - User storage backend
+/// * `email` - Admin email
+/// * `password` - Password for a newly created admin, and the one an
+///   existing account must have to be promoted
+///
+/// # Returns
+/// * `Result<BootstrapOutcome, BootstrapError>` - What was done or error
+///
+/// # Example
+/// ```
+/// if let Some((email, password)) = settings.admin.credentials() {
+///     ensure_admin(&UserRepository::new(&pool), &email, &password).await?;
+/// }
+/// ```
+pub async fn ensure_admin(
+    users: &dyn UserStore,
+    email: &str,
+    password: &str,
+) -> Result<BootstrapOutcome, BootstrapError> {
+    let user = match users.find_by_email(email).await {
+        Ok(user) => user,
+        Err(UserError::NotFound) => {
+            let password_hash = PasswordManager::hash(password)
+                .map_err(|e| BootstrapError::PasswordValidationFailed(e.to_string()))?;
+
+            let create_user = CreateUser {
+                email: email.to_string(),
+                username: None,
+                password_hash,
+            };
+
+            match users.create(create_user).await {
+                Ok(user) => {
+                    users.set_role(&user.id, ROLE_ADMIN).await?;
+                    log::info!("Bootstrap admin created: {} (ID: {})", user.email, user.id);
+                    return Ok(BootstrapOutcome::Created);
+                }
+                // Another instance created it first
+                Err(UserError::EmailExists) => users.find_by_email(email).await?,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if user.is_admin() {
+        return Ok(BootstrapOutcome::Existing);
+    }
+    if !PasswordManager::verify(password, &user.password_hash).unwrap_or(false) {
+        return Err(BootstrapError::NotAdmin(user.email));
+    }
+
+    users.set_role(&user.id, ROLE_ADMIN).await?;
+    log::info!("Existing user {} promoted to admin", user.email);
+
+    Ok(BootstrapOutcome::Promoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory::MemoryStore;
+
+    #[tokio::test]
+    async fn test_bootstrap_creates_admin_once() {
+        let store = MemoryStore::new();
+
+        assert_eq!(
+            ensure_admin(&store, "admin@example.com", "SecurePass123!")
+                .await
+                .unwrap(),
+            BootstrapOutcome::Created
+        );
+        assert_eq!(
+            ensure_admin(&store, "admin@example.com", "SecurePass123!")
+                .await
+                .unwrap(),
+            BootstrapOutcome::Existing
+        );
+
+        let admin = store.find_by_email("admin@example.com").await.unwrap();
+        assert!(admin.is_admin());
+        assert_eq!(store.user_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_promotes_existing_user_with_admin_password() {
+        let store = MemoryStore::new();
+        let user = store
+            .create(CreateUser {
+                email: "admin@example.com".to_string(),
+                username: None,
+                password_hash: PasswordManager::hash("SecurePass123!").unwrap(),
+            })
+            .await
+            .unwrap();
+        assert!(!user.is_admin());
+
+        assert_eq!(
+            ensure_admin(&store, "admin@example.com", "SecurePass123!")
+                .await
+                .unwrap(),
+            BootstrapOutcome::Promoted
+        );
+
+        // The account is reused as-is
+        let admin = store.find_by_email("admin@example.com").await.unwrap();
+        assert!(admin.is_admin());
+        assert_eq!(admin.id, user.id);
+        assert_eq!(admin.password_hash, user.password_hash);
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_refuses_account_with_another_password() {
+        // Someone registered the admin email before the admin was bootstrapped
+        let store = MemoryStore::new();
+        store
+            .create(CreateUser {
+                email: "admin@example.com".to_string(),
+                username: None,
+                password_hash: PasswordManager::hash("AttackerPass456!").unwrap(),
+            })
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            ensure_admin(&store, "admin@example.com", "SecurePass123!").await,
+            Err(BootstrapError::NotAdmin(_))
+        ));
+        let user = store.find_by_email("admin@example.com").await.unwrap();
+        assert!(!user.is_admin());
+    }
+}
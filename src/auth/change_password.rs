@@ -0,0 +1,285 @@
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::auth::schema::{Field, Schema, MAX_PASSWORD_LENGTH};
+use crate::auth::{JwtManager, PasswordManager};
+use crate::db::error::DbFailure;
+use crate::db::user::UserError;
+use crate::db::{TokenStore, UserStore};
+
+/// Change password request payload
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+impl Schema for ChangePasswordRequest {
+    const FIELDS: &'static [Field] = &[
+        Field::required("current_password", MAX_PASSWORD_LENGTH),
+        Field::required("new_password", MAX_PASSWORD_LENGTH),
+    ];
+}
+
+/// Change password response
+///
+/// Every token issued before the change is revoked, so the caller gets a
+/// fresh pair to stay logged in.
+#[derive(Debug, Serialize)]
+pub struct ChangePasswordResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    /// Access token expiry as an RFC3339 instant (`jwt.absolute_expiry` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// Refresh token expiry as an RFC3339 instant (`jwt.absolute_expiry` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_at: Option<String>,
+}
+
+/// Change password error types
+#[derive(Debug, Error)]
+pub enum ChangePasswordError {
+    #[error("Invalid credentials")]
+    InvalidCredentials,
+
+    #[error("Password validation failed: {0}")]
+    PasswordValidationFailed(String),
+
+    #[error("Password verification error: {0}")]
+    PasswordVerification(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(DbFailure),
+
+    #[error("Token generation failed: {0}")]
+    TokenError(String),
+}
+
+/// Change an authenticated user's password
+///
+/// Bumps the user's token version and revokes their refresh tokens, which
+/// signs out every other session; new tokens are returned for this one.
+///
+/// # Arguments
+/// * `users` - User storage backend
+/// * `tokens` - Refresh token storage backend
+/// * `jwt_manager` - JWT token manager
+/// * `user_id` - User from the verified access token
+/// * `request` - Current and new password
+///
+/// # Returns
+/// * `Result<ChangePasswordResponse, ChangePasswordError>` - New tokens or error
+///
+/// # Example
+/// ```
+/// let request = ChangePasswordRequest {
+///     current_password: "SecurePass123!".to_string(),
+///     new_password: "EvenBetterPass456!".to_string(),
+/// };
+///
+/// let response = change_password(
+///     &UserRepository::new(&pool),
+///     &TokenRepository::new(&pool),
+///     &jwt_manager,
+///     &user_id,
+///     request
+/// ).await?;
+/// ```
+pub async fn change_password(
+    users: &dyn UserStore,
+    tokens: &dyn TokenStore,
+    jwt_manager: &JwtManager,
+    user_id: &Uuid,
+    request: ChangePasswordRequest,
+) -> Result<ChangePasswordResponse, ChangePasswordError> {
+    let user = users.find_by_id(user_id).await.map_err(|e| match e {
+        UserError::NotFound => ChangePasswordError::InvalidCredentials,
+        e => ChangePasswordError::DatabaseError(e.into()),
+    })?;
+
+    // Verify current password
+    if !PasswordManager::verify(&request.current_password, &user.password_hash)
+        .map_err(|e| ChangePasswordError::PasswordVerification(e.to_string()))?
+    {
+        log::warn!(
+            "Password change with wrong current password for user: {}",
+            user.email
+        );
+        return Err(ChangePasswordError::InvalidCredentials);
+    }
+
+    // Hash new password (enforces the strength policy)
+    let password_hash = PasswordManager::hash(&request.new_password)
+        .map_err(|e| ChangePasswordError::PasswordValidationFailed(e.to_string()))?;
+
+    users
+        .update_password(&user.id, &password_hash)
+        .await
+        .map_err(|e| ChangePasswordError::DatabaseError(e.into()))?;
+
+    // Sign out every session: old access tokens fail the version check and
+    // old refresh tokens are gone
+    let token_version = users
+        .bump_token_version(&user.id)
+        .await
+        .map_err(|e| ChangePasswordError::DatabaseError(e.into()))?;
+
+    let revoked = tokens
+        .revoke_all_user_tokens(&user.id)
+        .await
+        .map_err(|e| ChangePasswordError::DatabaseError(e.into()))?;
+
+    log::info!(
+        "Password changed for user: {} ({} refresh tokens revoked)",
+        user.email,
+        revoked
+    );
+
+    // Generate tokens for the current session
+    let issued_at = Utc::now();
+    let access_token = jwt_manager
+        .generate_access_token(&user.id, token_version)
+        .map_err(|e| ChangePasswordError::TokenError(e.to_string()))?;
+
+    let (refresh_token, refresh_token_hash, refresh_expires_at) = jwt_manager
+        .generate_refresh_token(&user.id, token_version)
+        .map_err(|e| ChangePasswordError::TokenError(e.to_string()))?;
+
+    tokens
+        .save_refresh_token(&user.id, &refresh_token_hash, refresh_expires_at)
+        .await
+        .map_err(|e| ChangePasswordError::DatabaseError(e.into()))?;
+
+    Ok(ChangePasswordResponse {
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: jwt_manager.access_token_expiration(),
+        expires_at: jwt_manager
+            .expiry_timestamp(issued_at + Duration::seconds(jwt_manager.access_token_expiration())),
+        refresh_expires_at: jwt_manager.expiry_timestamp(refresh_expires_at),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::login::login;
+    use crate::auth::{refresh_token, RefreshRequest};
+    use crate::db::memory::MemoryStore;
+    use crate::db::user::CreateUser;
+
+    const EMAIL: &str = "user@example.com";
+    const PASSWORD: &str = "SecurePass123!";
+    const NEW_PASSWORD: &str = "EvenBetterPass456!";
+
+    async fn store_with_user() -> (MemoryStore, Uuid) {
+        let store = MemoryStore::new();
+        let user = store
+            .create(CreateUser {
+                email: EMAIL.to_string(),
+                username: None,
+                password_hash: PasswordManager::hash(PASSWORD).unwrap(),
+            })
+            .await
+            .unwrap();
+        (store, user.id)
+    }
+
+    fn request(current_password: &str) -> ChangePasswordRequest {
+        ChangePasswordRequest {
+            current_password: current_password.to_string(),
+            new_password: NEW_PASSWORD.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_password() {
+        let (store, user_id) = store_with_user().await;
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+
+        let response = change_password(&store, &store, &jwt_manager, &user_id, request(PASSWORD))
+            .await
+            .unwrap();
+
+        let claims = jwt_manager.decode_token(&response.access_token).unwrap();
+        assert_eq!(claims.token_version, 1);
+        assert_eq!(store.token_count(), 1);
+
+        // Only the new password logs in
+        assert!(login(&store, EMAIL, NEW_PASSWORD).await.is_ok());
+        assert!(login(&store, EMAIL, PASSWORD).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wrong_current_password() {
+        let (store, user_id) = store_with_user().await;
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+
+        let result = change_password(
+            &store,
+            &store,
+            &jwt_manager,
+            &user_id,
+            request("WrongPass123!"),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(ChangePasswordError::InvalidCredentials)
+        ));
+        assert_eq!(store.token_version(&user_id).await.unwrap(), 0);
+        assert!(login(&store, EMAIL, PASSWORD).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_weak_new_password_rejected() {
+        let (store, user_id) = store_with_user().await;
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+
+        let mut weak = request(PASSWORD);
+        weak.new_password = "short".to_string();
+        let result = change_password(&store, &store, &jwt_manager, &user_id, weak).await;
+
+        assert!(matches!(
+            result,
+            Err(ChangePasswordError::PasswordValidationFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_other_sessions_invalidated() {
+        let (store, user_id) = store_with_user().await;
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+
+        // Another device is logged in
+        let other = login(&store, EMAIL, PASSWORD).await.unwrap();
+
+        change_password(&store, &store, &jwt_manager, &user_id, request(PASSWORD))
+            .await
+            .unwrap();
+
+        // Its refresh token no longer works...
+        let refresh = RefreshRequest {
+            refresh_token: other.refresh_token,
+        };
+        assert!(
+            refresh_token(&store, &store, &store, &jwt_manager, refresh, &store, None)
+                .await
+                .is_err()
+        );
+
+        // ...and its access token carries a stale version
+        let claims = jwt_manager.decode_token(&other.access_token).unwrap();
+        assert_ne!(
+            claims.token_version,
+            store.token_version(&user_id).await.unwrap()
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// SHA-256 digest of a secret, for storage and constant-time comparison
+pub fn secret_digest(secret: &str) -> [u8; 32] {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+/// Compare two digests in constant time
+///
+/// Secrets are hashed first so the comparison runs over fixed-length
+/// digests and leaks neither content nor length through timing.
+pub fn digests_equal(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Check a presented secret against a set of stored digests
+///
+/// Every digest is compared, so the time taken does not reveal which
+/// entry (if any) matched.
+pub fn matches_any_digest(secret: &str, digests: &[[u8; 32]]) -> bool {
+    let presented = secret_digest(secret);
+    digests.iter().fold(false, |found, digest| {
+        found | digests_equal(digest, &presented)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digests_equal() {
+        let equal = |a: &str, b: &str| digests_equal(&secret_digest(a), &secret_digest(b));
+
+        assert!(equal("machine-key-1", "machine-key-1"));
+        assert!(!equal("machine-key-1", "machine-key-2"));
+        assert!(!equal("machine-key-1", "machine-key-1-longer"));
+        assert!(!equal("", "machine-key-1"));
+        assert!(equal("", ""));
+    }
+
+    #[test]
+    fn test_matches_any_digest() {
+        let digests = [secret_digest("key-a"), secret_digest("key-b")];
+
+        assert!(matches_any_digest("key-a", &digests));
+        assert!(matches_any_digest("key-b", &digests));
+        assert!(!matches_any_digest("key-c", &digests));
+        assert!(!matches_any_digest("key-a", &[]));
+    }
+}
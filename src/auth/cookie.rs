@@ -0,0 +1,76 @@
+use crate::config::settings::RefreshCookieConfig;
+
+/// Find a cookie's value in a `Cookie` request header
+///
+/// # Arguments
+/// * `header` - Header value, e.g. `theme=dark; refresh_token=eyJ...`
+/// * `name` - Cookie name to look for
+///
+/// # Returns
+/// * `Option<&str>` - The first non-empty value with that name
+pub fn get_cookie<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, value)| *key == name && !value.is_empty())
+        .map(|(_, value)| value.trim_matches('"'))
+}
+
+/// `Set-Cookie` value carrying a refresh token
+///
+/// The cookie is `HttpOnly` so scripts never see the token, and
+/// `SameSite=Strict` so it is not sent on cross-site requests.
+///
+/// # Arguments
+/// * `config` - Cookie name, path and `Secure` flag
+/// * `token` - Refresh token
+/// * `max_age` - Lifetime in seconds (the refresh token's expiration)
+///
+/// # Example
+/// ```
+/// let header = refresh_cookie(&settings.jwt.refresh_cookie, &token, 604800);
+/// // refresh_token=eyJ...; Path=/auth; Max-Age=604800; HttpOnly; Secure; SameSite=Strict
+/// ```
+pub fn refresh_cookie(config: &RefreshCookieConfig, token: &str, max_age: i64) -> String {
+    let mut cookie = format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly",
+        config.name, token, config.path, max_age
+    );
+    if config.secure {
+        cookie.push_str("; Secure");
+    }
+    cookie.push_str("; SameSite=Strict");
+    cookie
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_cookie() {
+        let header = "theme=dark; refresh_token=abc.def.ghi;other=1";
+        assert_eq!(get_cookie(header, "refresh_token"), Some("abc.def.ghi"));
+        assert_eq!(get_cookie(header, "other"), Some("1"));
+        assert_eq!(get_cookie(header, "missing"), None);
+        assert_eq!(get_cookie("refresh_token=", "refresh_token"), None);
+        // Name must match exactly
+        assert_eq!(get_cookie("xrefresh_token=abc", "refresh_token"), None);
+    }
+
+    #[test]
+    fn test_refresh_cookie_attributes() {
+        let config = RefreshCookieConfig::default();
+        let cookie = refresh_cookie(&config, "abc", 604800);
+        assert_eq!(
+            cookie,
+            "refresh_token=abc; Path=/auth; Max-Age=604800; HttpOnly; Secure; SameSite=Strict"
+        );
+
+        let insecure = RefreshCookieConfig {
+            secure: false,
+            ..RefreshCookieConfig::default()
+        };
+        assert!(!refresh_cookie(&insecure, "abc", 60).contains("Secure"));
+    }
+}
@@ -0,0 +1,246 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::config::settings::EncryptionConfig;
+
+/// AES-GCM nonce length in bytes
+const NONCE_LEN: usize = 12;
+
+/// Encryption error types
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("Invalid encryption key version {0}: {1}")]
+    InvalidKey(u32, String),
+
+    #[error("No encryption key with version {0}")]
+    UnknownKeyVersion(u32),
+
+    #[error("Malformed ciphertext")]
+    Malformed,
+
+    #[error("Decryption failed")]
+    DecryptFailed,
+}
+
+/// AES-256-GCM encryption for secrets stored reversibly
+///
+/// Stored values are `v<version>:<nonce>:<ciphertext>` (base64), so each
+/// carries its own random nonce and the key version that sealed it.
+/// After a rotation, values sealed with an older key still decrypt for as
+/// long as that key stays configured.
+pub struct EncryptionService {
+    current_version: u32,
+    keys: HashMap<u32, Aes256Gcm>,
+}
+
+impl EncryptionService {
+    /// Load the keys in `encryption`
+    ///
+    /// # Arguments
+    /// * `config` - `encryption` section (validated by `Settings::validate`)
+    ///
+    /// # Returns
+    /// * `Result<Option<Self>, EncryptionError>` - `None` when no keys are configured
+    pub fn from_config(config: &EncryptionConfig) -> Result<Option<Self>, EncryptionError> {
+        if config.keys.is_empty() {
+            return Ok(None);
+        }
+
+        let mut keys = Vec::new();
+        for key in &config.keys {
+            let invalid = |reason: String| EncryptionError::InvalidKey(key.version, reason);
+
+            let encoded = match (&key.key, &key.key_file) {
+                (Some(inline), _) => inline.clone(),
+                (None, Some(path)) => {
+                    std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?
+                }
+                (None, None) => return Err(invalid("no key or key_file".to_string())),
+            };
+            let bytes = STANDARD
+                .decode(encoded.trim())
+                .map_err(|e| invalid(e.to_string()))?;
+
+            keys.push((key.version, bytes));
+        }
+
+        Self::from_keys(config.current_version, &keys).map(Some)
+    }
+
+    /// Service over raw 32-byte keys by version
+    ///
+    /// # Example
+    /// ```
+    /// let service = EncryptionService::from_keys(2, &[(1, old_key), (2, new_key)])?;
+    /// ```
+    pub fn from_keys<K: AsRef<[u8]>>(
+        current_version: u32,
+        keys: &[(u32, K)],
+    ) -> Result<Self, EncryptionError> {
+        let keys: HashMap<u32, Aes256Gcm> = keys
+            .iter()
+            .map(|(version, key)| Ok((*version, Self::cipher(*version, key.as_ref())?)))
+            .collect::<Result<_, EncryptionError>>()?;
+
+        if !keys.contains_key(&current_version) {
+            return Err(EncryptionError::UnknownKeyVersion(current_version));
+        }
+
+        Ok(Self {
+            current_version,
+            keys,
+        })
+    }
+
+    fn cipher(version: u32, key: &[u8]) -> Result<Aes256Gcm, EncryptionError> {
+        Aes256Gcm::new_from_slice(key).map_err(|_| {
+            EncryptionError::InvalidKey(version, format!("expected 32 bytes, got {}", key.len()))
+        })
+    }
+
+    /// Encrypt a secret with the current key
+    ///
+    /// # Returns
+    /// * `String` - `v<version>:<nonce>:<ciphertext>`, ready to store
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self.keys[&self.current_version]
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+            .expect("AES-GCM encryption of an in-memory buffer cannot fail");
+
+        format!(
+            "v{}:{}:{}",
+            self.current_version,
+            STANDARD.encode(nonce),
+            STANDARD.encode(ciphertext)
+        )
+    }
+
+    /// Decrypt a value produced by [`encrypt`](Self::encrypt) with any known key
+    ///
+    /// # Returns
+    /// * `Result<String, EncryptionError>` - The secret, or why it could not be recovered
+    pub fn decrypt(&self, stored: &str) -> Result<String, EncryptionError> {
+        let (version, nonce, ciphertext) = parse(stored)?;
+        let cipher = self
+            .keys
+            .get(&version)
+            .ok_or(EncryptionError::UnknownKeyVersion(version))?;
+
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| EncryptionError::DecryptFailed)?;
+
+        String::from_utf8(plaintext).map_err(|_| EncryptionError::DecryptFailed)
+    }
+}
+
+/// Split a stored value into key version, nonce and ciphertext
+fn parse(stored: &str) -> Result<(u32, Vec<u8>, Vec<u8>), EncryptionError> {
+    let mut parts = stored.splitn(3, ':');
+    let (Some(version), Some(nonce), Some(ciphertext)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(EncryptionError::Malformed);
+    };
+
+    let version = version
+        .strip_prefix('v')
+        .and_then(|v| v.parse().ok())
+        .ok_or(EncryptionError::Malformed)?;
+    let nonce = STANDARD
+        .decode(nonce)
+        .ok()
+        .filter(|n| n.len() == NONCE_LEN)
+        .ok_or(EncryptionError::Malformed)?;
+    let ciphertext = STANDARD
+        .decode(ciphertext)
+        .map_err(|_| EncryptionError::Malformed)?;
+
+    Ok((version, nonce, ciphertext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::EncryptionKeyConfig;
+
+    const KEY_1: [u8; 32] = [1; 32];
+    const KEY_2: [u8; 32] = [2; 32];
+
+    #[test]
+    fn test_round_trip() {
+        let service = EncryptionService::from_keys(1, &[(1, KEY_1)]).unwrap();
+
+        let stored = service.encrypt("JBSWY3DPEHPK3PXP");
+        assert!(stored.starts_with("v1:"));
+        assert!(!stored.contains("JBSWY3DPEHPK3PXP"));
+        assert_eq!(service.decrypt(&stored).unwrap(), "JBSWY3DPEHPK3PXP");
+
+        // Fresh nonce every time
+        assert_ne!(service.encrypt("JBSWY3DPEHPK3PXP"), stored);
+    }
+
+    #[test]
+    fn test_wrong_key_fails() {
+        let stored = EncryptionService::from_keys(1, &[(1, KEY_1)])
+            .unwrap()
+            .encrypt("secret");
+
+        let other = EncryptionService::from_keys(1, &[(1, KEY_2)]).unwrap();
+        assert!(matches!(
+            other.decrypt(&stored),
+            Err(EncryptionError::DecryptFailed)
+        ));
+
+        // Tampered ciphertext fails authentication too
+        let service = EncryptionService::from_keys(1, &[(1, KEY_1)]).unwrap();
+        let (prefix, ciphertext) = stored.rsplit_once(':').unwrap();
+        let mut bytes = STANDARD.decode(ciphertext).unwrap();
+        bytes[0] ^= 1;
+        let tampered = format!("{}:{}", prefix, STANDARD.encode(bytes));
+        assert!(service.decrypt(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_values_readable() {
+        let old = EncryptionService::from_keys(1, &[(1, KEY_1)]).unwrap();
+        let stored = old.encrypt("secret");
+
+        let rotated = EncryptionService::from_keys(2, &[(1, KEY_1), (2, KEY_2)]).unwrap();
+        assert_eq!(rotated.decrypt(&stored).unwrap(), "secret");
+
+        let fresh = rotated.encrypt("secret");
+        assert!(fresh.starts_with("v2:"));
+        assert_eq!(rotated.decrypt(&fresh).unwrap(), "secret");
+
+        // Once the old key is dropped its values are unreadable
+        let retired = EncryptionService::from_keys(2, &[(2, KEY_2)]).unwrap();
+        assert!(matches!(
+            retired.decrypt(&stored),
+            Err(EncryptionError::UnknownKeyVersion(1))
+        ));
+    }
+
+    #[test]
+    fn test_from_config() {
+        assert!(EncryptionService::from_config(&EncryptionConfig::default())
+            .unwrap()
+            .is_none());
+
+        let config = EncryptionConfig {
+            current_version: 1,
+            keys: vec![EncryptionKeyConfig {
+                version: 1,
+                key: Some(STANDARD.encode([7u8; 16])),
+                key_file: None,
+            }],
+        };
+        assert!(matches!(
+            EncryptionService::from_config(&config),
+            Err(EncryptionError::InvalidKey(1, _))
+        ));
+    }
+}
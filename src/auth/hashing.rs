@@ -0,0 +1,42 @@
+use sha2::{Digest, Sha256};
+
+/// Hash of a refresh token, as stored and looked up in `refresh_tokens`
+///
+/// SHA-256 rather than a password hash: refresh tokens are long random
+/// JWTs, so a fast hash is enough, and unlike `DefaultHasher` the result
+/// is the same on every Rust version.
+///
+/// # Returns
+/// * `String` - 64 lowercase hex characters
+///
+/// # Example
+/// ```
+/// let token_hash = hash_refresh_token(&refresh_token);
+/// tokens.save_refresh_token(&user.id, &token_hash, expires_at).await?;
+/// ```
+pub fn hash_refresh_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_is_stable_sha256_hex() {
+        let hash = hash_refresh_token("refresh-token");
+        assert_eq!(hash, hash_refresh_token("refresh-token"));
+        assert_eq!(hash.len(), 64);
+        assert!(hash.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')));
+        assert_ne!(hash, hash_refresh_token("refresh-token2"));
+
+        // Known SHA-256 digest, so the stored form cannot drift
+        assert_eq!(
+            hash_refresh_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::auth::PasswordManager;
+use crate::db::error::DbFailure;
+use crate::db::user::{CreateUser, UserError};
+use crate::db::UserStore;
+
+/// Most users accepted by one import request
+pub const MAX_IMPORT_ROWS: usize = 10_000;
+
+/// One user to import
+///
+/// Exactly one of `password` (hashed here, subject to the password policy)
+/// or `password_hash` (a bcrypt hash from the legacy system) must be set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportUserRequest {
+    pub email: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub password_hash: Option<String>,
+}
+
+/// Import response
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    pub created: usize,
+    pub failed: usize,
+    /// One entry per submitted user, in request order
+    pub results: Vec<ImportRowResult>,
+}
+
+/// Outcome of importing one user
+#[derive(Debug, Serialize)]
+pub struct ImportRowResult {
+    pub email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Import error types
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("At most {MAX_IMPORT_ROWS} users can be imported at once, got {0}")]
+    TooManyUsers(usize),
+
+    #[error("Database error: {0}")]
+    DatabaseError(DbFailure),
+}
+
+/// Create users in bulk, reporting each row's outcome
+///
+/// Invalid rows and duplicate emails fail on their own; the other users
+/// are still created.
+///
+/// # Arguments
+/// * `users` - User storage backend
+/// * `rows` - Users to create
+///
+/// # Returns
+/// * `Result<ImportResponse, ImportError>` - Per-row results, or why nothing
+///   (or only part) could be imported
+///
+/// # Example
+/// ```
+/// let rows: Vec<ImportUserRequest> = serde_json::from_slice(&body)?;
+/// let response = import_users(&UserRepository::new(&pool), rows).await?;
+/// ```
+pub async fn import_users(
+    users: &dyn UserStore,
+    rows: Vec<ImportUserRequest>,
+) -> Result<ImportResponse, ImportError> {
+    if rows.len() > MAX_IMPORT_ROWS {
+        return Err(ImportError::TooManyUsers(rows.len()));
+    }
+
+    // bcrypt work for plain passwords stays off the async workers
+    let prepared = tokio::task::spawn_blocking(move || {
+        rows.into_iter()
+            .map(|row| {
+                let user = prepare(&row);
+                (row.email, user)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .expect("import preparation panicked");
+
+    let valid = prepared
+        .iter()
+        .filter_map(|(_, user)| user.as_ref().ok().cloned())
+        .collect();
+    let mut created = users
+        .create_many(valid)
+        .await
+        .map_err(|e| ImportError::DatabaseError(e.into()))?
+        .into_iter();
+
+    let results: Vec<ImportRowResult> = prepared
+        .into_iter()
+        .map(|(email, user)| {
+            let outcome = match user {
+                Ok(_) => created
+                    .next()
+                    .unwrap_or_else(|| Err(UserError::NotFound))
+                    .map_err(|e| e.to_string()),
+                Err(reason) => Err(reason),
+            };
+
+            match outcome {
+                Ok(user) => ImportRowResult {
+                    email,
+                    user_id: Some(user.id.to_string()),
+                    error: None,
+                },
+                Err(reason) => ImportRowResult {
+                    email,
+                    user_id: None,
+                    error: Some(reason),
+                },
+            }
+        })
+        .collect();
+
+    let created = results.iter().filter(|row| row.user_id.is_some()).count();
+    log::info!(
+        "Imported {} users, {} failed",
+        created,
+        results.len() - created
+    );
+
+    Ok(ImportResponse {
+        created,
+        failed: results.len() - created,
+        results,
+    })
+}
+
+/// Validate a row and work out the hash to store
+fn prepare(row: &ImportUserRequest) -> Result<CreateUser, String> {
+    if !row.email.contains('@') || !row.email.contains('.') {
+        return Err("Invalid email format".to_string());
+    }
+
+    let password_hash = match (&row.password, &row.password_hash) {
+        (Some(password), None) => PasswordManager::hash(password).map_err(|e| e.to_string())?,
+        (None, Some(hash)) if PasswordManager::is_valid_hash(hash) => hash.clone(),
+        (None, Some(_)) => return Err("Unsupported password hash".to_string()),
+        _ => return Err("Exactly one of password or password_hash is required".to_string()),
+    };
+
+    Ok(CreateUser {
+        email: row.email.clone(),
+        username: None,
+        password_hash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory::MemoryStore;
+
+    fn row(email: &str, password_hash: &str) -> ImportUserRequest {
+        ImportUserRequest {
+            email: email.to_string(),
+            password: None,
+            password_hash: Some(password_hash.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_is_reported_and_rest_imported() {
+        let store = MemoryStore::new();
+        let hash = bcrypt::hash("LegacyPass1", 4).unwrap();
+        store
+            .create(CreateUser {
+                email: "taken@example.com".to_string(),
+                username: None,
+                password_hash: hash.clone(),
+            })
+            .await
+            .unwrap();
+
+        let response = import_users(
+            &store,
+            vec![
+                row("a@example.com", &hash),
+                row("taken@example.com", &hash),
+                row("b@example.com", &hash),
+                // Repeated within the import
+                row("a@example.com", &hash),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.created, 2);
+        assert_eq!(response.failed, 2);
+        assert_eq!(store.user_count(), 3);
+
+        let errors: Vec<_> = response
+            .results
+            .iter()
+            .map(|row| row.error.as_deref())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                None,
+                Some("Email already exists"),
+                None,
+                Some("Email already exists")
+            ]
+        );
+        assert!(response.results[0].user_id.is_some());
+        assert!(response.results[1].user_id.is_none());
+
+        // Imported hashes log in as before
+        let user = store.find_by_email("b@example.com").await.unwrap();
+        assert!(PasswordManager::verify("LegacyPass1", &user.password_hash).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_rows_fail_alone() {
+        let store = MemoryStore::new();
+        let hash = bcrypt::hash("LegacyPass1", 4).unwrap();
+
+        let response = import_users(
+            &store,
+            vec![
+                row("not-an-email", &hash),
+                row("md5@example.com", "5f4dcc3b5aa765d61d8327deb882cf99"),
+                ImportUserRequest {
+                    email: "weak@example.com".to_string(),
+                    password: Some("weak".to_string()),
+                    password_hash: None,
+                },
+                ImportUserRequest {
+                    email: "both@example.com".to_string(),
+                    password: Some("SecurePass123!".to_string()),
+                    password_hash: Some(hash.clone()),
+                },
+                ImportUserRequest {
+                    email: "plain@example.com".to_string(),
+                    password: Some("SecurePass123!".to_string()),
+                    password_hash: None,
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.created, 1);
+        assert_eq!(response.failed, 4);
+        assert_eq!(
+            response.results[2].error.as_deref(),
+            Some("Password must be at least 8 characters long")
+        );
+
+        // Plain passwords are hashed on the way in
+        let user = store.find_by_email("plain@example.com").await.unwrap();
+        assert_ne!(user.password_hash, "SecurePass123!");
+        assert!(PasswordManager::verify("SecurePass123!", &user.password_hash).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_import_size_is_capped() {
+        let rows = vec![row("a@example.com", "x"); MAX_IMPORT_ROWS + 1];
+        assert!(matches!(
+            import_users(&MemoryStore::new(), rows).await,
+            Err(ImportError::TooManyUsers(_))
+        ));
+    }
+}
@@ -1,8 +1,12 @@
-use chrono::{Duration, Utc};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
+use crate::auth::hashing::hash_refresh_token;
+
 /// JWT Claims structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
@@ -11,14 +15,89 @@ pub struct Claims {
     pub iat: i64,           // Issued at (as UTC timestamp)
     pub jti: String,        // JWT ID (unique identifier for this token)
     pub token_type: String, // "access" or "refresh"
+    /// User's token version at issue time; tokens from before a bump are rejected
+    #[serde(default)]
+    pub token_version: i32,
+    /// When the login that began this refresh chain happened (refresh
+    /// tokens only); carried over on rotation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_started_at: Option<i64>,
+    /// App the token was issued for, when the login asked for one; carried
+    /// over on refresh
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+}
+
+impl Claims {
+    /// Start of the session this token belongs to
+    ///
+    /// Tokens from before `session_started_at` existed count from their `iat`.
+    pub fn session_started_at(&self) -> i64 {
+        self.session_started_at.unwrap_or(self.iat)
+    }
+}
+
+/// What a token claims about itself, read without verifying it
+///
+/// Anyone can write any `sub` into a token, so these are only fit for
+/// logging; they carry no authority. Both fields are optional because a
+/// foreign token need not have them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct UnverifiedClaims {
+    #[serde(default)]
+    pub sub: Option<String>,
+    #[serde(default)]
+    pub jti: Option<String>,
+}
+
+/// Read a token's claims with no signature, expiry or type checks
+///
+/// **Not authoritative:** use only to log who a rejected token claims to
+/// belong to. Authorization must go through [`JwtManager::validate_token`].
+///
+/// # Arguments
+/// * `token` - JWT token string, possibly expired, foreign or forged
+///
+/// # Returns
+/// * `Option<UnverifiedClaims>` - `None` if the payload is not base64url JSON
+///
+/// # Example
+/// ```
+/// if let Err(e) = jwt_manager.validate_token(&token) {
+///     let claimed = peek_claims(&token).unwrap_or_default();
+///     log::warn!("Rejected token for sub {:?}: {}", claimed.sub, e);
+/// }
+/// ```
+pub fn peek_claims(token: &str) -> Option<UnverifiedClaims> {
+    let mut parts = token.split('.');
+    let (Some(_header), Some(payload), Some(_signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+
+    let json = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    serde_json::from_slice(&json).ok()
 }
 
+/// Clock skew tolerated by default, matching `jsonwebtoken`'s own default
+const DEFAULT_LEEWAY: u64 = 60;
+
 /// JWT token manager
 #[derive(Clone)]
 pub struct JwtManager {
     secret: String,
-    access_token_expiration: i64,  // in seconds
-    refresh_token_expiration: i64, // in seconds
+    access_token_expiration: i64,            // in seconds
+    suspicious_access_token_expiration: i64, // in seconds, for flagged logins
+    suspicious_session_count: Option<usize>, // live sessions that flag a login
+    refresh_token_expiration: i64,           // in seconds
+    absolute_expiry: bool,                   // add RFC3339 expiry instants to responses
+    leeway: u64,                             // clock skew tolerated, in seconds
+    max_session_lifetime: Option<i64>,       // in seconds, across refresh rotations
+    expired_grace: Option<i64>,              // in seconds, for `recently_expired`
+    clients: BTreeMap<String, Vec<String>>,  // audiences each client may request
+    audience: Option<String>,                // `aud` of issued tokens
+    single_session: bool,                    // a login revokes the user's other sessions
 }
 
 impl JwtManager {
@@ -36,28 +115,130 @@ impl JwtManager {
         Self {
             secret,
             access_token_expiration,
+            suspicious_access_token_expiration: access_token_expiration,
+            suspicious_session_count: None,
             refresh_token_expiration,
+            absolute_expiry: false,
+            leeway: DEFAULT_LEEWAY,
+            max_session_lifetime: None,
+            expired_grace: None,
+            clients: BTreeMap::new(),
+            audience: None,
+            single_session: false,
         }
     }
 
+    /// Also report absolute RFC3339 expiry instants in token responses
+    pub fn with_absolute_expiry(mut self, enabled: bool) -> Self {
+        self.absolute_expiry = enabled;
+        self
+    }
+
+    /// Access token lifetime (seconds) for logins flagged as suspicious;
+    /// `None` keeps the normal lifetime
+    pub fn with_suspicious_access_token_expiration(mut self, expiration: Option<i64>) -> Self {
+        self.suspicious_access_token_expiration =
+            expiration.unwrap_or(self.access_token_expiration);
+        self
+    }
+
+    /// Flag a login as suspicious when the user already has this many live
+    /// sessions; `None` never flags
+    pub fn with_suspicious_session_count(mut self, count: Option<usize>) -> Self {
+        self.suspicious_session_count = count;
+        self
+    }
+
+    /// Refuse refreshes once a session is this old (seconds) since login;
+    /// `None` lets rotation extend sessions indefinitely
+    pub fn with_max_session_lifetime(mut self, lifetime: Option<i64>) -> Self {
+        self.max_session_lifetime = lifetime;
+        self
+    }
+
+    /// Sign out a user's other sessions whenever they log in; `false`
+    /// (the default) lets sessions on several devices coexist
+    pub fn with_single_session(mut self, enabled: bool) -> Self {
+        self.single_session = enabled;
+        self
+    }
+
+    /// Tolerate this much clock skew (seconds) between issuer and proxy
+    pub fn with_leeway(mut self, leeway: u64) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Audiences each client id may request at login
+    pub fn with_clients(mut self, clients: BTreeMap<String, Vec<String>>) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// Put this `aud` on the tokens issued (none: unscoped tokens)
+    ///
+    /// # Example
+    /// ```
+    /// let app_jwt = jwt_manager.clone().with_audience(Some("app-a".to_string()));
+    /// let token = app_jwt.generate_access_token(&user.id, user.token_version)?;
+    /// ```
+    pub fn with_audience(mut self, audience: Option<String>) -> Self {
+        self.audience = audience;
+        self
+    }
+
+    /// Whether a client may request tokens for an audience
+    pub fn allows_audience(&self, client_id: &str, audience: &str) -> bool {
+        self.clients
+            .get(client_id)
+            .is_some_and(|audiences| audiences.iter().any(|a| a == audience))
+    }
+
+    /// Report tokens that expired at most this long ago (seconds) as
+    /// `recently_expired`; `None` treats every expired token as invalid
+    pub fn with_expired_grace(mut self, grace: Option<i64>) -> Self {
+        self.expired_grace = grace;
+        self
+    }
+
     /// Generate an access token for a user
     ///
     /// # Arguments
     /// * `user_id` - User's UUID
+    /// * `token_version` - User's current token version
     ///
     /// # Returns
     /// * `Result<String, jsonwebtoken::errors::Error>` - JWT token or error
     ///
     /// # Example
     /// ```
-    /// let token = jwt_manager.generate_access_token(&user_id)?;
+    /// let token = jwt_manager.generate_access_token(&user.id, user.token_version)?;
     /// ```
     pub fn generate_access_token(
         &self,
         user_id: &Uuid,
+        token_version: i32,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        self.generate_access_token_expiring_in(user_id, token_version, self.access_token_expiration)
+    }
+
+    /// Generate an access token with a lifetime other than the configured one
+    ///
+    /// # Arguments
+    /// * `user_id` - User's UUID
+    /// * `token_version` - User's current token version
+    /// * `expiration` - Lifetime in seconds
+    ///
+    /// # Returns
+    /// * `Result<String, jsonwebtoken::errors::Error>` - JWT token or error
+    pub fn generate_access_token_expiring_in(
+        &self,
+        user_id: &Uuid,
+        token_version: i32,
+        expiration: i64,
     ) -> Result<String, jsonwebtoken::errors::Error> {
         let now = Utc::now();
-        let expiration = now + Duration::seconds(self.access_token_expiration);
+        let expiration = now + Duration::seconds(expiration);
 
         let claims = Claims {
             sub: user_id.to_string(),
@@ -65,6 +246,9 @@ impl JwtManager {
             iat: now.timestamp(),
             jti: Uuid::new_v4().to_string(), // Unique ID for this token
             token_type: "access".to_string(),
+            token_version,
+            session_started_at: None,
+            aud: self.audience.clone(),
         };
 
         self.encode_token(&claims)
@@ -74,16 +258,43 @@ impl JwtManager {
     ///
     /// # Arguments
     /// * `user_id` - User's UUID
+    /// * `token_version` - User's current token version
     ///
     /// # Returns
-    /// * `Result<(String, String), jsonwebtoken::errors::Error>` - (token, token_hash) or error
+    /// * `Result<(String, String, DateTime<Utc>), jsonwebtoken::errors::Error>` -
+    ///   (token, token_hash, expires_at) or error
     ///
     /// # Note
-    /// Returns both the token (to send to client) and its hash (to store in database)
+    /// Returns the token (to send to client), its hash and its expiry (to store
+    /// in database). `expires_at` is the same instant encoded in the `exp` claim,
+    /// so the database row and the JWT expire together.
+    ///
+    /// The token starts a new session; rotations use
+    /// [`generate_rotated_refresh_token`](Self::generate_rotated_refresh_token).
     pub fn generate_refresh_token(
         &self,
         user_id: &Uuid,
-    ) -> Result<(String, String), jsonwebtoken::errors::Error> {
+        token_version: i32,
+    ) -> Result<(String, String, DateTime<Utc>), jsonwebtoken::errors::Error> {
+        self.generate_rotated_refresh_token(user_id, token_version, Utc::now().timestamp())
+    }
+
+    /// Generate a refresh token continuing an existing session
+    ///
+    /// # Arguments
+    /// * `user_id` - User's UUID
+    /// * `token_version` - User's current token version
+    /// * `session_started_at` - From the replaced token's [`Claims::session_started_at`]
+    ///
+    /// # Returns
+    /// * `Result<(String, String, DateTime<Utc>), jsonwebtoken::errors::Error>` -
+    ///   (token, token_hash, expires_at) or error
+    pub fn generate_rotated_refresh_token(
+        &self,
+        user_id: &Uuid,
+        token_version: i32,
+        session_started_at: i64,
+    ) -> Result<(String, String, DateTime<Utc>), jsonwebtoken::errors::Error> {
         let now = Utc::now();
         let expiration = now + Duration::seconds(self.refresh_token_expiration);
 
@@ -93,14 +304,17 @@ impl JwtManager {
             iat: now.timestamp(),
             jti: Uuid::new_v4().to_string(),
             token_type: "refresh".to_string(),
+            token_version,
+            session_started_at: Some(session_started_at),
+            aud: self.audience.clone(),
         };
 
         let token = self.encode_token(&claims)?;
 
         // Hash the token for storage (similar to password hashing)
-        let token_hash = self.hash_token(&token);
+        let token_hash = hash_refresh_token(&token);
 
-        Ok((token, token_hash))
+        Ok((token, token_hash, expiration))
     }
 
     /// Decode and validate a JWT token
@@ -118,7 +332,10 @@ impl JwtManager {
     /// ```
     pub fn decode_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
         let decoding_key = DecodingKey::from_secret(self.secret.as_bytes());
-        let validation = Validation::new(Algorithm::HS256);
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = self.leeway;
+        // `aud` is checked per route by the proxy, not for every token
+        validation.validate_aud = false;
 
         let token_data = decode::<Claims>(token, &decoding_key, &validation)?;
 
@@ -127,6 +344,10 @@ impl JwtManager {
 
     /// Validate token and check if it's not expired
     ///
+    /// A token whose `iat` is more than the leeway ahead of now is
+    /// rejected too: it was minted by an issuer with a wrong clock, or
+    /// forged to outlive its real lifetime.
+    ///
     /// # Arguments
     /// * `token` - JWT token string
     ///
@@ -141,33 +362,86 @@ impl JwtManager {
                     return Err("Token has expired".to_string());
                 }
 
+                if claims.iat > now + self.leeway as i64 {
+                    return Err("Token issued in the future".to_string());
+                }
+
                 Ok(claims)
             }
             Err(e) => Err(format!("Invalid token: {}", e)),
         }
     }
 
+    /// Whether a token failing validation is one of ours that only just expired
+    ///
+    /// The signature must still verify; only the expiry is ignored, and it
+    /// must be within the grace set by [`JwtManager::with_expired_grace`].
+    /// Clients are told to refresh such a token rather than that it is
+    /// invalid.
+    pub fn recently_expired(&self, token: &str) -> bool {
+        let Some(grace) = self.expired_grace else {
+            return false;
+        };
+        let decoding_key = DecodingKey::from_secret(self.secret.as_bytes());
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        let Ok(token_data) = decode::<Claims>(token, &decoding_key, &validation) else {
+            return false;
+        };
+
+        let expired_for = Utc::now().timestamp() - token_data.claims.exp;
+        expired_for > 0 && expired_for <= grace
+    }
+
     /// Encode claims into JWT token
     fn encode_token(&self, claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
         let encoding_key = EncodingKey::from_secret(self.secret.as_bytes());
         encode(&Header::default(), claims, &encoding_key)
     }
 
-    /// Hash a token for secure storage
-    /// Uses SHA256 for fast hashing (tokens are already random)
-    fn hash_token(&self, token: &str) -> String {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-
-        let mut hasher = DefaultHasher::new();
-        token.hash(&mut hasher);
-        format!("{:x}", hasher.finish())
-    }
-
     /// Get access token expiration in seconds
     pub fn access_token_expiration(&self) -> i64 {
         self.access_token_expiration
     }
+
+    /// Whether a login revokes the user's other sessions
+    pub fn single_session(&self) -> bool {
+        self.single_session
+    }
+
+    /// Get the access token expiration for suspicious logins in seconds
+    pub fn suspicious_access_token_expiration(&self) -> i64 {
+        self.suspicious_access_token_expiration
+    }
+
+    /// Live sessions at which a login is flagged as suspicious, if any
+    pub fn suspicious_session_count(&self) -> Option<usize> {
+        self.suspicious_session_count
+    }
+
+    /// Whether the session a refresh token belongs to has outlived `max_session_lifetime`
+    pub fn session_expired(&self, claims: &Claims) -> bool {
+        self.max_session_lifetime
+            .is_some_and(|lifetime| Utc::now().timestamp() - claims.session_started_at() > lifetime)
+    }
+
+    /// Get refresh token expiration in seconds
+    pub fn refresh_token_expiration(&self) -> i64 {
+        self.refresh_token_expiration
+    }
+
+    /// Absolute expiry for a token response, if enabled
+    ///
+    /// # Arguments
+    /// * `expires_at` - Expiry instant
+    ///
+    /// # Returns
+    /// * `Some("2025-01-01T12:15:00Z")` when absolute expiry is enabled, `None` otherwise
+    pub fn expiry_timestamp(&self, expires_at: DateTime<Utc>) -> Option<String> {
+        self.absolute_expiry
+            .then(|| expires_at.to_rfc3339_opts(SecondsFormat::Secs, true))
+    }
 }
 
 #[cfg(test)]
@@ -182,12 +456,27 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_expiry_timestamp() {
+        let expires_at = DateTime::parse_from_rfc3339("2025-01-01T12:15:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(create_test_manager().expiry_timestamp(expires_at), None);
+        assert_eq!(
+            create_test_manager()
+                .with_absolute_expiry(true)
+                .expiry_timestamp(expires_at),
+            Some("2025-01-01T12:15:00Z".to_string())
+        );
+    }
+
     #[test]
     fn test_generate_and_decode_access_token() {
         let manager = create_test_manager();
         let user_id = Uuid::new_v4();
 
-        let token = manager.generate_access_token(&user_id).unwrap();
+        let token = manager.generate_access_token(&user_id, 0).unwrap();
         let claims = manager.decode_token(&token).unwrap();
 
         assert_eq!(claims.sub, user_id.to_string());
@@ -199,11 +488,12 @@ mod tests {
         let manager = create_test_manager();
         let user_id = Uuid::new_v4();
 
-        let (token, hash) = manager.generate_refresh_token(&user_id).unwrap();
+        let (token, hash, expires_at) = manager.generate_refresh_token(&user_id, 0).unwrap();
         let claims = manager.decode_token(&token).unwrap();
 
         assert_eq!(claims.sub, user_id.to_string());
         assert_eq!(claims.token_type, "refresh");
+        assert_eq!(claims.exp, expires_at.timestamp());
         assert!(!hash.is_empty());
     }
 
@@ -212,7 +502,7 @@ mod tests {
         let manager = create_test_manager();
         let user_id = Uuid::new_v4();
 
-        let token = manager.generate_access_token(&user_id).unwrap();
+        let token = manager.generate_access_token(&user_id, 0).unwrap();
         let result = manager.validate_token(&token);
 
         assert!(result.is_ok());
@@ -234,9 +524,131 @@ mod tests {
         let manager2 = JwtManager::new("secret2".to_string(), 900, 604800);
 
         let user_id = Uuid::new_v4();
-        let token1 = manager1.generate_access_token(&user_id).unwrap();
+        let token1 = manager1.generate_access_token(&user_id, 0).unwrap();
 
         // Token from manager1 should not be valid for manager2
         assert!(manager2.decode_token(&token1).is_err());
     }
+
+    /// Access token signed by `manager` claiming to be issued `offset` from now
+    fn token_issued_in(manager: &JwtManager, offset: Duration) -> String {
+        let iat = Utc::now() + offset;
+        let claims = Claims {
+            sub: Uuid::new_v4().to_string(),
+            exp: (iat + Duration::seconds(900)).timestamp(),
+            iat: iat.timestamp(),
+            jti: Uuid::new_v4().to_string(),
+            token_type: "access".to_string(),
+            token_version: 0,
+            session_started_at: None,
+            aud: None,
+        };
+        manager.encode_token(&claims).unwrap()
+    }
+
+    #[test]
+    fn test_recently_expired_within_grace() {
+        let manager = create_test_manager().with_expired_grace(Some(300));
+        let user_id = Uuid::new_v4();
+        let expired = |secs: i64| {
+            JwtManager::new("test_secret_key_12345".to_string(), -secs, 604800)
+                .generate_access_token(&user_id, 0)
+                .unwrap()
+        };
+
+        assert!(manager.validate_token(&expired(30)).is_err());
+        assert!(manager.recently_expired(&expired(30)));
+        assert!(!manager.recently_expired(&expired(600)));
+        assert!(!manager.recently_expired("not.a.jwt"));
+        // Still valid, so not expired at all
+        let token = manager.generate_access_token(&user_id, 0).unwrap();
+        assert!(!manager.recently_expired(&token));
+        // Without a grace, no token counts as recently expired
+        assert!(!create_test_manager().recently_expired(&expired(30)));
+        // A token signed with another secret is never ours
+        let foreign = JwtManager::new("other_secret".to_string(), -30, 604800)
+            .generate_access_token(&user_id, 0)
+            .unwrap();
+        assert!(!manager.recently_expired(&foreign));
+    }
+
+    #[test]
+    fn test_audience_is_carried_by_issued_tokens() {
+        let manager = create_test_manager().with_clients(BTreeMap::from([(
+            "app-a-web".to_string(),
+            vec!["app-a".to_string()],
+        )]));
+        assert!(manager.allows_audience("app-a-web", "app-a"));
+        assert!(!manager.allows_audience("app-a-web", "app-b"));
+        assert!(!manager.allows_audience("unknown", "app-a"));
+
+        let user_id = Uuid::new_v4();
+        let app_a = manager.clone().with_audience(Some("app-a".to_string()));
+        let access = app_a.generate_access_token(&user_id, 0).unwrap();
+        let (refresh, _, _) = app_a.generate_refresh_token(&user_id, 0).unwrap();
+
+        // Verified by the unscoped manager; the route decides on `aud`
+        assert_eq!(
+            manager.validate_token(&access).unwrap().aud.as_deref(),
+            Some("app-a")
+        );
+        assert_eq!(
+            manager.validate_token(&refresh).unwrap().aud.as_deref(),
+            Some("app-a")
+        );
+        let unscoped = manager.generate_access_token(&user_id, 0).unwrap();
+        assert_eq!(manager.validate_token(&unscoped).unwrap().aud, None);
+    }
+
+    #[test]
+    fn test_peek_claims_reads_expired_token() {
+        let manager = create_test_manager();
+        let iat = Utc::now() - Duration::hours(2);
+        let claims = Claims {
+            sub: Uuid::new_v4().to_string(),
+            exp: (iat + Duration::seconds(900)).timestamp(),
+            iat: iat.timestamp(),
+            jti: Uuid::new_v4().to_string(),
+            token_type: "access".to_string(),
+            token_version: 0,
+            session_started_at: None,
+            aud: None,
+        };
+        let token = manager.encode_token(&claims).unwrap();
+
+        assert!(manager.validate_token(&token).is_err());
+
+        let claimed = peek_claims(&token).unwrap();
+        assert_eq!(claimed.sub, Some(claims.sub));
+        assert_eq!(claimed.jti, Some(claims.jti));
+    }
+
+    #[test]
+    fn test_peek_claims_ignores_signature() {
+        let token = create_test_manager()
+            .generate_access_token(&Uuid::new_v4(), 0)
+            .unwrap();
+        let other = JwtManager::new("other_secret".to_string(), 900, 604800);
+
+        assert!(other.validate_token(&token).is_err());
+        assert!(peek_claims(&token).unwrap().sub.is_some());
+
+        assert_eq!(peek_claims("invalid.jwt.token"), None);
+        assert_eq!(peek_claims("not-a-jwt"), None);
+    }
+
+    #[test]
+    fn test_future_iat_is_rejected() {
+        let manager = create_test_manager().with_leeway(30);
+
+        let token = token_issued_in(&manager, Duration::hours(1));
+        assert_eq!(
+            manager.validate_token(&token).unwrap_err(),
+            "Token issued in the future"
+        );
+
+        // Ahead by less than the leeway: ordinary clock skew
+        let token = token_issued_in(&manager, Duration::seconds(10));
+        assert!(manager.validate_token(&token).is_ok());
+    }
 }
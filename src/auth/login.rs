@@ -1,15 +1,52 @@
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use std::net::IpAddr;
 use thiserror::Error;
 
+use crate::auth::audit::{AuditEvent, AuditLog, AuditSeverity};
+use crate::auth::schema::{
+    Field, Schema, MAX_AUDIENCE_LENGTH, MAX_EMAIL_LENGTH, MAX_PASSWORD_LENGTH,
+};
 use crate::auth::{JwtManager, PasswordManager};
-use crate::db::{TokenRepository, UserRepository};
+use crate::db::error::DbFailure;
+use crate::db::user::{CredentialCheck, User, UserError, Usernames};
+use crate::db::{TokenStore, UserStore};
+use crate::metrics;
 
 /// Login request payload
 #[derive(Debug, Clone, Deserialize)]
 pub struct LoginRequest {
-    pub email: String,
+    /// Email, or username with `middleware.auth.usernames`; still accepted
+    /// as `email` from older clients
+    #[serde(alias = "email")]
+    pub identifier: String,
     pub password: String,
+    /// App asking for the tokens, one of `jwt.clients`
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// `aud` for the tokens, from the client's allowed audiences; tokens
+    /// without one are accepted on every route that requires no audience
+    #[serde(default)]
+    pub audience: Option<String>,
+}
+
+impl Schema for LoginRequest {
+    const FIELDS: &'static [Field] = &[
+        Field::required("identifier", MAX_EMAIL_LENGTH).with_aliases(&["email"]),
+        Field::required("password", MAX_PASSWORD_LENGTH),
+        Field::optional("client_id", MAX_AUDIENCE_LENGTH),
+        Field::optional("audience", MAX_AUDIENCE_LENGTH),
+    ];
+}
+
+/// Risk assessment of a login, see [`assess_risk`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoginRisk {
+    #[default]
+    Normal,
+    /// Flagged (the user already holds `jwt.suspicious_session_count` live
+    /// sessions): the access token gets `jwt.suspicious_access_token_expiration`
+    Suspicious,
 }
 
 /// Login response
@@ -18,9 +55,27 @@ pub struct LoginResponse {
     pub user_id: String,
     pub email: String,
     pub access_token: String,
+    /// Empty, and left out, for logins while the database is read-only
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub refresh_token: String,
     pub token_type: String,
     pub expires_in: i64,
+    /// Access token expiry as an RFC3339 instant (`jwt.absolute_expiry` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// Refresh token expiry as an RFC3339 instant (`jwt.absolute_expiry` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_at: Option<String>,
+    /// The user's roles, for rendering UI without another call
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    /// Permissions derived from `roles`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
+    /// The login was flagged as suspicious: the access token is short-lived
+    /// and the client should prompt for step-up auth
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub step_up_required: bool,
 }
 
 /// Login error types
@@ -29,23 +84,62 @@ pub enum LoginError {
     #[error("Invalid credentials")]
     InvalidCredentials,
 
-    #[error("User not found")]
-    UserNotFound,
+    #[error("Audience not allowed for this client")]
+    AudienceNotAllowed,
+
+    #[error("Password verification error: {0}")]
+    PasswordVerification(String),
 
     #[error("Database error: {0}")]
-    DatabaseError(String),
+    DatabaseError(DbFailure),
 
     #[error("Token generation failed: {0}")]
     TokenError(String),
 }
 
+/// Backends a login reads and records its state in
+#[derive(Clone, Copy)]
+pub struct LoginStores<'a> {
+    /// User storage backend
+    pub users: &'a dyn UserStore,
+    /// Refresh token storage backend
+    pub tokens: &'a dyn TokenStore,
+    /// Where signing out other sessions (single-session mode) is recorded
+    pub audit: &'a dyn AuditLog,
+    /// The database cannot be written (`server.read_only`)
+    pub read_only: bool,
+}
+
+impl<'a> LoginStores<'a> {
+    pub fn new(
+        users: &'a dyn UserStore,
+        tokens: &'a dyn TokenStore,
+        audit: &'a dyn AuditLog,
+    ) -> Self {
+        Self {
+            users,
+            tokens,
+            audit,
+            read_only: false,
+        }
+    }
+
+    /// Log in without writing to the database: no password hash upgrade,
+    /// no single-session sign-out and no refresh token, just an access token
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+}
+
 /// Authenticate user and generate tokens
 ///
 /// # Arguments
-/// * `pool` - Database connection pool
+/// * `stores` - User, refresh token and audit backends
 /// * `jwt_manager` - JWT token manager
 /// * `request` - Login request data
-/// * `refresh_token_expiration` - Refresh token expiration in seconds
+/// * `usernames` - Whether `request.identifier` may be a username
+/// * `client_ip` - Client address, for the audit event
 ///
 /// # Returns
 /// * `Result<LoginResponse, LoginError>` - Login response or error
@@ -53,79 +147,267 @@ pub enum LoginError {
 /// # Example
 /// ```
 /// let request = LoginRequest {
-///     email: "user@example.com".to_string(),
+///     identifier: "user@example.com".to_string(),
 ///     password: "SecurePass123!".to_string(),
+///     client_id: None,
+///     audience: None,
 /// };
 ///
-/// let response = login_user(
-///     &pool,
-///     &jwt_manager,
-///     request,
-///     604800
-/// ).await?;
+/// let stores = LoginStores::new(
+///     &UserRepository::new(&pool),
+///     &TokenRepository::new(&pool),
+///     &LogAudit,
+/// );
+/// let response = login_user(stores, &jwt_manager, request, Usernames::Disabled, None).await?;
 /// ```
 pub async fn login_user(
-    pool: &PgPool,
+    stores: LoginStores<'_>,
     jwt_manager: &JwtManager,
     request: LoginRequest,
-    refresh_token_expiration: i64,
+    usernames: Usernames,
+    client_ip: Option<IpAddr>,
 ) -> Result<LoginResponse, LoginError> {
-    let user_repo = UserRepository::new(pool);
+    let LoginStores {
+        users,
+        tokens,
+        audit,
+        read_only,
+    } = stores;
+
+    // Tokens for an app only go to a client allowed to ask for them
+    if let Some(audience) = &request.audience {
+        let client_id = request.client_id.as_deref().unwrap_or_default();
+        if !jwt_manager.allows_audience(client_id, audience) {
+            log::warn!(
+                "Login by client {:?} refused: audience {} not allowed",
+                request.client_id,
+                audience
+            );
+            return Err(LoginError::AudienceNotAllowed);
+        }
+    }
+    let jwt_manager = &jwt_manager.clone().with_audience(request.audience.clone());
 
-    // Find user by email
-    let user = user_repo
-        .find_by_email(&request.email)
+    // Find user and verify password. Unknown accounts and wrong passwords
+    // produce the same error (and the same bcrypt cost) so the response
+    // cannot be used to probe which accounts exist.
+    let mut user = match users
+        .verify_credentials(&request.identifier, &request.password, usernames)
         .await
         .map_err(|e| match e {
-            crate::db::user::UserError::NotFound => LoginError::UserNotFound,
-            _ => LoginError::DatabaseError(e.to_string()),
-        })?;
+            UserError::PasswordVerification(e) => LoginError::PasswordVerification(e.to_string()),
+            e => LoginError::DatabaseError(e.into()),
+        })? {
+        CredentialCheck::Valid(user) => user,
+        CredentialCheck::InvalidPassword | CredentialCheck::UnknownUser => {
+            log::warn!("Failed login attempt for user: {}", request.identifier);
+            return Err(LoginError::InvalidCredentials);
+        }
+    };
 
-    // Verify password
-    let is_valid = PasswordManager::verify(&request.password, &user.password_hash)
-        .map_err(|e| LoginError::DatabaseError(e.to_string()))?;
+    log::info!("User logged in: {} (ID: {})", user.email, user.id);
 
-    if !is_valid {
-        log::warn!("Failed login attempt for user: {}", request.email);
-        return Err(LoginError::InvalidCredentials);
+    // Upgrade hashes made with an outdated cost or pepper while we have the password
+    if !read_only && PasswordManager::needs_rehash(&user.password_hash) {
+        upgrade_password_hash(users, &user, &request.password).await;
     }
 
-    log::info!("User logged in: {} (ID: {})", user.email, user.id);
+    // Assessed before single-session mode signs the other sessions out
+    let risk = assess_risk(tokens, jwt_manager, &user).await?;
+
+    if jwt_manager.single_session() && !read_only {
+        user.token_version = revoke_other_sessions(users, tokens, &user, audit, client_ip).await?;
+    }
+
+    let step_up_required = risk == LoginRisk::Suspicious;
+    let access_expiration = if step_up_required {
+        log::warn!(
+            "Suspicious login for user {}, issuing a short-lived access token",
+            user.id
+        );
+        jwt_manager.suspicious_access_token_expiration()
+    } else {
+        jwt_manager.access_token_expiration()
+    };
 
     // Generate tokens
+    let issued_at = Utc::now();
     let access_token = jwt_manager
-        .generate_access_token(&user.id)
+        .generate_access_token_expiring_in(&user.id, user.token_version, access_expiration)
         .map_err(|e| LoginError::TokenError(e.to_string()))?;
 
-    let (refresh_token, refresh_token_hash) = jwt_manager
-        .generate_refresh_token(&user.id)
-        .map_err(|e| LoginError::TokenError(e.to_string()))?;
+    // A refresh token that cannot be stored would be refused as a replay
+    let (refresh_token, refresh_expires_at) = if read_only {
+        log::info!(
+            "Read-only mode: no refresh token issued for user {}",
+            user.id
+        );
+        (String::new(), None)
+    } else {
+        let (refresh_token, refresh_token_hash, refresh_expires_at) = jwt_manager
+            .generate_refresh_token(&user.id, user.token_version)
+            .map_err(|e| LoginError::TokenError(e.to_string()))?;
 
-    // Save refresh token to database
-    let token_repo = TokenRepository::new(pool);
-    token_repo
-        .save_refresh_token(&user.id, &refresh_token_hash, refresh_token_expiration)
-        .await
-        .map_err(|e| LoginError::DatabaseError(e.to_string()))?;
+        // Save refresh token to database, expiring with the JWT itself
+        tokens
+            .save_refresh_token(&user.id, &refresh_token_hash, refresh_expires_at)
+            .await
+            .map_err(|e| LoginError::DatabaseError(e.into()))?;
+
+        (
+            refresh_token,
+            jwt_manager.expiry_timestamp(refresh_expires_at),
+        )
+    };
 
     log::info!("Tokens generated for user: {}", user.email);
 
     Ok(LoginResponse {
         user_id: user.id.to_string(),
+        roles: user.roles(),
+        permissions: user.permissions(),
         email: user.email,
         access_token,
         refresh_token,
         token_type: "Bearer".to_string(),
-        expires_in: jwt_manager.access_token_expiration(),
+        expires_in: access_expiration,
+        expires_at: jwt_manager.expiry_timestamp(issued_at + Duration::seconds(access_expiration)),
+        refresh_expires_at,
+        step_up_required,
     })
 }
 
+/// Assess how risky a login with valid credentials is
+///
+/// A user who already holds `jwt.suspicious_session_count` unexpired refresh
+/// tokens is logging in from yet another device, which is flagged.
+///
+/// # Returns
+/// * `Result<LoginRisk, LoginError>` - The verdict, `Normal` when the check is off
+async fn assess_risk(
+    tokens: &dyn TokenStore,
+    jwt_manager: &JwtManager,
+    user: &User,
+) -> Result<LoginRisk, LoginError> {
+    let Some(limit) = jwt_manager.suspicious_session_count() else {
+        return Ok(LoginRisk::Normal);
+    };
+
+    let now = Utc::now();
+    let sessions = tokens
+        .user_tokens(&user.id)
+        .await
+        .map_err(|e| LoginError::DatabaseError(e.into()))?
+        .iter()
+        .filter(|token| token.expires_at > now)
+        .count();
+
+    if sessions >= limit {
+        log::warn!(
+            "User {} already has {} live sessions (limit {})",
+            user.id,
+            sessions,
+            limit
+        );
+        Ok(LoginRisk::Suspicious)
+    } else {
+        Ok(LoginRisk::Normal)
+    }
+}
+
+/// Sign out every existing session of a user who is logging in again
+///
+/// Like a password change: old access tokens fail the version check and
+/// old refresh tokens are gone.
+///
+/// # Returns
+/// * `Result<i32, LoginError>` - The new token version, for the new session
+async fn revoke_other_sessions(
+    users: &dyn UserStore,
+    tokens: &dyn TokenStore,
+    user: &User,
+    audit: &dyn AuditLog,
+    client_ip: Option<IpAddr>,
+) -> Result<i32, LoginError> {
+    let token_version = users
+        .bump_token_version(&user.id)
+        .await
+        .map_err(|e| LoginError::DatabaseError(e.into()))?;
+    let revoked = tokens
+        .revoke_all_user_tokens(&user.id)
+        .await
+        .map_err(|e| LoginError::DatabaseError(e.into()))?;
+
+    log::info!(
+        "Single-session login for user {}: {} other sessions signed out",
+        user.id,
+        revoked
+    );
+    if revoked > 0 {
+        audit.record(AuditEvent {
+            event: "other_sessions_revoked",
+            severity: AuditSeverity::Info,
+            user_id: user.id,
+            client_ip,
+        });
+    }
+
+    Ok(token_version)
+}
+
+/// Re-hash the password at the current cost and pepper and store it
+///
+/// Failures are logged but never fail the login; the old hash still works.
+async fn upgrade_password_hash(users: &dyn UserStore, user: &User, password: &str) {
+    let new_hash = match PasswordManager::rehash(password) {
+        Ok(new_hash) => new_hash,
+        Err(e) => {
+            log::warn!("Password rehash failed for user {}: {}", user.id, e);
+            return;
+        }
+    };
+
+    match users.update_password(&user.id, &new_hash).await {
+        Ok(_) => {
+            metrics::PASSWORD_REHASHES.inc();
+            log::info!("Password hash upgraded for user: {}", user.email);
+        }
+        Err(e) => log::warn!("Failed to store upgraded hash for user {}: {}", user.id, e),
+    }
+}
+
+/// Log in to a memory store by email, as tests across the auth module do
+#[cfg(test)]
+pub(crate) async fn login(
+    store: &crate::db::memory::MemoryStore,
+    email: &str,
+    password: &str,
+) -> Result<LoginResponse, LoginError> {
+    let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+    let request = LoginRequest {
+        identifier: email.to_string(),
+        password: password.to_string(),
+        client_id: None,
+        audience: None,
+    };
+    login_user(
+        LoginStores::new(store, store, store),
+        &jwt_manager,
+        request,
+        Usernames::Disabled,
+        None,
+    )
+    .await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::auth::{JwtManager, PasswordManager};
-    use crate::db::user::CreateUser;
-    use crate::db::UserRepository;
+    use crate::auth::LogAudit;
+    use crate::db::memory::MemoryStore;
+    use crate::db::user::{CreateUser, ROLE_ADMIN};
+    use crate::db::{TokenRepository, UserRepository};
+    use sqlx::PgPool;
 
     #[tokio::test]
     #[ignore]
@@ -144,6 +426,7 @@ mod tests {
 
         let create_user = CreateUser {
             email: email.clone(),
+            username: None,
             password_hash,
         };
 
@@ -151,15 +434,489 @@ mod tests {
 
         // Test login
         let request = LoginRequest {
-            email,
+            identifier: email,
             password: password.to_string(),
+            client_id: None,
+            audience: None,
         };
 
-        let response = login_user(&pool, &jwt_manager, request, 604800)
-            .await
-            .unwrap();
+        let response = login_user(
+            LoginStores::new(&user_repo, &TokenRepository::new(&pool), &LogAudit),
+            &jwt_manager,
+            request,
+            Usernames::Disabled,
+            None,
+        )
+        .await
+        .unwrap();
 
         assert!(!response.access_token.is_empty());
         assert!(!response.refresh_token.is_empty());
     }
+
+    /// Store with a single registered user
+    async fn store_with_user(email: &str, password: &str) -> MemoryStore {
+        let store = MemoryStore::new();
+        store
+            .create(CreateUser {
+                email: email.to_string(),
+                username: None,
+                password_hash: PasswordManager::hash(password).unwrap(),
+            })
+            .await
+            .unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_login_user_in_memory() {
+        let store = store_with_user("user@example.com", "SecurePass123!").await;
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+
+        let response = login(&store, "user@example.com", "SecurePass123!")
+            .await
+            .unwrap();
+
+        assert_eq!(response.email, "user@example.com");
+        assert!(jwt_manager.validate_token(&response.access_token).is_ok());
+        assert_eq!(store.token_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_login_for_an_allowed_audience() {
+        let store = store_with_user("user@example.com", "SecurePass123!").await;
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800).with_clients(
+            std::collections::BTreeMap::from([(
+                "app-a-web".to_string(),
+                vec!["app-a".to_string()],
+            )]),
+        );
+        let login = |client_id: &str, audience: &str| LoginRequest {
+            identifier: "user@example.com".to_string(),
+            password: "SecurePass123!".to_string(),
+            client_id: Some(client_id.to_string()),
+            audience: Some(audience.to_string()),
+        };
+        let run = |request| {
+            login_user(
+                LoginStores::new(&store, &store, &store),
+                &jwt_manager,
+                request,
+                Usernames::Disabled,
+                None,
+            )
+        };
+
+        let response = run(login("app-a-web", "app-a")).await.unwrap();
+        let access = jwt_manager.validate_token(&response.access_token).unwrap();
+        let refresh = jwt_manager.validate_token(&response.refresh_token).unwrap();
+        assert_eq!(access.aud.as_deref(), Some("app-a"));
+        assert_eq!(refresh.aud.as_deref(), Some("app-a"));
+
+        // Another app's audience, or an unknown client, is refused
+        assert!(matches!(
+            run(login("app-a-web", "app-b")).await,
+            Err(LoginError::AudienceNotAllowed)
+        ));
+        assert!(matches!(
+            run(login("app-b-web", "app-a")).await,
+            Err(LoginError::AudienceNotAllowed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_login_by_email_or_username() {
+        let store = MemoryStore::new();
+        store
+            .create(CreateUser {
+                email: "alice@example.com".to_string(),
+                username: Some("Alice".to_string()),
+                password_hash: PasswordManager::hash("SecurePass123!").unwrap(),
+            })
+            .await
+            .unwrap();
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let login = |identifier: &str, usernames| {
+            login_user(
+                LoginStores::new(&store, &store, &store),
+                &jwt_manager,
+                LoginRequest {
+                    identifier: identifier.to_string(),
+                    password: "SecurePass123!".to_string(),
+                    client_id: None,
+                    audience: None,
+                },
+                usernames,
+                None,
+            )
+        };
+
+        for identifier in ["alice@example.com", "Alice", "alice"] {
+            let response = login(identifier, Usernames::Enabled).await.unwrap();
+            assert_eq!(response.email, "alice@example.com", "{}", identifier);
+        }
+
+        // Email only while usernames are disabled
+        assert!(login("alice@example.com", Usernames::Disabled)
+            .await
+            .is_ok());
+        assert!(matches!(
+            login("alice", Usernames::Disabled).await,
+            Err(LoginError::InvalidCredentials)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_expiry_matches_db_row() {
+        let store = store_with_user("user@example.com", "SecurePass123!").await;
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+
+        let response = login(&store, "user@example.com", "SecurePass123!")
+            .await
+            .unwrap();
+
+        let claims = jwt_manager.decode_token(&response.refresh_token).unwrap();
+        let stored = store.refresh_tokens();
+        assert_eq!(stored.len(), 1);
+
+        let drift = (stored[0].expires_at.timestamp() - claims.exp).abs();
+        assert!(drift <= 1, "JWT exp and DB expires_at differ by {}s", drift);
+    }
+
+    #[tokio::test]
+    async fn test_absolute_expiry_matches_relative() {
+        let store = store_with_user("user@example.com", "SecurePass123!").await;
+        let jwt_manager =
+            JwtManager::new("test_secret".to_string(), 900, 604800).with_absolute_expiry(true);
+
+        let request = LoginRequest {
+            identifier: "user@example.com".to_string(),
+            password: "SecurePass123!".to_string(),
+            client_id: None,
+            audience: None,
+        };
+
+        let before = Utc::now().timestamp();
+        let response = login_user(
+            LoginStores::new(&store, &store, &store),
+            &jwt_manager,
+            request,
+            Usernames::Disabled,
+            None,
+        )
+        .await
+        .unwrap();
+        let after = Utc::now().timestamp();
+
+        let expires_at =
+            chrono::DateTime::parse_from_rfc3339(response.expires_at.as_deref().unwrap())
+                .unwrap()
+                .timestamp();
+        // Issued somewhere between `before` and `after`
+        assert!(expires_at >= before + response.expires_in);
+        assert!(expires_at <= after + response.expires_in);
+
+        let access_claims = jwt_manager.decode_token(&response.access_token).unwrap();
+        assert!((expires_at - access_claims.exp).abs() <= 1);
+
+        let refresh_expires_at =
+            chrono::DateTime::parse_from_rfc3339(response.refresh_expires_at.as_deref().unwrap())
+                .unwrap()
+                .timestamp();
+        let refresh_claims = jwt_manager.decode_token(&response.refresh_token).unwrap();
+        assert_eq!(refresh_expires_at, refresh_claims.exp);
+    }
+
+    #[tokio::test]
+    async fn test_absolute_expiry_omitted_by_default() {
+        let store = store_with_user("user@example.com", "SecurePass123!").await;
+        let response = login(&store, "user@example.com", "SecurePass123!")
+            .await
+            .unwrap();
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["expires_in"], 900);
+        assert!(json.get("expires_at").is_none());
+        assert!(json.get("refresh_expires_at").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_login_response_carries_roles() {
+        let store = store_with_user("user@example.com", "SecurePass123!").await;
+        let login = || login(&store, "user@example.com", "SecurePass123!");
+        let user = store.find_by_email("user@example.com").await.unwrap();
+
+        store.set_role(&user.id, ROLE_ADMIN).await.unwrap();
+        let response = login().await.unwrap();
+        assert_eq!(response.roles, vec![ROLE_ADMIN.to_string()]);
+        assert!(!response.permissions.is_empty());
+
+        // A row without a role (predating roles) leaves both fields out
+        store.set_role(&user.id, "").await.unwrap();
+        let response = login().await.unwrap();
+        let json = serde_json::to_value(&response).unwrap();
+        assert!(json.get("roles").is_none());
+        assert!(json.get("permissions").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_login_unknown_email_matches_wrong_password() {
+        let store = store_with_user("user@example.com", "SecurePass123!").await;
+
+        // Wrong password for an existing user
+        let start = std::time::Instant::now();
+        let wrong_password = login(&store, "user@example.com", "WrongPass123!").await;
+        let wrong_password_elapsed = start.elapsed();
+
+        // Email that was never registered
+        let start = std::time::Instant::now();
+        let unknown_email = login(&store, "missing@example.com", "WrongPass123!").await;
+        let unknown_email_elapsed = start.elapsed();
+
+        assert!(matches!(
+            wrong_password,
+            Err(LoginError::InvalidCredentials)
+        ));
+        assert!(matches!(unknown_email, Err(LoginError::InvalidCredentials)));
+        assert_eq!(
+            wrong_password.unwrap_err().to_string(),
+            unknown_email.unwrap_err().to_string()
+        );
+
+        // The unknown email still paid for a (dummy) bcrypt verification
+        assert!(unknown_email_elapsed * 2 > wrong_password_elapsed);
+        assert_eq!(store.token_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_login_upgrades_legacy_hash_once() {
+        let store = MemoryStore::new();
+        store
+            .create(CreateUser {
+                email: "legacy@example.com".to_string(),
+                username: None,
+                password_hash: bcrypt::hash("SecurePass123!", 4).unwrap(),
+            })
+            .await
+            .unwrap();
+        let before = metrics::PASSWORD_REHASHES.get();
+
+        for _ in 0..2 {
+            login(&store, "legacy@example.com", "SecurePass123!")
+                .await
+                .unwrap();
+        }
+
+        // Only the first login found a legacy hash
+        assert_eq!(metrics::PASSWORD_REHASHES.get(), before + 1);
+
+        let user = store.find_by_email("legacy@example.com").await.unwrap();
+        assert!(!PasswordManager::needs_rehash(&user.password_hash));
+        assert!(PasswordManager::verify("SecurePass123!", &user.password_hash).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_read_only_login_writes_nothing() {
+        let store = MemoryStore::new();
+        store
+            .create(CreateUser {
+                email: "legacy@example.com".to_string(),
+                username: None,
+                password_hash: bcrypt::hash("SecurePass123!", 4).unwrap(),
+            })
+            .await
+            .unwrap();
+        let existing = login(&store, "legacy@example.com", "SecurePass123!")
+            .await
+            .unwrap();
+        let user = store.find_by_email("legacy@example.com").await.unwrap();
+
+        // Single-session mode would sign the other session out, and the
+        // hash would be upgraded, if the database could be written
+        let jwt_manager =
+            JwtManager::new("test_secret".to_string(), 900, 604800).with_single_session(true);
+        let response = login_user(
+            LoginStores::new(&store, &store, &store).with_read_only(true),
+            &jwt_manager,
+            LoginRequest {
+                identifier: "legacy@example.com".to_string(),
+                password: "SecurePass123!".to_string(),
+                client_id: None,
+                audience: None,
+            },
+            Usernames::Disabled,
+            None,
+        )
+        .await
+        .unwrap();
+
+        // An access token, and no refresh token that could not be stored
+        let claims = jwt_manager.validate_token(&response.access_token).unwrap();
+        assert_eq!(claims.sub, user.id.to_string());
+        assert!(response.refresh_token.is_empty());
+        let json = serde_json::to_value(&response).unwrap();
+        assert!(json.get("refresh_token").is_none());
+
+        let after = store.find_by_email("legacy@example.com").await.unwrap();
+        assert_eq!(after.password_hash, user.password_hash);
+        assert_eq!(after.token_version, user.token_version);
+        assert_eq!(store.token_count(), 1);
+        assert!(store.audit_events().is_empty());
+        assert!(!existing.refresh_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_hash_is_password_verification_error() {
+        let store = MemoryStore::new();
+        store
+            .create(CreateUser {
+                email: "corrupt@example.com".to_string(),
+                username: None,
+                password_hash: "not-a-bcrypt-hash".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let check = store
+            .verify_credentials("corrupt@example.com", "SecurePass123!", Usernames::Disabled)
+            .await;
+        assert!(matches!(check, Err(UserError::PasswordVerification(_))));
+
+        let result = login(&store, "corrupt@example.com", "SecurePass123!").await;
+
+        assert!(matches!(result, Err(LoginError::PasswordVerification(_))));
+    }
+
+    #[tokio::test]
+    async fn test_login_with_many_sessions_gets_short_lived_token() {
+        let store = store_with_user("user@example.com", "SecurePass123!").await;
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800)
+            .with_suspicious_access_token_expiration(Some(120))
+            .with_suspicious_session_count(Some(2));
+        let login_with = |jwt_manager| {
+            let store = &store;
+            async move {
+                login_user(
+                    LoginStores::new(store, store, store),
+                    jwt_manager,
+                    LoginRequest {
+                        identifier: "user@example.com".to_string(),
+                        password: "SecurePass123!".to_string(),
+                        client_id: None,
+                        audience: None,
+                    },
+                    Usernames::Disabled,
+                    None,
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        // The first two sessions are normal
+        for _ in 0..2 {
+            let normal = login_with(&jwt_manager).await;
+            assert!(!normal.step_up_required);
+            assert_eq!(normal.expires_in, 900);
+            let claims = jwt_manager.decode_token(&normal.access_token).unwrap();
+            assert_eq!(claims.exp - claims.iat, 900);
+            let json = serde_json::to_value(&normal).unwrap();
+            assert!(json.get("step_up_required").is_none());
+        }
+
+        // A third device while both are live is flagged
+        let flagged = login_with(&jwt_manager).await;
+        assert!(flagged.step_up_required);
+        assert_eq!(flagged.expires_in, 120);
+        let claims = jwt_manager.decode_token(&flagged.access_token).unwrap();
+        assert_eq!(claims.exp - claims.iat, 120);
+
+        // Expired refresh tokens are not live sessions
+        let user = store.find_by_email("user@example.com").await.unwrap();
+        store.revoke_all_user_tokens(&user.id).await.unwrap();
+        store
+            .save_refresh_token(&user.id, "stale-1", Utc::now() - Duration::seconds(1))
+            .await
+            .unwrap();
+        store
+            .save_refresh_token(&user.id, "stale-2", Utc::now() - Duration::seconds(1))
+            .await
+            .unwrap();
+        assert!(!login_with(&jwt_manager).await.step_up_required);
+
+        // Without a short lifetime configured, a flagged login keeps the
+        // normal one but is still flagged
+        let default_manager = JwtManager::new("test_secret".to_string(), 900, 604800)
+            .with_suspicious_session_count(Some(1));
+        let flagged = login_with(&default_manager).await;
+        assert!(flagged.step_up_required);
+        assert_eq!(flagged.expires_in, 900);
+
+        // Unconfigured, no login is flagged however many sessions are live
+        let unchecked = JwtManager::new("test_secret".to_string(), 900, 604800);
+        assert!(!login_with(&unchecked).await.step_up_required);
+    }
+
+    #[tokio::test]
+    async fn test_single_session_login_signs_out_other_sessions() {
+        use crate::auth::{refresh_token, RefreshRequest};
+
+        for single_session in [true, false] {
+            let store = store_with_user("user@example.com", "SecurePass123!").await;
+            let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800)
+                .with_single_session(single_session);
+            let login = || {
+                login_user(
+                    LoginStores::new(&store, &store, &store),
+                    &jwt_manager,
+                    LoginRequest {
+                        identifier: "user@example.com".to_string(),
+                        password: "SecurePass123!".to_string(),
+                        client_id: None,
+                        audience: None,
+                    },
+                    Usernames::Disabled,
+                    None,
+                )
+            };
+            let refresh = |token: &str| {
+                refresh_token(
+                    &store,
+                    &store,
+                    &store,
+                    &jwt_manager,
+                    RefreshRequest {
+                        refresh_token: token.to_string(),
+                    },
+                    &store,
+                    None,
+                )
+            };
+
+            let first = login().await.unwrap();
+            let second = login().await.unwrap();
+
+            // The second device always works; the first only without single-session
+            assert!(refresh(&second.refresh_token).await.is_ok());
+            assert_eq!(
+                refresh(&first.refresh_token).await.is_ok(),
+                !single_session,
+                "single_session: {}",
+                single_session
+            );
+
+            let user_id = uuid::Uuid::parse_str(&first.user_id).unwrap();
+            let expected = if single_session {
+                vec![AuditEvent {
+                    event: "other_sessions_revoked",
+                    severity: AuditSeverity::Info,
+                    user_id,
+                    client_ip: None,
+                }]
+            } else {
+                Vec::new()
+            };
+            assert_eq!(store.audit_events(), expected);
+        }
+    }
 }
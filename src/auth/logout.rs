@@ -1,15 +1,43 @@
-use serde::Deserialize;
-use sqlx::PgPool;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::auth::hashing::hash_refresh_token;
+use crate::auth::schema::{self, Field, Schema, SchemaError, MAX_TOKEN_LENGTH};
 use crate::auth::JwtManager;
-use crate::cache::RedisClient;
-use crate::db::TokenRepository;
+use crate::cache::TokenBlacklist;
+use crate::db::error::DbFailure;
+use crate::db::token::TokenError;
+use crate::db::TokenStore;
 
 /// Logout request payload
-#[derive(Debug, Clone, Deserialize)]
+///
+/// `refresh_token` may be left out (or the body omitted entirely) by a
+/// client that only holds its access token; that token is still revoked.
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct LogoutRequest {
-    pub refresh_token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+impl Schema for LogoutRequest {
+    const FIELDS: &'static [Field] = &[Field::optional("refresh_token", MAX_TOKEN_LENGTH)];
+}
+
+impl LogoutRequest {
+    /// Parse a request body, treating an empty one as no refresh token
+    pub fn from_body(body: &[u8]) -> Result<Self, SchemaError> {
+        if body.trim_ascii().is_empty() {
+            return Ok(Self::default());
+        }
+        schema::parse(body)
+    }
+}
+
+/// Logout-all response
+#[derive(Debug, Serialize)]
+pub struct LogoutAllResponse {
+    /// Refresh tokens revoked
+    pub revoked: u64,
 }
 
 /// Logout error types
@@ -19,7 +47,7 @@ pub enum LogoutError {
     InvalidToken,
 
     #[error("Database error: {0}")]
-    DatabaseError(String),
+    DatabaseError(DbFailure),
 
     #[error("Cache error: {0}")]
     CacheError(String),
@@ -27,12 +55,19 @@ pub enum LogoutError {
 
 /// Logout user by revoking tokens
 ///
+/// Idempotent: a refresh token that is already revoked (e.g. a retried or
+/// double-clicked logout) counts as success, and the access token is still
+/// blacklisted. Without a refresh token only the access token is revoked;
+/// the refresh token stays valid until it expires.
+///
 /// # Arguments
-/// * `pool` - Database connection pool
-/// * `redis_client` - Redis client for blacklisting
+/// * `tokens` - Refresh token storage backend
+/// * `blacklist` - Token blacklist (Redis in production)
 /// * `jwt_manager` - JWT token manager
 /// * `access_token` - Access token to blacklist
 /// * `request` - Logout request data
+/// * `read_only` - The database cannot be written: the refresh token is
+///   blacklisted instead of deleted, which refreshes check first
 ///
 /// # Returns
 /// * `Result<(), LogoutError>` - Success or error
@@ -40,23 +75,25 @@ pub enum LogoutError {
 /// # Example
 /// ```
 /// let request = LogoutRequest {
-///     refresh_token: "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9...".to_string(),
+///     refresh_token: Some("eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9...".to_string()),
 /// };
 ///
 /// logout_user(
-///     &pool,
+///     &TokenRepository::new(&pool),
 ///     &redis_client,
 ///     &jwt_manager,
 ///     &access_token,
-///     request
+///     request,
+///     false,
 /// ).await?;
 /// ```
 pub async fn logout_user(
-    pool: &PgPool,
-    redis_client: &RedisClient,
+    tokens: &dyn TokenStore,
+    blacklist: &dyn TokenBlacklist,
     jwt_manager: &JwtManager,
     access_token: &str,
     request: LogoutRequest,
+    read_only: bool,
 ) -> Result<(), LogoutError> {
     // Decode access token to get user_id
     let access_claims = jwt_manager
@@ -71,7 +108,7 @@ pub async fn logout_user(
     // Add access token to blacklist (with remaining TTL)
     let remaining_ttl = access_claims.exp - chrono::Utc::now().timestamp();
     if remaining_ttl > 0 {
-        redis_client
+        blacklist
             .blacklist_token(access_token, remaining_ttl as u64)
             .await
             .map_err(|e| LogoutError::CacheError(e.to_string()))?;
@@ -79,25 +116,67 @@ pub async fn logout_user(
         log::info!("Access token blacklisted for {} seconds", remaining_ttl);
     }
 
+    let Some(refresh_token) = request.refresh_token else {
+        log::info!("Logout without refresh token for user: {}", user_id);
+        return Ok(());
+    };
+
+    if read_only {
+        return blacklist_refresh_token(blacklist, jwt_manager, &refresh_token, &user_id).await;
+    }
+
     // Revoke refresh token from database
-    let token_hash = hash_token(&request.refresh_token);
-    let token_repo = TokenRepository::new(pool);
+    let token_hash = hash_refresh_token(&refresh_token);
+
+    match tokens.revoke_token_by_hash(&token_hash).await {
+        Ok(()) => log::info!("Refresh token revoked for user: {}", user_id),
+        Err(TokenError::NotFound) => {
+            log::info!("Refresh token already revoked for user: {}", user_id)
+        }
+        Err(e) => return Err(LogoutError::DatabaseError(e.into())),
+    }
 
-    token_repo
-        .revoke_token_by_hash(&token_hash)
-        .await
-        .map_err(|e| LogoutError::DatabaseError(e.to_string()))?;
+    Ok(())
+}
 
-    log::info!("Refresh token revoked for user: {}", user_id);
+/// Blacklist a refresh token until it expires, for logouts in read-only mode
+///
+/// A token that does not verify could not be refreshed anyway and is left
+/// alone, as an unknown token is by a normal logout.
+async fn blacklist_refresh_token(
+    blacklist: &dyn TokenBlacklist,
+    jwt_manager: &JwtManager,
+    refresh_token: &str,
+    user_id: &uuid::Uuid,
+) -> Result<(), LogoutError> {
+    let Ok(claims) = jwt_manager.validate_token(refresh_token) else {
+        log::info!(
+            "Ignoring invalid refresh token at logout for user: {}",
+            user_id
+        );
+        return Ok(());
+    };
+
+    let remaining_ttl = claims.exp - chrono::Utc::now().timestamp();
+    if remaining_ttl > 0 {
+        blacklist
+            .blacklist_token(refresh_token, remaining_ttl as u64)
+            .await
+            .map_err(|e| LogoutError::CacheError(e.to_string()))?;
+    }
 
+    log::info!(
+        "Read-only mode: refresh token blacklisted for user: {}",
+        user_id
+    );
     Ok(())
 }
 
 /// Logout user from all devices
 ///
 /// # Arguments
-/// * `pool` - Database connection pool
-/// * `redis_client` - Redis client for blacklisting
+/// * `tokens` - Refresh token storage backend
+/// * `blacklist` - Token blacklist (Redis in production)
 /// * `jwt_manager` - JWT token manager
 /// * `access_token` - Current access token
 ///
@@ -107,7 +186,7 @@ pub async fn logout_user(
 /// # Example
 /// ```
 /// let revoked_count = logout_all_devices(
-///     &pool,
+///     &TokenRepository::new(&pool),
 ///     &redis_client,
 ///     &jwt_manager,
 ///     &access_token
@@ -116,8 +195,8 @@ pub async fn logout_user(
 /// println!("Revoked {} refresh tokens", revoked_count);
 /// ```
 pub async fn logout_all_devices(
-    pool: &PgPool,
-    redis_client: &RedisClient,
+    tokens: &dyn TokenStore,
+    blacklist: &dyn TokenBlacklist,
     jwt_manager: &JwtManager,
     access_token: &str,
 ) -> Result<u64, LogoutError> {
@@ -134,18 +213,17 @@ pub async fn logout_all_devices(
     // Add current access token to blacklist
     let remaining_ttl = access_claims.exp - chrono::Utc::now().timestamp();
     if remaining_ttl > 0 {
-        redis_client
+        blacklist
             .blacklist_token(access_token, remaining_ttl as u64)
             .await
             .map_err(|e| LogoutError::CacheError(e.to_string()))?;
     }
 
     // Revoke all refresh tokens for user
-    let token_repo = TokenRepository::new(pool);
-    let revoked_count = token_repo
+    let revoked_count = tokens
         .revoke_all_user_tokens(&user_id)
         .await
-        .map_err(|e| LogoutError::DatabaseError(e.to_string()))?;
+        .map_err(|e| LogoutError::DatabaseError(e.into()))?;
 
     log::info!(
         "Revoked {} refresh tokens for user: {}",
@@ -156,21 +234,14 @@ pub async fn logout_all_devices(
     Ok(revoked_count)
 }
 
-/// Hash token for database lookup
-fn hash_token(token: &str) -> String {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-
-    let mut hasher = DefaultHasher::new();
-    token.hash(&mut hasher);
-    format!("{:x}", hasher.finish())
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::auth::JwtManager;
+    use crate::cache::RedisClient;
+    use crate::db::memory::MemoryStore;
     use crate::db::TokenRepository;
+    use sqlx::PgPool;
 
     #[tokio::test]
     #[ignore]
@@ -186,27 +257,29 @@ mod tests {
         let user_id = uuid::Uuid::new_v4();
 
         // Generate tokens
-        let access_token_str = jwt_manager.generate_access_token(&user_id).unwrap();
-        let (refresh_token_str, token_hash) = jwt_manager.generate_refresh_token(&user_id).unwrap();
+        let access_token_str = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        let (refresh_token_str, token_hash, expires_at) =
+            jwt_manager.generate_refresh_token(&user_id, 0).unwrap();
 
         // Save refresh token
         let token_repo = TokenRepository::new(&pool);
         token_repo
-            .save_refresh_token(&user_id, &token_hash, 604800)
+            .save_refresh_token(&user_id, &token_hash, expires_at)
             .await
             .unwrap();
 
         // Test logout
         let request = LogoutRequest {
-            refresh_token: refresh_token_str,
+            refresh_token: Some(refresh_token_str),
         };
 
         logout_user(
-            &pool,
+            &token_repo,
             &redis_client,
             &jwt_manager,
             &access_token_str,
             request,
+            false,
         )
         .await
         .unwrap();
@@ -218,4 +291,162 @@ mod tests {
             .unwrap();
         assert!(is_blacklisted);
     }
+
+    #[tokio::test]
+    async fn test_logout_twice_succeeds() {
+        let store = MemoryStore::new();
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let user_id = uuid::Uuid::new_v4();
+
+        let access_token_str = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        let (refresh_token_str, token_hash, expires_at) =
+            jwt_manager.generate_refresh_token(&user_id, 0).unwrap();
+        store
+            .save_refresh_token(&user_id, &token_hash, expires_at)
+            .await
+            .unwrap();
+
+        // The retry finds the refresh token already gone
+        for _ in 0..2 {
+            let request = LogoutRequest {
+                refresh_token: Some(refresh_token_str.clone()),
+            };
+            logout_user(
+                &store,
+                &store,
+                &jwt_manager,
+                &access_token_str,
+                request,
+                false,
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(store.token_count(), 0);
+        assert!(store.is_token_blacklisted(&access_token_str).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_logout_with_only_access_token() {
+        let store = MemoryStore::new();
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let user_id = uuid::Uuid::new_v4();
+
+        let access_token_str = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        let (_, token_hash, expires_at) = jwt_manager.generate_refresh_token(&user_id, 0).unwrap();
+        store
+            .save_refresh_token(&user_id, &token_hash, expires_at)
+            .await
+            .unwrap();
+
+        // Empty, whitespace-only and `{}` bodies all mean "no refresh token"
+        for body in [&b""[..], b"  \n", b"{}"] {
+            let request = LogoutRequest::from_body(body).unwrap();
+            assert!(request.refresh_token.is_none());
+
+            logout_user(
+                &store,
+                &store,
+                &jwt_manager,
+                &access_token_str,
+                request,
+                false,
+            )
+            .await
+            .unwrap();
+        }
+
+        assert!(store.is_token_blacklisted(&access_token_str).await.unwrap());
+        // The refresh token was not sent, so it is left alone
+        assert_eq!(store.token_count(), 1);
+
+        assert!(LogoutRequest::from_body(b"not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_only_logout_blacklists_refresh_token() {
+        use crate::auth::refresh::RefreshError;
+        use crate::auth::{refresh_token, RefreshRequest};
+
+        let store = MemoryStore::new();
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let user_id = uuid::Uuid::new_v4();
+
+        let access_token_str = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        let (refresh_token_str, token_hash, expires_at) =
+            jwt_manager.generate_refresh_token(&user_id, 0).unwrap();
+        store
+            .save_refresh_token(&user_id, &token_hash, expires_at)
+            .await
+            .unwrap();
+
+        let request = LogoutRequest {
+            refresh_token: Some(refresh_token_str.clone()),
+        };
+        logout_user(
+            &store,
+            &store,
+            &jwt_manager,
+            &access_token_str,
+            request,
+            true,
+        )
+        .await
+        .unwrap();
+
+        // The row is left alone, but both tokens are blacklisted
+        assert_eq!(store.token_count(), 1);
+        assert!(store.is_token_blacklisted(&access_token_str).await.unwrap());
+        assert!(store
+            .is_token_blacklisted(&refresh_token_str)
+            .await
+            .unwrap());
+
+        // so the refresh token is refused, without the reuse alarm
+        let refresh = RefreshRequest {
+            refresh_token: refresh_token_str,
+        };
+        assert!(matches!(
+            refresh_token(&store, &store, &store, &jwt_manager, refresh, &store, None).await,
+            Err(RefreshError::TokenBlacklisted)
+        ));
+        assert_eq!(store.token_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_logout_all_devices_revokes_everything() {
+        let store = MemoryStore::new();
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let user_id = uuid::Uuid::new_v4();
+        let other_user = uuid::Uuid::new_v4();
+
+        // Two devices for this user, one for someone else
+        for user in [user_id, user_id, other_user] {
+            let (_, token_hash, expires_at) = jwt_manager.generate_refresh_token(&user, 0).unwrap();
+            store
+                .save_refresh_token(&user, &token_hash, expires_at)
+                .await
+                .unwrap();
+        }
+        let access_token_str = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+
+        let revoked = logout_all_devices(&store, &store, &jwt_manager, &access_token_str)
+            .await
+            .unwrap();
+
+        assert_eq!(revoked, 2);
+        assert!(store
+            .refresh_tokens()
+            .iter()
+            .all(|token| token.user_id == other_user));
+        assert!(store.is_token_blacklisted(&access_token_str).await.unwrap());
+
+        // A garbage access token revokes nothing
+        assert!(matches!(
+            logout_all_devices(&store, &store, &jwt_manager, "not-a-token").await,
+            Err(LogoutError::InvalidToken)
+        ));
+        assert_eq!(store.token_count(), 1);
+    }
 }
@@ -1,13 +1,33 @@
+pub mod admin_nonce;
+pub mod audit;
+pub mod bootstrap;
+pub mod change_password;
+pub mod compare;
+pub mod cookie;
+pub mod encryption;
+pub mod hashing;
+pub mod import;
+pub mod jwks;
 pub mod jwt;
 pub mod login;
 pub mod logout;
+pub mod openapi;
 pub mod password;
 pub mod refresh;
 pub mod register;
+pub mod schema;
+pub mod user_data;
+pub mod validate_password;
 
+pub use audit::LogAudit;
+pub use bootstrap::ensure_admin;
+pub use change_password::{change_password, ChangePasswordRequest};
+pub use import::{import_users, ImportUserRequest};
 pub use jwt::JwtManager;
-pub use login::{login_user, LoginRequest};
-pub use logout::{logout_user, LogoutRequest};
-pub use password::PasswordManager;
+pub use login::{login_user, LoginRequest, LoginStores};
+pub use logout::{logout_all_devices, logout_user, LogoutRequest};
+pub use password::{PasswordManager, Peppers};
 pub use refresh::{refresh_token, RefreshRequest};
 pub use register::{register_user, RegisterRequest};
+pub use user_data::{erase_user, export_user};
+pub use validate_password::{validate_password, ValidatePasswordRequest};
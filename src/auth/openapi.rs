@@ -0,0 +1,430 @@
+use serde_json::{json, Value};
+
+/// OpenAPI 3.0 document for the `/auth/*` endpoints
+///
+/// Hand-maintained alongside the request/response types in this module;
+/// the tests below fail if a schema drifts from its serde type.
+///
+/// # Returns
+/// * `Value` - The OpenAPI document, served at `GET /auth/openapi.json`
+pub fn spec() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Pingora proxy auth API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/auth/register": {
+                "post": operation(
+                    "Create an account and issue tokens",
+                    Some("RegisterRequest"),
+                    ("201", "RegisterResponse"),
+                    &[
+                        ("400", "Invalid email, weak password or email taken"),
+                        ("422", "Missing, empty or too long fields"),
+                    ],
+                ),
+            },
+            "/auth/login": {
+                "post": operation(
+                    "Exchange credentials for tokens",
+                    Some("LoginRequest"),
+                    ("200", "LoginResponse"),
+                    &[
+                        ("401", "Invalid credentials"),
+                        ("403", "Audience not allowed for this client"),
+                        ("422", "Missing, empty or too long fields"),
+                        ("500", "Server error"),
+                    ],
+                ),
+            },
+            "/auth/refresh": {
+                "post": operation(
+                    "Exchange a refresh token for a new access token",
+                    Some("RefreshRequest"),
+                    ("200", "RefreshResponse"),
+                    &[
+                        ("401", "Invalid, expired or revoked refresh token"),
+                        ("422", "Refresh token too long"),
+                    ],
+                ),
+            },
+            "/auth/logout": {
+                "post": with_bearer(operation(
+                    "Revoke the access token and, if sent, the refresh token",
+                    Some("LogoutRequest"),
+                    ("200", "LogoutResponse"),
+                    &[("400", "Invalid token"), ("422", "Refresh token too long")],
+                )),
+            },
+            "/auth/logout-all": {
+                "post": with_bearer(operation(
+                    "Revoke the access token and every refresh token of the user",
+                    None,
+                    ("200", "LogoutAllResponse"),
+                    &[("401", "Missing, invalid or revoked access token")],
+                )),
+            },
+            "/auth/change-password": {
+                "post": with_bearer(operation(
+                    "Change the password and sign out every other session",
+                    Some("ChangePasswordRequest"),
+                    ("200", "ChangePasswordResponse"),
+                    &[
+                        ("400", "New password fails the policy"),
+                        ("401", "Missing token or wrong current password"),
+                        ("422", "Missing, empty or too long fields"),
+                    ],
+                )),
+            },
+            "/auth/validate-password": {
+                "post": operation(
+                    "Check a password against the policy without creating anything",
+                    Some("ValidatePasswordRequest"),
+                    ("200", "ValidatePasswordResponse"),
+                    &[
+                        ("422", "Missing, empty or too long fields"),
+                        ("429", "Rate limit exceeded"),
+                    ],
+                ),
+            },
+        },
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer", "bearerFormat": "JWT" },
+            },
+            "schemas": {
+                // `username` only with `middleware.auth.usernames`
+                "RegisterRequest": object(
+                    &[("email", "string"), ("password", "string")],
+                    &[("username", "string")],
+                ),
+                // `identifier` is the email or username (`email` still accepted);
+                // `audience` must be allowed for `client_id` in `jwt.clients`
+                "LoginRequest": object(
+                    &[("identifier", "string"), ("password", "string")],
+                    &[("client_id", "string"), ("audience", "string")],
+                ),
+                // The token may come from the `jwt.refresh_cookie` cookie instead
+                "RefreshRequest": object(&[], &[("refresh_token", "string")]),
+                // Optional: without it only the access token is revoked
+                "LogoutRequest": object(&[], &[("refresh_token", "string")]),
+                "RegisterResponse": token_response(),
+                "LoginResponse": login_response(),
+                "RefreshResponse": object(
+                    &[
+                        ("access_token", "string"),
+                        ("refresh_token", "string"),
+                        ("token_type", "string"),
+                        ("expires_in", "integer"),
+                    ],
+                    &[("expires_at", "string")],
+                ),
+                "LogoutResponse": object(&[("message", "string")], &[]),
+                "LogoutAllResponse": object(&[("revoked", "integer")], &[]),
+                "ChangePasswordRequest": object(
+                    &[("current_password", "string"), ("new_password", "string")],
+                    &[],
+                ),
+                "ChangePasswordResponse": object(
+                    &[
+                        ("access_token", "string"),
+                        ("refresh_token", "string"),
+                        ("token_type", "string"),
+                        ("expires_in", "integer"),
+                    ],
+                    &[("expires_at", "string"), ("refresh_expires_at", "string")],
+                ),
+                "ValidatePasswordRequest": object(&[("password", "string")], &[]),
+                "ValidatePasswordResponse": validate_password_response(),
+                "Error": object(&[("error", "string")], &[]),
+                "ValidationError": validation_error(),
+            },
+        },
+    })
+}
+
+/// Schema shared by the register and login responses
+fn token_response() -> Value {
+    object(
+        &[
+            ("user_id", "string"),
+            ("email", "string"),
+            ("access_token", "string"),
+            ("refresh_token", "string"),
+            ("token_type", "string"),
+            ("expires_in", "integer"),
+        ],
+        // Expiries only with `jwt.absolute_expiry`; roles only when assigned
+        &[
+            ("expires_at", "string"),
+            ("refresh_expires_at", "string"),
+            ("roles", "array"),
+            ("permissions", "array"),
+        ],
+    )
+}
+
+/// Schema of the login response: the token response plus the step-up flag,
+/// with an optional refresh token
+fn login_response() -> Value {
+    let mut schema = token_response();
+    // Only present (and true) for logins flagged as suspicious
+    schema["properties"]["step_up_required"] = json!({ "type": "boolean" });
+    // No refresh token is issued in `server.read_only` mode
+    schema["required"]
+        .as_array_mut()
+        .unwrap()
+        .retain(|name| name != "refresh_token");
+    schema
+}
+
+/// Schema of the validate-password response: one entry per policy rule
+fn validate_password_response() -> Value {
+    let mut schema = object(&[("valid", "boolean"), ("criteria", "array")], &[]);
+    schema["properties"]["criteria"]["items"] =
+        object(&[("name", "string"), ("satisfied", "boolean")], &[]);
+    schema
+}
+
+/// Schema of a 422 response: one entry per rejected field
+fn validation_error() -> Value {
+    let mut schema = object(&[("error", "string"), ("fields", "array")], &[]);
+    schema["properties"]["fields"]["items"] =
+        object(&[("field", "string"), ("message", "string")], &[]);
+    schema
+}
+
+/// Object schema with required and optional properties
+///
+/// Properties are scalars, or `array`s of strings.
+fn object(required: &[(&str, &str)], optional: &[(&str, &str)]) -> Value {
+    let property = |ty: &str| match ty {
+        "array" => json!({ "type": "array", "items": { "type": "string" } }),
+        ty => json!({ "type": ty }),
+    };
+    let properties: serde_json::Map<String, Value> = required
+        .iter()
+        .chain(optional)
+        .map(|(name, ty)| (name.to_string(), property(ty)))
+        .collect();
+    let required: Vec<&str> = required.iter().map(|(name, _)| *name).collect();
+
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+/// Operation with an optional JSON body, a success response and failures
+///
+/// Failures are `Error`s, except 422s which are `ValidationError`s.
+fn operation(
+    summary: &str,
+    request: Option<&str>,
+    (status, response): (&str, &str),
+    errors: &[(&str, &str)],
+) -> Value {
+    let body = |schema: &str| json!({ "application/json": { "schema": { "$ref": format!("#/components/schemas/{}", schema) } } });
+
+    let mut responses = serde_json::Map::new();
+    responses.insert(
+        status.to_string(),
+        json!({ "description": "Success", "content": body(response) }),
+    );
+    for (status, description) in errors {
+        responses.insert(
+            status.to_string(),
+            json!({
+                "description": description,
+                "content": body(if *status == "422" { "ValidationError" } else { "Error" }),
+            }),
+        );
+    }
+
+    let mut operation = json!({ "summary": summary, "responses": responses });
+    if let Some(request) = request {
+        operation["requestBody"] = json!({ "required": true, "content": body(request) });
+    }
+    operation
+}
+
+/// Operation that requires the access token as a bearer credential
+fn with_bearer(mut operation: Value) -> Value {
+    operation["security"] = json!([{ "bearerAuth": [] }]);
+    operation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::change_password::ChangePasswordResponse;
+    use crate::auth::login::LoginResponse;
+    use crate::auth::logout::LogoutAllResponse;
+    use crate::auth::refresh::RefreshResponse;
+    use crate::auth::register::RegisterResponse;
+    use crate::auth::schema::{FieldError, ValidationErrorResponse};
+    use crate::auth::{
+        validate_password, ChangePasswordRequest, LoginRequest, LogoutRequest, RefreshRequest,
+        RegisterRequest, ValidatePasswordRequest,
+    };
+    use crate::db::user::ROLE_ADMIN;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use std::collections::BTreeSet;
+
+    fn schema(name: &str) -> Value {
+        spec()["components"]["schemas"][name].clone()
+    }
+
+    fn property_names(schema: &Value) -> BTreeSet<String> {
+        schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Every property of the type (with options filled in) is in the schema and vice versa
+    fn assert_response_matches<T: Serialize>(name: &str, value: T) {
+        let serialized = serde_json::to_value(value).unwrap();
+        let fields: BTreeSet<String> = serialized.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(fields, property_names(&schema(name)), "{}", name);
+    }
+
+    /// An object with just the required properties deserializes into the type
+    fn assert_request_matches<T: DeserializeOwned>(name: &str) {
+        let schema = schema(name);
+        let example: serde_json::Map<String, Value> = schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|name| (name.as_str().unwrap().to_string(), json!("value")))
+            .collect();
+        assert!(
+            serde_json::from_value::<T>(Value::Object(example)).is_ok(),
+            "{}",
+            name
+        );
+    }
+
+    #[test]
+    fn test_spec_is_json_with_auth_paths() {
+        let json = serde_json::to_string(&spec()).unwrap();
+        let parsed: Value = serde_json::from_str(&json).unwrap();
+
+        let paths = parsed["paths"].as_object().unwrap();
+        for path in [
+            "/auth/register",
+            "/auth/login",
+            "/auth/refresh",
+            "/auth/logout",
+            "/auth/logout-all",
+            "/auth/change-password",
+            "/auth/validate-password",
+        ] {
+            assert!(paths.contains_key(path), "missing {}", path);
+        }
+        assert!(parsed["paths"]["/auth/logout"]["post"]["security"].is_array());
+        assert!(parsed["paths"]["/auth/logout"]["post"]["responses"]["200"].is_object());
+        assert!(parsed["paths"]["/auth/logout-all"]["post"]["security"].is_array());
+        assert!(parsed["paths"]["/auth/logout-all"]["post"]["requestBody"].is_null());
+    }
+
+    #[test]
+    fn test_schemas_match_serde_types() {
+        assert_request_matches::<RegisterRequest>("RegisterRequest");
+        assert_request_matches::<LoginRequest>("LoginRequest");
+        assert_request_matches::<RefreshRequest>("RefreshRequest");
+        assert_request_matches::<LogoutRequest>("LogoutRequest");
+        assert_request_matches::<ChangePasswordRequest>("ChangePasswordRequest");
+        assert_request_matches::<ValidatePasswordRequest>("ValidatePasswordRequest");
+
+        let expiry = Some("2026-01-01T00:00:00Z".to_string());
+        assert_response_matches(
+            "RegisterResponse",
+            RegisterResponse {
+                user_id: String::new(),
+                email: String::new(),
+                access_token: String::new(),
+                refresh_token: String::new(),
+                token_type: String::new(),
+                expires_in: 0,
+                expires_at: expiry.clone(),
+                refresh_expires_at: expiry.clone(),
+                roles: vec![ROLE_ADMIN.to_string()],
+                permissions: vec![String::new()],
+            },
+        );
+        assert_response_matches(
+            "LoginResponse",
+            LoginResponse {
+                user_id: String::new(),
+                email: String::new(),
+                access_token: String::new(),
+                refresh_token: "token".to_string(),
+                token_type: String::new(),
+                expires_in: 0,
+                expires_at: expiry.clone(),
+                refresh_expires_at: expiry.clone(),
+                roles: vec![ROLE_ADMIN.to_string()],
+                permissions: vec![String::new()],
+                step_up_required: true,
+            },
+        );
+        assert_response_matches(
+            "ChangePasswordResponse",
+            ChangePasswordResponse {
+                access_token: String::new(),
+                refresh_token: String::new(),
+                token_type: String::new(),
+                expires_in: 0,
+                expires_at: expiry.clone(),
+                refresh_expires_at: expiry.clone(),
+            },
+        );
+        assert_response_matches(
+            "RefreshResponse",
+            RefreshResponse {
+                access_token: String::new(),
+                refresh_token: String::new(),
+                token_type: String::new(),
+                expires_in: 0,
+                expires_at: expiry,
+            },
+        );
+
+        let validation = validate_password(ValidatePasswordRequest {
+            password: String::new(),
+        });
+        let criterion = serde_json::to_value(&validation.criteria[0]).unwrap();
+        let criteria = &schema("ValidatePasswordResponse")["properties"]["criteria"];
+        assert_eq!(
+            criterion
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<BTreeSet<_>>(),
+            property_names(&criteria["items"])
+        );
+        assert_response_matches("ValidatePasswordResponse", validation);
+        assert_response_matches("LogoutAllResponse", LogoutAllResponse { revoked: 2 });
+
+        let rejection = ValidationErrorResponse::new(vec![FieldError {
+            field: "email".to_string(),
+            message: "is required".to_string(),
+        }]);
+        let field = serde_json::to_value(&rejection.fields[0]).unwrap();
+        let fields = &schema("ValidationError")["properties"]["fields"];
+        assert_eq!(
+            field
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<BTreeSet<_>>(),
+            property_names(&fields["items"])
+        );
+        assert_response_matches("ValidationError", rejection);
+    }
+}
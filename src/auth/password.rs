@@ -1,6 +1,101 @@
-use bcrypt::{hash, verify, DEFAULT_COST};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bcrypt::{hash, verify, HashParts, DEFAULT_COST};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
 use thiserror::Error;
 
+/// Placeholder hash verified against when the account does not exist,
+/// so that a lookup miss costs the same bcrypt work as a real check
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+/// Peppers installed at startup (none until then)
+static PEPPERS: OnceLock<Peppers> = OnceLock::new();
+
+/// Stored hashes of peppered passwords are `$pepper-<id>` + the bcrypt hash,
+/// so verification knows which key to use
+const PEPPER_PREFIX: &str = "$pepper-";
+
+/// Hex digits of the key fingerprint used as pepper ID
+const PEPPER_ID_LEN: usize = 8;
+
+/// Server-side key mixed into a password before bcrypt
+#[derive(Clone)]
+struct Pepper {
+    id: String,
+    key: Vec<u8>,
+}
+
+impl Pepper {
+    fn new(key: &str) -> Self {
+        let fingerprint = Sha256::digest(key.as_bytes());
+        let id: String = fingerprint
+            .iter()
+            .take(PEPPER_ID_LEN / 2)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Self {
+            id,
+            key: key.as_bytes().to_vec(),
+        }
+    }
+
+    /// HMAC-SHA256 of the password, base64 encoded (44 bytes, within
+    /// bcrypt's 72-byte input limit)
+    fn apply(&self, password: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(password.as_bytes());
+        STANDARD.encode(mac.finalize().into_bytes())
+    }
+}
+
+/// Current and previous password peppers (`password.pepper`)
+///
+/// New hashes use the current pepper. Hashes made with the previous one
+/// (or with none) still verify and are reported by `needs_rehash`, so
+/// they move to the current pepper on the user's next login.
+#[derive(Clone, Default)]
+pub struct Peppers {
+    current: Option<Pepper>,
+    previous: Option<Pepper>,
+}
+
+impl Peppers {
+    /// Build from config values; empty strings count as unset
+    pub fn new(current: Option<&str>, previous: Option<&str>) -> Self {
+        let pepper = |key: Option<&str>| key.filter(|k| !k.is_empty()).map(Pepper::new);
+        Self {
+            current: pepper(current),
+            previous: pepper(previous),
+        }
+    }
+
+    fn find(&self, id: &str) -> Option<&Pepper> {
+        [&self.current, &self.previous]
+            .into_iter()
+            .flatten()
+            .find(|pepper| pepper.id == id)
+    }
+
+    fn current_id(&self) -> Option<&str> {
+        self.current.as_ref().map(|pepper| pepper.id.as_str())
+    }
+}
+
+/// Split a stored hash into its pepper ID (if peppered) and bcrypt hash
+fn split_pepper(stored: &str) -> (Option<&str>, &str) {
+    match stored.strip_prefix(PEPPER_PREFIX) {
+        Some(rest) if rest.len() > PEPPER_ID_LEN && rest.is_char_boundary(PEPPER_ID_LEN) => {
+            let (id, bcrypt_hash) = rest.split_at(PEPPER_ID_LEN);
+            (Some(id), bcrypt_hash)
+        }
+        _ => (None, stored),
+    }
+}
+
 /// Custom password error type
 #[derive(Debug, Error)]
 pub enum PasswordError {
@@ -20,43 +115,168 @@ pub enum PasswordError {
     BcryptError(#[from] bcrypt::BcryptError),
 }
 
+/// Password policy rules: name, check, and the error when it fails
+type PolicyRule = (&'static str, fn(&str) -> bool, fn() -> PasswordError);
+
+/// Rules every new password must meet, checked in this order
+const POLICY: &[PolicyRule] = &[
+    ("min_length", |p| p.len() >= 8, || PasswordError::TooShort),
+    (
+        "uppercase",
+        |p| p.chars().any(|c| c.is_uppercase()),
+        || PasswordError::NoUppercase,
+    ),
+    (
+        "lowercase",
+        |p| p.chars().any(|c| c.is_lowercase()),
+        || PasswordError::NoLowercase,
+    ),
+    (
+        "digit",
+        |p| p.chars().any(|c| c.is_numeric()),
+        || PasswordError::NoDigit,
+    ),
+];
+
+/// Outcome of one password policy rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyCriterion {
+    pub name: &'static str,
+    pub satisfied: bool,
+}
+
 /// Password hashing and verification manager
 pub struct PasswordManager;
 
 impl PasswordManager {
+    /// Install the password peppers; call once at startup, before any hashing
+    ///
+    /// Later calls are ignored.
+    pub fn set_peppers(peppers: Peppers) {
+        if PEPPERS.set(peppers).is_err() {
+            log::warn!("Password peppers already set, ignoring");
+        }
+    }
+
+    fn peppers() -> &'static Peppers {
+        PEPPERS.get_or_init(Peppers::default)
+    }
+
     /// Hash a plain text password
     pub fn hash(password: &str) -> Result<String, PasswordError> {
         // Validate password strength
         Self::validate_password_strength(password)?;
 
-        // Hash with default cost (12 rounds)
-        Ok(hash(password, DEFAULT_COST)?)
+        Self::hash_with(password, Self::peppers())
     }
 
     /// Verify a password against a hash
     pub fn verify(password: &str, hash: &str) -> Result<bool, PasswordError> {
-        Ok(verify(password, hash)?)
+        Self::verify_with(password, hash, Self::peppers())
     }
 
-    /// Validate password strength
-    fn validate_password_strength(password: &str) -> Result<(), PasswordError> {
-        if password.len() < 8 {
-            return Err(PasswordError::TooShort);
-        }
+    /// Check whether a stored hash should be replaced on the next login
+    ///
+    /// True if it was made with a lower cost than we use now, or with a
+    /// pepper other than the current one. Hashes that cannot be parsed are
+    /// left alone.
+    pub fn needs_rehash(hash: &str) -> bool {
+        Self::needs_rehash_with(hash, Self::peppers())
+    }
 
-        if !password.chars().any(|c| c.is_uppercase()) {
-            return Err(PasswordError::NoUppercase);
-        }
+    /// Whether a hash imported from elsewhere can be stored as-is
+    ///
+    /// Accepts plain bcrypt hashes (moved to the current pepper on the
+    /// user's next login) and hashes peppered with a configured pepper.
+    pub fn is_valid_hash(stored: &str) -> bool {
+        let (pepper_id, bcrypt_hash) = split_pepper(stored);
+        pepper_id.is_none_or(|id| Self::peppers().find(id).is_some())
+            && bcrypt_hash.parse::<HashParts>().is_ok()
+    }
+
+    /// Re-hash an already verified password at the current cost and pepper
+    ///
+    /// Skips the strength rules: the password predates them and the user
+    /// has just proven they know it.
+    pub fn rehash(password: &str) -> Result<String, PasswordError> {
+        Self::hash_with(password, Self::peppers())
+    }
 
-        if !password.chars().any(|c| c.is_lowercase()) {
-            return Err(PasswordError::NoLowercase);
+    /// Hash with default cost (12 rounds), peppered if configured
+    fn hash_with(password: &str, peppers: &Peppers) -> Result<String, PasswordError> {
+        match &peppers.current {
+            Some(pepper) => Ok(format!(
+                "{}{}{}",
+                PEPPER_PREFIX,
+                pepper.id,
+                hash(pepper.apply(password), DEFAULT_COST)?
+            )),
+            None => Ok(hash(password, DEFAULT_COST)?),
         }
+    }
 
-        if !password.chars().any(|c| c.is_numeric()) {
-            return Err(PasswordError::NoDigit);
+    fn verify_with(password: &str, stored: &str, peppers: &Peppers) -> Result<bool, PasswordError> {
+        match split_pepper(stored) {
+            (None, bcrypt_hash) => Ok(verify(password, bcrypt_hash)?),
+            (Some(id), bcrypt_hash) => match peppers.find(id) {
+                Some(pepper) => Ok(verify(pepper.apply(password), bcrypt_hash)?),
+                None => {
+                    // Keep the bcrypt cost so the failure is not faster
+                    log::warn!("Password hash made with unknown pepper {}", id);
+                    Self::dummy_verify(password);
+                    Ok(false)
+                }
+            },
         }
+    }
+
+    fn needs_rehash_with(stored: &str, peppers: &Peppers) -> bool {
+        let (pepper_id, bcrypt_hash) = split_pepper(stored);
+        bcrypt_hash
+            .parse::<HashParts>()
+            .map(|parts| parts.get_cost() < DEFAULT_COST || pepper_id != peppers.current_id())
+            .unwrap_or(false)
+    }
+
+    /// Run a bcrypt verification against a fixed placeholder hash
+    ///
+    /// Used when no user matches so that the response time does not reveal
+    /// whether an account exists. The result is always discarded.
+    pub fn dummy_verify(password: &str) {
+        let dummy_hash = DUMMY_HASH.get_or_init(|| {
+            hash("dummy-password-for-timing", DEFAULT_COST)
+                .expect("bcrypt hashing of a constant input cannot fail")
+        });
 
-        Ok(())
+        let _ = verify(password, dummy_hash);
+    }
+
+    /// Check a password against each rule of the policy
+    ///
+    /// Nothing is hashed or stored; used by `POST /auth/validate-password`
+    /// so clients can show which rules a password still misses.
+    ///
+    /// # Arguments
+    /// * `password` - Candidate password
+    ///
+    /// # Returns
+    /// * `Vec<PolicyCriterion>` - Every rule, in policy order
+    pub fn check_policy(password: &str) -> Vec<PolicyCriterion> {
+        POLICY
+            .iter()
+            .map(|(name, satisfied, _)| PolicyCriterion {
+                name,
+                satisfied: satisfied(password),
+            })
+            .collect()
+    }
+
+    /// Validate password strength
+    fn validate_password_strength(password: &str) -> Result<(), PasswordError> {
+        match POLICY.iter().find(|(_, satisfied, _)| !satisfied(password)) {
+            Some((_, _, error)) => Err(error()),
+            None => Ok(()),
+        }
     }
 }
 
@@ -73,6 +293,96 @@ mod tests {
         assert!(!PasswordManager::verify("WrongPassword", &hashed).unwrap());
     }
 
+    #[test]
+    fn test_dummy_verify_costs_like_real_verify() {
+        let hashed = PasswordManager::hash("TestPassword123").unwrap();
+
+        // Warm up the lazily computed placeholder hash
+        PasswordManager::dummy_verify("warmup");
+
+        let start = std::time::Instant::now();
+        PasswordManager::verify("WrongPassword1", &hashed).unwrap();
+        let real = start.elapsed();
+
+        let start = std::time::Instant::now();
+        PasswordManager::dummy_verify("WrongPassword1");
+        let dummy = start.elapsed();
+
+        // Both paths run a full bcrypt round at the same cost
+        assert!(dummy * 2 > real, "dummy {:?} vs real {:?}", dummy, real);
+        assert!(real * 2 > dummy, "dummy {:?} vs real {:?}", dummy, real);
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        let legacy = hash("TestPassword123", 4).unwrap();
+        let current = PasswordManager::hash("TestPassword123").unwrap();
+
+        assert!(PasswordManager::needs_rehash(&legacy));
+        assert!(!PasswordManager::needs_rehash(&current));
+        assert!(!PasswordManager::needs_rehash("not-a-bcrypt-hash"));
+    }
+
+    const PEPPER: &str = "pepper-key-0123456789abcdef";
+    const NEW_PEPPER: &str = "pepper-key-fedcba9876543210";
+
+    #[test]
+    fn test_peppered_hash_verifies() {
+        let peppers = Peppers::new(Some(PEPPER), None);
+        let hashed = PasswordManager::hash_with("TestPassword123", &peppers).unwrap();
+
+        assert!(hashed.starts_with(PEPPER_PREFIX));
+        assert!(PasswordManager::verify_with("TestPassword123", &hashed, &peppers).unwrap());
+        assert!(!PasswordManager::verify_with("WrongPassword1", &hashed, &peppers).unwrap());
+        assert!(!PasswordManager::needs_rehash_with(&hashed, &peppers));
+
+        // The bcrypt input is the HMAC, not the password
+        let (_, bcrypt_hash) = split_pepper(&hashed);
+        assert!(!verify("TestPassword123", bcrypt_hash).unwrap());
+    }
+
+    #[test]
+    fn test_wrong_pepper_fails() {
+        let hashed =
+            PasswordManager::hash_with("TestPassword123", &Peppers::new(Some(PEPPER), None))
+                .unwrap();
+
+        let other = Peppers::new(Some(NEW_PEPPER), None);
+        assert!(!PasswordManager::verify_with("TestPassword123", &hashed, &other).unwrap());
+        assert!(
+            !PasswordManager::verify_with("TestPassword123", &hashed, &Peppers::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_pepper_rotation() {
+        let hashed =
+            PasswordManager::hash_with("TestPassword123", &Peppers::new(Some(PEPPER), None))
+                .unwrap();
+
+        // The old pepper is still accepted, and the hash is marked for rehash
+        let rotated = Peppers::new(Some(NEW_PEPPER), Some(PEPPER));
+        assert!(PasswordManager::verify_with("TestPassword123", &hashed, &rotated).unwrap());
+        assert!(PasswordManager::needs_rehash_with(&hashed, &rotated));
+
+        let rehashed = PasswordManager::hash_with("TestPassword123", &rotated).unwrap();
+        assert!(PasswordManager::verify_with("TestPassword123", &rehashed, &rotated).unwrap());
+        assert!(!PasswordManager::needs_rehash_with(&rehashed, &rotated));
+    }
+
+    #[test]
+    fn test_unpeppered_hashes_keep_working() {
+        let none = Peppers::default();
+        let plain = PasswordManager::hash_with("TestPassword123", &none).unwrap();
+        assert!(plain.starts_with("$2"));
+        assert!(!PasswordManager::needs_rehash_with(&plain, &none));
+
+        // Adding a pepper later: existing hashes verify and get upgraded
+        let peppered = Peppers::new(Some(PEPPER), None);
+        assert!(PasswordManager::verify_with("TestPassword123", &plain, &peppered).unwrap());
+        assert!(PasswordManager::needs_rehash_with(&plain, &peppered));
+    }
+
     #[test]
     fn test_password_validation() {
         // Valid password
@@ -1,23 +1,43 @@
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use std::net::IpAddr;
 use thiserror::Error;
 
+use crate::auth::audit::{AuditEvent, AuditLog, AuditSeverity};
+use crate::auth::hashing::hash_refresh_token;
+use crate::auth::jwt::Claims;
+use crate::auth::schema::{Field, Schema, MAX_TOKEN_LENGTH};
 use crate::auth::JwtManager;
-use crate::cache::RedisClient;
-use crate::db::TokenRepository;
+use crate::cache::TokenBlacklist;
+use crate::db::error::DbFailure;
+use crate::db::{TokenStore, UserStore};
+use crate::metrics;
 
 /// Refresh token request payload
-#[derive(Debug, Clone, Deserialize)]
+///
+/// `refresh_token` may be left out when it is sent as a cookie instead
+/// (`jwt.refresh_cookie`).
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct RefreshRequest {
+    #[serde(default)]
     pub refresh_token: String,
 }
 
+impl Schema for RefreshRequest {
+    const FIELDS: &'static [Field] = &[Field::optional("refresh_token", MAX_TOKEN_LENGTH)];
+}
+
 /// Refresh token response
 #[derive(Debug, Serialize)]
 pub struct RefreshResponse {
     pub access_token: String,
+    /// Replacement for the presented refresh token, which is now revoked
+    pub refresh_token: String,
     pub token_type: String,
     pub expires_in: i64,
+    /// Access token expiry as an RFC3339 instant (`jwt.absolute_expiry` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
 }
 
 /// Refresh token error types
@@ -35,8 +55,11 @@ pub enum RefreshError {
     #[error("Token is blacklisted")]
     TokenBlacklisted,
 
+    #[error("Session has reached its maximum lifetime, please log in again")]
+    SessionExpired,
+
     #[error("Database error: {0}")]
-    DatabaseError(String),
+    DatabaseError(DbFailure),
 
     #[error("Token generation failed: {0}")]
     TokenError(String),
@@ -45,16 +68,27 @@ pub enum RefreshError {
     CacheError(String),
 }
 
-/// Refresh access token using refresh token
+/// Refresh access token and rotate the refresh token
+///
+/// On success the presented refresh token is revoked and a new one is
+/// stored, so a leaked token is good for one use at most.
+///
+/// A validly signed token that is no longer stored means two parties may
+/// hold it. Every token of the user is then revoked (see
+/// [`revoke_on_reuse`]), so the attacker's copy of the replacement dies too.
 ///
 /// # Arguments
-/// * `pool` - Database connection pool
-/// * `redis_client` - Redis client for blacklist checking
+/// * `users` - User storage backend (for the current token version)
+/// * `tokens` - Refresh token storage backend
+/// * `blacklist` - Token blacklist (Redis in production)
 /// * `jwt_manager` - JWT token manager
 /// * `request` - Refresh request data
+/// * `audit` - Where a detected reuse is recorded
+/// * `client_ip` - Client address, for the audit event
 ///
 /// # Returns
-/// * `Result<RefreshResponse, RefreshError>` - New access token or error
+/// * `Result<RefreshResponse, RefreshError>` - New access and refresh
+///   tokens, or error
 ///
 /// # Example
 /// ```
@@ -63,17 +97,23 @@ pub enum RefreshError {
 /// };
 ///
 /// let response = refresh_token(
-///     &pool,
+///     &UserRepository::new(&pool),
+///     &TokenRepository::new(&pool),
 ///     &redis_client,
 ///     &jwt_manager,
-///     request
+///     request,
+///     &LogAudit,
+///     client_ip
 /// ).await?;
 /// ```
 pub async fn refresh_token(
-    pool: &PgPool,
-    redis_client: &RedisClient,
+    users: &dyn UserStore,
+    tokens: &dyn TokenStore,
+    blacklist: &dyn TokenBlacklist,
     jwt_manager: &JwtManager,
     request: RefreshRequest,
+    audit: &dyn AuditLog,
+    client_ip: Option<IpAddr>,
 ) -> Result<RefreshResponse, RefreshError> {
     // Decode and validate refresh token
     let claims = jwt_manager
@@ -86,8 +126,18 @@ pub async fn refresh_token(
         return Err(RefreshError::InvalidToken);
     }
 
+    // Rotation keeps the session start, so this caps the whole chain
+    if jwt_manager.session_expired(&claims) {
+        log::info!(
+            "Refresh refused for user {}: session started at {} is past max_session_lifetime",
+            claims.sub,
+            claims.session_started_at()
+        );
+        return Err(RefreshError::SessionExpired);
+    }
+
     // Check if token is blacklisted in Redis
-    let is_blacklisted = redis_client
+    let is_blacklisted = blacklist
         .is_token_blacklisted(&request.refresh_token)
         .await
         .map_err(|e| RefreshError::CacheError(e.to_string()))?;
@@ -98,52 +148,143 @@ pub async fn refresh_token(
     }
 
     // Hash the token to check database
-    let token_hash = hash_token(&request.refresh_token);
+    let token_hash = hash_refresh_token(&request.refresh_token);
 
     // Verify refresh token exists in database and is not expired
-    let token_repo = TokenRepository::new(pool);
-    let stored_token = token_repo
-        .verify_refresh_token(&token_hash)
-        .await
-        .map_err(|e| match e {
-            crate::db::token::TokenError::NotFound => RefreshError::TokenRevoked,
-            crate::db::token::TokenError::Expired => RefreshError::TokenExpired,
-            _ => RefreshError::DatabaseError(e.to_string()),
-        })?;
+    let stored_token = match tokens.verify_refresh_token(&token_hash).await {
+        Ok(stored_token) => stored_token,
+        Err(crate::db::token::TokenError::NotFound) => {
+            revoke_on_reuse(users, tokens, &claims, audit, client_ip).await?;
+            return Err(RefreshError::TokenRevoked);
+        }
+        Err(crate::db::token::TokenError::Expired) => return Err(RefreshError::TokenExpired),
+        Err(e) => return Err(RefreshError::DatabaseError(e.into())),
+    };
 
     log::info!("Refresh token validated for user: {}", stored_token.user_id);
 
     // Parse user_id from claims
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| RefreshError::InvalidToken)?;
 
-    // Generate new access token
-    let new_access_token = jwt_manager
-        .generate_access_token(&user_id)
+    // Tokens issued before the user's version was bumped are revoked
+    let token_version = users.token_version(&user_id).await.map_err(|e| match e {
+        crate::db::user::UserError::NotFound => RefreshError::TokenRevoked,
+        _ => RefreshError::DatabaseError(e.into()),
+    })?;
+
+    if claims.token_version != token_version {
+        log::warn!(
+            "Refresh token for user {} predates a token version bump",
+            user_id
+        );
+        return Err(RefreshError::TokenRevoked);
+    }
+
+    // Generate new tokens, for the app the session was issued for
+    let issuer = jwt_manager.clone().with_audience(claims.aud.clone());
+    let issued_at = Utc::now();
+    let new_access_token = issuer
+        .generate_access_token(&user_id, token_version)
         .map_err(|e| RefreshError::TokenError(e.to_string()))?;
 
     log::info!("New access token generated for user: {}", user_id);
 
+    // Rotation keeps the session start, so max_session_lifetime still applies
+    let (new_refresh_token, new_token_hash, expires_at) = issuer
+        .generate_rotated_refresh_token(&user_id, token_version, claims.session_started_at())
+        .map_err(|e| RefreshError::TokenError(e.to_string()))?;
+
+    // A concurrent refresh of the same token got there first: this request
+    // is a replay of a revoked token, like any other
+    match tokens
+        .rotate_refresh_token(&token_hash, &user_id, &new_token_hash, expires_at)
+        .await
+    {
+        Ok(_) => {}
+        Err(crate::db::token::TokenError::NotFound) => {
+            revoke_on_reuse(users, tokens, &claims, audit, client_ip).await?;
+            return Err(RefreshError::TokenRevoked);
+        }
+        Err(e) => return Err(RefreshError::DatabaseError(e.into())),
+    }
+
+    log::info!("Refresh token rotated for user: {}", user_id);
+
     Ok(RefreshResponse {
         access_token: new_access_token,
+        refresh_token: new_refresh_token,
         token_type: "Bearer".to_string(),
         expires_in: jwt_manager.access_token_expiration(),
+        expires_at: jwt_manager
+            .expiry_timestamp(issued_at + Duration::seconds(jwt_manager.access_token_expiration())),
     })
 }
 
-/// Hash token for database storage (simple hash function)
-fn hash_token(token: &str) -> String {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
+/// Revoke all of a user's tokens if a revoked refresh token was replayed
+///
+/// A token counts as reused when it is signed by us and no longer stored,
+/// yet still carries the user's current token version. The mass
+/// revocation bumps that version, so later replays of any old token are
+/// plain revocations and the event fires once per compromise.
+async fn revoke_on_reuse(
+    users: &dyn UserStore,
+    tokens: &dyn TokenStore,
+    claims: &Claims,
+    audit: &dyn AuditLog,
+    client_ip: Option<IpAddr>,
+) -> Result<(), RefreshError> {
+    let Ok(user_id) = uuid::Uuid::parse_str(&claims.sub) else {
+        return Ok(());
+    };
+
+    let token_version = match users.token_version(&user_id).await {
+        Ok(version) => version,
+        Err(crate::db::user::UserError::NotFound) => return Ok(()),
+        Err(e) => return Err(RefreshError::DatabaseError(e.into())),
+    };
+    if claims.token_version != token_version {
+        return Ok(());
+    }
 
-    let mut hasher = DefaultHasher::new();
-    token.hash(&mut hasher);
-    format!("{:x}", hasher.finish())
+    log::error!(
+        "Refresh token reuse detected for user {} from {:?}; revoking all tokens",
+        user_id,
+        client_ip
+    );
+
+    users
+        .bump_token_version(&user_id)
+        .await
+        .map_err(|e| RefreshError::DatabaseError(e.into()))?;
+    tokens
+        .revoke_all_user_tokens(&user_id)
+        .await
+        .map_err(|e| RefreshError::DatabaseError(e.into()))?;
+
+    metrics::REFRESH_TOKEN_REUSE_DETECTED.inc();
+    audit.record(AuditEvent {
+        event: "refresh_token_reuse",
+        severity: AuditSeverity::High,
+        user_id,
+        client_ip,
+    });
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::auth::audit::LogAudit;
     use crate::auth::JwtManager;
+    use crate::cache::RedisClient;
+    use crate::db::memory::MemoryStore;
+    use crate::db::token::{RefreshToken, TokenError};
+    use crate::db::user::CreateUser;
+    use crate::db::{TokenRepository, UserRepository};
+    use async_trait::async_trait;
+    use chrono::DateTime;
+    use sqlx::PgPool;
 
     #[tokio::test]
     #[ignore]
@@ -156,14 +297,25 @@ mod tests {
 
         let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
 
-        let user_id = uuid::Uuid::new_v4();
-        let (refresh_token_str, token_hash) = jwt_manager.generate_refresh_token(&user_id).unwrap();
+        let user_repo = UserRepository::new(&pool);
+        let user = user_repo
+            .create(CreateUser {
+                email: format!("refresh_{}@example.com", uuid::Uuid::new_v4()),
+                username: None,
+                password_hash: "unused".to_string(),
+            })
+            .await
+            .unwrap();
+        let user_id = user.id;
+        let (refresh_token_str, token_hash, expires_at) = jwt_manager
+            .generate_refresh_token(&user_id, user.token_version)
+            .unwrap();
         //   ^^^^^^^^^^^^^^^^^^ 重命名变量，避免与函数名冲突
 
         // Save to database
         let token_repo = TokenRepository::new(&pool);
         token_repo
-            .save_refresh_token(&user_id, &token_hash, 604800)
+            .save_refresh_token(&user_id, &token_hash, expires_at)
             .await
             .unwrap();
 
@@ -172,10 +324,365 @@ mod tests {
             refresh_token: refresh_token_str,
         };
 
-        let response = refresh_token(&pool, &redis_client, &jwt_manager, request)
+        let response = refresh_token(
+            &user_repo,
+            &token_repo,
+            &redis_client,
+            &jwt_manager,
+            request,
+            &LogAudit,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert!(!response.access_token.is_empty());
+    }
+
+    /// Store holding one user and a saved refresh token for them
+    async fn store_with_refresh_token(
+        jwt_manager: &JwtManager,
+    ) -> (MemoryStore, uuid::Uuid, String) {
+        let store = MemoryStore::new();
+        let user = store
+            .create(CreateUser {
+                email: "user@example.com".to_string(),
+                username: None,
+                password_hash: "unused".to_string(),
+            })
             .await
             .unwrap();
 
-        assert!(!response.access_token.is_empty());
+        let (refresh_token_str, token_hash, expires_at) = jwt_manager
+            .generate_refresh_token(&user.id, user.token_version)
+            .unwrap();
+        store
+            .save_refresh_token(&user.id, &token_hash, expires_at)
+            .await
+            .unwrap();
+
+        (store, user.id, refresh_token_str)
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_in_memory() {
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let (store, user_id, refresh_token_str) = store_with_refresh_token(&jwt_manager).await;
+
+        let request = RefreshRequest {
+            refresh_token: refresh_token_str,
+        };
+
+        let response = refresh_token(&store, &store, &store, &jwt_manager, request, &store, None)
+            .await
+            .unwrap();
+
+        let claims = jwt_manager.validate_token(&response.access_token).unwrap();
+        assert_eq!(claims.sub, user_id.to_string());
+        assert_eq!(claims.token_type, "access");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_absolute_expiry_matches_relative() {
+        let jwt_manager =
+            JwtManager::new("test_secret".to_string(), 900, 604800).with_absolute_expiry(true);
+        let (store, _, refresh_token_str) = store_with_refresh_token(&jwt_manager).await;
+
+        let request = RefreshRequest {
+            refresh_token: refresh_token_str,
+        };
+
+        let before = Utc::now().timestamp();
+        let response = refresh_token(&store, &store, &store, &jwt_manager, request, &store, None)
+            .await
+            .unwrap();
+        let after = Utc::now().timestamp();
+
+        let expires_at =
+            chrono::DateTime::parse_from_rfc3339(response.expires_at.as_deref().unwrap())
+                .unwrap()
+                .timestamp();
+        // Issued somewhere between `before` and `after`
+        assert!(expires_at >= before + response.expires_in);
+        assert!(expires_at <= after + response.expires_in);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_unknown_token_is_revoked() {
+        let store = MemoryStore::new();
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+
+        // Validly signed but never stored
+        let (refresh_token_str, _, _) = jwt_manager
+            .generate_refresh_token(&uuid::Uuid::new_v4(), 0)
+            .unwrap();
+
+        let request = RefreshRequest {
+            refresh_token: refresh_token_str,
+        };
+
+        let result =
+            refresh_token(&store, &store, &store, &jwt_manager, request, &store, None).await;
+        assert!(matches!(result, Err(RefreshError::TokenRevoked)));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rejected_after_token_version_bump() {
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let (store, user_id, refresh_token_str) = store_with_refresh_token(&jwt_manager).await;
+
+        store.bump_token_version(&user_id).await.unwrap();
+
+        let request = RefreshRequest {
+            refresh_token: refresh_token_str,
+        };
+
+        let result =
+            refresh_token(&store, &store, &store, &jwt_manager, request, &store, None).await;
+        assert!(matches!(result, Err(RefreshError::TokenRevoked)));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rotates_token() {
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let (store, user_id, refresh_token_str) = store_with_refresh_token(&jwt_manager).await;
+
+        // A body without a token is accepted by the parser (cookie mode)
+        let empty: RefreshRequest = serde_json::from_str("{}").unwrap();
+        assert!(empty.refresh_token.is_empty());
+
+        let request = RefreshRequest {
+            refresh_token: refresh_token_str.clone(),
+        };
+        let response = refresh_token(&store, &store, &store, &jwt_manager, request, &store, None)
+            .await
+            .unwrap();
+
+        let claims = jwt_manager.validate_token(&response.access_token).unwrap();
+        assert_eq!(claims.sub, user_id.to_string());
+        assert_ne!(response.refresh_token, refresh_token_str);
+
+        // The old token no longer verifies; the new one does
+        assert_eq!(store.token_count(), 1);
+        assert!(matches!(
+            store
+                .verify_refresh_token(&hash_refresh_token(&refresh_token_str))
+                .await,
+            Err(crate::db::token::TokenError::NotFound)
+        ));
+        assert!(store
+            .verify_refresh_token(&hash_refresh_token(&response.refresh_token))
+            .await
+            .is_ok());
+
+        let new = RefreshRequest {
+            refresh_token: response.refresh_token,
+        };
+        assert!(
+            refresh_token(&store, &store, &store, &jwt_manager, new, &store, None)
+                .await
+                .is_ok()
+        );
+        assert!(store.audit_events().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reuse_revokes_all_tokens_once() {
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let (store, user_id, refresh_token_str) = store_with_refresh_token(&jwt_manager).await;
+        let client_ip: Option<IpAddr> = Some("203.0.113.7".parse().unwrap());
+        let rotate = |token: &str| {
+            let request = RefreshRequest {
+                refresh_token: token.to_string(),
+            };
+            refresh_token(
+                &store,
+                &store,
+                &store,
+                &jwt_manager,
+                request,
+                &store,
+                client_ip,
+            )
+        };
+
+        let rotated = rotate(&refresh_token_str).await.unwrap().refresh_token;
+        let before = metrics::REFRESH_TOKEN_REUSE_DETECTED.get();
+
+        // The old token is replayed: rejected, and everything is revoked
+        assert!(matches!(
+            rotate(&refresh_token_str).await,
+            Err(RefreshError::TokenRevoked)
+        ));
+        assert_eq!(store.token_count(), 0);
+        assert!(matches!(
+            rotate(&rotated).await,
+            Err(RefreshError::TokenRevoked)
+        ));
+
+        // Further replays are plain revocations, not new detections
+        assert!(rotate(&refresh_token_str).await.is_err());
+
+        assert_eq!(metrics::REFRESH_TOKEN_REUSE_DETECTED.get(), before + 1);
+        assert_eq!(
+            store.audit_events(),
+            vec![AuditEvent {
+                event: "refresh_token_reuse",
+                severity: AuditSeverity::High,
+                user_id,
+                client_ip,
+            }]
+        );
+    }
+
+    /// Token store where another refresh rotates the token between this
+    /// refresh's check and its rotation
+    struct LosingRace<'a>(&'a MemoryStore);
+
+    #[async_trait]
+    impl TokenStore for LosingRace<'_> {
+        async fn save_refresh_token(
+            &self,
+            user_id: &uuid::Uuid,
+            token_hash: &str,
+            expires_at: DateTime<Utc>,
+        ) -> Result<RefreshToken, TokenError> {
+            self.0
+                .save_refresh_token(user_id, token_hash, expires_at)
+                .await
+        }
+
+        async fn verify_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, TokenError> {
+            self.0.verify_refresh_token(token_hash).await
+        }
+
+        async fn revoke_token_by_hash(&self, token_hash: &str) -> Result<(), TokenError> {
+            self.0.revoke_token_by_hash(token_hash).await
+        }
+
+        async fn rotate_refresh_token(
+            &self,
+            old_hash: &str,
+            user_id: &uuid::Uuid,
+            new_hash: &str,
+            expires_at: DateTime<Utc>,
+        ) -> Result<RefreshToken, TokenError> {
+            // The winner's rotation
+            self.0.revoke_token_by_hash(old_hash).await?;
+            self.0
+                .rotate_refresh_token(old_hash, user_id, new_hash, expires_at)
+                .await
+        }
+
+        async fn revoke_all_user_tokens(&self, user_id: &uuid::Uuid) -> Result<u64, TokenError> {
+            self.0.revoke_all_user_tokens(user_id).await
+        }
+
+        async fn user_tokens(&self, user_id: &uuid::Uuid) -> Result<Vec<RefreshToken>, TokenError> {
+            self.0.user_tokens(user_id).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_losing_a_concurrent_refresh_is_reuse_not_an_error() {
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let (store, _, refresh_token_str) = store_with_refresh_token(&jwt_manager).await;
+
+        let request = RefreshRequest {
+            refresh_token: refresh_token_str,
+        };
+        let result = refresh_token(
+            &store,
+            &LosingRace(&store),
+            &store,
+            &jwt_manager,
+            request,
+            &store,
+            None,
+        )
+        .await;
+
+        assert!(matches!(result, Err(RefreshError::TokenRevoked)));
+        // Nothing was saved for the loser, and the session is revoked
+        assert_eq!(store.token_count(), 0);
+        assert_eq!(store.audit_events().len(), 1);
+    }
+
+    /// Refresh token saved for `user_id`, its session begun `age` seconds ago
+    async fn save_session_token(
+        store: &MemoryStore,
+        jwt_manager: &JwtManager,
+        user_id: &uuid::Uuid,
+        age: i64,
+    ) -> String {
+        let started_at = Utc::now().timestamp() - age;
+        let (token, token_hash, expires_at) = jwt_manager
+            .generate_rotated_refresh_token(user_id, 0, started_at)
+            .unwrap();
+        store
+            .save_refresh_token(user_id, &token_hash, expires_at)
+            .await
+            .unwrap();
+        token
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_session_start_within_max_lifetime() {
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800)
+            .with_max_session_lifetime(Some(3600));
+        let (store, user_id, _) = store_with_refresh_token(&jwt_manager).await;
+        let token = save_session_token(&store, &jwt_manager, &user_id, 1800).await;
+        let started_at = jwt_manager
+            .validate_token(&token)
+            .unwrap()
+            .session_started_at();
+
+        let request = RefreshRequest {
+            refresh_token: token,
+        };
+        let rotated = refresh_token(&store, &store, &store, &jwt_manager, request, &store, None)
+            .await
+            .unwrap()
+            .refresh_token;
+
+        // The replacement is newer, but its session is not
+        let claims = jwt_manager.validate_token(&rotated).unwrap();
+        assert_eq!(claims.session_started_at, Some(started_at));
+
+        let request = RefreshRequest {
+            refresh_token: rotated,
+        };
+        assert!(
+            refresh_token(&store, &store, &store, &jwt_manager, request, &store, None)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rejected_past_max_session_lifetime() {
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800)
+            .with_max_session_lifetime(Some(3600));
+        let (store, user_id, _) = store_with_refresh_token(&jwt_manager).await;
+        let token = save_session_token(&store, &jwt_manager, &user_id, 7200).await;
+
+        let request = RefreshRequest {
+            refresh_token: token.clone(),
+        };
+        assert!(matches!(
+            refresh_token(&store, &store, &store, &jwt_manager, request, &store, None).await,
+            Err(RefreshError::SessionExpired)
+        ));
+
+        // Without the cap the same session could go on
+        let uncapped = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let request = RefreshRequest {
+            refresh_token: token,
+        };
+        assert!(
+            refresh_token(&store, &store, &store, &uncapped, request, &store, None)
+                .await
+                .is_ok()
+        );
     }
 }
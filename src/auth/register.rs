@@ -1,16 +1,43 @@
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
 use thiserror::Error;
 
+use crate::auth::schema::{
+    Field, Schema, MAX_EMAIL_LENGTH, MAX_PASSWORD_LENGTH, MAX_USERNAME_LENGTH,
+};
 use crate::auth::{JwtManager, PasswordManager};
-use crate::db::user::CreateUser;
-use crate::db::{TokenRepository, UserRepository};
+use crate::db::error::DbFailure;
+use crate::db::user::{CreateUser, Usernames};
+use crate::db::{TokenStore, UserStore};
 
 /// Register request payload
 #[derive(Debug, Clone, Deserialize)]
 pub struct RegisterRequest {
     pub email: String,
     pub password: String,
+    /// Name to log in with besides the email (`middleware.auth.usernames`)
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+impl Schema for RegisterRequest {
+    const FIELDS: &'static [Field] = &[
+        Field::required("email", MAX_EMAIL_LENGTH),
+        Field::required("password", MAX_PASSWORD_LENGTH),
+        Field::optional("username", MAX_USERNAME_LENGTH),
+    ];
+}
+
+/// Shortest accepted username
+const MIN_USERNAME_LENGTH: usize = 3;
+
+/// Whether a username has an accepted length and only letters, digits,
+/// `_`, `.` and `-` (never `@`, so it cannot be mistaken for an email)
+fn is_valid_username(username: &str) -> bool {
+    (MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
 }
 
 /// Register response
@@ -22,6 +49,18 @@ pub struct RegisterResponse {
     pub refresh_token: String,
     pub token_type: String,
     pub expires_in: i64,
+    /// Access token expiry as an RFC3339 instant (`jwt.absolute_expiry` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// Refresh token expiry as an RFC3339 instant (`jwt.absolute_expiry` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_at: Option<String>,
+    /// The user's roles, for rendering UI without another call
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    /// Permissions derived from `roles`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
 }
 
 /// Registration error types
@@ -33,11 +72,20 @@ pub enum RegisterError {
     #[error("Invalid email format")]
     InvalidEmail,
 
+    #[error("Username already exists")]
+    UsernameExists,
+
+    #[error("Invalid username: use 3-32 letters, digits, '_', '.' or '-'")]
+    InvalidUsername,
+
+    #[error("Usernames are not enabled")]
+    UsernamesDisabled,
+
     #[error("Password validation failed: {0}")]
     PasswordValidationFailed(String),
 
     #[error("Database error: {0}")]
-    DatabaseError(String),
+    DatabaseError(DbFailure),
 
     #[error("Token generation failed: {0}")]
     TokenError(String),
@@ -46,10 +94,11 @@ pub enum RegisterError {
 /// Register a new user
 ///
 /// # Arguments
-/// * `pool` - Database connection pool
+/// * `users` - User storage backend
+/// * `tokens` - Refresh token storage backend
 /// * `jwt_manager` - JWT token manager
 /// * `request` - Registration request data
-/// * `refresh_token_expiration` - Refresh token expiration in seconds
+/// * `usernames` - Whether `request.username` may be set
 ///
 /// # Returns
 /// * `Result<RegisterResponse, RegisterError>` - Registration response or error
@@ -62,29 +111,46 @@ pub enum RegisterError {
 /// };
 ///
 /// let response = register_user(
-///     &pool,
+///     &UserRepository::new(&pool),
+///     &TokenRepository::new(&pool),
 ///     &jwt_manager,
 ///     request,
-///     604800
+///     Usernames::Disabled,
 /// ).await?;
 /// ```
 pub async fn register_user(
-    pool: &PgPool,
+    users: &dyn UserStore,
+    tokens: &dyn TokenStore,
     jwt_manager: &JwtManager,
     request: RegisterRequest,
-    refresh_token_expiration: i64,
+    usernames: Usernames,
 ) -> Result<RegisterResponse, RegisterError> {
     // Validate email format (basic check)
     if !request.email.contains('@') || !request.email.contains('.') {
         return Err(RegisterError::InvalidEmail);
     }
 
+    if let Some(username) = &request.username {
+        if usernames == Usernames::Disabled {
+            return Err(RegisterError::UsernamesDisabled);
+        }
+        if !is_valid_username(username) {
+            return Err(RegisterError::InvalidUsername);
+        }
+        if users
+            .username_exists(username)
+            .await
+            .map_err(|e| RegisterError::DatabaseError(e.into()))?
+        {
+            return Err(RegisterError::UsernameExists);
+        }
+    }
+
     // Check if email already exists
-    let user_repo = UserRepository::new(pool);
-    if user_repo
+    if users
         .email_exists(&request.email)
         .await
-        .map_err(|e| RegisterError::DatabaseError(e.to_string()))?
+        .map_err(|e| RegisterError::DatabaseError(e.into()))?
     {
         return Err(RegisterError::EmailExists);
     }
@@ -96,41 +162,47 @@ pub async fn register_user(
     // Create user
     let create_user = CreateUser {
         email: request.email.clone(),
+        username: request.username.clone(),
         password_hash,
     };
 
-    let user = user_repo
+    let user = users
         .create(create_user)
         .await
-        .map_err(|e| RegisterError::DatabaseError(e.to_string()))?;
+        .map_err(|e| RegisterError::DatabaseError(e.into()))?;
 
     log::info!("New user registered: {} (ID: {})", user.email, user.id);
 
     // Generate tokens
+    let issued_at = Utc::now();
     let access_token = jwt_manager
-        .generate_access_token(&user.id)
+        .generate_access_token(&user.id, user.token_version)
         .map_err(|e| RegisterError::TokenError(e.to_string()))?;
 
-    let (refresh_token, refresh_token_hash) = jwt_manager
-        .generate_refresh_token(&user.id)
+    let (refresh_token, refresh_token_hash, refresh_expires_at) = jwt_manager
+        .generate_refresh_token(&user.id, user.token_version)
         .map_err(|e| RegisterError::TokenError(e.to_string()))?;
 
-    // Save refresh token to database
-    let token_repo = TokenRepository::new(pool);
-    token_repo
-        .save_refresh_token(&user.id, &refresh_token_hash, refresh_token_expiration)
+    // Save refresh token to database, expiring with the JWT itself
+    tokens
+        .save_refresh_token(&user.id, &refresh_token_hash, refresh_expires_at)
         .await
-        .map_err(|e| RegisterError::DatabaseError(e.to_string()))?;
+        .map_err(|e| RegisterError::DatabaseError(e.into()))?;
 
     log::info!("Tokens generated for user: {}", user.email);
 
     Ok(RegisterResponse {
         user_id: user.id.to_string(),
+        roles: user.roles(),
+        permissions: user.permissions(),
         email: user.email,
         access_token,
         refresh_token,
         token_type: "Bearer".to_string(),
         expires_in: jwt_manager.access_token_expiration(),
+        expires_at: jwt_manager
+            .expiry_timestamp(issued_at + Duration::seconds(jwt_manager.access_token_expiration())),
+        refresh_expires_at: jwt_manager.expiry_timestamp(refresh_expires_at),
     })
 }
 
@@ -138,6 +210,9 @@ pub async fn register_user(
 mod tests {
     use super::*;
     use crate::auth::JwtManager;
+    use crate::db::memory::MemoryStore;
+    use crate::db::{TokenRepository, UserRepository};
+    use sqlx::PgPool;
 
     #[tokio::test]
     #[ignore]
@@ -151,13 +226,82 @@ mod tests {
         let request = RegisterRequest {
             email: format!("test_{}@example.com", uuid::Uuid::new_v4()),
             password: "SecurePass123!".to_string(),
+            username: None,
         };
 
-        let response = register_user(&pool, &jwt_manager, request, 604800)
-            .await
-            .unwrap();
+        let response = register_user(
+            &UserRepository::new(&pool),
+            &TokenRepository::new(&pool),
+            &jwt_manager,
+            request,
+            Usernames::Disabled,
+        )
+        .await
+        .unwrap();
 
         assert!(!response.access_token.is_empty());
         assert!(!response.refresh_token.is_empty());
     }
+
+    fn request(email: &str, username: Option<&str>) -> RegisterRequest {
+        RegisterRequest {
+            email: email.to_string(),
+            password: "SecurePass123!".to_string(),
+            username: username.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_username_is_unique_ignoring_case() {
+        let store = MemoryStore::new();
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+
+        register_user(
+            &store,
+            &store,
+            &jwt_manager,
+            request("alice@example.com", Some("Alice")),
+            Usernames::Enabled,
+        )
+        .await
+        .unwrap();
+
+        let taken = register_user(
+            &store,
+            &store,
+            &jwt_manager,
+            request("other@example.com", Some("alice")),
+            Usernames::Enabled,
+        )
+        .await;
+        assert!(matches!(taken, Err(RegisterError::UsernameExists)));
+        assert_eq!(store.user_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_username_rules() {
+        let store = MemoryStore::new();
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let register = |username: &str, usernames| {
+            register_user(
+                &store,
+                &store,
+                &jwt_manager,
+                request("user@example.com", Some(username)),
+                usernames,
+            )
+        };
+
+        assert!(matches!(
+            register("alice", Usernames::Disabled).await,
+            Err(RegisterError::UsernamesDisabled)
+        ));
+        for invalid in ["al", "alice@example", "alice smith", &"a".repeat(33)] {
+            assert!(matches!(
+                register(invalid, Usernames::Enabled).await,
+                Err(RegisterError::InvalidUsername)
+            ));
+        }
+        assert_eq!(store.user_count(), 0);
+    }
 }
@@ -0,0 +1,265 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+
+/// Longest accepted email address (the RFC 5321 path limit)
+pub const MAX_EMAIL_LENGTH: usize = 254;
+/// Longest accepted password; hashing anything longer is wasted work
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+/// Longest accepted refresh token
+pub const MAX_TOKEN_LENGTH: usize = 4096;
+/// Longest accepted username
+pub const MAX_USERNAME_LENGTH: usize = 32;
+/// Longest accepted client id or token audience
+pub const MAX_AUDIENCE_LENGTH: usize = 255;
+
+/// Constraint on one string field of a request body
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    /// Older names the field may still be sent under
+    pub aliases: &'static [&'static str],
+    /// Must be present, not null and not blank
+    pub required: bool,
+    /// Longest accepted value, in characters
+    pub max_len: usize,
+}
+
+impl Field {
+    pub const fn required(name: &'static str, max_len: usize) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            required: true,
+            max_len,
+        }
+    }
+
+    pub const fn optional(name: &'static str, max_len: usize) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            required: false,
+            max_len,
+        }
+    }
+
+    /// Also accept the field under these names
+    pub const fn with_aliases(self, aliases: &'static [&'static str]) -> Self {
+        Self { aliases, ..self }
+    }
+}
+
+/// Request payload whose fields are checked before it is deserialized
+pub trait Schema {
+    const FIELDS: &'static [Field];
+}
+
+/// One rejected field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// 422 response body
+#[derive(Debug, Serialize)]
+pub struct ValidationErrorResponse {
+    pub error: String,
+    pub fields: Vec<FieldError>,
+}
+
+impl ValidationErrorResponse {
+    pub fn new(fields: Vec<FieldError>) -> Self {
+        Self {
+            error: "Validation failed".to_string(),
+            fields,
+        }
+    }
+}
+
+/// Why a request body was rejected
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    /// Not JSON, or not the payload's shape (answered with 400)
+    #[error("Invalid JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    /// Fields breaking the schema (answered with 422)
+    #[error("Validation failed")]
+    Invalid(Vec<FieldError>),
+}
+
+/// Parse a request body, checking the payload's fields first
+///
+/// Missing or null required fields are reported as field errors rather
+/// than failing deserialization, so clients learn which field to fix.
+///
+/// # Arguments
+/// * `body` - Raw JSON request body
+///
+/// # Returns
+/// * `Ok(T)` - The payload, with every field within its constraints
+/// * `Err(SchemaError)` - Invalid JSON or the rejected fields
+pub fn parse<T: Schema + DeserializeOwned>(body: &[u8]) -> Result<T, SchemaError> {
+    let value: Value = serde_json::from_slice(body)?;
+
+    // Non-objects fail deserialization below with the type mismatch
+    if let Some(object) = value.as_object() {
+        let errors: Vec<FieldError> = T::FIELDS
+            .iter()
+            .filter_map(|field| {
+                let value = std::iter::once(&field.name)
+                    .chain(field.aliases)
+                    .find_map(|name| object.get(*name));
+                check(field, value)
+            })
+            .collect();
+        if !errors.is_empty() {
+            return Err(SchemaError::Invalid(errors));
+        }
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+fn check(field: &Field, value: Option<&Value>) -> Option<FieldError> {
+    let value = match value {
+        None | Some(Value::Null) if field.required => {
+            return Some(FieldError::new(field.name, "is required"))
+        }
+        None | Some(Value::Null) => return None,
+        Some(Value::String(value)) => value,
+        Some(_) => return Some(FieldError::new(field.name, "must be a string")),
+    };
+
+    if field.required && value.trim().is_empty() {
+        return Some(FieldError::new(field.name, "must not be empty"));
+    }
+    if value.chars().count() > field.max_len {
+        return Some(FieldError::new(
+            field.name,
+            format!("must be at most {} characters", field.max_len),
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{LoginRequest, LogoutRequest, RegisterRequest};
+
+    fn field_errors<T: Schema + DeserializeOwned + std::fmt::Debug>(body: &str) -> Vec<FieldError> {
+        match parse::<T>(body.as_bytes()) {
+            Err(SchemaError::Invalid(errors)) => errors,
+            other => panic!("expected field errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_valid_body_parses() {
+        let request: RegisterRequest =
+            parse(br#"{"email":"user@example.com","password":"S3cure!pass"}"#).unwrap();
+        assert_eq!(request.email, "user@example.com");
+    }
+
+    #[test]
+    fn test_empty_email_is_a_field_error() {
+        assert_eq!(
+            field_errors::<RegisterRequest>(r#"{"email":"  ","password":"S3cure!pass"}"#),
+            vec![FieldError::new("email", "must not be empty")]
+        );
+    }
+
+    #[test]
+    fn test_missing_password_is_a_field_error() {
+        assert_eq!(
+            field_errors::<RegisterRequest>(r#"{"email":"user@example.com"}"#),
+            vec![FieldError::new("password", "is required")]
+        );
+        assert_eq!(
+            field_errors::<LoginRequest>(r#"{"identifier":"user@example.com","password":null}"#),
+            vec![FieldError::new("password", "is required")]
+        );
+    }
+
+    #[test]
+    fn test_every_rejected_field_is_reported() {
+        let long_email = format!("{}@example.com", "a".repeat(MAX_EMAIL_LENGTH));
+        let body = serde_json::json!({ "email": long_email, "password": 42 }).to_string();
+
+        assert_eq!(
+            field_errors::<RegisterRequest>(&body),
+            vec![
+                FieldError::new("email", "must be at most 254 characters"),
+                FieldError::new("password", "must be a string"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aliased_field_is_checked() {
+        let request: LoginRequest =
+            parse(br#"{"email":"user@example.com","password":"S3cure!pass"}"#).unwrap();
+        assert_eq!(request.identifier, "user@example.com");
+
+        assert_eq!(
+            field_errors::<LoginRequest>(r#"{"email":"","password":"S3cure!pass"}"#),
+            vec![FieldError::new("identifier", "must not be empty")]
+        );
+        assert_eq!(
+            field_errors::<LoginRequest>(r#"{"password":"S3cure!pass"}"#),
+            vec![FieldError::new("identifier", "is required")]
+        );
+    }
+
+    #[test]
+    fn test_optional_fields_may_be_left_out() {
+        let request: LogoutRequest = parse(b"{}").unwrap();
+        assert!(request.refresh_token.is_none());
+
+        let long_token = serde_json::json!({ "refresh_token": "a".repeat(MAX_TOKEN_LENGTH + 1) });
+        assert!(matches!(
+            parse::<LogoutRequest>(long_token.to_string().as_bytes()),
+            Err(SchemaError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_json_is_not_a_field_error() {
+        assert!(matches!(
+            parse::<LoginRequest>(b"not json"),
+            Err(SchemaError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            parse::<LoginRequest>(b"[]"),
+            Err(SchemaError::InvalidJson(_))
+        ));
+    }
+
+    #[test]
+    fn test_validation_response_body() {
+        let json = serde_json::to_value(ValidationErrorResponse::new(vec![FieldError::new(
+            "email",
+            "is required",
+        )]))
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "error": "Validation failed",
+                "fields": [{ "field": "email", "message": "is required" }],
+            })
+        );
+    }
+}
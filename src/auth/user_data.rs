@@ -0,0 +1,250 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::auth::audit::{AuditEvent, AuditLog};
+use crate::db::error::DbFailure;
+use crate::db::user::{User, UserError};
+use crate::db::{TokenStore, UserStore};
+
+/// Everything stored about a user, for a data-subject access request
+#[derive(Debug, Serialize)]
+pub struct UserExport {
+    pub exported_at: DateTime<Utc>,
+    pub profile: UserProfile,
+    pub sessions: Vec<SessionExport>,
+    /// Events from the audit store (none when audit events only go to logs)
+    pub audit_events: Vec<AuditEvent>,
+}
+
+/// Account data, without the password hash
+#[derive(Debug, Serialize)]
+pub struct UserProfile {
+    pub id: Uuid,
+    pub email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub role: String,
+    /// Times every token of the user was invalidated (logins in
+    /// single-session mode, password changes, admin revocations)
+    pub token_version: i32,
+}
+
+impl From<User> for UserProfile {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            username: user.username,
+            role: user.role,
+            token_version: user.token_version,
+        }
+    }
+}
+
+/// One session (stored refresh token); the token hash is left out
+#[derive(Debug, Serialize)]
+pub struct SessionExport {
+    pub id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// What an erasure removed
+#[derive(Debug, Serialize)]
+pub struct ErasureReport {
+    pub user_id: Uuid,
+    pub sessions_revoked: u64,
+    pub audit_events_redacted: usize,
+}
+
+/// User data error types
+#[derive(Debug, Error)]
+pub enum UserDataError {
+    #[error("User not found")]
+    NotFound,
+
+    #[error("Database error: {0}")]
+    DatabaseError(DbFailure),
+}
+
+impl From<UserError> for UserDataError {
+    fn from(e: UserError) -> Self {
+        match e {
+            UserError::NotFound => UserDataError::NotFound,
+            e => UserDataError::DatabaseError(e.into()),
+        }
+    }
+}
+
+/// Collect a user's profile, sessions and audit events
+///
+/// # Arguments
+/// * `users` - User storage backend
+/// * `tokens` - Refresh token storage backend
+/// * `audit` - Audit store the user's events are read from
+/// * `user_id` - User to export
+///
+/// # Returns
+/// * `Result<UserExport, UserDataError>` - The user's data or error
+pub async fn export_user(
+    users: &dyn UserStore,
+    tokens: &dyn TokenStore,
+    audit: &dyn AuditLog,
+    user_id: &Uuid,
+) -> Result<UserExport, UserDataError> {
+    let user = users.find_by_id(user_id).await?;
+    let sessions = tokens
+        .user_tokens(user_id)
+        .await
+        .map_err(|e| UserDataError::DatabaseError(e.into()))?
+        .into_iter()
+        .map(|token| SessionExport {
+            id: token.id,
+            expires_at: token.expires_at,
+        })
+        .collect();
+
+    Ok(UserExport {
+        exported_at: Utc::now(),
+        profile: user.into(),
+        sessions,
+        audit_events: audit.events_for(user_id),
+    })
+}
+
+/// Erase a user: sessions, audit events and the account itself
+///
+/// The account goes last, so a failure part-way leaves it in place to be
+/// erased again rather than leaving orphaned data behind. Access tokens
+/// still out there stop working once the account is gone.
+///
+/// # Arguments
+/// * `users` - User storage backend
+/// * `tokens` - Refresh token storage backend
+/// * `audit` - Audit store the user's events are redacted from
+/// * `user_id` - User to erase
+///
+/// # Returns
+/// * `Result<ErasureReport, UserDataError>` - What was removed or error
+pub async fn erase_user(
+    users: &dyn UserStore,
+    tokens: &dyn TokenStore,
+    audit: &dyn AuditLog,
+    user_id: &Uuid,
+) -> Result<ErasureReport, UserDataError> {
+    // Fail with NotFound before touching anything
+    users.find_by_id(user_id).await?;
+
+    let sessions_revoked = tokens
+        .revoke_all_user_tokens(user_id)
+        .await
+        .map_err(|e| UserDataError::DatabaseError(e.into()))?;
+    let audit_events_redacted = audit.redact(user_id);
+    users.delete(user_id).await?;
+
+    log::warn!(
+        "User {} erased ({} sessions, {} audit events)",
+        user_id,
+        sessions_revoked,
+        audit_events_redacted
+    );
+
+    Ok(ErasureReport {
+        user_id: *user_id,
+        sessions_revoked,
+        audit_events_redacted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::audit::AuditSeverity;
+    use crate::auth::login::login;
+    use crate::auth::PasswordManager;
+    use crate::db::memory::MemoryStore;
+    use crate::db::user::CreateUser;
+
+    /// Store with two users, the first logged in on two devices
+    async fn store_with_sessions() -> (MemoryStore, Uuid, Uuid) {
+        let store = MemoryStore::new();
+        let mut ids = Vec::new();
+        for email in ["erase@example.com", "keep@example.com"] {
+            let user = store
+                .create(CreateUser {
+                    email: email.to_string(),
+                    username: None,
+                    password_hash: PasswordManager::hash("SecurePass123!").unwrap(),
+                })
+                .await
+                .unwrap();
+            ids.push(user.id);
+        }
+
+        for email in ["erase@example.com", "erase@example.com", "keep@example.com"] {
+            login(&store, email, "SecurePass123!").await.unwrap();
+        }
+        for user_id in &ids {
+            store.record(AuditEvent {
+                event: "refresh_token_reuse",
+                severity: AuditSeverity::High,
+                user_id: *user_id,
+                client_ip: None,
+            });
+        }
+
+        (store, ids[0], ids[1])
+    }
+
+    #[tokio::test]
+    async fn test_export_has_every_section_without_password_hash() {
+        let (store, user_id, _) = store_with_sessions().await;
+
+        let export = export_user(&store, &store, &store, &user_id).await.unwrap();
+        assert_eq!(export.profile.id, user_id);
+        assert_eq!(export.profile.email, "erase@example.com");
+        assert_eq!(export.sessions.len(), 2);
+        assert_eq!(export.audit_events.len(), 1);
+        assert_eq!(export.audit_events[0].user_id, user_id);
+
+        let json = serde_json::to_value(&export).unwrap();
+        for section in ["exported_at", "profile", "sessions", "audit_events"] {
+            assert!(json.get(section).is_some(), "{}", section);
+        }
+        let text = json.to_string();
+        assert!(!text.contains("password"));
+        assert!(!text.contains("token_hash"));
+
+        assert!(matches!(
+            export_user(&store, &store, &store, &Uuid::new_v4()).await,
+            Err(UserDataError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_erase_removes_user_sessions_and_audit_events() {
+        let (store, user_id, other_id) = store_with_sessions().await;
+
+        let report = erase_user(&store, &store, &store, &user_id).await.unwrap();
+        assert_eq!(report.sessions_revoked, 2);
+        assert_eq!(report.audit_events_redacted, 1);
+
+        assert!(matches!(
+            store.find_by_id(&user_id).await,
+            Err(UserError::NotFound)
+        ));
+        assert!(store.user_tokens(&user_id).await.unwrap().is_empty());
+        assert!(store.events_for(&user_id).is_empty());
+
+        // Other users are untouched
+        assert!(store.find_by_id(&other_id).await.is_ok());
+        assert_eq!(store.token_count(), 1);
+        assert_eq!(store.events_for(&other_id).len(), 1);
+
+        assert!(matches!(
+            erase_user(&store, &store, &store, &user_id).await,
+            Err(UserDataError::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+
+use crate::auth::password::PolicyCriterion;
+use crate::auth::schema::{Field, Schema, MAX_PASSWORD_LENGTH};
+use crate::auth::PasswordManager;
+
+/// Validate-password request payload
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidatePasswordRequest {
+    pub password: String,
+}
+
+impl Schema for ValidatePasswordRequest {
+    const FIELDS: &'static [Field] = &[Field::required("password", MAX_PASSWORD_LENGTH)];
+}
+
+/// Validate-password response
+#[derive(Debug, Serialize)]
+pub struct ValidatePasswordResponse {
+    /// True when every criterion is satisfied
+    pub valid: bool,
+    pub criteria: Vec<PolicyCriterion>,
+}
+
+/// Check a password against the policy without creating anything
+///
+/// # Arguments
+/// * `request` - Candidate password
+///
+/// # Returns
+/// * `ValidatePasswordResponse` - Each policy rule and whether it is met
+///
+/// # Example
+/// ```
+/// let request = ValidatePasswordRequest {
+///     password: "short".to_string(),
+/// };
+///
+/// let response = validate_password(request);
+/// // {"valid":false,"criteria":[{"name":"min_length","satisfied":false},...]}
+/// ```
+pub fn validate_password(request: ValidatePasswordRequest) -> ValidatePasswordResponse {
+    let criteria = PasswordManager::check_policy(&request.password);
+
+    ValidatePasswordResponse {
+        valid: criteria.iter().all(|c| c.satisfied),
+        criteria,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(password: &str) -> ValidatePasswordResponse {
+        validate_password(ValidatePasswordRequest {
+            password: password.to_string(),
+        })
+    }
+
+    fn failed(response: &ValidatePasswordResponse) -> Vec<&str> {
+        response
+            .criteria
+            .iter()
+            .filter(|c| !c.satisfied)
+            .map(|c| c.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_weak_password_lists_failed_criteria() {
+        let response = check("short");
+        assert!(!response.valid);
+        assert_eq!(failed(&response), vec!["min_length", "uppercase", "digit"]);
+
+        // Every rule is reported, not just the first failure
+        assert_eq!(response.criteria.len(), 4);
+    }
+
+    #[test]
+    fn test_strong_password_satisfies_all() {
+        let response = check("StrongPassword123");
+        assert!(response.valid);
+        assert!(failed(&response).is_empty());
+
+        // Agrees with what registration would accept
+        assert!(PasswordManager::hash("StrongPassword123").is_ok());
+    }
+}
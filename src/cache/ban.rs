@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+
+use crate::cache::RedisClient;
+
+/// Sorted set of active bans, scored by expiry (Unix seconds)
+const BANS_KEY: &str = "bans";
+
+/// Temporary bans applied through the admin API
+///
+/// Backed by Redis in production, so a ban reaches every proxy instance
+/// at once; the trait lets the ban flow run against an in-memory store in
+/// tests. Targets are opaque strings here (see `proxy::ban::BanTarget`).
+#[async_trait]
+pub trait BanStore: Send + Sync {
+    /// Ban `target` until `expires_at`, replacing any earlier expiry
+    ///
+    /// Bans that expired by `now` may be dropped at the same time.
+    async fn add_ban(
+        &self,
+        target: &str,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<(), redis::RedisError>;
+
+    /// Lift a ban
+    ///
+    /// # Returns
+    /// * `Result<bool, redis::RedisError>` - Whether `target` was banned
+    async fn remove_ban(&self, target: &str) -> Result<bool, redis::RedisError>;
+
+    /// Bans expiring after `now`, with their expiry
+    async fn active_bans(&self, now: i64) -> Result<Vec<(String, i64)>, redis::RedisError>;
+}
+
+#[async_trait]
+impl BanStore for RedisClient {
+    async fn add_ban(
+        &self,
+        target: &str,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<(), redis::RedisError> {
+        // Reads skip expired members; this keeps the set from growing
+        self.zrem_up_to_score(BANS_KEY, now).await?;
+        self.zadd(BANS_KEY, target, expires_at).await
+    }
+
+    async fn remove_ban(&self, target: &str) -> Result<bool, redis::RedisError> {
+        self.zrem(BANS_KEY, target).await
+    }
+
+    async fn active_bans(&self, now: i64) -> Result<Vec<(String, i64)>, redis::RedisError> {
+        self.zrange_above_score(BANS_KEY, now).await
+    }
+}
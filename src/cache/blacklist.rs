@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+
+use crate::cache::RedisClient;
+
+/// Revocation list for JWTs that must be rejected before they expire
+///
+/// Backed by Redis in production; the trait lets the auth flows run
+/// against an in-memory list in tests.
+#[async_trait]
+pub trait TokenBlacklist: Send + Sync {
+    /// Add token to blacklist for the given number of seconds
+    async fn blacklist_token(
+        &self,
+        token: &str,
+        expiration_seconds: u64,
+    ) -> Result<(), redis::RedisError>;
+
+    /// Check if token is blacklisted
+    async fn is_token_blacklisted(&self, token: &str) -> Result<bool, redis::RedisError>;
+}
+
+#[async_trait]
+impl TokenBlacklist for RedisClient {
+    async fn blacklist_token(
+        &self,
+        token: &str,
+        expiration_seconds: u64,
+    ) -> Result<(), redis::RedisError> {
+        RedisClient::blacklist_token(self, token, expiration_seconds).await
+    }
+
+    async fn is_token_blacklisted(&self, token: &str) -> Result<bool, redis::RedisError> {
+        RedisClient::is_token_blacklisted(self, token).await
+    }
+}
@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+
+use crate::cache::RedisClient;
+
+/// Trust-on-first-use certificate pins for TLS upstreams
+///
+/// Backed by Redis in production, so every proxy instance enforces the
+/// same pin; the trait lets the pinning flow run against an in-memory
+/// store in tests.
+#[async_trait]
+pub trait CertPinStore: Send + Sync {
+    /// Pin `fingerprint` for `upstream` unless a pin already exists
+    ///
+    /// Pinning is one atomic step, so of two instances connecting for the
+    /// first time only one pin wins.
+    ///
+    /// # Returns
+    /// * `Result<Option<String>, redis::RedisError>` - The existing pin, or
+    ///   `None` when `fingerprint` was just pinned
+    async fn pin_certificate(
+        &self,
+        upstream: &str,
+        fingerprint: &str,
+    ) -> Result<Option<String>, redis::RedisError>;
+}
+
+#[async_trait]
+impl CertPinStore for RedisClient {
+    async fn pin_certificate(
+        &self,
+        upstream: &str,
+        fingerprint: &str,
+    ) -> Result<Option<String>, redis::RedisError> {
+        let key = format!("upstream_cert_pin:{}", upstream);
+        if self.set_nx(&key, fingerprint).await? {
+            return Ok(None);
+        }
+
+        // Deleted in between (an operator resetting the pin): pin this one
+        match self.get(&key).await? {
+            Some(pinned) => Ok(Some(pinned)),
+            None => self.pin_certificate(upstream, fingerprint).await,
+        }
+    }
+}
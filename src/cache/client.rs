@@ -1,11 +1,19 @@
 use redis::aio::ConnectionManager;
-use redis::{AsyncCommands, Client};
-use std::time::Duration;
+use redis::{AsyncCommands, Client, ErrorKind, RedisError};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cache::health::{is_connection_error, RedisHealth};
 
 /// Redis client wrapper with connection pooling
+///
+/// Clones share one connection and one health flag, so an outage seen by
+/// any request makes all of them back off.
 #[derive(Clone)]
 pub struct RedisClient {
     manager: ConnectionManager,
+    health: Arc<RedisHealth>,
 }
 
 impl RedisClient {
@@ -19,7 +27,42 @@ impl RedisClient {
 
         log::info!("Redis connection initialized successfully");
 
-        Ok(Self { manager })
+        Ok(Self {
+            manager,
+            health: Arc::new(RedisHealth::new()),
+        })
+    }
+
+    /// Whether Redis is currently considered reachable
+    pub fn is_healthy(&self) -> bool {
+        self.health.is_healthy()
+    }
+
+    /// Run a command unless Redis is down and still inside its backoff window
+    ///
+    /// Connection failures mark Redis unhealthy; while it is, commands fail
+    /// fast with an `IoError` instead of triggering another reconnect.
+    async fn guarded<T, F, Fut>(&self, command: F) -> Result<T, RedisError>
+    where
+        F: FnOnce(ConnectionManager) -> Fut,
+        Fut: Future<Output = Result<T, RedisError>>,
+    {
+        if !self.health.allow_attempt(Instant::now()) {
+            return Err(RedisError::from((
+                ErrorKind::IoError,
+                "Redis unavailable, backing off",
+            )));
+        }
+
+        let result = command(self.manager.clone()).await;
+        match &result {
+            Ok(_) => self.health.record_success(),
+            Err(e) if is_connection_error(e) => self.health.record_failure(Instant::now()),
+            // Command errors (wrong type, script errors, ...) mean Redis is up
+            Err(_) => self.health.record_success(),
+        }
+
+        result
     }
 
     /// Test Redis connection
@@ -37,38 +80,113 @@ impl RedisClient {
         value: &str,
         expiration_seconds: u64,
     ) -> Result<(), redis::RedisError> {
-        let mut conn = self.manager.clone();
-        conn.set_ex(key, value, expiration_seconds).await
+        self.guarded(|mut conn| async move { conn.set_ex(key, value, expiration_seconds).await })
+            .await
+    }
+
+    /// Set a key that never expires, only if it does not exist
+    ///
+    /// # Returns
+    /// * `Result<bool, redis::RedisError>` - Whether the key was set
+    pub async fn set_nx(&self, key: &str, value: &str) -> Result<bool, redis::RedisError> {
+        self.guarded(|mut conn| async move { conn.set_nx(key, value).await })
+            .await
+    }
+
+    /// Set a key with expiration (in seconds) only if it does not exist
+    ///
+    /// # Returns
+    /// * `Result<bool, redis::RedisError>` - Whether the key was set
+    pub async fn set_nx_ex(
+        &self,
+        key: &str,
+        value: &str,
+        expiration_seconds: u64,
+    ) -> Result<bool, redis::RedisError> {
+        self.guarded(|mut conn| async move {
+            let set: Option<String> = redis::cmd("SET")
+                .arg(key)
+                .arg(value)
+                .arg("NX")
+                .arg("EX")
+                .arg(expiration_seconds)
+                .query_async(&mut conn)
+                .await?;
+            Ok(set.is_some())
+        })
+        .await
     }
 
     /// Get a value by key
     pub async fn get(&self, key: &str) -> Result<Option<String>, redis::RedisError> {
-        let mut conn = self.manager.clone();
-        conn.get(key).await
+        self.guarded(|mut conn| async move { conn.get(key).await })
+            .await
+    }
+
+    /// Get a value and delete its key in one step (`GETDEL`, Redis 6.2+)
+    pub async fn get_del(&self, key: &str) -> Result<Option<String>, redis::RedisError> {
+        self.guarded(|mut conn| async move {
+            redis::cmd("GETDEL").arg(key).query_async(&mut conn).await
+        })
+        .await
     }
 
     /// Delete a key
     pub async fn del(&self, key: &str) -> Result<(), redis::RedisError> {
-        let mut conn = self.manager.clone();
-        conn.del(key).await
+        self.guarded(|mut conn| async move { conn.del(key).await })
+            .await
+    }
+
+    /// Add a sorted set member, or update its score
+    pub async fn zadd(&self, key: &str, member: &str, score: i64) -> Result<(), redis::RedisError> {
+        self.guarded(|mut conn| async move { conn.zadd(key, member, score).await })
+            .await
+    }
+
+    /// Remove a sorted set member
+    ///
+    /// # Returns
+    /// * `Result<bool, redis::RedisError>` - Whether the member existed
+    pub async fn zrem(&self, key: &str, member: &str) -> Result<bool, redis::RedisError> {
+        self.guarded(|mut conn| async move { conn.zrem(key, member).await })
+            .await
+    }
+
+    /// Sorted set members scored above `min`, with their scores, lowest first
+    pub async fn zrange_above_score(
+        &self,
+        key: &str,
+        min: i64,
+    ) -> Result<Vec<(String, i64)>, redis::RedisError> {
+        self.guarded(|mut conn| async move {
+            conn.zrangebyscore_withscores(key, format!("({}", min), "+inf")
+                .await
+        })
+        .await
+    }
+
+    /// Remove sorted set members scored at or below `max`
+    pub async fn zrem_up_to_score(&self, key: &str, max: i64) -> Result<(), redis::RedisError> {
+        self.guarded(|mut conn| async move { conn.zrembyscore(key, "-inf", max).await })
+            .await
     }
 
     /// Check if a key exists
     pub async fn exists(&self, key: &str) -> Result<bool, redis::RedisError> {
-        let mut conn = self.manager.clone();
-        conn.exists(key).await
+        self.guarded(|mut conn| async move { conn.exists(key).await })
+            .await
     }
 
     /// Set a key with TTL (Time To Live) in seconds
     pub async fn expire(&self, key: &str, seconds: u64) -> Result<bool, redis::RedisError> {
-        let mut conn = self.manager.clone();
-        conn.expire(key, seconds as i64).await
+        self.guarded(|mut conn| async move { conn.expire(key, seconds as i64).await })
+            .await
     }
 
     /// Increment a counter (used for rate limiting)
     pub async fn incr(&self, key: &str) -> Result<i64, redis::RedisError> {
-        let mut conn = self.manager.clone();
-        conn.incr(key, 1).await
+        self.guarded(|mut conn| async move { conn.incr(key, 1).await })
+            .await
     }
 
     /// Increment a counter with expiration
@@ -77,17 +195,18 @@ impl RedisClient {
         key: &str,
         expiration_seconds: u64,
     ) -> Result<i64, redis::RedisError> {
-        let mut conn = self.manager.clone();
-
-        // Use Redis transaction to atomically increment and set expiration
-        let count: i64 = conn.incr(key, 1).await?;
+        self.guarded(|mut conn| async move {
+            // Use Redis transaction to atomically increment and set expiration
+            let count: i64 = conn.incr(key, 1).await?;
 
-        // Only set expiration if this is the first increment
-        if count == 1 {
-            conn.expire::<_, ()>(key, expiration_seconds as i64).await?;
-        }
+            // Only set expiration if this is the first increment
+            if count == 1 {
+                conn.expire::<_, ()>(key, expiration_seconds as i64).await?;
+            }
 
-        Ok(count)
+            Ok(count)
+        })
+        .await
     }
 
     /// Add token to blacklist (for JWT logout)
@@ -118,8 +237,9 @@ impl RedisClient {
         let allowed = count <= max_requests;
 
         // Get remaining TTL
-        let mut conn = self.manager.clone();
-        let ttl: i64 = conn.ttl(key).await?;
+        let ttl: i64 = self
+            .guarded(|mut conn| async move { conn.ttl(key).await })
+            .await?;
         let ttl_duration = if ttl > 0 {
             Some(Duration::from_secs(ttl as u64))
         } else {
@@ -130,7 +250,7 @@ impl RedisClient {
     }
 
     /// Mask password in Redis URL for logging
-    fn mask_password(url: &str) -> String {
+    pub fn mask_password(url: &str) -> String {
         if let Some(at_pos) = url.rfind('@') {
             if let Some(colon_pos) = url[..at_pos].rfind(':') {
                 let mut masked = url.to_string();
@@ -147,6 +267,7 @@ impl std::fmt::Debug for RedisClient {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RedisClient")
             .field("manager", &"ConnectionManager { ... }")
+            .field("healthy", &self.health.is_healthy())
             .finish()
     }
 }
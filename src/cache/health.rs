@@ -0,0 +1,166 @@
+use rand::Rng;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Backoff after the first connection failure
+const BASE_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest wait between reconnection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Shared Redis availability flag acting as a circuit breaker
+///
+/// After a connection-level failure every request fails fast instead of
+/// making the `ConnectionManager` reconnect again; one request per backoff
+/// window is let through as a probe. The backoff doubles per consecutive
+/// failure (with jitter so proxy instances don't probe in lockstep) and
+/// resets on the first success. Outage and recovery are logged once each.
+#[derive(Debug, Default)]
+pub struct RedisHealth {
+    state: Mutex<HealthState>,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    /// Consecutive connection failures (0 while healthy)
+    failures: u32,
+    /// No attempts before this instant while unhealthy
+    retry_at: Option<Instant>,
+}
+
+impl RedisHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether Redis is currently considered reachable
+    pub fn is_healthy(&self) -> bool {
+        self.state.lock().unwrap().failures == 0
+    }
+
+    /// Whether a command may be sent to Redis as of `now`
+    ///
+    /// While unhealthy, only the first caller after the backoff expires gets
+    /// through; the window is pushed forward so concurrent callers keep
+    /// failing fast until that probe reports back.
+    pub fn allow_attempt(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.retry_at {
+            None => true,
+            Some(retry_at) if now >= retry_at => {
+                state.retry_at = Some(now + backoff(state.failures));
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    /// Record a successful command
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.failures > 0 {
+            log::info!(
+                "Redis connection restored after {} failed attempts",
+                state.failures
+            );
+        }
+        *state = HealthState::default();
+    }
+
+    /// Record a connection-level failure as of `now`
+    pub fn record_failure(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        if state.failures == 0 {
+            log::warn!("Redis unavailable, backing off reconnection attempts");
+        }
+        state.failures = state.failures.saturating_add(1);
+        state.retry_at = Some(now + backoff(state.failures));
+    }
+}
+
+/// Jittered exponential backoff for the given number of consecutive failures
+///
+/// Uniform in `[d/2, d]` where `d = BASE_BACKOFF * 2^(failures - 1)`, capped at `MAX_BACKOFF`.
+fn backoff(failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(16);
+    let full = BASE_BACKOFF.saturating_mul(1 << exponent).min(MAX_BACKOFF);
+    let half = full / 2;
+    half + half.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+/// Whether an error means Redis could not be reached (as opposed to a command error)
+pub fn is_connection_error(e: &redis::RedisError) -> bool {
+    e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() || e.is_timeout()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_bounds() {
+        for failures in 1..20 {
+            let full = BASE_BACKOFF
+                .saturating_mul(1 << (failures - 1).min(16))
+                .min(MAX_BACKOFF);
+            let delay = backoff(failures);
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn test_outage_attempts_are_rate_limited() {
+        let health = RedisHealth::new();
+        let start = Instant::now();
+
+        // 10s outage with a request every 10ms, every attempt failing
+        let mut attempts = 0;
+        for i in 0..1000 {
+            let now = start + Duration::from_millis(i * 10);
+            if health.allow_attempt(now) {
+                attempts += 1;
+                health.record_failure(now);
+            }
+        }
+
+        // Backoff grows 100ms, 200ms, ... up to 5s: a handful of probes, not 1000
+        assert!(attempts >= 5, "attempts = {}", attempts);
+        assert!(attempts <= 12, "attempts = {}", attempts);
+        assert!(!health.is_healthy());
+    }
+
+    #[test]
+    fn test_flapping_recovers_and_resets_backoff() {
+        let health = RedisHealth::new();
+        let start = Instant::now();
+
+        for cycle in 0..3 {
+            let down = start + Duration::from_secs(cycle * 60);
+            assert!(health.allow_attempt(down));
+            health.record_failure(down);
+            assert!(!health.is_healthy());
+
+            // Requests right after the failure fail fast
+            assert!(!health.allow_attempt(down + Duration::from_millis(10)));
+
+            // The first request after the backoff probes and succeeds
+            let probe = down + MAX_BACKOFF;
+            assert!(health.allow_attempt(probe));
+            health.record_success();
+            assert!(health.is_healthy());
+            assert!(health.allow_attempt(probe));
+        }
+    }
+
+    #[test]
+    fn test_only_one_probe_per_window() {
+        let health = RedisHealth::new();
+        let start = Instant::now();
+        health.record_failure(start);
+
+        let probe = start + MAX_BACKOFF;
+        assert!(health.allow_attempt(probe));
+        assert!(!health.allow_attempt(probe));
+        assert!(!health.allow_attempt(probe + Duration::from_millis(1)));
+    }
+}
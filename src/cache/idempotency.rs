@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+
+use crate::auth::encryption::EncryptionService;
+use crate::cache::RedisClient;
+
+/// Locks and cached responses for request de-duplication
+///
+/// Backed by Redis in production; the trait lets the de-duplication flow
+/// run against an in-memory store in tests.
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Claim `key` for one request; `false` if another request holds it
+    async fn try_lock(&self, key: &str, ttl_seconds: u64) -> Result<bool, redis::RedisError>;
+
+    /// Release a claim taken with `try_lock`
+    async fn unlock(&self, key: &str) -> Result<(), redis::RedisError>;
+
+    /// Response recorded for `key`, if any
+    async fn cached_response(&self, key: &str) -> Result<Option<String>, redis::RedisError>;
+
+    /// Record the response for `key` for the given number of seconds
+    async fn cache_response(
+        &self,
+        key: &str,
+        response: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), redis::RedisError>;
+}
+
+#[async_trait]
+impl IdempotencyStore for RedisClient {
+    async fn try_lock(&self, key: &str, ttl_seconds: u64) -> Result<bool, redis::RedisError> {
+        let key = format!("idempotency:lock:{}", key);
+        self.set_nx_ex(&key, "1", ttl_seconds).await
+    }
+
+    async fn unlock(&self, key: &str) -> Result<(), redis::RedisError> {
+        let key = format!("idempotency:lock:{}", key);
+        self.del(&key).await
+    }
+
+    async fn cached_response(&self, key: &str) -> Result<Option<String>, redis::RedisError> {
+        let key = format!("idempotency:response:{}", key);
+        self.get(&key).await
+    }
+
+    async fn cache_response(
+        &self,
+        key: &str,
+        response: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), redis::RedisError> {
+        let key = format!("idempotency:response:{}", key);
+        self.set_ex(&key, response, ttl_seconds).await
+    }
+}
+
+/// Idempotency store whose recorded responses are encrypted at rest
+///
+/// A replayed response holds whatever the upstream returned, such as a
+/// newly issued API key, so with `encryption` keys configured it is
+/// sealed before it reaches the store. Locks pass straight through.
+pub struct SealedResponses<'a> {
+    store: &'a dyn IdempotencyStore,
+    encryption: Option<&'a EncryptionService>,
+}
+
+impl<'a> SealedResponses<'a> {
+    /// Wrap `store`; without `encryption` responses are stored as they are
+    pub fn new(store: &'a dyn IdempotencyStore, encryption: Option<&'a EncryptionService>) -> Self {
+        Self { store, encryption }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for SealedResponses<'_> {
+    async fn try_lock(&self, key: &str, ttl_seconds: u64) -> Result<bool, redis::RedisError> {
+        self.store.try_lock(key, ttl_seconds).await
+    }
+
+    async fn unlock(&self, key: &str) -> Result<(), redis::RedisError> {
+        self.store.unlock(key).await
+    }
+
+    async fn cached_response(&self, key: &str) -> Result<Option<String>, redis::RedisError> {
+        let stored = self.store.cached_response(key).await?;
+        let Some(encryption) = self.encryption else {
+            return Ok(stored);
+        };
+
+        // Unreadable (e.g. sealed with a retired key): forward the request again
+        Ok(stored.and_then(|stored| match encryption.decrypt(&stored) {
+            Ok(response) => Some(response),
+            Err(e) => {
+                log::warn!("Ignoring idempotent response {}: {}", key, e);
+                None
+            }
+        }))
+    }
+
+    async fn cache_response(
+        &self,
+        key: &str,
+        response: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), redis::RedisError> {
+        match self.encryption {
+            Some(encryption) => {
+                let sealed = encryption.encrypt(response);
+                self.store.cache_response(key, &sealed, ttl_seconds).await
+            }
+            None => self.store.cache_response(key, response, ttl_seconds).await,
+        }
+    }
+}
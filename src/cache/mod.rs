@@ -1,3 +1,14 @@
+pub mod ban;
+pub mod blacklist;
+pub mod cert_pin;
 pub mod client;
+pub mod health;
+pub mod idempotency;
+pub mod nonce;
 
+pub use ban::BanStore;
+pub use blacklist::TokenBlacklist;
+pub use cert_pin::CertPinStore;
 pub use client::RedisClient;
+pub use idempotency::IdempotencyStore;
+pub use nonce::NonceStore;
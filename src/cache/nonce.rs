@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+
+use crate::cache::RedisClient;
+
+/// Single-use nonces for mutating admin requests
+///
+/// Backed by Redis in production; the trait lets the nonce flow run
+/// against an in-memory store in tests.
+#[async_trait]
+pub trait NonceStore: Send + Sync {
+    /// Store a nonce issued to `user_id` for the given number of seconds
+    async fn store_nonce(
+        &self,
+        nonce: &str,
+        user_id: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), redis::RedisError>;
+
+    /// Remove a nonce and return the user it was issued to
+    ///
+    /// Removal and lookup are one atomic step, so of two requests racing
+    /// with the same nonce only one gets it back.
+    async fn take_nonce(&self, nonce: &str) -> Result<Option<String>, redis::RedisError>;
+}
+
+#[async_trait]
+impl NonceStore for RedisClient {
+    async fn store_nonce(
+        &self,
+        nonce: &str,
+        user_id: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), redis::RedisError> {
+        let key = format!("admin_nonce:{}", nonce);
+        self.set_ex(&key, user_id, ttl_seconds).await
+    }
+
+    async fn take_nonce(&self, nonce: &str) -> Result<Option<String>, redis::RedisError> {
+        let key = format!("admin_nonce:{}", nonce);
+        self.get_del(&key).await
+    }
+}
@@ -1,2 +1,3 @@
 pub mod settings;
+pub mod summary;
 pub use settings::Settings;
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -9,12 +10,394 @@ pub struct Settings {
     pub jwt: JwtConfig,
     pub load_balancing: LoadBalancingConfig,
     pub middleware: MiddlewareConfig,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    #[serde(default)]
+    pub request_id: RequestIdConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub password: PasswordConfig,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    #[serde(default)]
+    pub response: ResponseConfig,
+    #[serde(default)]
+    pub debug: DebugConfig,
+}
+
+/// Conveniences for developing against the proxy; keep off in production
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DebugConfig {
+    /// Indent the JSON bodies the proxy writes itself (errors, auth and
+    /// admin responses); proxied bodies are untouched
+    #[serde(default)]
+    pub pretty_json: bool,
+}
+
+/// Headers added to every response the proxy sends
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ResponseConfig {
+    /// Name to value, e.g. `X-Content-Type-Options: nosniff`. Not added
+    /// where the upstream already set the header; `Strict-Transport-Security`
+    /// only goes out on TLS connections.
+    #[serde(default)]
+    pub default_headers: BTreeMap<String, String>,
+    /// Headers stripped from upstream responses before the proxy adds its own
+    #[serde(default)]
+    pub upstream_headers: UpstreamHeadersConfig,
+    /// `Accept-Encoding` negotiation for proxied requests
+    #[serde(default)]
+    pub encoding: EncodingConfig,
+}
+
+/// Content codings negotiated by the proxy
+///
+/// Compression is left to the upstreams unless `compression_level` is set.
+/// The proxy picks one coding the client accepts from `codings` (or
+/// identity) and forwards only that in `Accept-Encoding`, so `q=0`
+/// exclusions hold whatever the upstream does with quality values.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EncodingConfig {
+    /// Codings the upstreams produce, most preferred first, e.g.
+    /// `["br", "gzip"]`. Empty leaves `Accept-Encoding` untouched.
+    #[serde(default)]
+    pub codings: Vec<String>,
+    /// What to do when the client accepts none of them, nor identity
+    #[serde(default)]
+    pub unsatisfiable: UnsatisfiableEncoding,
+    /// Level (1-9) at which the proxy compresses responses the upstream
+    /// left uncompressed; 0 leaves compression to the upstreams
+    #[serde(default)]
+    pub compression_level: u32,
+    /// Media types the proxy never compresses because they are compressed
+    /// already, e.g. `image/jpeg`; `type/*` covers a whole type
+    #[serde(default = "default_compression_exclude_types")]
+    pub exclude_types: Vec<String>,
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        Self {
+            codings: Vec::new(),
+            unsatisfiable: UnsatisfiableEncoding::default(),
+            compression_level: 0,
+            exclude_types: default_compression_exclude_types(),
+        }
+    }
+}
+
+/// Answer to a request whose `Accept-Encoding` cannot be satisfied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnsatisfiableEncoding {
+    /// Proxy it anyway and have the upstream send identity
+    #[default]
+    Identity,
+    /// Answer 406 Not Acceptable
+    Reject,
+}
+
+/// Denylist and allowlist for headers the upstream sends
+///
+/// Names are matched case-insensitively. Framing headers (`Content-Length`,
+/// `Transfer-Encoding`, `Content-Encoding`, `Connection`) are always kept.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct UpstreamHeadersConfig {
+    /// Always removed, even when also allowed, e.g. `X-Debug-Token`, `Server`
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// When set, every header not listed here is removed as well
+    #[serde(default)]
+    pub allow: Option<Vec<String>>,
+}
+
+/// Keys for secrets stored reversibly (responses kept for idempotent replay)
+///
+/// Values are encrypted with `current_version`; older versions are kept
+/// so existing ciphertexts still decrypt after a rotation. Encryption is
+/// off when no keys are listed.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EncryptionConfig {
+    #[serde(default)]
+    pub current_version: u32,
+    #[serde(default)]
+    pub keys: Vec<EncryptionKeyConfig>,
+}
+
+/// One AES-256 key: base64 of 32 bytes, inline or in a file
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EncryptionKeyConfig {
+    pub version: u32,
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub key_file: Option<String>,
+}
+
+/// Server-side pepper HMAC'd into passwords before bcrypt
+///
+/// Keep `previous_pepper` set after rotating until old hashes have been
+/// upgraded on login. Empty values count as unset.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PasswordConfig {
+    #[serde(default)]
+    pub pepper: Option<String>,
+    #[serde(default)]
+    pub previous_pepper: Option<String>,
+}
+
+/// Bootstrap admin account, created (or promoted) at startup if missing
+///
+/// Each field falls back to the `ADMIN_EMAIL` / `ADMIN_PASSWORD`
+/// environment variable when unset or empty.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AdminConfig {
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl AdminConfig {
+    /// Admin email and password, if both are configured
+    pub fn credentials(&self) -> Option<(String, String)> {
+        let resolve = |value: &Option<String>, var: &str| {
+            value
+                .clone()
+                .filter(|v| !v.is_empty())
+                .or_else(|| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        };
+
+        Some((
+            resolve(&self.email, "ADMIN_EMAIL")?,
+            resolve(&self.password, "ADMIN_PASSWORD")?,
+        ))
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ServerConfig {
+    /// IP the proxy, admin and metrics listeners bind to: `0.0.0.0` (IPv4),
+    /// `::` or `[::]` (IPv6, dual-stack unless `ipv6_only`) or a specific address
+    #[serde(default = "default_listen_address")]
+    pub listen_address: String,
+    /// With an IPv6 `listen_address`, refuse IPv4 clients instead of
+    /// accepting them as IPv4-mapped addresses
+    #[serde(default)]
+    pub ipv6_only: bool,
     pub listen_port: u16,
     pub max_connections: u32,
+    /// Maximum request URI length in bytes; longer URIs get 414
+    #[serde(default = "default_max_uri_length")]
+    pub max_uri_length: usize,
+    /// Maximum number of request header fields; more get 431
+    #[serde(default = "default_max_headers")]
+    pub max_headers: usize,
+    /// Maximum length of a request header value in bytes; longer gets 431
+    #[serde(default = "default_max_header_value_len")]
+    pub max_header_value_len: usize,
+    /// Maximum request body size in bytes; larger bodies get 413
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    /// Maximum `multipart/form-data` upload size in bytes on proxied routes;
+    /// larger uploads get 413
+    #[serde(default = "default_max_upload_size")]
+    pub max_upload_size: usize,
+    /// Requests served on one HTTP/1 client connection before it is closed
+    /// (unlimited when unset)
+    #[serde(default)]
+    pub max_requests_per_connection: Option<u32>,
+    /// Seconds a client gets to send a request's headers before the
+    /// connection is dropped (no limit when unset)
+    #[serde(default)]
+    pub header_read_timeout_secs: Option<u64>,
+    /// Answer 503 to endpoints that write to the database (registration,
+    /// password changes, token rotation, user imports) while reads and
+    /// non-rotating refreshes keep working; logins issue an access token
+    /// only and logouts blacklist in Redis
+    #[serde(default)]
+    pub read_only: bool,
+    /// Serve the auth API's OpenAPI document at `GET /auth/openapi.json`
+    #[serde(default)]
+    pub openapi: bool,
+    /// Port for the Prometheus `/metrics` listener (disabled when unset)
+    #[serde(default)]
+    pub metrics_port: Option<u16>,
+    /// Second listener that alone serves `/admin/*` and `/metrics`
+    /// (admin routes stay on the public port when unset)
+    #[serde(default)]
+    pub admin_listener: Option<AdminListenerConfig>,
+    /// Socket options for the proxy listener
+    #[serde(default)]
+    pub tcp: TcpConfig,
+    /// Terminate TLS on the proxy listener (plain HTTP when unset)
+    #[serde(default)]
+    pub tls: Option<ListenerTlsConfig>,
+    /// Only requests slower than this reach the access log at warn; the
+    /// rest are logged at debug (every request at info when unset)
+    #[serde(default)]
+    pub slow_request_threshold_ms: Option<u64>,
+    /// On `SIGTERM`, seconds `GET /ready` answers 503 while requests are
+    /// still served, before the graceful shutdown starts
+    #[serde(default)]
+    pub drain_timeout_secs: u64,
+}
+
+impl ServerConfig {
+    /// `listen_address` parsed, with optional IPv6 brackets removed
+    pub fn listen_ip(&self) -> Result<std::net::IpAddr, String> {
+        let address = self.listen_address.trim();
+        let unbracketed = address
+            .strip_prefix('[')
+            .and_then(|a| a.strip_suffix(']'))
+            .unwrap_or(address);
+
+        unbracketed
+            .parse()
+            .map_err(|_| format!("Server listen_address {} is not an IP address", address))
+    }
+}
+
+/// Certificate and protocol policy for the TLS proxy listener
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ListenerTlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    /// Oldest protocol version accepted from clients
+    #[serde(default = "default_min_tls_version")]
+    pub min_tls_version: TlsVersion,
+    /// OpenSSL cipher names allowed (library defaults when empty); TLS 1.3
+    /// suites are the `TLS_*` names
+    #[serde(default)]
+    pub ciphers: Vec<String>,
+}
+
+/// TLS protocol version, written `1.2` / `1.3` (or `TLSv1.2` / `TLSv1.3`)
+///
+/// Older versions are not supported and fail config parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl std::str::FromStr for TlsVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().trim_start_matches("TLSv") {
+            "1.2" => Ok(TlsVersion::Tls12),
+            "1.3" => Ok(TlsVersion::Tls13),
+            _ => Err(format!(
+                "unsupported TLS version {} (expected 1.2 or 1.3)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsVersion::Tls12 => write!(f, "TLSv1.2"),
+            TlsVersion::Tls13 => write!(f, "TLSv1.3"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TlsVersion {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Unquoted `1.2` in YAML is a number
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Number(f64),
+        }
+
+        let text = match Raw::deserialize(deserializer)? {
+            Raw::Text(text) => text,
+            Raw::Number(number) => number.to_string(),
+        };
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Dedicated listener for admin and metrics routes
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdminListenerConfig {
+    pub port: u16,
+    /// Client IPs allowed to use the listener (any when empty)
+    #[serde(default)]
+    pub allowlist: Vec<std::net::IpAddr>,
+}
+
+/// Request ID generation and propagation
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RequestIdConfig {
+    /// Header carrying the request ID upstream and back to the client
+    #[serde(default = "default_request_id_header")]
+    pub header: String,
+    #[serde(default)]
+    pub format: RequestIdFormat,
+    /// Forward a W3C `traceparent` upstream, keeping a valid incoming one
+    #[serde(default)]
+    pub traceparent: bool,
+}
+
+impl Default for RequestIdConfig {
+    fn default() -> Self {
+        Self {
+            header: default_request_id_header(),
+            format: RequestIdFormat::default(),
+            traceparent: false,
+        }
+    }
+}
+
+/// Request ID shape
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestIdFormat {
+    /// Hyphenated UUID v4 (`550e8400-e29b-41d4-a716-446655440000`)
+    #[default]
+    Uuid,
+    /// 32 lowercase hex digits (`550e8400e29b41d4a716446655440000`)
+    Hex,
+}
+
+/// Listener socket options
+///
+/// The listen backlog and TCP_NODELAY are fixed by Pingora (65535 and
+/// always on for accepted sockets), so they are not configurable here.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TcpConfig {
+    /// Idle seconds before keepalive probes start (keepalive off when unset)
+    #[serde(default)]
+    pub keepalive_secs: Option<u64>,
+    /// Seconds between keepalive probes
+    #[serde(default = "default_keepalive_interval_secs")]
+    pub keepalive_interval_secs: u64,
+    /// Unanswered probes before the connection is dropped
+    #[serde(default = "default_keepalive_count")]
+    pub keepalive_count: usize,
+    /// TCP Fast Open pending-connection backlog (TFO off when unset)
+    #[serde(default)]
+    pub fastopen_backlog: Option<usize>,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            keepalive_secs: None,
+            keepalive_interval_secs: default_keepalive_interval_secs(),
+            keepalive_count: default_keepalive_count(),
+            fastopen_backlog: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -34,13 +417,266 @@ pub struct RedisConfig {
 pub struct JwtConfig {
     pub secret: String,
     pub access_token_expiration: i64,
+    /// Shorter access token lifetime for logins flagged as suspicious, so
+    /// step-up auth comes sooner (the normal lifetime when unset)
+    #[serde(default)]
+    pub suspicious_access_token_expiration: Option<i64>,
+    /// Flag a login as suspicious when the user already holds this many
+    /// unexpired refresh tokens (never flagged when unset)
+    #[serde(default)]
+    pub suspicious_session_count: Option<usize>,
     pub refresh_token_expiration: i64,
+    /// Longest a session may be kept alive by refreshing, in seconds from
+    /// login; past it the refresh token is refused and the user must log
+    /// in again (no cap when unset)
+    #[serde(default)]
+    pub max_session_lifetime: Option<i64>,
+    /// A login signs out the user's other sessions (refresh tokens revoked,
+    /// access tokens invalidated) and records an audit event; off, sessions
+    /// on several devices coexist
+    #[serde(default)]
+    pub single_session: bool,
+    /// Access tokens that expired at most this long ago (seconds) are
+    /// answered with a `token-expired` 401 telling the client to refresh,
+    /// instead of `invalid-token` (unset: every expired token is invalid)
+    #[serde(default)]
+    pub expired_token_grace: Option<i64>,
+    /// Add RFC3339 `expires_at` / `refresh_expires_at` to token responses
+    /// alongside the relative `expires_in` seconds
+    #[serde(default)]
+    pub absolute_expiry: bool,
+    /// Clock skew tolerated between issuer and proxy, in seconds; tokens
+    /// with an `iat` further ahead than this are rejected
+    #[serde(default = "default_jwt_leeway")]
+    pub leeway_seconds: u64,
+    /// Audiences each client id may request at login, e.g.
+    /// `app-a-web: ["app-a"]`; the token's `aud` is then the audience
+    #[serde(default)]
+    pub clients: BTreeMap<String, Vec<String>>,
+    /// External identity provider whose tokens are also accepted, checked
+    /// against its published JWKS
+    #[serde(default)]
+    pub idp: Option<IdpConfig>,
+    #[serde(default)]
+    pub refresh_cookie: RefreshCookieConfig,
+    /// Minimum strength of `secret`, checked at startup
+    #[serde(default)]
+    pub secret_strength: SecretStrengthConfig,
+}
+
+/// Access tokens signed by an external IdP (RS*/ES*/PS*/EdDSA with a `kid`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IdpConfig {
+    /// `https://` URL of the IdP's JWKS document
+    pub jwks_url: String,
+    /// Required `iss` claim (not checked when unset)
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// Required `aud` claim, so tokens the IdP issued for other services
+    /// are not accepted
+    pub audience: String,
+    /// Age at which the cached keys are fetched again, in seconds
+    #[serde(default = "default_jwks_refresh_interval")]
+    pub refresh_interval_secs: u64,
+    /// Least time between fetches triggered by unknown `kid`s, in seconds
+    #[serde(default = "default_jwks_min_refresh_interval")]
+    pub min_refresh_interval_secs: u64,
+    /// Longest wait for the JWKS, in milliseconds
+    #[serde(default = "default_jwks_timeout")]
+    pub timeout_ms: u64,
+    /// Most keys cached; the least recently used are evicted first
+    #[serde(default = "default_jwks_max_keys")]
+    pub max_keys: usize,
+    /// Key ids accepted from the JWKS; tokens naming any other are rejected
+    /// even if that key is published and verifies them (empty: any)
+    #[serde(default)]
+    pub allowed_kids: Vec<String>,
+}
+
+/// HS256 is only as strong as its secret: short or guessable ones let
+/// anyone who brute-forces them mint valid tokens
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SecretStrengthConfig {
+    /// Shortest accepted secret, in bytes
+    #[serde(default = "default_jwt_secret_min_length")]
+    pub min_length: usize,
+    /// What a weak secret does at startup
+    #[serde(default)]
+    pub on_weak: WeakSecretAction,
+}
+
+impl Default for SecretStrengthConfig {
+    fn default() -> Self {
+        Self {
+            min_length: default_jwt_secret_min_length(),
+            on_weak: WeakSecretAction::default(),
+        }
+    }
+}
+
+/// Response to a `jwt.secret` that fails the strength check
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WeakSecretAction {
+    /// Refuse to start
+    #[default]
+    Error,
+    /// Start, logging a warning
+    Warn,
+}
+
+/// Placeholder values that turn up as secrets, compared case-insensitively
+const WEAK_SECRETS: &[&str] = &[
+    "secret",
+    "changeme",
+    "change-me",
+    "change_me",
+    "password",
+    "jwt_secret",
+    "jwt-secret",
+    "your-secret-key",
+    "your_secret_key",
+    "supersecret",
+    "test",
+    "default",
+];
+
+/// Fewest distinct bytes a secret of `min_length` must use
+const MIN_DISTINCT_SECRET_BYTES: usize = 10;
+
+/// Why a JWT secret is too weak for HS256, if it is
+///
+/// Besides the length, rejects well-known placeholder values (also
+/// repeated, e.g. `secretsecret...`) and secrets made of very few
+/// distinct characters, which carry far less entropy than their length.
+///
+/// # Arguments
+/// * `secret` - `jwt.secret`
+/// * `min_length` - Shortest accepted secret, in bytes
+///
+/// # Returns
+/// * `Option<String>` - Reason the secret is weak, `None` if it passes
+pub fn jwt_secret_weakness(secret: &str, min_length: usize) -> Option<String> {
+    let lower = secret.to_ascii_lowercase();
+    let placeholder = WEAK_SECRETS
+        .iter()
+        .any(|weak| lower == weak.repeat(lower.len() / weak.len()));
+    if placeholder {
+        return Some("it is a well-known placeholder value".to_string());
+    }
+
+    if secret.len() < min_length {
+        return Some(format!(
+            "it is {} bytes, at least {} are required",
+            secret.len(),
+            min_length
+        ));
+    }
+
+    let mut distinct = secret.bytes().collect::<Vec<_>>();
+    distinct.sort_unstable();
+    distinct.dedup();
+    if distinct.len() < MIN_DISTINCT_SECRET_BYTES.min(min_length) {
+        return Some(format!(
+            "it uses only {} distinct characters",
+            distinct.len()
+        ));
+    }
+
+    None
+}
+
+/// Refresh token delivered as an HttpOnly cookie (for browser clients)
+///
+/// When enabled, login and register also set the cookie, and
+/// `/auth/refresh` accepts it in place of a body token, rotating it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RefreshCookieConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_refresh_cookie_name")]
+    pub name: String,
+    /// Cookie `Path`; the cookie is only sent to these endpoints
+    #[serde(default = "default_refresh_cookie_path")]
+    pub path: String,
+    /// Only send over HTTPS
+    #[serde(default = "default_true")]
+    pub secure: bool,
+}
+
+impl Default for RefreshCookieConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: default_refresh_cookie_name(),
+            path: default_refresh_cookie_path(),
+            secure: true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LoadBalancingConfig {
     pub strategy: String,
+    /// Strategies tried in order when `strategy` finds no healthy upstream
+    /// (`any` ignores ejection and is the usual last resort)
+    #[serde(default)]
+    pub fallback: Vec<String>,
     pub upstreams: Vec<UpstreamConfig>,
+    /// Verification for upstreams with `tls: true`, unless they set `tls_options`
+    #[serde(default)]
+    pub tls: UpstreamTlsConfig,
+    /// Connections opened to each healthy upstream at startup (0 skips warmup)
+    #[serde(default)]
+    pub warmup_connections: usize,
+    /// Upstream connect timeout, unless the upstream sets its own (none when unset)
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    /// Upstream response read timeout, unless the upstream sets its own (none when unset)
+    #[serde(default)]
+    pub read_timeout_secs: Option<u64>,
+    /// Trying another upstream after a failed attempt
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Take upstreams that keep failing to connect out of rotation (off when unset)
+    #[serde(default)]
+    pub passive_ejection: Option<PassiveEjectionConfig>,
+}
+
+/// Circuit breaking per upstream on connect failures
+///
+/// After `consecutive_failures` failed connects in a row an upstream is
+/// ejected for `cooldown_secs`, then tried again. With every upstream of
+/// a group ejected the group's breaker is open.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PassiveEjectionConfig {
+    #[serde(default = "default_ejection_failures")]
+    pub consecutive_failures: u32,
+    #[serde(default = "default_ejection_cooldown")]
+    pub cooldown_secs: u64,
+}
+
+/// Retries of failed upstream attempts, each against the next selected peer
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RetryConfig {
+    /// Attempts allowed after the first (0 disables retries)
+    #[serde(default)]
+    pub max_retries: u32,
+    /// Which failures are retried
+    #[serde(default)]
+    pub on: RetryOn,
+}
+
+/// Upstream failures that are retried
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryOn {
+    /// Only failures to connect, where no backend saw the request
+    #[default]
+    ConnectError,
+    /// Connect failures and 5xx responses; the backend that answered may
+    /// already have acted on the request
+    ConnectOr5xx,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -49,17 +685,326 @@ pub struct UpstreamConfig {
     pub address: String,
     pub port: u16,
     pub weight: u32,
+    /// Connect over TLS, with `address` as the SNI and verified hostname
+    #[serde(default)]
+    pub tls: bool,
+    /// Replaces `load_balancing.tls` for this upstream
+    #[serde(default)]
+    pub tls_options: Option<UpstreamTlsConfig>,
+    /// Replaces `load_balancing.connect_timeout_secs` for this upstream
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    /// Replaces `load_balancing.read_timeout_secs` for this upstream
+    #[serde(default)]
+    pub read_timeout_secs: Option<u64>,
+}
+
+/// Certificate verification for TLS upstreams
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct UpstreamTlsConfig {
+    /// PEM bundle of CAs to trust instead of the system roots (private CAs)
+    #[serde(default)]
+    pub ca_cert_path: Option<String>,
+    /// Accept any certificate, e.g. a dev self-signed one; warned at startup
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+    /// Pin the first certificate seen in Redis and refuse any other after it,
+    /// in place of CA verification (self-signed upstreams)
+    #[serde(default)]
+    pub trust_on_first_use: bool,
+    /// Oldest protocol version accepted from the upstream (library default when unset)
+    #[serde(default)]
+    pub min_tls_version: Option<TlsVersion>,
+    /// OpenSSL cipher names allowed (any when empty)
+    #[serde(default)]
+    pub ciphers: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MiddlewareConfig {
     pub auth: AuthConfig,
     pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub response_transform: ResponseTransformConfig,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuthConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Takes precedence over `enabled` when set
+    #[serde(default)]
+    pub mode: Option<AuthMode>,
+    /// Scheme used to present static tokens on `api_key` / `any` routes
+    #[serde(default)]
+    pub auth_type: StaticAuthType,
+    /// Static tokens accepted on `api_key` / `any` routes
+    /// (`user:password` pairs for `basic`)
+    #[serde(default)]
+    pub valid_tokens: Vec<String>,
+    /// Who serves `/auth/*`: the built-in handlers or an external service
+    #[serde(default)]
+    pub backend: AuthBackend,
+    /// Auth service group `/auth/*` is proxied to (`backend: upstream`)
+    #[serde(default)]
+    pub upstreams: Vec<UpstreamConfig>,
+    /// Let accounts register a username and log in with it instead of
+    /// their email (built-in backend); email only when off
+    #[serde(default)]
+    pub usernames: bool,
+}
+
+/// Where `/auth/*` requests are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthBackend {
+    /// Register / login / refresh / logout against the proxy's own database
+    #[default]
+    Builtin,
+    /// Proxied (rate limited, not authenticated) to `auth.upstreams`
+    Upstream,
+}
+
+/// How clients present a static token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StaticAuthType {
+    /// `X-API-Key: <token>`
+    #[default]
+    ApiKey,
+    /// `Authorization: Bearer <token>`
+    Bearer,
+    /// `Authorization: Basic base64(user:password)`
+    Basic,
+}
+
+impl StaticAuthType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StaticAuthType::ApiKey => "api_key",
+            StaticAuthType::Bearer => "bearer",
+            StaticAuthType::Basic => "basic",
+        }
+    }
+}
+
+/// How authentication failures on protected routes are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// Authentication is not checked
+    Off,
+    /// Failures are logged and metered, but the request still passes
+    Shadow,
+    /// Failures are rejected with 401
+    Enforce,
+}
+
+impl AuthConfig {
+    /// Effective auth mode (`enabled` alone maps to `enforce` / `off`)
+    pub fn mode(&self) -> AuthMode {
+        match self.mode {
+            Some(mode) => mode,
+            None if self.enabled => AuthMode::Enforce,
+            None => AuthMode::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RoutingConfig {
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+}
+
+/// Per-route settings, matched by the longest path prefix
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RouteConfig {
+    pub prefix: String,
+    /// Remove `prefix` from the path sent upstream
+    #[serde(default)]
+    pub strip_prefix: bool,
+    /// Regex replacement applied to the upstream path (after `strip_prefix`)
+    #[serde(default)]
+    pub rewrite: Option<RewriteRule>,
+    /// Credential required on this route (defaults to `jwt`); `false`
+    /// is the same as `none`
+    #[serde(default, deserialize_with = "deserialize_route_auth")]
+    pub auth: Option<RouteAuthType>,
+    /// Apply `middleware.rate_limit` on this route; `false` leaves e.g.
+    /// webhook receivers unthrottled
+    #[serde(default = "default_true")]
+    pub rate_limit: bool,
+    /// Copy a sample of idempotent requests to a shadow upstream
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+    /// Media types accepted in request bodies, e.g. `application/json` or
+    /// `text/*`; others get 415. Empty allows anything.
+    #[serde(default)]
+    pub allowed_content_types: Vec<String>,
+    /// Replay the response to a repeated `Idempotency-Key` POST
+    #[serde(default)]
+    pub idempotency: Option<IdempotencyConfig>,
+    /// Rewrite upstream redirects that point at the backend
+    #[serde(default)]
+    pub redirects: Option<RedirectConfig>,
+    /// Overrides of `response.default_headers` on this route; an empty
+    /// value drops the header
+    #[serde(default)]
+    pub response_headers: BTreeMap<String, String>,
+    /// Response served instead of a 503 while the breaker is open
+    #[serde(default)]
+    pub fallback: Option<FallbackConfig>,
+    /// Send identical concurrent GETs upstream once and share the response
+    #[serde(default)]
+    pub coalesce: Option<CoalesceConfig>,
+    /// `aud` a JWT must carry on this route (tokens of other apps and
+    /// unscoped ones are rejected); API keys are not checked
+    #[serde(default)]
+    pub audience: Option<String>,
+}
+
+/// Single-flight fetching of identical concurrent GETs
+///
+/// While one GET is in flight upstream, identical ones wait for it and
+/// get its response. Requests are identical when they have the same path
+/// and query, user and `Accept` / `Accept-Encoding` headers. Only
+/// responses fit to share (below 500, no cookies, not `private` /
+/// `no-store`) are shared; otherwise each waiting request is forwarded.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CoalesceConfig {
+    /// How long an identical request waits before going upstream itself
+    #[serde(default = "default_coalesce_wait")]
+    pub wait_ms: u64,
+}
+
+/// Stand-in response for a route whose upstreams are all ejected
+///
+/// With `last_good`, the last 2xx GET response proxied on the route is
+/// served; the static `status` / `body` cover the time before there is one.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FallbackConfig {
+    #[serde(default)]
+    pub last_good: bool,
+    #[serde(default = "default_fallback_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default = "default_fallback_content_type")]
+    pub content_type: String,
+}
+
+/// Rewriting of `Location` headers on upstream 3xx responses
+///
+/// A `Location` naming the upstream that served the request (its address
+/// or TLS name), or one of `internal_hosts`, is rewritten to the public
+/// host the client used, with the route prefix restored if it was
+/// stripped. Redirects to other hosts pass through unchanged.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RedirectConfig {
+    #[serde(default = "default_true")]
+    pub rewrite_location: bool,
+    /// Other names the backend uses for itself, as `host` or `host:port`
+    #[serde(default)]
+    pub internal_hosts: Vec<String>,
+}
+
+/// De-duplication of POSTs carrying an `Idempotency-Key` header
+///
+/// The first request with a key is forwarded; repeats of the same key and
+/// path within `ttl_seconds` get its response back without reaching the
+/// upstream. Needs Redis.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IdempotencyConfig {
+    /// How long a response is replayed for
+    #[serde(default = "default_idempotency_ttl")]
+    pub ttl_seconds: u64,
+    /// How long a duplicate waits for the first request to finish (409 after)
+    #[serde(default = "default_idempotency_lock")]
+    pub lock_seconds: u64,
+}
+
+/// Traffic mirroring to a shadow upstream
+///
+/// The shadow's responses are discarded; clients always get the primary's.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MirrorConfig {
+    /// Shadow upstream as `host:port`
+    pub address: String,
+    /// Percentage of GET / HEAD / OPTIONS requests mirrored (0-100)
+    pub percent: f64,
+}
+
+/// Credential a route requires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteAuthType {
+    /// Public route
+    None,
+    /// User access token (`Authorization: Bearer`)
+    Jwt,
+    /// Static machine key (`X-API-Key`)
+    ApiKey,
+    /// Either an API key or a JWT
+    Any,
+}
+
+/// `auth: false` / `auth: true` as shorthands for `none` / `jwt`
+fn deserialize_route_auth<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<RouteAuthType>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Flag(bool),
+        Type(RouteAuthType),
+    }
+
+    Ok(
+        Option::<Raw>::deserialize(deserializer)?.map(|raw| match raw {
+            Raw::Flag(false) => RouteAuthType::None,
+            Raw::Flag(true) => RouteAuthType::Jwt,
+            Raw::Type(auth_type) => auth_type,
+        }),
+    )
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RewriteRule {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+/// Field injection into small JSON upstream responses
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResponseTransformConfig {
+    #[serde(default)]
     pub enabled: bool,
+    /// Route prefixes whose responses are transformed (all routes when empty)
+    #[serde(default)]
+    pub routes: Vec<String>,
+    /// Response media types eligible for transformation
+    #[serde(default = "default_transform_content_types")]
+    pub content_types: Vec<String>,
+    /// Bodies larger than this many bytes pass through untouched
+    #[serde(default = "default_max_transform_size")]
+    pub max_size: usize,
+    /// Top-level fields set on the JSON object; `{request_id}` in a value is
+    /// replaced with the request ID
+    #[serde(default)]
+    pub inject: BTreeMap<String, String>,
+}
+
+impl Default for ResponseTransformConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            routes: Vec::new(),
+            content_types: default_transform_content_types(),
+            max_size: default_max_transform_size(),
+            inject: BTreeMap::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -67,6 +1012,316 @@ pub struct RateLimitConfig {
     pub enabled: bool,
     pub requests_per_minute: u32,
     pub burst_size: u32,
+    /// Extra request attributes that get their own bucket per client
+    #[serde(default)]
+    pub key_dimensions: Vec<RateLimitDimension>,
+    /// Where buckets are kept
+    #[serde(default)]
+    pub backend: RateLimitBackend,
+    /// Percentage of `burst_size` used up at which allowed requests get an
+    /// `X-RateLimit-Warning` header (no warning when unset)
+    #[serde(default)]
+    pub warn_threshold_percent: Option<u8>,
+    /// Replaces the limits above for clients with a verified user
+    #[serde(default)]
+    pub authenticated: Option<ClientRateLimit>,
+    /// Replaces the limits above for clients keyed by IP or request
+    #[serde(default)]
+    pub anonymous: Option<ClientRateLimit>,
+    /// Rate-limit service asked instead of local buckets (`backend: external`)
+    #[serde(default)]
+    pub external: Option<ExternalRateLimitConfig>,
+}
+
+/// Service implementing the Envoy ratelimit JSON API (`POST /json`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExternalRateLimitConfig {
+    /// `host:port` of the service
+    pub address: String,
+    #[serde(default = "default_external_rate_limit_path")]
+    pub path: String,
+    /// Rate-limit domain the service's rules are configured under
+    #[serde(default = "default_external_rate_limit_domain")]
+    pub domain: String,
+    /// Longest wait for a decision, in milliseconds
+    #[serde(default = "default_external_rate_limit_timeout")]
+    pub timeout_ms: u64,
+    /// Let requests through when the service fails or times out
+    #[serde(default = "default_true")]
+    pub fail_open: bool,
+}
+
+/// Token bucket size and refill rate for one class of client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClientRateLimit {
+    pub requests_per_minute: u32,
+    pub burst_size: u32,
+}
+
+impl RateLimitConfig {
+    /// Limits for an authenticated or anonymous client
+    pub fn limit_for(&self, authenticated: bool) -> ClientRateLimit {
+        let specific = if authenticated {
+            self.authenticated
+        } else {
+            self.anonymous
+        };
+
+        specific.unwrap_or(ClientRateLimit {
+            requests_per_minute: self.requests_per_minute,
+            burst_size: self.burst_size,
+        })
+    }
+}
+
+/// Token bucket storage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitBackend {
+    /// Shared across proxy instances
+    #[default]
+    Redis,
+    /// Per instance, no Redis round trip
+    Memory,
+    /// Decided by `external`, a dedicated rate-limit service
+    External,
+}
+
+/// Request attribute added to the rate-limit key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitDimension {
+    /// HTTP method (`GET`, `POST`, ...)
+    Method,
+    /// Matched route prefix (`default` for unrouted paths)
+    Route,
+}
+
+fn default_request_id_header() -> String {
+    "X-Request-ID".to_string()
+}
+
+fn default_max_uri_length() -> usize {
+    8192
+}
+
+fn default_max_headers() -> usize {
+    100
+}
+
+fn default_max_header_value_len() -> usize {
+    8192
+}
+
+fn default_max_body_size() -> usize {
+    1024 * 1024
+}
+
+fn default_max_upload_size() -> usize {
+    50 * 1024 * 1024
+}
+
+fn default_transform_content_types() -> Vec<String> {
+    vec!["application/json".to_string()]
+}
+
+fn default_compression_exclude_types() -> Vec<String> {
+    [
+        "image/jpeg",
+        "image/png",
+        "image/gif",
+        "image/webp",
+        "image/avif",
+        "video/*",
+        "audio/*",
+        "font/woff",
+        "font/woff2",
+        "application/gzip",
+        "application/zip",
+        "application/zstd",
+        "application/x-bzip2",
+        "application/x-xz",
+        "application/x-7z-compressed",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+fn default_max_transform_size() -> usize {
+    64 * 1024
+}
+
+fn default_keepalive_interval_secs() -> u64 {
+    10
+}
+
+fn default_keepalive_count() -> usize {
+    3
+}
+
+fn validate_upstreams(upstreams: &[UpstreamConfig]) -> Result<(), String> {
+    for upstream in upstreams {
+        if upstream.name.is_empty() {
+            return Err("Upstream name cannot be empty".to_string());
+        }
+        if upstream.address.is_empty() {
+            return Err(format!(
+                "Upstream {} address cannot be empty",
+                upstream.name
+            ));
+        }
+        if upstream.port == 0 {
+            return Err(format!("Upstream {} port cannot be 0", upstream.name));
+        }
+        if let Some(tls) = &upstream.tls_options {
+            validate_upstream_tls(tls)
+                .map_err(|e| format!("Upstream {} tls_options: {}", upstream.name, e))?;
+        }
+        validate_timeouts(upstream.connect_timeout_secs, upstream.read_timeout_secs)
+            .map_err(|e| format!("Upstream {} {}", upstream.name, e))?;
+    }
+    Ok(())
+}
+
+fn validate_timeouts(connect: Option<u64>, read: Option<u64>) -> Result<(), String> {
+    if connect == Some(0) {
+        return Err("connect_timeout_secs must be positive".to_string());
+    }
+    if read == Some(0) {
+        return Err("read_timeout_secs must be positive".to_string());
+    }
+    Ok(())
+}
+
+fn validate_upstream_tls(tls: &UpstreamTlsConfig) -> Result<(), String> {
+    if tls.ca_cert_path.as_deref() == Some("") {
+        return Err("ca_cert_path cannot be empty".to_string());
+    }
+    if tls.ca_cert_path.is_some() && tls.insecure_skip_verify {
+        return Err("ca_cert_path has no effect with insecure_skip_verify".to_string());
+    }
+    if tls.trust_on_first_use && tls.insecure_skip_verify {
+        return Err("trust_on_first_use cannot be combined with insecure_skip_verify".to_string());
+    }
+    if tls.trust_on_first_use && tls.ca_cert_path.is_some() {
+        return Err("ca_cert_path has no effect with trust_on_first_use".to_string());
+    }
+    validate_ciphers(&tls.ciphers)
+}
+
+/// Cipher names must be single OpenSSL names, not a `:`-joined list
+fn validate_ciphers(ciphers: &[String]) -> Result<(), String> {
+    match ciphers
+        .iter()
+        .find(|c| c.is_empty() || c.contains(':') || c.contains(char::is_whitespace))
+    {
+        Some(cipher) => Err(format!("invalid cipher name {:?}", cipher)),
+        None => Ok(()),
+    }
+}
+
+/// Check that configured headers are valid HTTP header names and values
+fn validate_headers(section: &str, headers: &BTreeMap<String, String>) -> Result<(), String> {
+    for (name, value) in headers {
+        if http::HeaderName::from_bytes(name.as_bytes()).is_err() {
+            return Err(format!(
+                "{}: {:?} is not a valid header name",
+                section, name
+            ));
+        }
+        if http::HeaderValue::from_str(value).is_err() {
+            return Err(format!(
+                "{}: value of {} is not a valid header value",
+                section, name
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn default_min_tls_version() -> TlsVersion {
+    TlsVersion::Tls12
+}
+
+fn default_ejection_failures() -> u32 {
+    5
+}
+
+fn default_ejection_cooldown() -> u64 {
+    30
+}
+
+fn default_fallback_status() -> u16 {
+    503
+}
+
+fn default_fallback_content_type() -> String {
+    "application/json".to_string()
+}
+
+fn default_coalesce_wait() -> u64 {
+    5000
+}
+
+fn default_idempotency_ttl() -> u64 {
+    300
+}
+
+fn default_idempotency_lock() -> u64 {
+    30
+}
+
+fn default_listen_address() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_jwt_leeway() -> u64 {
+    60
+}
+
+fn default_external_rate_limit_path() -> String {
+    "/json".to_string()
+}
+
+fn default_external_rate_limit_domain() -> String {
+    "pingora_proxy".to_string()
+}
+
+fn default_external_rate_limit_timeout() -> u64 {
+    100
+}
+
+fn default_jwks_refresh_interval() -> u64 {
+    300
+}
+
+fn default_jwks_min_refresh_interval() -> u64 {
+    30
+}
+
+fn default_jwks_timeout() -> u64 {
+    2000
+}
+
+fn default_jwks_max_keys() -> usize {
+    64
+}
+
+fn default_jwt_secret_min_length() -> usize {
+    32
+}
+
+fn default_refresh_cookie_name() -> String {
+    "refresh_token".to_string()
+}
+
+fn default_refresh_cookie_path() -> String {
+    "/auth".to_string()
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Settings {
@@ -114,9 +1369,176 @@ impl Settings {
     /// Returns Result with String error (not implementing std::error::Error)
     pub fn validate(&self) -> Result<(), String> {
         // Validate server config
+        let listen_ip = self.server.listen_ip()?;
+        if self.server.ipv6_only && !listen_ip.is_ipv6() {
+            return Err(format!(
+                "Server ipv6_only requires an IPv6 listen_address, got {}",
+                listen_ip
+            ));
+        }
         if self.server.listen_port == 0 {
             return Err("Server listen_port cannot be 0".to_string());
         }
+        if let Some(tls) = &self.server.tls {
+            if tls.cert_path.is_empty() || tls.key_path.is_empty() {
+                return Err("Server tls cert_path and key_path cannot be empty".to_string());
+            }
+            validate_ciphers(&tls.ciphers).map_err(|e| format!("Server tls: {}", e))?;
+        }
+        if self.server.metrics_port == Some(0) {
+            return Err("Server metrics_port cannot be 0".to_string());
+        }
+        if self.server.metrics_port == Some(self.server.listen_port) {
+            return Err("Server metrics_port must differ from listen_port".to_string());
+        }
+        if let Some(admin) = &self.server.admin_listener {
+            if admin.port == 0 {
+                return Err("Server admin_listener.port cannot be 0".to_string());
+            }
+            if admin.port == self.server.listen_port || Some(admin.port) == self.server.metrics_port
+            {
+                return Err(
+                    "Server admin_listener.port must differ from listen_port and metrics_port"
+                        .to_string(),
+                );
+            }
+        }
+        if self.server.max_uri_length == 0 {
+            return Err("Server max_uri_length must be positive".to_string());
+        }
+        if self.server.max_headers == 0 {
+            return Err("Server max_headers must be positive".to_string());
+        }
+        if self.server.max_header_value_len == 0 {
+            return Err("Server max_header_value_len must be positive".to_string());
+        }
+        if self.server.max_body_size == 0 {
+            return Err("Server max_body_size must be positive".to_string());
+        }
+        if self.server.max_upload_size == 0 {
+            return Err("Server max_upload_size must be positive".to_string());
+        }
+        if self.server.max_requests_per_connection == Some(0) {
+            return Err("Server max_requests_per_connection must be positive".to_string());
+        }
+        if self.server.header_read_timeout_secs == Some(0) {
+            return Err("Server header_read_timeout_secs must be positive".to_string());
+        }
+
+        if http::HeaderName::from_bytes(self.request_id.header.as_bytes()).is_err() {
+            return Err(format!(
+                "Request ID header {:?} is not a valid header name",
+                self.request_id.header
+            ));
+        }
+
+        validate_headers("response.default_headers", &self.response.default_headers)?;
+        if let Some(coding) = self.response.encoding.codings.iter().find(|coding| {
+            coding.is_empty()
+                || coding.contains([',', ';', ' '])
+                || coding.eq_ignore_ascii_case("identity")
+                || *coding == "*"
+        }) {
+            return Err(format!(
+                "response.encoding.codings has an invalid coding {:?}",
+                coding
+            ));
+        }
+        if self.response.encoding.compression_level > 9 {
+            return Err("response.encoding.compression_level must be 0-9".to_string());
+        }
+
+        let upstream_headers = &self.response.upstream_headers;
+        for name in upstream_headers
+            .deny
+            .iter()
+            .chain(upstream_headers.allow.iter().flatten())
+        {
+            if http::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                return Err(format!(
+                    "response.upstream_headers: {:?} is not a valid header name",
+                    name
+                ));
+            }
+        }
+        if let Some(name) = upstream_headers.deny.iter().find(|name| {
+            crate::proxy::header_filter::FRAMING_HEADERS
+                .contains(&name.to_ascii_lowercase().as_str())
+        }) {
+            return Err(format!(
+                "response.upstream_headers: {} is needed to relay the body and cannot be denied",
+                name
+            ));
+        }
+
+        if self
+            .middleware
+            .rate_limit
+            .warn_threshold_percent
+            .is_some_and(|percent| percent == 0 || percent >= 100)
+        {
+            return Err("Rate limit warn_threshold_percent must be between 1 and 99".to_string());
+        }
+        let rate_limit = &self.middleware.rate_limit;
+        if rate_limit.backend == RateLimitBackend::External {
+            match &rate_limit.external {
+                None => {
+                    return Err("Rate limit backend external needs rate_limit.external".to_string());
+                }
+                Some(external) if external.address.is_empty() || external.timeout_ms == 0 => {
+                    return Err(
+                        "Rate limit external address must be set and timeout_ms positive"
+                            .to_string(),
+                    );
+                }
+                Some(external) if !external.path.starts_with('/') => {
+                    return Err("Rate limit external path must start with /".to_string());
+                }
+                Some(external)
+                    if external
+                        .address
+                        .rsplit_once(':')
+                        .and_then(|(_, port)| port.parse::<u16>().ok())
+                        .is_none() =>
+                {
+                    return Err(format!(
+                        "Rate limit external address {} must be host:port",
+                        external.address
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+        for (class, limit) in [
+            ("authenticated", self.middleware.rate_limit.authenticated),
+            ("anonymous", self.middleware.rate_limit.anonymous),
+        ] {
+            if limit.is_some_and(|limit| limit.requests_per_minute == 0 || limit.burst_size == 0) {
+                return Err(format!(
+                    "Rate limit {} requests_per_minute and burst_size must be positive",
+                    class
+                ));
+            }
+        }
+
+        // Validate listener TCP options (Linux socket option limits)
+        let tcp = &self.server.tcp;
+        if let Some(keepalive_secs) = tcp.keepalive_secs {
+            if !(1..=32767).contains(&keepalive_secs) {
+                return Err("Server tcp.keepalive_secs must be between 1 and 32767".to_string());
+            }
+            if !(1..=32767).contains(&tcp.keepalive_interval_secs) {
+                return Err(
+                    "Server tcp.keepalive_interval_secs must be between 1 and 32767".to_string(),
+                );
+            }
+            if !(1..=127).contains(&tcp.keepalive_count) {
+                return Err("Server tcp.keepalive_count must be between 1 and 127".to_string());
+            }
+        }
+        if tcp.fastopen_backlog == Some(0) {
+            return Err("Server tcp.fastopen_backlog must be positive".to_string());
+        }
 
         // Validate database config
         if self.database.url.is_empty() {
@@ -135,33 +1557,414 @@ impl Settings {
         if self.jwt.secret.is_empty() {
             return Err("JWT secret cannot be empty".to_string());
         }
+        let strength = &self.jwt.secret_strength;
+        if let Some(weakness) = jwt_secret_weakness(&self.jwt.secret, strength.min_length) {
+            match strength.on_weak {
+                WeakSecretAction::Error => {
+                    return Err(format!(
+                        "JWT secret is too weak: {} (set jwt.secret_strength.on_weak: warn to allow)",
+                        weakness
+                    ));
+                }
+                WeakSecretAction::Warn => log::warn!("JWT secret is weak: {}", weakness),
+            }
+        }
         if self.jwt.access_token_expiration <= 0 {
             return Err("JWT access_token_expiration must be positive".to_string());
         }
+        if let Some(suspicious) = self.jwt.suspicious_access_token_expiration {
+            if suspicious <= 0 || suspicious > self.jwt.access_token_expiration {
+                return Err(
+                    "JWT suspicious_access_token_expiration must be positive and at most access_token_expiration"
+                        .to_string(),
+                );
+            }
+        }
+        if self.jwt.suspicious_session_count == Some(0) {
+            return Err("JWT suspicious_session_count must be at least 1".to_string());
+        }
         if self.jwt.refresh_token_expiration <= 0 {
             return Err("JWT refresh_token_expiration must be positive".to_string());
         }
+        if self
+            .jwt
+            .max_session_lifetime
+            .is_some_and(|lifetime| lifetime <= 0)
+        {
+            return Err("JWT max_session_lifetime must be positive".to_string());
+        }
+        if self.jwt.expired_token_grace.is_some_and(|grace| grace <= 0) {
+            return Err("JWT expired_token_grace must be positive".to_string());
+        }
+        if let Some((client_id, _)) =
+            self.jwt.clients.iter().find(|(_, audiences)| {
+                audiences.is_empty() || audiences.iter().any(String::is_empty)
+            })
+        {
+            return Err(format!(
+                "JWT client {} must list at least one non-empty audience",
+                client_id
+            ));
+        }
+        if let Some(idp) = &self.jwt.idp {
+            if !idp.jwks_url.starts_with("https://") {
+                return Err("JWT idp jwks_url must be an https:// URL".to_string());
+            }
+            if idp.audience.is_empty() {
+                return Err("JWT idp audience must be set".to_string());
+            }
+            if idp.refresh_interval_secs == 0 || idp.timeout_ms == 0 || idp.max_keys == 0 {
+                return Err(
+                    "JWT idp refresh_interval_secs, timeout_ms and max_keys must be positive"
+                        .to_string(),
+                );
+            }
+        }
+        let cookie = &self.jwt.refresh_cookie;
+        if cookie.enabled {
+            let valid_name = !cookie.name.is_empty()
+                && cookie
+                    .name
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.'));
+            if !valid_name {
+                return Err(format!(
+                    "JWT refresh_cookie name {:?} is invalid",
+                    cookie.name
+                ));
+            }
+            if !cookie.path.starts_with('/') || cookie.path.contains(';') {
+                return Err("JWT refresh_cookie path must start with '/'".to_string());
+            }
+        }
+
+        // Validate password peppers
+        let peppers = [&self.password.pepper, &self.password.previous_pepper];
+        for pepper in peppers.into_iter().flatten().filter(|p| !p.is_empty()) {
+            if pepper.len() < 16 {
+                return Err("Password pepper must be at least 16 bytes".to_string());
+            }
+        }
+
+        // Validate encryption keys
+        let encryption = &self.encryption;
+        if !encryption.keys.is_empty() {
+            for (i, key) in encryption.keys.iter().enumerate() {
+                if key.key.is_some() == key.key_file.is_some() {
+                    return Err(format!(
+                        "Encryption key version {} needs exactly one of key or key_file",
+                        key.version
+                    ));
+                }
+                if encryption.keys[..i]
+                    .iter()
+                    .any(|k| k.version == key.version)
+                {
+                    return Err(format!(
+                        "Encryption key version {} is listed twice",
+                        key.version
+                    ));
+                }
+            }
+            if !encryption
+                .keys
+                .iter()
+                .any(|k| k.version == encryption.current_version)
+            {
+                return Err(format!(
+                    "Encryption current_version {} has no key",
+                    encryption.current_version
+                ));
+            }
+        }
+
+        // Validate bootstrap admin
+        let admin_email = self.admin.email.as_deref().unwrap_or_default();
+        let admin_password = self.admin.password.as_deref().unwrap_or_default();
+        if admin_email.is_empty() != admin_password.is_empty() {
+            return Err("Admin email and password must be set together".to_string());
+        }
+
+        // Validate load balancing strategies
+        if ![
+            "round_robin",
+            "weighted_round_robin",
+            "random",
+            "p2c",
+            "least_conn",
+        ]
+        .contains(&self.load_balancing.strategy.as_str())
+        {
+            return Err(format!(
+                "Unknown load balancing strategy: {}",
+                self.load_balancing.strategy
+            ));
+        }
+        for strategy in &self.load_balancing.fallback {
+            if ![
+                "round_robin",
+                "weighted_round_robin",
+                "random",
+                "p2c",
+                "least_conn",
+                "any",
+            ]
+            .contains(&strategy.as_str())
+            {
+                return Err(format!("Unknown fallback strategy: {}", strategy));
+            }
+        }
 
         // Validate upstreams
         if self.load_balancing.upstreams.is_empty() {
             return Err("At least one upstream must be configured".to_string());
         }
+        validate_upstreams(&self.load_balancing.upstreams)?;
+        validate_upstream_tls(&self.load_balancing.tls)
+            .map_err(|e| format!("load_balancing.tls: {}", e))?;
+        validate_timeouts(
+            self.load_balancing.connect_timeout_secs,
+            self.load_balancing.read_timeout_secs,
+        )
+        .map_err(|e| format!("load_balancing {}", e))?;
+        if let Some(ejection) = &self.load_balancing.passive_ejection {
+            if ejection.consecutive_failures == 0 || ejection.cooldown_secs == 0 {
+                return Err(
+                    "passive_ejection consecutive_failures and cooldown_secs must be > 0"
+                        .to_string(),
+                );
+            }
+        }
+
+        // Validate the auth service group
+        let auth = &self.middleware.auth;
+        if auth.backend == AuthBackend::Upstream && auth.upstreams.is_empty() {
+            return Err("Auth backend upstream requires at least one auth upstream".to_string());
+        }
+        validate_upstreams(&auth.upstreams)?;
 
-        for upstream in &self.load_balancing.upstreams {
-            if upstream.name.is_empty() {
-                return Err("Upstream name cannot be empty".to_string());
+        // Validate response transform
+        let transform = &self.middleware.response_transform;
+        if transform.enabled {
+            if transform.max_size == 0 {
+                return Err("Response transform max_size must be positive".to_string());
+            }
+            if transform.inject.is_empty() {
+                return Err("Response transform is enabled but inject is empty".to_string());
             }
-            if upstream.address.is_empty() {
+            if let Some(route) = transform.routes.iter().find(|r| !r.starts_with('/')) {
                 return Err(format!(
-                    "Upstream {} address cannot be empty",
-                    upstream.name
+                    "Response transform route {} must start with '/'",
+                    route
                 ));
             }
-            if upstream.port == 0 {
-                return Err(format!("Upstream {} port cannot be 0", upstream.name));
+        }
+
+        // Validate routes
+        for route in &self.routing.routes {
+            if !route.prefix.starts_with('/') {
+                return Err(format!("Route prefix {} must start with '/'", route.prefix));
+            }
+            if matches!(route.auth, Some(RouteAuthType::ApiKey))
+                && self.middleware.auth.valid_tokens.is_empty()
+            {
+                return Err(format!(
+                    "Route {} requires a static token but no valid_tokens are configured",
+                    route.prefix
+                ));
+            }
+            if let Some(mirror) = &route.mirror {
+                let port = mirror.address.rsplit_once(':').map(|(_, port)| port);
+                if port
+                    .and_then(|p| p.parse::<u16>().ok())
+                    .is_none_or(|p| p == 0)
+                {
+                    return Err(format!(
+                        "Route {} mirror address {} must be host:port",
+                        route.prefix, mirror.address
+                    ));
+                }
+                if !(0.0..=100.0).contains(&mirror.percent) {
+                    return Err(format!(
+                        "Route {} mirror percent must be between 0 and 100",
+                        route.prefix
+                    ));
+                }
+            }
+            if let Some(content_type) = route
+                .allowed_content_types
+                .iter()
+                .find(|ct| !ct.contains('/') || ct.contains(';'))
+            {
+                return Err(format!(
+                    "Route {} allowed content type {} must be a type/subtype without parameters",
+                    route.prefix, content_type
+                ));
+            }
+            if let Some(idempotency) = &route.idempotency {
+                if idempotency.ttl_seconds == 0 || idempotency.lock_seconds == 0 {
+                    return Err(format!(
+                        "Route {} idempotency ttl_seconds and lock_seconds must be > 0",
+                        route.prefix
+                    ));
+                }
+            }
+            if let Some(host) = route
+                .redirects
+                .iter()
+                .flat_map(|r| &r.internal_hosts)
+                .find(|host| host.is_empty() || host.contains("://") || host.contains('/'))
+            {
+                return Err(format!(
+                    "Route {} internal host {:?} must be host or host:port",
+                    route.prefix, host
+                ));
+            }
+            validate_headers(
+                &format!("Route {} response_headers", route.prefix),
+                &route.response_headers,
+            )?;
+            if let Some(fallback) = &route.fallback {
+                if !(200..=599).contains(&fallback.status) {
+                    return Err(format!(
+                        "Route {} fallback status {} is not a valid HTTP status",
+                        route.prefix, fallback.status
+                    ));
+                }
+                if http::HeaderValue::from_str(&fallback.content_type).is_err() {
+                    return Err(format!(
+                        "Route {} fallback content_type is not a valid header value",
+                        route.prefix
+                    ));
+                }
+            }
+            if route.coalesce.as_ref().is_some_and(|c| c.wait_ms == 0) {
+                return Err(format!(
+                    "Route {} coalesce wait_ms must be positive",
+                    route.prefix
+                ));
+            }
+            if let Some(rule) = &route.rewrite {
+                if let Err(e) = regex::Regex::new(&rule.pattern) {
+                    return Err(format!(
+                        "Route {} has invalid rewrite pattern: {}",
+                        route.prefix, e
+                    ));
+                }
             }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tls_version_parsing() {
+        let parse = |yaml: &str| serde_yaml::from_str::<UpstreamTlsConfig>(yaml);
+
+        // Quoted, unquoted and OpenSSL spellings
+        for (yaml, version) in [
+            ("min_tls_version: \"1.2\"", TlsVersion::Tls12),
+            ("min_tls_version: 1.3", TlsVersion::Tls13),
+            ("min_tls_version: TLSv1.2", TlsVersion::Tls12),
+        ] {
+            assert_eq!(parse(yaml).unwrap().min_tls_version, Some(version));
+        }
+        assert_eq!(parse("{}").unwrap().min_tls_version, None);
+
+        // Anything older (or unknown) fails startup
+        for yaml in [
+            "min_tls_version: 1.1",
+            "min_tls_version: \"1.0\"",
+            "min_tls_version: ssl3",
+        ] {
+            let error = parse(yaml).unwrap_err().to_string();
+            assert!(error.contains("unsupported TLS version"), "{}", error);
+        }
+
+        assert!(TlsVersion::Tls12 < TlsVersion::Tls13);
+    }
+
+    #[test]
+    fn test_listener_tls_defaults_to_1_2() {
+        let tls: ListenerTlsConfig =
+            serde_yaml::from_str("cert_path: /etc/proxy/cert.pem\nkey_path: /etc/proxy/key.pem")
+                .unwrap();
+
+        assert_eq!(tls.min_tls_version, TlsVersion::Tls12);
+        assert!(tls.ciphers.is_empty());
+    }
+
+    #[test]
+    fn test_jwt_secret_strength() {
+        let strong = "k7Q2vX9pL4mZ8rT1wY6bN3cH5jF0dS2a";
+        assert_eq!(jwt_secret_weakness(strong, 32), None);
+
+        // Too short, a placeholder (also repeated to length), too few characters
+        assert!(jwt_secret_weakness("k7Q2vX9pL4mZ8rT1", 32)
+            .unwrap()
+            .contains("at least 32"));
+        for weak in ["secret", "ChangeMe", &"secret".repeat(6)] {
+            assert!(jwt_secret_weakness(weak, 32)
+                .unwrap()
+                .contains("placeholder"));
+        }
+        assert!(jwt_secret_weakness(&"ab".repeat(20), 32)
+            .unwrap()
+            .contains("distinct"));
+
+        // A lower minimum lets a shorter secret through
+        assert_eq!(jwt_secret_weakness("k7Q2vX9pL4mZ8rT1", 16), None);
+    }
+
+    #[test]
+    fn test_weak_secret_action_parsing() {
+        let strength: SecretStrengthConfig = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(strength.min_length, 32);
+        assert_eq!(strength.on_weak, WeakSecretAction::Error);
+
+        let strength: SecretStrengthConfig =
+            serde_yaml::from_str("min_length: 48\non_weak: warn").unwrap();
+        assert_eq!(strength.min_length, 48);
+        assert_eq!(strength.on_weak, WeakSecretAction::Warn);
+    }
+
+    #[test]
+    fn test_idp_audience_is_required() {
+        let parse = |yaml: &str| serde_yaml::from_str::<IdpConfig>(yaml);
+
+        let idp = parse("jwks_url: https://idp.example.com/jwks\naudience: proxy").unwrap();
+        assert_eq!(idp.audience, "proxy");
+        assert!(parse("jwks_url: https://idp.example.com/jwks").is_err());
+    }
+
+    #[test]
+    fn test_cipher_names_are_validated() {
+        assert!(validate_ciphers(&["ECDHE-RSA-AES128-GCM-SHA256".to_string()]).is_ok());
+        assert!(validate_ciphers(&["AES128:AES256".to_string()]).is_err());
+        assert!(validate_ciphers(&[String::new()]).is_err());
+    }
+
+    #[test]
+    fn test_route_overrides_parsing() {
+        let parse = |yaml: &str| serde_yaml::from_str::<RouteConfig>(yaml).unwrap();
+
+        let route = parse("prefix: /webhooks\nauth: false\nrate_limit: false");
+        assert_eq!(route.auth, Some(RouteAuthType::None));
+        assert!(!route.rate_limit);
+
+        // Names still work; both middlewares apply unless turned off
+        let route = parse("prefix: /internal\nauth: api_key");
+        assert_eq!(route.auth, Some(RouteAuthType::ApiKey));
+        assert!(route.rate_limit);
+        assert_eq!(
+            parse("prefix: /api\nauth: true").auth,
+            Some(RouteAuthType::Jwt)
+        );
+        assert_eq!(parse("prefix: /api").auth, None);
+    }
+}
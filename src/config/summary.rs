@@ -0,0 +1,136 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::cache::RedisClient;
+use crate::config::settings::{AuthBackend, AuthMode, Settings};
+use crate::db::DbPool;
+
+/// Hex characters kept from the config hash
+const FINGERPRINT_LEN: usize = 16;
+
+/// Hash of the loaded configuration
+///
+/// Instances running identical config (after `${VAR}` expansion) report
+/// the same fingerprint, so a fleet can be checked for drift. Secrets go
+/// into the hash but only a truncated digest comes out.
+///
+/// # Returns
+/// * `String` - First 16 hex characters of the SHA-256 of the config
+pub fn fingerprint(settings: &Settings) -> String {
+    // Settings only holds ordered maps, so the JSON form is deterministic
+    let json = serde_json::to_vec(settings).expect("settings serialize to JSON");
+
+    Sha256::digest(&json)
+        .iter()
+        .take(FINGERPRINT_LEN / 2)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// One-line overview of what the proxy is about to run with
+///
+/// Logged as JSON after the configuration validates; credentials in the
+/// database and Redis URLs are masked.
+#[derive(Debug, Serialize)]
+pub struct StartupSummary {
+    /// Listener name (`proxy`, `admin`, `metrics`) to bind address
+    pub listeners: BTreeMap<&'static str, String>,
+    pub tls: bool,
+    pub upstreams: usize,
+    pub auth_upstreams: usize,
+    pub middlewares: Vec<&'static str>,
+    pub auth_mode: AuthMode,
+    pub auth_backend: AuthBackend,
+    pub routes: usize,
+    pub database_url: String,
+    pub redis_url: String,
+    pub config_fingerprint: String,
+}
+
+impl StartupSummary {
+    /// Summarize validated settings
+    pub fn new(settings: &Settings) -> Self {
+        let server = &settings.server;
+        let address = |port: u16| match server.listen_ip() {
+            Ok(ip) => crate::proxy::listener::bind_address(ip, port),
+            Err(_) => format!("{}:{}", server.listen_address, port),
+        };
+
+        let mut listeners = BTreeMap::new();
+        listeners.insert("proxy", address(server.listen_port));
+        if let Some(admin) = &server.admin_listener {
+            listeners.insert("admin", address(admin.port));
+        }
+        if let Some(port) = server.metrics_port {
+            listeners.insert("metrics", address(port));
+        }
+
+        let auth = &settings.middleware.auth;
+        let middlewares = [
+            ("auth", auth.mode() != AuthMode::Off),
+            ("rate_limit", settings.middleware.rate_limit.enabled),
+            (
+                "response_transform",
+                settings.middleware.response_transform.enabled,
+            ),
+            (
+                "default_headers",
+                !settings.response.default_headers.is_empty(),
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name)
+        .collect();
+
+        Self {
+            listeners,
+            tls: server.tls.is_some(),
+            upstreams: settings.load_balancing.upstreams.len(),
+            auth_upstreams: auth.upstreams.len(),
+            middlewares,
+            auth_mode: auth.mode(),
+            auth_backend: auth.backend,
+            routes: settings.routing.routes.len(),
+            database_url: DbPool::mask_password(&settings.database.url),
+            redis_url: RedisClient::mask_password(&settings.redis.url),
+            config_fingerprint: fingerprint(settings),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        serde_yaml::from_str(include_str!("../../config/proxy.yaml")).unwrap()
+    }
+
+    #[test]
+    fn test_fingerprint_tracks_config() {
+        let original = settings();
+        assert_eq!(fingerprint(&original), fingerprint(&settings()));
+        assert_eq!(fingerprint(&original).len(), FINGERPRINT_LEN);
+
+        let mut changed = settings();
+        changed.load_balancing.upstreams[0].weight += 1;
+        assert_ne!(fingerprint(&original), fingerprint(&changed));
+    }
+
+    #[test]
+    fn test_summary_masks_credentials() {
+        let mut settings = settings();
+        settings.database.url = "postgres://proxy:hunter2@db:5432/proxy".to_string();
+        settings.redis.url = "redis://:hunter2@cache:6379".to_string();
+
+        let summary = StartupSummary::new(&settings);
+        let json = serde_json::to_string(&summary).unwrap();
+
+        assert!(!json.contains("hunter2"));
+        assert_eq!(summary.database_url, "postgres://proxy:****@db:5432/proxy");
+        assert_eq!(summary.config_fingerprint, fingerprint(&settings));
+        assert!(summary.listeners.contains_key("proxy"));
+    }
+}
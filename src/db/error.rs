@@ -0,0 +1,161 @@
+use sqlx::error::ErrorKind;
+
+/// Coarse class of a database failure, used to pick a response status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbErrorKind {
+    /// Connection loss, pool exhaustion or a retryable conflict (503)
+    Transient,
+    /// Constraint violation caused by the request's data (409)
+    Constraint,
+    /// Anything else (500)
+    Other,
+}
+
+impl DbErrorKind {
+    /// HTTP status to answer with
+    pub fn status(self) -> u16 {
+        match self {
+            DbErrorKind::Transient => 503,
+            DbErrorKind::Constraint => 409,
+            DbErrorKind::Other => 500,
+        }
+    }
+}
+
+/// Classify a sqlx error
+///
+/// Postgres reports transient conditions through SQLSTATE codes: class `08`
+/// (connection exception), `53300` (too many connections), `57P0x`
+/// (server shutting down) and `40001` / `40P01` (serialization failure,
+/// deadlock; safe to retry).
+pub fn classify(e: &sqlx::Error) -> DbErrorKind {
+    match e {
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => DbErrorKind::Transient,
+        sqlx::Error::Database(db) => match db.kind() {
+            ErrorKind::UniqueViolation
+            | ErrorKind::ForeignKeyViolation
+            | ErrorKind::NotNullViolation
+            | ErrorKind::CheckViolation => DbErrorKind::Constraint,
+            _ => match db.code().as_deref() {
+                Some(code)
+                    if code.starts_with("08")
+                        || code.starts_with("57P0")
+                        || matches!(code, "53300" | "40001" | "40P01") =>
+                {
+                    DbErrorKind::Transient
+                }
+                _ => DbErrorKind::Other,
+            },
+        },
+        _ => DbErrorKind::Other,
+    }
+}
+
+/// Database failure carried by the auth flow errors
+///
+/// Keeps the class so handlers can answer 503 / 409 / 500.
+#[derive(Debug)]
+pub struct DbFailure {
+    pub kind: DbErrorKind,
+    message: String,
+}
+
+impl DbFailure {
+    pub fn new(kind: DbErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for DbFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+
+    /// Database error as a driver would report it, from its SQLSTATE
+    #[derive(Debug)]
+    struct TestDbError {
+        code: &'static str,
+    }
+
+    impl std::fmt::Display for TestDbError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "database error {}", self.code)
+        }
+    }
+
+    impl std::error::Error for TestDbError {}
+
+    impl sqlx::error::DatabaseError for TestDbError {
+        fn message(&self) -> &str {
+            "database error"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.code))
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            match self.code {
+                "23505" => ErrorKind::UniqueViolation,
+                _ => ErrorKind::Other,
+            }
+        }
+    }
+
+    fn database_error(code: &'static str) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(TestDbError { code }))
+    }
+
+    #[test]
+    fn test_connection_errors_are_transient() {
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        assert_eq!(classify(&sqlx::Error::Io(refused)), DbErrorKind::Transient);
+        assert_eq!(classify(&sqlx::Error::PoolTimedOut), DbErrorKind::Transient);
+
+        // Server-reported connection failure and shutdown
+        let lost = database_error("08006");
+        assert_eq!(classify(&lost), DbErrorKind::Transient);
+        let shutdown = database_error("57P01");
+        assert_eq!(classify(&shutdown), DbErrorKind::Transient);
+        assert_eq!(DbErrorKind::Transient.status(), 503);
+    }
+
+    #[test]
+    fn test_unique_violation_is_constraint() {
+        let duplicate = database_error("23505");
+        assert_eq!(classify(&duplicate), DbErrorKind::Constraint);
+        assert_eq!(DbErrorKind::Constraint.status(), 409);
+    }
+
+    #[test]
+    fn test_other_errors() {
+        assert_eq!(classify(&sqlx::Error::RowNotFound), DbErrorKind::Other);
+        let syntax = database_error("42601");
+        assert_eq!(classify(&syntax), DbErrorKind::Other);
+    }
+}
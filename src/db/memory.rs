@@ -0,0 +1,429 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::auth::audit::{AuditEvent, AuditLog};
+use crate::cache::{BanStore, CertPinStore, IdempotencyStore, NonceStore, TokenBlacklist};
+use crate::db::store::{TokenStore, UserStore};
+use crate::db::token::{RefreshToken, TokenError};
+use crate::db::user::{CreateUser, User, UserError, ROLE_USER};
+
+/// In-memory user, token, blacklist, audit, idempotency, nonce, certificate pin
+/// and ban store for unit tests
+#[derive(Default)]
+pub struct MemoryStore {
+    users: Mutex<HashMap<String, User>>,
+    tokens: Mutex<HashMap<String, RefreshToken>>,
+    blacklist: Mutex<HashMap<String, u64>>,
+    audit: Mutex<Vec<AuditEvent>>,
+    idempotency_locks: Mutex<HashSet<String>>,
+    idempotency_responses: Mutex<HashMap<String, String>>,
+    nonces: Mutex<HashMap<String, (String, Instant)>>,
+    cert_pins: Mutex<HashMap<String, String>>,
+    bans: Mutex<HashMap<String, i64>>,
+}
+
+impl MemoryStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of users currently stored
+    pub fn user_count(&self) -> usize {
+        self.users.lock().unwrap().len()
+    }
+
+    /// Number of refresh tokens currently stored
+    pub fn token_count(&self) -> usize {
+        self.tokens.lock().unwrap().len()
+    }
+
+    /// Snapshot of the stored refresh tokens
+    pub fn refresh_tokens(&self) -> Vec<RefreshToken> {
+        self.tokens.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Audit events recorded so far, oldest first
+    pub fn audit_events(&self) -> Vec<AuditEvent> {
+        self.audit.lock().unwrap().clone()
+    }
+}
+
+impl AuditLog for MemoryStore {
+    fn record(&self, event: AuditEvent) {
+        self.audit.lock().unwrap().push(event);
+    }
+
+    fn events_for(&self, user_id: &Uuid) -> Vec<AuditEvent> {
+        self.audit
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.user_id == *user_id)
+            .cloned()
+            .collect()
+    }
+
+    fn redact(&self, user_id: &Uuid) -> usize {
+        let mut audit = self.audit.lock().unwrap();
+        let before = audit.len();
+        audit.retain(|event| event.user_id != *user_id);
+        before - audit.len()
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn create(&self, user_data: CreateUser) -> Result<User, UserError> {
+        let mut users = self.users.lock().unwrap();
+        if users.contains_key(&user_data.email) {
+            return Err(UserError::EmailExists);
+        }
+        if let Some(username) = &user_data.username {
+            if users.values().any(|user| has_username(user, username)) {
+                return Err(UserError::UsernameExists);
+            }
+        }
+
+        let user = User {
+            id: Uuid::new_v4(),
+            email: user_data.email,
+            username: user_data.username,
+            password_hash: user_data.password_hash,
+            token_version: 0,
+            role: ROLE_USER.to_string(),
+        };
+        users.insert(user.email.clone(), user.clone());
+
+        Ok(user)
+    }
+
+    async fn find_by_id(&self, user_id: &Uuid) -> Result<User, UserError> {
+        self.users
+            .lock()
+            .unwrap()
+            .values()
+            .find(|user| user.id == *user_id)
+            .cloned()
+            .ok_or(UserError::NotFound)
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<User, UserError> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(email)
+            .cloned()
+            .ok_or(UserError::NotFound)
+    }
+
+    async fn find_by_identifier(&self, identifier: &str) -> Result<User, UserError> {
+        self.users
+            .lock()
+            .unwrap()
+            .values()
+            .find(|user| user.email == identifier || has_username(user, identifier))
+            .cloned()
+            .ok_or(UserError::NotFound)
+    }
+
+    async fn email_exists(&self, email: &str) -> Result<bool, UserError> {
+        Ok(self.users.lock().unwrap().contains_key(email))
+    }
+
+    async fn username_exists(&self, username: &str) -> Result<bool, UserError> {
+        Ok(self
+            .users
+            .lock()
+            .unwrap()
+            .values()
+            .any(|user| has_username(user, username)))
+    }
+
+    async fn update_password(
+        &self,
+        user_id: &Uuid,
+        new_password_hash: &str,
+    ) -> Result<User, UserError> {
+        let mut users = self.users.lock().unwrap();
+        let user = users
+            .values_mut()
+            .find(|user| user.id == *user_id)
+            .ok_or(UserError::NotFound)?;
+        user.password_hash = new_password_hash.to_string();
+
+        Ok(user.clone())
+    }
+
+    async fn set_role(&self, user_id: &Uuid, role: &str) -> Result<User, UserError> {
+        let mut users = self.users.lock().unwrap();
+        let user = users
+            .values_mut()
+            .find(|user| user.id == *user_id)
+            .ok_or(UserError::NotFound)?;
+        user.role = role.to_string();
+
+        Ok(user.clone())
+    }
+
+    async fn token_version(&self, user_id: &Uuid) -> Result<i32, UserError> {
+        self.users
+            .lock()
+            .unwrap()
+            .values()
+            .find(|user| user.id == *user_id)
+            .map(|user| user.token_version)
+            .ok_or(UserError::NotFound)
+    }
+
+    async fn bump_token_version(&self, user_id: &Uuid) -> Result<i32, UserError> {
+        let mut users = self.users.lock().unwrap();
+        let user = users
+            .values_mut()
+            .find(|user| user.id == *user_id)
+            .ok_or(UserError::NotFound)?;
+        user.token_version += 1;
+
+        Ok(user.token_version)
+    }
+
+    async fn delete(&self, user_id: &Uuid) -> Result<(), UserError> {
+        let mut users = self.users.lock().unwrap();
+        let before = users.len();
+        users.retain(|_, user| user.id != *user_id);
+        if users.len() == before {
+            return Err(UserError::NotFound);
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the user has this username, ignoring case
+fn has_username(user: &User, username: &str) -> bool {
+    user.username
+        .as_deref()
+        .is_some_and(|name| name.eq_ignore_ascii_case(username))
+}
+
+#[async_trait]
+impl TokenStore for MemoryStore {
+    async fn save_refresh_token(
+        &self,
+        user_id: &Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, TokenError> {
+        let token = RefreshToken {
+            id: Uuid::new_v4(),
+            user_id: *user_id,
+            token_hash: token_hash.to_string(),
+            expires_at,
+        };
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(token_hash.to_string(), token.clone());
+
+        Ok(token)
+    }
+
+    async fn verify_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, TokenError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let token = tokens
+            .get(token_hash)
+            .cloned()
+            .ok_or(TokenError::NotFound)?;
+
+        if token.expires_at < Utc::now() {
+            tokens.remove(token_hash);
+            return Err(TokenError::Expired);
+        }
+
+        Ok(token)
+    }
+
+    async fn revoke_token_by_hash(&self, token_hash: &str) -> Result<(), TokenError> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .remove(token_hash)
+            .map(|_| ())
+            .ok_or(TokenError::NotFound)
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        user_id: &Uuid,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, TokenError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.remove(old_hash).ok_or(TokenError::NotFound)?;
+
+        let token = RefreshToken {
+            id: Uuid::new_v4(),
+            user_id: *user_id,
+            token_hash: new_hash.to_string(),
+            expires_at,
+        };
+        tokens.insert(new_hash.to_string(), token.clone());
+        Ok(token)
+    }
+
+    async fn revoke_all_user_tokens(&self, user_id: &Uuid) -> Result<u64, TokenError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let before = tokens.len();
+        tokens.retain(|_, token| token.user_id != *user_id);
+
+        Ok((before - tokens.len()) as u64)
+    }
+
+    async fn user_tokens(&self, user_id: &Uuid) -> Result<Vec<RefreshToken>, TokenError> {
+        let mut tokens: Vec<RefreshToken> = self
+            .tokens
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|token| token.user_id == *user_id)
+            .cloned()
+            .collect();
+        tokens.sort_by_key(|token| token.expires_at);
+
+        Ok(tokens)
+    }
+}
+
+#[async_trait]
+impl TokenBlacklist for MemoryStore {
+    async fn blacklist_token(
+        &self,
+        token: &str,
+        expiration_seconds: u64,
+    ) -> Result<(), redis::RedisError> {
+        self.blacklist
+            .lock()
+            .unwrap()
+            .insert(token.to_string(), expiration_seconds);
+        Ok(())
+    }
+
+    async fn is_token_blacklisted(&self, token: &str) -> Result<bool, redis::RedisError> {
+        Ok(self.blacklist.lock().unwrap().contains_key(token))
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for MemoryStore {
+    async fn try_lock(&self, key: &str, _ttl_seconds: u64) -> Result<bool, redis::RedisError> {
+        Ok(self
+            .idempotency_locks
+            .lock()
+            .unwrap()
+            .insert(key.to_string()))
+    }
+
+    async fn unlock(&self, key: &str) -> Result<(), redis::RedisError> {
+        self.idempotency_locks.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn cached_response(&self, key: &str) -> Result<Option<String>, redis::RedisError> {
+        Ok(self.idempotency_responses.lock().unwrap().get(key).cloned())
+    }
+
+    async fn cache_response(
+        &self,
+        key: &str,
+        response: &str,
+        _ttl_seconds: u64,
+    ) -> Result<(), redis::RedisError> {
+        self.idempotency_responses
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), response.to_string());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl NonceStore for MemoryStore {
+    async fn store_nonce(
+        &self,
+        nonce: &str,
+        user_id: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), redis::RedisError> {
+        let expires_at = Instant::now() + Duration::from_secs(ttl_seconds);
+        self.nonces
+            .lock()
+            .unwrap()
+            .insert(nonce.to_string(), (user_id.to_string(), expires_at));
+        Ok(())
+    }
+
+    async fn take_nonce(&self, nonce: &str) -> Result<Option<String>, redis::RedisError> {
+        Ok(self
+            .nonces
+            .lock()
+            .unwrap()
+            .remove(nonce)
+            .filter(|(_, expires_at)| Instant::now() < *expires_at)
+            .map(|(user_id, _)| user_id))
+    }
+}
+
+#[async_trait]
+impl CertPinStore for MemoryStore {
+    async fn pin_certificate(
+        &self,
+        upstream: &str,
+        fingerprint: &str,
+    ) -> Result<Option<String>, redis::RedisError> {
+        let mut pins = self.cert_pins.lock().unwrap();
+        match pins.get(upstream) {
+            Some(pinned) => Ok(Some(pinned.clone())),
+            None => {
+                pins.insert(upstream.to_string(), fingerprint.to_string());
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BanStore for MemoryStore {
+    async fn add_ban(
+        &self,
+        target: &str,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<(), redis::RedisError> {
+        let mut bans = self.bans.lock().unwrap();
+        bans.retain(|_, expiry| *expiry > now);
+        bans.insert(target.to_string(), expires_at);
+        Ok(())
+    }
+
+    async fn remove_ban(&self, target: &str) -> Result<bool, redis::RedisError> {
+        Ok(self.bans.lock().unwrap().remove(target).is_some())
+    }
+
+    async fn active_bans(&self, now: i64) -> Result<Vec<(String, i64)>, redis::RedisError> {
+        let mut bans: Vec<_> = self
+            .bans
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, expires_at)| **expires_at > now)
+            .map(|(target, expires_at)| (target.clone(), *expires_at))
+            .collect();
+        bans.sort_by_key(|(_, expires_at)| *expires_at);
+        Ok(bans)
+    }
+}
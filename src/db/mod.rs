@@ -1,7 +1,13 @@
+pub mod error;
+#[cfg(test)]
+pub mod memory;
 pub mod pool;
+pub mod store;
 pub mod token;
 pub mod user;
+pub mod version_cache;
 
 pub use pool::DbPool;
+pub use store::{TokenStore, UserStore};
 pub use token::TokenRepository;
 pub use user::UserRepository;
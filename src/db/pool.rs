@@ -55,7 +55,7 @@ impl DbPool {
     }
 
     /// Mask password in database URL for logging
-    fn mask_password(url: &str) -> String {
+    pub fn mask_password(url: &str) -> String {
         if let Some(at_pos) = url.rfind('@') {
             if let Some(colon_pos) = url[..at_pos].rfind(':') {
                 let mut masked = url.to_string();
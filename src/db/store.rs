@@ -0,0 +1,251 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::auth::PasswordManager;
+use crate::db::token::{RefreshToken, TokenError, TokenRepository};
+use crate::db::user::{CreateUser, CredentialCheck, User, UserError, UserRepository, Usernames};
+
+/// Storage backend for user accounts
+///
+/// The auth flows only talk to users through this trait, so an alternate
+/// store (e.g. in-memory for tests) can be injected in place of Postgres.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Create a new user
+    async fn create(&self, user_data: CreateUser) -> Result<User, UserError>;
+
+    /// Create many users, reporting each row's outcome
+    ///
+    /// A taken email fails only its own row (`EmailExists`). `Err` means
+    /// the store itself failed. Row by row unless the store batches.
+    async fn create_many(
+        &self,
+        users: Vec<CreateUser>,
+    ) -> Result<Vec<Result<User, UserError>>, UserError> {
+        let mut results = Vec::with_capacity(users.len());
+        for user_data in users {
+            match self.create(user_data).await {
+                Err(e @ (UserError::Transient(_) | UserError::Other(_))) => return Err(e),
+                result => results.push(result),
+            }
+        }
+        Ok(results)
+    }
+
+    /// Find user by ID
+    async fn find_by_id(&self, user_id: &Uuid) -> Result<User, UserError>;
+
+    /// Find user by email
+    async fn find_by_email(&self, email: &str) -> Result<User, UserError>;
+
+    /// Find user by email or, case-insensitively, by username
+    async fn find_by_identifier(&self, identifier: &str) -> Result<User, UserError>;
+
+    /// Check if email already exists
+    async fn email_exists(&self, email: &str) -> Result<bool, UserError>;
+
+    /// Check if a username is taken, ignoring case
+    async fn username_exists(&self, username: &str) -> Result<bool, UserError>;
+
+    /// Replace a user's password hash
+    async fn update_password(
+        &self,
+        user_id: &Uuid,
+        new_password_hash: &str,
+    ) -> Result<User, UserError>;
+
+    /// Set a user's role
+    async fn set_role(&self, user_id: &Uuid, role: &str) -> Result<User, UserError>;
+
+    /// Get the user's current token version
+    async fn token_version(&self, user_id: &Uuid) -> Result<i32, UserError>;
+
+    /// Increment the user's token version, invalidating all issued tokens
+    async fn bump_token_version(&self, user_id: &Uuid) -> Result<i32, UserError>;
+
+    /// Delete a user for good
+    async fn delete(&self, user_id: &Uuid) -> Result<(), UserError>;
+
+    /// Verify user's credentials
+    ///
+    /// When no user matches, a dummy bcrypt verification still runs so the
+    /// time taken does not reveal whether the account exists.
+    ///
+    /// # Arguments
+    /// * `identifier` - User's email, or username with `Usernames::Enabled`
+    /// * `password` - Plain text password to verify
+    /// * `usernames` - Whether `identifier` may be a username
+    ///
+    /// # Returns
+    /// * `Result<CredentialCheck, UserError>` - Outcome of the check or error
+    ///
+    /// # Example
+    /// ```
+    /// match user_store
+    ///     .verify_credentials("user@example.com", "password", Usernames::Disabled)
+    ///     .await?
+    /// {
+    ///     CredentialCheck::Valid(user) => println!("Login successful: {}", user.email),
+    ///     CredentialCheck::InvalidPassword | CredentialCheck::UnknownUser => {
+    ///         println!("Invalid credentials")
+    ///     }
+    /// }
+    /// ```
+    async fn verify_credentials(
+        &self,
+        identifier: &str,
+        password: &str,
+        usernames: Usernames,
+    ) -> Result<CredentialCheck, UserError> {
+        let found = match usernames {
+            Usernames::Disabled => self.find_by_email(identifier).await,
+            Usernames::Enabled => self.find_by_identifier(identifier).await,
+        };
+        let user = match found {
+            Ok(user) => user,
+            Err(UserError::NotFound) => {
+                PasswordManager::dummy_verify(password);
+                return Ok(CredentialCheck::UnknownUser);
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Verify password
+        if PasswordManager::verify(password, &user.password_hash)? {
+            Ok(CredentialCheck::Valid(user))
+        } else {
+            Ok(CredentialCheck::InvalidPassword)
+        }
+    }
+}
+
+/// Storage backend for refresh tokens
+#[async_trait]
+pub trait TokenStore: Send + Sync {
+    /// Save a refresh token
+    async fn save_refresh_token(
+        &self,
+        user_id: &Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, TokenError>;
+
+    /// Verify refresh token is valid (exists and not expired)
+    async fn verify_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, TokenError>;
+
+    /// Revoke refresh token by hash
+    async fn revoke_token_by_hash(&self, token_hash: &str) -> Result<(), TokenError>;
+
+    /// Atomically replace a refresh token with its successor; `NotFound`
+    /// if the old token was already revoked (e.g. by a concurrent refresh)
+    async fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        user_id: &Uuid,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, TokenError>;
+
+    /// Revoke all refresh tokens for a user
+    async fn revoke_all_user_tokens(&self, user_id: &Uuid) -> Result<u64, TokenError>;
+
+    /// A user's refresh tokens, i.e. their sessions
+    async fn user_tokens(&self, user_id: &Uuid) -> Result<Vec<RefreshToken>, TokenError>;
+}
+
+#[async_trait]
+impl UserStore for UserRepository<'_> {
+    async fn create(&self, user_data: CreateUser) -> Result<User, UserError> {
+        UserRepository::create(self, user_data).await
+    }
+
+    async fn create_many(
+        &self,
+        users: Vec<CreateUser>,
+    ) -> Result<Vec<Result<User, UserError>>, UserError> {
+        UserRepository::create_many(self, users).await
+    }
+
+    async fn find_by_id(&self, user_id: &Uuid) -> Result<User, UserError> {
+        UserRepository::find_by_id(self, user_id).await
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<User, UserError> {
+        UserRepository::find_by_email(self, email).await
+    }
+
+    async fn find_by_identifier(&self, identifier: &str) -> Result<User, UserError> {
+        UserRepository::find_by_identifier(self, identifier).await
+    }
+
+    async fn email_exists(&self, email: &str) -> Result<bool, UserError> {
+        UserRepository::email_exists(self, email).await
+    }
+
+    async fn username_exists(&self, username: &str) -> Result<bool, UserError> {
+        UserRepository::username_exists(self, username).await
+    }
+
+    async fn update_password(
+        &self,
+        user_id: &Uuid,
+        new_password_hash: &str,
+    ) -> Result<User, UserError> {
+        UserRepository::update_password(self, user_id, new_password_hash).await
+    }
+
+    async fn set_role(&self, user_id: &Uuid, role: &str) -> Result<User, UserError> {
+        UserRepository::set_role(self, user_id, role).await
+    }
+
+    async fn token_version(&self, user_id: &Uuid) -> Result<i32, UserError> {
+        UserRepository::token_version(self, user_id).await
+    }
+
+    async fn bump_token_version(&self, user_id: &Uuid) -> Result<i32, UserError> {
+        UserRepository::bump_token_version(self, user_id).await
+    }
+
+    async fn delete(&self, user_id: &Uuid) -> Result<(), UserError> {
+        UserRepository::delete(self, user_id).await
+    }
+}
+
+#[async_trait]
+impl TokenStore for TokenRepository<'_> {
+    async fn save_refresh_token(
+        &self,
+        user_id: &Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, TokenError> {
+        TokenRepository::save_refresh_token(self, user_id, token_hash, expires_at).await
+    }
+
+    async fn verify_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, TokenError> {
+        TokenRepository::verify_refresh_token(self, token_hash).await
+    }
+
+    async fn revoke_token_by_hash(&self, token_hash: &str) -> Result<(), TokenError> {
+        TokenRepository::revoke_token_by_hash(self, token_hash).await
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        user_id: &Uuid,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, TokenError> {
+        TokenRepository::rotate_refresh_token(self, old_hash, user_id, new_hash, expires_at).await
+    }
+
+    async fn revoke_all_user_tokens(&self, user_id: &Uuid) -> Result<u64, TokenError> {
+        TokenRepository::revoke_all_user_tokens(self, user_id).await
+    }
+
+    async fn user_tokens(&self, user_id: &Uuid) -> Result<Vec<RefreshToken>, TokenError> {
+        TokenRepository::find_by_user(self, user_id).await
+    }
+}
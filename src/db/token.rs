@@ -1,8 +1,10 @@
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use thiserror::Error;
 use uuid::Uuid;
 
+use crate::db::error::{classify, DbErrorKind, DbFailure};
+
 /// Refresh token database model
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct RefreshToken {
@@ -21,8 +23,35 @@ pub enum TokenError {
     #[error("Token has expired")]
     Expired,
 
+    #[error("Database unavailable: {0}")]
+    Transient(sqlx::Error),
+
+    #[error("Constraint violation: {0}")]
+    Constraint(sqlx::Error),
+
     #[error("Database error: {0}")]
-    DatabaseError(#[from] sqlx::Error),
+    Other(sqlx::Error),
+}
+
+impl From<sqlx::Error> for TokenError {
+    fn from(e: sqlx::Error) -> Self {
+        match classify(&e) {
+            DbErrorKind::Transient => TokenError::Transient(e),
+            DbErrorKind::Constraint => TokenError::Constraint(e),
+            DbErrorKind::Other => TokenError::Other(e),
+        }
+    }
+}
+
+impl From<TokenError> for DbFailure {
+    fn from(e: TokenError) -> Self {
+        let kind = match e {
+            TokenError::Transient(_) => DbErrorKind::Transient,
+            TokenError::Constraint(_) => DbErrorKind::Constraint,
+            _ => DbErrorKind::Other,
+        };
+        DbFailure::new(kind, e.to_string())
+    }
 }
 
 /// Token repository for database operations
@@ -41,7 +70,7 @@ impl<'a> TokenRepository<'a> {
     /// # Arguments
     /// * `user_id` - User's UUID
     /// * `token_hash` - Hashed refresh token
-    /// * `expires_in_seconds` - Token expiration time in seconds
+    /// * `expires_at` - Token expiry, as encoded in the refresh JWT's `exp` claim
     ///
     /// # Returns
     /// * `Result<RefreshToken, TokenError>` - Saved token or error
@@ -51,17 +80,15 @@ impl<'a> TokenRepository<'a> {
     /// let token = token_repo.save_refresh_token(
     ///     &user_id,
     ///     &token_hash,
-    ///     604800  // 7 days
+    ///     expires_at
     /// ).await?;
     /// ```
     pub async fn save_refresh_token(
         &self,
         user_id: &Uuid,
         token_hash: &str,
-        expires_in_seconds: i64,
+        expires_at: DateTime<Utc>,
     ) -> Result<RefreshToken, TokenError> {
-        let expires_at = Utc::now() + Duration::seconds(expires_in_seconds);
-
         let token = sqlx::query_as::<_, RefreshToken>(
             r#"
             INSERT INTO refresh_tokens (user_id, token_hash, expires_at)
@@ -191,6 +218,84 @@ impl<'a> TokenRepository<'a> {
         Ok(())
     }
 
+    /// Replace a refresh token with its successor in one transaction
+    ///
+    /// Only one of several concurrent rotations of the same token can
+    /// delete it; the others get `NotFound` and save nothing.
+    ///
+    /// # Arguments
+    /// * `old_hash` - Hash of the token being refreshed
+    /// * `user_id` - Owner of both tokens
+    /// * `new_hash` - Hash of the new token
+    /// * `expires_at` - Expiry of the new token
+    ///
+    /// # Returns
+    /// * `Result<RefreshToken, TokenError>` - The new token, or `NotFound`
+    ///   if the old one was already gone
+    pub async fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        user_id: &Uuid,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, TokenError> {
+        let mut tx = self.pool.begin().await?;
+
+        let deleted = sqlx::query(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(old_hash)
+        .execute(&mut *tx)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(TokenError::NotFound);
+        }
+
+        let token = sqlx::query_as::<_, RefreshToken>(
+            r#"
+            INSERT INTO refresh_tokens (user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)
+            RETURNING id, user_id, token_hash, expires_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(new_hash)
+        .bind(expires_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        log::info!("Refresh token rotated for user: {}", user_id);
+
+        Ok(token)
+    }
+
+    /// Refresh tokens of a user, soonest to expire first
+    ///
+    /// # Arguments
+    /// * `user_id` - User's UUID
+    ///
+    /// # Returns
+    /// * `Result<Vec<RefreshToken>, TokenError>` - The user's tokens or error
+    pub async fn find_by_user(&self, user_id: &Uuid) -> Result<Vec<RefreshToken>, TokenError> {
+        let tokens = sqlx::query_as::<_, RefreshToken>(
+            r#"
+            SELECT id, user_id, token_hash, expires_at
+            FROM refresh_tokens
+            WHERE user_id = $1
+            ORDER BY expires_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(tokens)
+    }
+
     /// Revoke all refresh tokens for a user (useful for logout from all devices)
     ///
     /// # Arguments
@@ -318,7 +423,7 @@ mod tests {
 
         // Save token
         let token = repo
-            .save_refresh_token(&user_id, token_hash, 604800)
+            .save_refresh_token(&user_id, token_hash, Utc::now() + chrono::Duration::days(7))
             .await
             .unwrap();
         assert_eq!(token.user_id, user_id);
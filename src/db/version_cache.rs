@@ -0,0 +1,200 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::db::store::UserStore;
+use crate::db::user::{CreateUser, CredentialCheck, User, UserError, Usernames};
+
+/// How long a user's token version is used before the store is asked again
+pub const TOKEN_VERSION_TTL: Duration = Duration::from_secs(5);
+
+/// Entries kept before expired ones are dropped
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Token versions recently read from the user store, by user
+///
+/// Every request with an access token compares it to the user's current
+/// version. Caching the version briefly saves a database round trip per
+/// request. A bump made through [`VersionCachedUsers`] updates the cache,
+/// so revocations through this instance apply at once. A bump made by
+/// another instance applies here within the TTL. Store errors are never
+/// cached.
+pub struct TokenVersionCache {
+    ttl: Duration,
+    /// Version per user and when it was read
+    versions: Mutex<HashMap<Uuid, (Instant, i32)>>,
+}
+
+impl TokenVersionCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            versions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Cached version of a user, if read within the TTL
+    fn get(&self, user_id: &Uuid) -> Option<i32> {
+        let versions = self.versions.lock().unwrap();
+        let (read_at, version) = versions.get(user_id)?;
+        (read_at.elapsed() < self.ttl).then_some(*version)
+    }
+
+    fn insert(&self, user_id: Uuid, version: i32) {
+        let mut versions = self.versions.lock().unwrap();
+        if versions.len() >= PRUNE_THRESHOLD {
+            versions.retain(|_, (read_at, _)| read_at.elapsed() < self.ttl);
+        }
+        versions.insert(user_id, (Instant::now(), version));
+    }
+
+    fn remove(&self, user_id: &Uuid) {
+        self.versions.lock().unwrap().remove(user_id);
+    }
+}
+
+/// User store whose token versions are read through a [`TokenVersionCache`]
+///
+/// Everything else goes straight to the wrapped store.
+pub struct VersionCachedUsers<'a, S> {
+    store: S,
+    cache: &'a TokenVersionCache,
+}
+
+impl<'a, S: UserStore> VersionCachedUsers<'a, S> {
+    pub fn new(store: S, cache: &'a TokenVersionCache) -> Self {
+        Self { store, cache }
+    }
+}
+
+#[async_trait]
+impl<S: UserStore> UserStore for VersionCachedUsers<'_, S> {
+    async fn create(&self, user_data: CreateUser) -> Result<User, UserError> {
+        self.store.create(user_data).await
+    }
+
+    async fn create_many(
+        &self,
+        users: Vec<CreateUser>,
+    ) -> Result<Vec<Result<User, UserError>>, UserError> {
+        self.store.create_many(users).await
+    }
+
+    async fn find_by_id(&self, user_id: &Uuid) -> Result<User, UserError> {
+        self.store.find_by_id(user_id).await
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<User, UserError> {
+        self.store.find_by_email(email).await
+    }
+
+    async fn find_by_identifier(&self, identifier: &str) -> Result<User, UserError> {
+        self.store.find_by_identifier(identifier).await
+    }
+
+    async fn email_exists(&self, email: &str) -> Result<bool, UserError> {
+        self.store.email_exists(email).await
+    }
+
+    async fn username_exists(&self, username: &str) -> Result<bool, UserError> {
+        self.store.username_exists(username).await
+    }
+
+    async fn update_password(
+        &self,
+        user_id: &Uuid,
+        new_password_hash: &str,
+    ) -> Result<User, UserError> {
+        self.store.update_password(user_id, new_password_hash).await
+    }
+
+    async fn set_role(&self, user_id: &Uuid, role: &str) -> Result<User, UserError> {
+        self.store.set_role(user_id, role).await
+    }
+
+    async fn token_version(&self, user_id: &Uuid) -> Result<i32, UserError> {
+        if let Some(version) = self.cache.get(user_id) {
+            return Ok(version);
+        }
+        let version = self.store.token_version(user_id).await?;
+        self.cache.insert(*user_id, version);
+        Ok(version)
+    }
+
+    async fn bump_token_version(&self, user_id: &Uuid) -> Result<i32, UserError> {
+        // Dropped first, so a failed bump does not leave an old version behind
+        self.cache.remove(user_id);
+        let version = self.store.bump_token_version(user_id).await?;
+        self.cache.insert(*user_id, version);
+        Ok(version)
+    }
+
+    async fn delete(&self, user_id: &Uuid) -> Result<(), UserError> {
+        self.cache.remove(user_id);
+        self.store.delete(user_id).await
+    }
+
+    async fn verify_credentials(
+        &self,
+        identifier: &str,
+        password: &str,
+        usernames: Usernames,
+    ) -> Result<CredentialCheck, UserError> {
+        self.store
+            .verify_credentials(identifier, password, usernames)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory::MemoryStore;
+
+    async fn user(users: &VersionCachedUsers<'_, MemoryStore>) -> Uuid {
+        users
+            .create(CreateUser {
+                email: "user@example.com".to_string(),
+                username: None,
+                password_hash: "unused".to_string(),
+            })
+            .await
+            .unwrap()
+            .id
+    }
+
+    #[tokio::test]
+    async fn test_versions_are_cached_for_the_ttl() {
+        let cache = TokenVersionCache::new(Duration::from_millis(100));
+        let users = VersionCachedUsers::new(MemoryStore::new(), &cache);
+        let user_id = user(&users).await;
+
+        assert_eq!(users.token_version(&user_id).await.unwrap(), 0);
+
+        // Bumped elsewhere (another instance): seen once the entry expires
+        users.store.bump_token_version(&user_id).await.unwrap();
+        assert_eq!(users.token_version(&user_id).await.unwrap(), 0);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(users.token_version(&user_id).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_bump_through_the_cache_applies_at_once() {
+        let cache = TokenVersionCache::new(Duration::from_secs(60));
+        let users = VersionCachedUsers::new(MemoryStore::new(), &cache);
+        let user_id = user(&users).await;
+
+        assert_eq!(users.token_version(&user_id).await.unwrap(), 0);
+        assert_eq!(users.bump_token_version(&user_id).await.unwrap(), 1);
+        assert_eq!(users.token_version(&user_id).await.unwrap(), 1);
+
+        // A deleted user is not answered from the cache
+        users.delete(&user_id).await.unwrap();
+        assert!(matches!(
+            users.token_version(&user_id).await,
+            Err(UserError::NotFound)
+        ));
+    }
+}
@@ -3,5 +3,6 @@ pub mod cache;
 pub mod config;
 pub mod db;
 pub mod load_balancing;
+pub mod metrics;
 pub mod middleware;
 pub mod proxy;
@@ -1,8 +1,15 @@
-use pingora_core::upstreams::peer::HttpPeer;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use pingora_core::upstreams::peer::{HttpPeer, Peer};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
-use crate::config::settings::LoadBalancingConfig;
+use crate::config::settings::{LoadBalancingConfig, UpstreamConfig};
+use crate::load_balancing::tls::PeerTls;
+
+/// Weighted draws `p2c` makes for each of its two candidates before
+/// falling back to a scan of the healthy upstreams
+const P2C_DRAWS: usize = 4;
 
 #[derive(Debug, Error)]
 pub enum LoadBalancerError {
@@ -11,12 +18,33 @@ pub enum LoadBalancerError {
 
     #[error("Invalid strategy: {0}")]
     InvalidStrategy(String),
+
+    #[error("No healthy upstreams available")]
+    NoHealthyUpstreams,
+
+    #[error("Cannot load CA certificates from {path}: {reason}")]
+    CaCert { path: String, reason: String },
 }
 
 /// Load balancer manager
 pub struct LoadBalancerManager {
     config: LoadBalancingConfig,
     round_robin_counter: AtomicUsize,
+    /// Ejection flag per upstream, in `config.upstreams` order
+    ejected: Vec<AtomicBool>,
+    /// Consecutive connect failures per upstream, in `config.upstreams` order
+    failures: Vec<AtomicU32>,
+    /// End of the passive ejection per upstream, in `config.upstreams` order
+    ejected_until: Vec<Mutex<Option<Instant>>>,
+    /// TLS settings per upstream (`None` for plain HTTP), in `config.upstreams` order
+    tls: Vec<Option<PeerTls>>,
+    /// Requests in flight per upstream, in `config.upstreams` order
+    in_flight: Arc<[AtomicUsize]>,
+    /// Running total of upstream weights, for weighted draws
+    cumulative_weights: Vec<u64>,
+    /// Current weight per upstream with a non-zero weight, as `(index,
+    /// current)`, for `weighted_round_robin`
+    current_weights: Mutex<Vec<(usize, i32)>>,
 }
 
 impl LoadBalancerManager {
@@ -26,50 +54,809 @@ impl LoadBalancerManager {
             return Err(LoadBalancerError::NoUpstreams);
         }
 
+        let ejected = config
+            .upstreams
+            .iter()
+            .map(|_| AtomicBool::new(false))
+            .collect();
+        let failures = config.upstreams.iter().map(|_| AtomicU32::new(0)).collect();
+        let ejected_until = config.upstreams.iter().map(|_| Mutex::new(None)).collect();
+
+        let tls = config
+            .upstreams
+            .iter()
+            .map(|upstream| {
+                upstream
+                    .tls
+                    .then(|| PeerTls::resolve(upstream, &config.tls))
+                    .transpose()
+            })
+            .collect::<Result<_, _>>()?;
+
+        let in_flight = config
+            .upstreams
+            .iter()
+            .map(|_| AtomicUsize::new(0))
+            .collect();
+        let cumulative_weights = config
+            .upstreams
+            .iter()
+            .scan(0u64, |total, upstream| {
+                *total += u64::from(upstream.weight);
+                Some(*total)
+            })
+            .collect();
+
+        // Weighted strategies leave out upstreams with a weight of 0
+        let weighted = std::iter::once(&config.strategy)
+            .chain(&config.fallback)
+            .find(|strategy| {
+                matches!(
+                    strategy.as_str(),
+                    "weighted_round_robin" | "p2c" | "least_conn"
+                )
+            });
+        if let Some(strategy) = weighted {
+            if config.upstreams.iter().all(|upstream| upstream.weight == 0) {
+                return Err(LoadBalancerError::InvalidStrategy(format!(
+                    "{} needs an upstream with a non-zero weight",
+                    strategy
+                )));
+            }
+        }
+        let current_weights = config
+            .upstreams
+            .iter()
+            .enumerate()
+            .filter(|(_, upstream)| upstream.weight > 0)
+            .map(|(index, _)| (index, 0))
+            .collect();
+
         Ok(Self {
             config,
             round_robin_counter: AtomicUsize::new(0),
+            ejected,
+            failures,
+            ejected_until,
+            tls,
+            in_flight,
+            cumulative_weights,
+            current_weights: Mutex::new(current_weights),
         })
     }
 
     /// Select next upstream peer
+    ///
+    /// Tries `strategy`, then each `fallback` strategy in order, until one
+    /// yields a peer. Strategies only pick healthy (non-ejected) upstreams,
+    /// except `any`, which is the last resort and ignores ejection.
     pub fn select_peer(&self) -> Result<Box<HttpPeer>, LoadBalancerError> {
-        match self.config.strategy.as_str() {
-            "round_robin" => self.round_robin(),
-            "random" => self.random(),
-            _ => Err(LoadBalancerError::InvalidStrategy(
-                self.config.strategy.clone(),
-            )),
+        let chain = std::iter::once(&self.config.strategy).chain(&self.config.fallback);
+
+        for strategy in chain {
+            let index = match strategy.as_str() {
+                "round_robin" => self.round_robin(),
+                "weighted_round_robin" => self.weighted_round_robin(),
+                "random" => self.random(),
+                // Least in flight of two draws stands in for a full scan
+                "p2c" | "least_conn" => self.p2c(),
+                "any" => self.any(),
+                _ => return Err(LoadBalancerError::InvalidStrategy(strategy.clone())),
+            };
+
+            if let Some(index) = index {
+                if strategy != &self.config.strategy {
+                    log::warn!("Upstream selected by fallback strategy: {}", strategy);
+                }
+                return Ok(self.peer(index));
+            }
+        }
+
+        Err(LoadBalancerError::NoHealthyUpstreams)
+    }
+
+    /// All configured upstreams, ejected or not
+    pub fn upstreams(&self) -> &[UpstreamConfig] {
+        &self.config.upstreams
+    }
+
+    /// Upstreams that are not ejected
+    pub fn healthy_upstreams(&self) -> Vec<&UpstreamConfig> {
+        self.healthy()
+            .into_iter()
+            .map(|index| &self.config.upstreams[index])
+            .collect()
+    }
+
+    /// Names of TLS upstreams whose certificates are not verified
+    pub fn insecure_upstreams(&self) -> Vec<&str> {
+        self.config
+            .upstreams
+            .iter()
+            .zip(&self.tls)
+            .filter(|(_, tls)| tls.as_ref().is_some_and(|tls| tls.insecure))
+            .map(|(upstream, _)| upstream.name.as_str())
+            .collect()
+    }
+
+    /// TLS settings of the upstream a peer from [`select_peer`](Self::select_peer) points at
+    ///
+    /// TLS peers carry their upstream's `address` as SNI, which with the
+    /// port identifies the upstream.
+    pub fn tls_for(&self, peer: &HttpPeer) -> Option<&PeerTls> {
+        let port = peer.address().as_inet().map(|addr| addr.port());
+
+        self.config
+            .upstreams
+            .iter()
+            .zip(&self.tls)
+            .find(|(upstream, tls)| {
+                tls.is_some() && upstream.address == peer.sni() && Some(upstream.port) == port
+            })
+            .and_then(|(_, tls)| tls.as_ref())
+    }
+
+    /// Count a failed connect to the upstream a peer points at
+    ///
+    /// With `passive_ejection` configured, the upstream is ejected for the
+    /// cooldown once its consecutive failures reach the threshold. Until a
+    /// connect succeeds again, every further failure (e.g. the first try
+    /// after the cooldown) ejects it again.
+    pub fn record_connect_failure(&self, peer: &HttpPeer) {
+        self.record_connect_failure_at(peer, Instant::now());
+    }
+
+    fn record_connect_failure_at(&self, peer: &HttpPeer, now: Instant) {
+        let (Some(ejection), Some(index)) = (&self.config.passive_ejection, self.index_of(peer))
+        else {
+            return;
+        };
+
+        let failures = self.failures[index].fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= ejection.consecutive_failures {
+            let until = now + Duration::from_secs(ejection.cooldown_secs);
+            *self.ejected_until[index].lock().unwrap() = Some(until);
+            log::warn!(
+                "Upstream {} ejected for {}s after {} consecutive connect failures",
+                self.config.upstreams[index].name,
+                ejection.cooldown_secs,
+                failures
+            );
+        }
+    }
+
+    /// Count a request as in flight to the upstream a peer points at
+    ///
+    /// The count drops when the returned guard does; `p2c` routes away
+    /// from upstreams with many requests in flight.
+    ///
+    /// # Returns
+    /// * `Option<UpstreamRequestGuard>` - `None` if the peer is not one of ours
+    pub fn start_request(&self, peer: &HttpPeer) -> Option<UpstreamRequestGuard> {
+        let index = self.index_of(peer)?;
+        self.in_flight[index].fetch_add(1, Ordering::Relaxed);
+
+        Some(UpstreamRequestGuard {
+            in_flight: Arc::clone(&self.in_flight),
+            index,
+        })
+    }
+
+    /// Requests in flight per upstream, in config order
+    #[cfg(test)]
+    fn in_flight(&self) -> Vec<usize> {
+        self.in_flight
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Reset the failure count of the upstream a peer points at
+    pub fn record_success(&self, peer: &HttpPeer) {
+        if let Some(index) = self.index_of(peer) {
+            self.failures[index].store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether every upstream is ejected, manually or passively
+    pub fn breaker_open(&self) -> bool {
+        self.healthy_at(Instant::now()).is_empty()
+    }
+
+    /// Take an upstream out of rotation
+    ///
+    /// # Returns
+    /// * `bool` - Whether an upstream with this name exists
+    pub fn eject(&self, name: &str) -> bool {
+        self.set_ejected(name, true)
+    }
+
+    /// Put an ejected upstream back into rotation
+    pub fn restore(&self, name: &str) -> bool {
+        self.set_ejected(name, false)
+    }
+
+    fn set_ejected(&self, name: &str, ejected: bool) -> bool {
+        match self.config.upstreams.iter().position(|u| u.name == name) {
+            Some(index) => {
+                self.ejected[index].store(ejected, Ordering::Relaxed);
+                true
+            }
+            None => false,
         }
     }
 
-    /// Round-robin load balancing
-    fn round_robin(&self) -> Result<Box<HttpPeer>, LoadBalancerError> {
+    /// Indexes of upstreams that are not ejected
+    fn healthy(&self) -> Vec<usize> {
+        self.healthy_at(Instant::now())
+    }
+
+    fn healthy_at(&self, now: Instant) -> Vec<usize> {
+        (0..self.config.upstreams.len())
+            .filter(|&index| self.is_healthy_at(index, now))
+            .collect()
+    }
+
+    fn is_healthy_at(&self, index: usize, now: Instant) -> bool {
+        !self.ejected[index].load(Ordering::Relaxed)
+            && self.ejected_until[index]
+                .lock()
+                .unwrap()
+                .is_none_or(|until| until <= now)
+    }
+
+    /// Index of the upstream a peer from [`select_peer`](Self::select_peer) points at
+    ///
+    /// TLS peers carry the upstream's `address` as SNI, plain ones its `name`.
+    fn index_of(&self, peer: &HttpPeer) -> Option<usize> {
+        let port = peer.address().as_inet().map(|addr| addr.port());
+
+        self.config
+            .upstreams
+            .iter()
+            .zip(&self.tls)
+            .position(|(upstream, tls)| {
+                let sni = match tls {
+                    Some(_) => &upstream.address,
+                    None => &upstream.name,
+                };
+                sni == peer.sni() && Some(upstream.port) == port
+            })
+    }
+
+    /// Round-robin load balancing over healthy upstreams
+    fn round_robin(&self) -> Option<usize> {
+        let healthy = self.healthy();
+        if healthy.is_empty() {
+            return None;
+        }
+
         let index = self.round_robin_counter.fetch_add(1, Ordering::Relaxed);
-        let upstream = &self.config.upstreams[index % self.config.upstreams.len()];
+        Some(healthy[index % healthy.len()])
+    }
 
-        let peer = Box::new(HttpPeer::new(
-            (upstream.address.as_str(), upstream.port),
-            false, // TLS
-            upstream.name.clone(),
-        ));
+    /// Smooth weighted round-robin over healthy upstreams (nginx's algorithm)
+    ///
+    /// Every pick adds each candidate's weight to its current weight, takes
+    /// the candidate with the highest and subtracts the candidates' total
+    /// from it. Upstreams get their share of picks spread out: weights 3
+    /// and 1 give a, a, b, a rather than a, a, a, b.
+    fn weighted_round_robin(&self) -> Option<usize> {
+        let now = Instant::now();
+        let mut current_weights = self.current_weights.lock().unwrap();
+
+        let mut total: i32 = 0;
+        // Slot in `current_weights` and current weight of the best candidate
+        let mut best: Option<(usize, i32)> = None;
+        for (slot, (index, current)) in current_weights.iter_mut().enumerate() {
+            if !self.is_healthy_at(*index, now) {
+                continue;
+            }
+            let weight = i32::try_from(self.config.upstreams[*index].weight).unwrap_or(i32::MAX);
+            *current = current.saturating_add(weight);
+            total = total.saturating_add(weight);
+            if best.is_none_or(|(_, highest)| *current > highest) {
+                best = Some((slot, *current));
+            }
+        }
 
-        Ok(peer)
+        let (slot, _) = best?;
+        let (index, current) = &mut current_weights[slot];
+        *current = current.saturating_sub(total);
+        Some(*index)
     }
 
-    /// Random load balancing
-    fn random(&self) -> Result<Box<HttpPeer>, LoadBalancerError> {
+    /// Random load balancing over healthy upstreams
+    fn random(&self) -> Option<usize> {
         use rand::Rng;
+        let healthy = self.healthy();
+        if healthy.is_empty() {
+            return None;
+        }
+
         let mut rng = rand::thread_rng();
-        let index = rng.gen_range(0..self.config.upstreams.len());
+        Some(healthy[rng.gen_range(0..healthy.len())])
+    }
+
+    /// Power of two choices: the less loaded of two weighted random upstreams
+    ///
+    /// Approximates weighted least-connections while looking at two
+    /// upstreams instead of the whole pool. Only when the draws keep
+    /// hitting ejected upstreams are the healthy ones scanned for candidates.
+    /// Upstreams with a weight of 0 are never picked.
+    fn p2c(&self) -> Option<usize> {
+        use rand::Rng;
+        let now = Instant::now();
+        let mut rng = rand::thread_rng();
+        let total = *self.cumulative_weights.last()?;
+
+        let mut draw = || {
+            let ticket = rng.gen_range(0..total);
+            let index = self
+                .cumulative_weights
+                .partition_point(|&end| end <= ticket);
+            self.is_healthy_at(index, now).then_some(index)
+        };
+        if let Some(index) = power_of_two_choices(&mut draw, |index| self.load(index)) {
+            return Some(index);
+        }
+
+        let weighted: Vec<usize> = self
+            .healthy_at(now)
+            .into_iter()
+            .filter(|&index| self.config.upstreams[index].weight > 0)
+            .collect();
+        if weighted.is_empty() {
+            return None;
+        }
+        let mut draw = || Some(weighted[rng.gen_range(0..weighted.len())]);
+        power_of_two_choices(&mut draw, |index| self.load(index))
+    }
+
+    /// In-flight requests of an upstream per unit of weight
+    fn load(&self, index: usize) -> f64 {
+        let in_flight = self.in_flight[index].load(Ordering::Relaxed);
+        in_flight as f64 / f64::from(self.config.upstreams[index].weight)
+    }
+
+    /// Round-robin over all upstreams, ejected or not
+    fn any(&self) -> Option<usize> {
+        let index = self.round_robin_counter.fetch_add(1, Ordering::Relaxed);
+        Some(index % self.config.upstreams.len())
+    }
+
+    fn peer(&self, index: usize) -> Box<HttpPeer> {
         let upstream = &self.config.upstreams[index];
 
-        let peer = Box::new(HttpPeer::new(
-            (upstream.address.as_str(), upstream.port),
-            false,
-            upstream.name.clone(),
+        let mut peer = match &self.tls[index] {
+            Some(tls) => {
+                let mut peer = HttpPeer::new(
+                    (upstream.address.as_str(), upstream.port),
+                    true,
+                    upstream.address.clone(),
+                );
+                tls.apply(&mut peer);
+                peer
+            }
+            None => HttpPeer::new(
+                (upstream.address.as_str(), upstream.port),
+                false, // TLS
+                upstream.name.clone(),
+            ),
+        };
+
+        let (connect, read) = self.timeouts(upstream);
+        peer.options.connection_timeout = connect;
+        peer.options.read_timeout = read;
+        Box::new(peer)
+    }
+
+    /// Connect and read timeouts of an upstream, falling back to the global ones
+    fn timeouts(&self, upstream: &UpstreamConfig) -> (Option<Duration>, Option<Duration>) {
+        let connect = upstream
+            .connect_timeout_secs
+            .or(self.config.connect_timeout_secs);
+        let read = upstream.read_timeout_secs.or(self.config.read_timeout_secs);
+
+        (
+            connect.map(Duration::from_secs),
+            read.map(Duration::from_secs),
+        )
+    }
+}
+
+/// One request in flight to an upstream; the count drops when this does
+#[derive(Debug)]
+pub struct UpstreamRequestGuard {
+    in_flight: Arc<[AtomicUsize]>,
+    index: usize,
+}
+
+impl Drop for UpstreamRequestGuard {
+    fn drop(&mut self) {
+        self.in_flight[self.index].fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The less loaded of two candidates, looking at no other upstream
+///
+/// # Arguments
+/// * `draw` - Random candidate, `None` for a draw that hit an ejected upstream
+/// * `load` - Load of a candidate
+///
+/// # Returns
+/// * `Option<usize>` - `None` if no draw found a candidate
+fn power_of_two_choices(
+    draw: &mut impl FnMut() -> Option<usize>,
+    load: impl Fn(usize) -> f64,
+) -> Option<usize> {
+    let mut candidate = || (0..P2C_DRAWS).find_map(|_| draw());
+
+    match (candidate(), candidate()) {
+        (Some(a), Some(b)) if load(b) < load(a) => Some(b),
+        (Some(a), _) | (None, Some(a)) => Some(a),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{PassiveEjectionConfig, RetryConfig, UpstreamTlsConfig};
+    use pingora_core::upstreams::peer::Peer;
+
+    fn manager(strategy: &str, fallback: &[&str]) -> LoadBalancerManager {
+        weighted_manager(strategy, fallback, &[1, 1])
+    }
+
+    fn weighted_manager(strategy: &str, fallback: &[&str], weights: &[u32]) -> LoadBalancerManager {
+        LoadBalancerManager::new(weighted_config(strategy, fallback, weights)).unwrap()
+    }
+
+    fn weighted_config(strategy: &str, fallback: &[&str], weights: &[u32]) -> LoadBalancingConfig {
+        let upstreams = (1..)
+            .zip(weights)
+            .map(|(i, &weight)| UpstreamConfig {
+                name: format!("backend{}", i),
+                address: "127.0.0.1".to_string(),
+                port: 3000 + i,
+                weight,
+                tls: false,
+                tls_options: None,
+                connect_timeout_secs: None,
+                read_timeout_secs: None,
+            })
+            .collect();
+
+        LoadBalancingConfig {
+            strategy: strategy.to_string(),
+            fallback: fallback.iter().map(|s| s.to_string()).collect(),
+            upstreams,
+            tls: UpstreamTlsConfig::default(),
+            warmup_connections: 0,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            retry: RetryConfig::default(),
+            passive_ejection: None,
+        }
+    }
+
+    #[test]
+    fn test_upstream_timeouts_override_global() {
+        let upstream = |name: &str, port: u16, read_timeout_secs: Option<u64>| UpstreamConfig {
+            name: name.to_string(),
+            address: "127.0.0.1".to_string(),
+            port,
+            weight: 1,
+            tls: false,
+            tls_options: None,
+            connect_timeout_secs: None,
+            read_timeout_secs,
+        };
+        let manager = LoadBalancerManager::new(LoadBalancingConfig {
+            strategy: "round_robin".to_string(),
+            fallback: Vec::new(),
+            upstreams: vec![
+                upstream("fast", 3001, Some(1)),
+                upstream("slow", 3002, None),
+            ],
+            tls: UpstreamTlsConfig::default(),
+            warmup_connections: 0,
+            connect_timeout_secs: Some(2),
+            read_timeout_secs: Some(30),
+            retry: RetryConfig::default(),
+            passive_ejection: None,
+        })
+        .unwrap();
+
+        // The override gives up sooner; the other upstream keeps the global
+        let fast = manager.select_peer().unwrap();
+        assert_eq!(fast.address().to_string(), "127.0.0.1:3001");
+        assert_eq!(fast.options.read_timeout, Some(Duration::from_secs(1)));
+        assert_eq!(
+            fast.options.connection_timeout,
+            Some(Duration::from_secs(2))
+        );
+
+        let slow = manager.select_peer().unwrap();
+        assert_eq!(slow.address().to_string(), "127.0.0.1:3002");
+        assert_eq!(slow.options.read_timeout, Some(Duration::from_secs(30)));
+        assert_eq!(
+            slow.options.connection_timeout,
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn test_no_timeouts_by_default() {
+        let peer = manager("round_robin", &[]).select_peer().unwrap();
+
+        assert_eq!(peer.options.read_timeout, None);
+        assert_eq!(peer.options.connection_timeout, None);
+    }
+
+    fn selected_address(manager: &LoadBalancerManager) -> String {
+        manager.select_peer().unwrap().address().to_string()
+    }
+
+    #[test]
+    fn test_ejected_upstreams_are_skipped() {
+        let manager = manager("round_robin", &[]);
+        manager.eject("backend1");
+
+        for _ in 0..4 {
+            assert_eq!(selected_address(&manager), "127.0.0.1:3002");
+        }
+    }
+
+    #[test]
+    fn test_all_ejected_falls_back_to_any() {
+        let manager = manager("round_robin", &["random", "any"]);
+        assert!(manager.eject("backend1"));
+        assert!(manager.eject("backend2"));
+
+        assert!(manager.select_peer().is_ok());
+    }
+
+    #[test]
+    fn test_all_ejected_without_any_errors() {
+        let manager = manager("round_robin", &["random"]);
+        manager.eject("backend1");
+        manager.eject("backend2");
+
+        assert!(matches!(
+            manager.select_peer(),
+            Err(LoadBalancerError::NoHealthyUpstreams)
+        ));
+
+        // Restored upstreams are selectable again
+        manager.restore("backend2");
+        assert_eq!(selected_address(&manager), "127.0.0.1:3002");
+    }
+
+    #[test]
+    fn test_unknown_strategy_in_chain() {
+        let manager = manager("round_robin", &["fastest"]);
+        manager.eject("backend1");
+        manager.eject("backend2");
+
+        assert!(matches!(
+            manager.select_peer(),
+            Err(LoadBalancerError::InvalidStrategy(s)) if s == "fastest"
         ));
+    }
+
+    #[test]
+    fn test_least_conn_chain() {
+        let manager = manager("least_conn", &["round_robin", "any"]);
+
+        // least_conn keeps requests in flight level, as p2c does
+        let guards = hold_requests(&manager, 1000);
+        let in_flight = manager.in_flight();
+        assert!(in_flight[0].abs_diff(in_flight[1]) <= 10, "{:?}", in_flight);
+        drop(guards);
+
+        // With every upstream ejected only the last resort picks one
+        manager.eject("backend1");
+        manager.eject("backend2");
+        assert!(manager.select_peer().is_ok());
+    }
+
+    #[test]
+    fn test_passive_ejection_after_consecutive_failures() {
+        let mut manager = manager("round_robin", &[]);
+        manager.config.passive_ejection = Some(PassiveEjectionConfig {
+            consecutive_failures: 2,
+            cooldown_secs: 30,
+        });
+        let now = Instant::now();
+        let peer = manager.peer(0);
+
+        // A success in between resets the count
+        manager.record_connect_failure_at(&peer, now);
+        manager.record_success(&peer);
+        manager.record_connect_failure_at(&peer, now);
+        assert_eq!(manager.healthy_at(now), vec![0, 1]);
+
+        manager.record_connect_failure_at(&peer, now);
+        assert_eq!(manager.healthy_at(now), vec![1]);
+
+        // Both ejected: the breaker is open
+        let other = manager.peer(1);
+        manager.record_connect_failure_at(&other, now);
+        manager.record_connect_failure_at(&other, now);
+        assert!(manager.healthy_at(now).is_empty());
+
+        // Back in rotation after the cooldown, but the next failure ejects again
+        let later = now + Duration::from_secs(30);
+        assert_eq!(manager.healthy_at(later), vec![0, 1]);
+        manager.record_connect_failure_at(&peer, later);
+        assert_eq!(manager.healthy_at(later), vec![1]);
+    }
+
+    #[test]
+    fn test_breaker_open_with_all_upstreams_ejected() {
+        let manager = manager("round_robin", &["any"]);
+        manager.eject("backend1");
+        assert!(!manager.breaker_open());
+
+        manager.eject("backend2");
+        assert!(manager.breaker_open());
+
+        manager.restore("backend1");
+        assert!(!manager.breaker_open());
+    }
+
+    #[test]
+    fn test_failures_ignored_without_passive_ejection() {
+        let manager = manager("round_robin", &[]);
+        let peer = manager.peer(1);
+
+        for _ in 0..10 {
+            manager.record_connect_failure(&peer);
+        }
+        assert!(!manager.breaker_open());
+    }
+
+    /// Pick `picks` upstreams, keeping every request in flight
+    fn hold_requests(manager: &LoadBalancerManager, picks: usize) -> Vec<UpstreamRequestGuard> {
+        (0..picks)
+            .map(|_| {
+                let peer = manager.select_peer().unwrap();
+                manager.start_request(&peer).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_p2c_balances_in_flight_requests() {
+        let manager = weighted_manager("p2c", &[], &[1; 8]);
+        let guards = hold_requests(&manager, 8000);
+
+        let in_flight = manager.in_flight();
+        let (min, max) = (
+            in_flight.iter().min().unwrap(),
+            in_flight.iter().max().unwrap(),
+        );
+        assert!(max - min <= 10, "unbalanced: {:?}", in_flight);
+
+        drop(guards);
+        assert!(manager.in_flight().iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn test_p2c_follows_weights() {
+        let manager = weighted_manager("p2c", &[], &[1, 3]);
+        let _guards = hold_requests(&manager, 4000);
+
+        let in_flight = manager.in_flight();
+        assert!((900..=1100).contains(&in_flight[0]), "{:?}", in_flight);
+        assert!((2900..=3100).contains(&in_flight[1]), "{:?}", in_flight);
+    }
+
+    #[test]
+    fn test_p2c_skips_ejected_upstreams() {
+        let manager = weighted_manager("p2c", &[], &[1, 1, 1]);
+        manager.eject("backend1");
+        manager.eject("backend3");
+
+        for _ in 0..20 {
+            assert_eq!(selected_address(&manager), "127.0.0.1:3002");
+        }
+    }
+
+    #[test]
+    fn test_weighted_round_robin_follows_weights() {
+        let manager = weighted_manager("weighted_round_robin", &[], &[3, 1]);
+
+        let picks: Vec<_> = (0..400).map(|_| selected_address(&manager)).collect();
+        let heavy = picks
+            .iter()
+            .filter(|&pick| pick == "127.0.0.1:3001")
+            .count();
+        assert!((290..=310).contains(&heavy), "{}", heavy);
+        assert!((90..=110).contains(&(400 - heavy)), "{}", 400 - heavy);
+
+        // Spread out within each cycle of 4, not 3 in a row
+        assert_eq!(
+            picks[..4],
+            [
+                "127.0.0.1:3001",
+                "127.0.0.1:3001",
+                "127.0.0.1:3002",
+                "127.0.0.1:3001"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_weighted_round_robin_skips_ejected_and_zero_weight() {
+        let manager = weighted_manager("weighted_round_robin", &[], &[2, 0, 1]);
+        for _ in 0..6 {
+            assert_ne!(selected_address(&manager), "127.0.0.1:3002");
+        }
+
+        manager.eject("backend1");
+        for _ in 0..6 {
+            assert_eq!(selected_address(&manager), "127.0.0.1:3003");
+        }
+    }
+
+    #[test]
+    fn test_weighted_strategies_reject_all_zero_weights() {
+        for (strategy, fallback) in [
+            ("weighted_round_robin", &[][..]),
+            ("p2c", &[]),
+            ("least_conn", &[]),
+            ("round_robin", &["p2c"]),
+        ] {
+            assert!(matches!(
+                LoadBalancerManager::new(weighted_config(strategy, fallback, &[0, 0])),
+                Err(LoadBalancerError::InvalidStrategy(_))
+            ));
+        }
+
+        // Unweighted strategies ignore weights
+        assert!(
+            LoadBalancerManager::new(weighted_config("round_robin", &["any"], &[0, 0])).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_p2c_skips_zero_weight() {
+        let manager = weighted_manager("p2c", &[], &[2, 0, 1]);
+        for _ in 0..20 {
+            assert_ne!(selected_address(&manager), "127.0.0.1:3002");
+        }
+
+        manager.eject("backend1");
+        for _ in 0..6 {
+            assert_eq!(selected_address(&manager), "127.0.0.1:3003");
+        }
+    }
+
+    #[test]
+    fn test_p2c_looks_at_two_upstreams_only() {
+        let pool = 10_000;
+        let draws = std::cell::Cell::new(0);
+        let loads = std::cell::RefCell::new(Vec::new());
+        let mut next = 0;
+        let mut draw = || {
+            draws.set(draws.get() + 1);
+            next += pool / 2 - 1;
+            Some(next % pool)
+        };
+
+        // Load of an upstream is its index: the lower index wins
+        let picked = power_of_two_choices(&mut draw, |index| {
+            loads.borrow_mut().push(index);
+            index as f64
+        });
 
-        Ok(peer)
+        assert_eq!(draws.get(), 2);
+        let mut loads = loads.into_inner();
+        loads.sort_unstable();
+        assert_eq!(loads, vec![pool / 2 - 1, pool - 2]);
+        assert_eq!(picked, Some(pool / 2 - 1));
     }
 }
@@ -1,2 +1,4 @@
 // src/load_balancing/mod.rs
 pub mod manager;
+pub mod tls;
+pub mod warmup;
@@ -0,0 +1,406 @@
+use pingora_core::tls::x509::X509;
+use pingora_core::upstreams::peer::HttpPeer;
+use std::sync::Arc;
+
+use crate::cache::CertPinStore;
+use crate::config::settings::{TlsVersion, UpstreamConfig, UpstreamTlsConfig};
+use crate::load_balancing::manager::LoadBalancerError;
+
+/// Certificate verification applied to one TLS upstream's peers
+#[derive(Debug, Clone)]
+pub struct PeerTls {
+    /// Trusted CAs in place of the system roots (`ca_cert_path`)
+    pub ca: Option<Arc<Box<[X509]>>>,
+    /// Certificate and hostname checks are off (`insecure_skip_verify`)
+    pub insecure: bool,
+    /// Pin key (`address:port`) when the certificate is trusted on first use
+    pub pin_key: Option<String>,
+    /// Oldest protocol version accepted (`min_tls_version`)
+    pub min_version: Option<TlsVersion>,
+    /// Cipher allowlist (`ciphers`, any when empty)
+    pub ciphers: Vec<String>,
+}
+
+impl PeerTls {
+    /// Resolve the TLS settings of an upstream, loading its CA bundle
+    ///
+    /// Per-upstream `tls_options` replace the global `load_balancing.tls`.
+    /// Skipping verification is logged as a warning every time, so it
+    /// cannot go unnoticed in startup logs.
+    ///
+    /// # Arguments
+    /// * `upstream` - Upstream with `tls: true`
+    /// * `global` - `load_balancing.tls`
+    ///
+    /// # Returns
+    /// * `Result<PeerTls, LoadBalancerError>` - Settings, or the CA load error
+    pub fn resolve(
+        upstream: &UpstreamConfig,
+        global: &UpstreamTlsConfig,
+    ) -> Result<Self, LoadBalancerError> {
+        let config = upstream.tls_options.as_ref().unwrap_or(global);
+
+        if config.insecure_skip_verify {
+            log::warn!(
+                "!!! TLS certificate verification is DISABLED for upstream {} ({}:{}); \
+                 traffic to it can be intercepted. Never use insecure_skip_verify in production",
+                upstream.name,
+                upstream.address,
+                upstream.port
+            );
+        }
+
+        let ca = match &config.ca_cert_path {
+            Some(path) => {
+                let ca = load_ca_bundle(path)?;
+                log::info!(
+                    "Upstream {} trusts {} CA certificate(s) from {}",
+                    upstream.name,
+                    ca.len(),
+                    path
+                );
+                Some(ca)
+            }
+            None => None,
+        };
+
+        let pin_key = config.trust_on_first_use.then(|| {
+            log::info!(
+                "Upstream {} certificate is trusted on first use and pinned",
+                upstream.name
+            );
+            format!("{}:{}", upstream.address, upstream.port)
+        });
+
+        Ok(Self {
+            ca,
+            insecure: config.insecure_skip_verify,
+            pin_key,
+            min_version: config.min_tls_version,
+            ciphers: config.ciphers.clone(),
+        })
+    }
+
+    /// Apply these settings to a peer's TLS options
+    pub fn apply(&self, peer: &mut HttpPeer) {
+        // A pinned certificate is checked after the handshake instead
+        let verify = !self.insecure && self.pin_key.is_none();
+        peer.options.ca = self.ca.clone();
+        peer.options.verify_cert = verify;
+        peer.options.verify_hostname = verify;
+    }
+
+    /// Check the protocol version and cipher a handshake negotiated
+    ///
+    /// The upstream connector has no per-peer protocol settings, so the
+    /// policy is enforced once the handshake completes, before any request
+    /// bytes are sent.
+    ///
+    /// # Arguments
+    /// * `version` - OpenSSL version name, e.g. `TLSv1.2`
+    /// * `cipher` - OpenSSL cipher name
+    ///
+    /// # Returns
+    /// * `Result<(), String>` - Why the connection must be dropped
+    pub fn check_negotiated(&self, version: &str, cipher: &str) -> Result<(), String> {
+        if let Some(min_version) = self.min_version {
+            // Versions older than 1.2 do not parse, and always fall short
+            if version
+                .parse::<TlsVersion>()
+                .map_or(true, |v| v < min_version)
+            {
+                return Err(format!(
+                    "negotiated {}, below the minimum {}",
+                    version, min_version
+                ));
+            }
+        }
+
+        if !self.ciphers.is_empty() && !self.ciphers.iter().any(|c| c == cipher) {
+            return Err(format!("negotiated cipher {} is not allowed", cipher));
+        }
+
+        Ok(())
+    }
+}
+
+/// Outcome of checking an upstream certificate against its pin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PinCheck {
+    /// First connection: this certificate is now the pin
+    Pinned(String),
+    /// Same certificate as the pin
+    Matched,
+    /// The upstream presents a different certificate than the pin
+    Changed { pinned: String, presented: String },
+}
+
+/// Check a handshake's certificate against the trust-on-first-use pin
+///
+/// # Arguments
+/// * `store` - Pin store shared by all proxy instances
+/// * `pin_key` - `PeerTls::pin_key`
+/// * `cert_digest` - SHA-256 digest of the upstream's leaf certificate
+///
+/// # Returns
+/// * `Result<PinCheck, redis::RedisError>` - Outcome, or the store error
+pub async fn check_pin(
+    store: &dyn CertPinStore,
+    pin_key: &str,
+    cert_digest: &[u8],
+) -> Result<PinCheck, redis::RedisError> {
+    let presented: String = cert_digest.iter().map(|b| format!("{:02x}", b)).collect();
+
+    Ok(match store.pin_certificate(pin_key, &presented).await? {
+        None => PinCheck::Pinned(presented),
+        Some(pinned) if pinned == presented => PinCheck::Matched,
+        Some(pinned) => PinCheck::Changed { pinned, presented },
+    })
+}
+
+/// Read a PEM file of one or more CA certificates
+///
+/// # Arguments
+/// * `path` - `ca_cert_path`
+///
+/// # Returns
+/// * `Result<Arc<Box<[X509]>>, LoadBalancerError>` - The certificates
+pub fn load_ca_bundle(path: &str) -> Result<Arc<Box<[X509]>>, LoadBalancerError> {
+    let ca_error = |reason: String| LoadBalancerError::CaCert {
+        path: path.to_string(),
+        reason,
+    };
+
+    let pem = std::fs::read(path).map_err(|e| ca_error(e.to_string()))?;
+    let certs = X509::stack_from_pem(&pem).map_err(|e| ca_error(e.to_string()))?;
+    if certs.is_empty() {
+        return Err(ca_error("no certificates found".to_string()));
+    }
+
+    Ok(Arc::new(certs.into_boxed_slice()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{LoadBalancingConfig, RetryConfig};
+    use crate::db::memory::MemoryStore;
+    use crate::load_balancing::manager::LoadBalancerManager;
+    use pingora_core::upstreams::peer::Peer;
+
+    /// Self-signed CA standing in for a private internal CA
+    const TEST_CA: &str = "-----BEGIN CERTIFICATE-----
+MIIBjjCCATOgAwIBAgIUGj5PkFX+rYjxs19pcYHX3A/IdvkwCgYIKoZIzj0EAwIw
+GzEZMBcGA1UEAwwQSW50ZXJuYWwgVGVzdCBDQTAgFw0yNjEwMTgwMTA2NDBaGA8y
+MTI2MDkyNDAxMDY0MFowGzEZMBcGA1UEAwwQSW50ZXJuYWwgVGVzdCBDQTBZMBMG
+ByqGSM49AgEGCCqGSM49AwEHA0IABJHqKpXhcRBtA+NQ9pHk2H14FDjtmmWIMFAV
+NPY0gSdRQ6aEMrWrbBEwlJ/WMQGf3Z1L6sP7jwm+K8qJ6jqhE1mjUzBRMB0GA1Ud
+DgQWBBTSaoLTpFIcWdZhb5zlFMVcj2M42jAfBgNVHSMEGDAWgBTSaoLTpFIcWdZh
+b5zlFMVcj2M42jAPBgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0kAMEYCIQDt
+wqhLdBUlQ8FR9Sm33TgxdiC3tummAT3044OW6pfrmwIhAM5hkDtTJe9Iov31ZrVg
+JhpHUiV3Db9QunI0sm9bfwDR
+-----END CERTIFICATE-----
+";
+
+    fn upstream(name: &str, tls: bool, tls_options: Option<UpstreamTlsConfig>) -> UpstreamConfig {
+        UpstreamConfig {
+            name: name.to_string(),
+            address: "127.0.0.1".to_string(),
+            port: 8443,
+            weight: 1,
+            tls,
+            tls_options,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+        }
+    }
+
+    fn manager(
+        upstreams: Vec<UpstreamConfig>,
+        tls: UpstreamTlsConfig,
+    ) -> Result<LoadBalancerManager, LoadBalancerError> {
+        LoadBalancerManager::new(LoadBalancingConfig {
+            strategy: "round_robin".to_string(),
+            fallback: Vec::new(),
+            upstreams,
+            tls,
+            warmup_connections: 0,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            retry: RetryConfig::default(),
+            passive_ejection: None,
+        })
+    }
+
+    #[test]
+    fn test_custom_ca_is_loaded() {
+        let path = std::env::temp_dir().join(format!("test-ca-{}.pem", uuid::Uuid::new_v4()));
+        std::fs::write(&path, TEST_CA).unwrap();
+
+        let options = UpstreamTlsConfig {
+            ca_cert_path: Some(path.to_string_lossy().into_owned()),
+            insecure_skip_verify: false,
+            ..Default::default()
+        };
+        let manager = manager(
+            vec![upstream("internal", true, Some(options))],
+            UpstreamTlsConfig::default(),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let peer = manager.select_peer().unwrap();
+        assert!(peer.tls());
+        assert_eq!(peer.sni(), "127.0.0.1");
+        assert_eq!(peer.options.ca.as_ref().unwrap().len(), 1);
+        assert!(peer.options.verify_cert);
+        assert!(peer.options.verify_hostname);
+        assert!(manager.insecure_upstreams().is_empty());
+    }
+
+    #[test]
+    fn test_unreadable_ca_fails_startup() {
+        let missing = UpstreamTlsConfig {
+            ca_cert_path: Some("/nonexistent/ca.pem".to_string()),
+            insecure_skip_verify: false,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            manager(vec![upstream("internal", true, None)], missing),
+            Err(LoadBalancerError::CaCert { path, .. }) if path == "/nonexistent/ca.pem"
+        ));
+    }
+
+    #[test]
+    fn test_negotiated_version_and_cipher_are_checked() {
+        let tls = PeerTls {
+            ca: None,
+            insecure: false,
+            pin_key: None,
+            min_version: Some(TlsVersion::Tls12),
+            ciphers: vec![
+                "ECDHE-RSA-AES128-GCM-SHA256".to_string(),
+                "TLS_AES_128_GCM_SHA256".to_string(),
+            ],
+        };
+
+        assert!(tls
+            .check_negotiated("TLSv1.2", "ECDHE-RSA-AES128-GCM-SHA256")
+            .is_ok());
+        assert!(tls
+            .check_negotiated("TLSv1.3", "TLS_AES_128_GCM_SHA256")
+            .is_ok());
+
+        // A TLS 1.1 handshake is refused when the minimum is 1.2
+        let error = tls
+            .check_negotiated("TLSv1.1", "ECDHE-RSA-AES128-SHA")
+            .unwrap_err();
+        assert!(error.contains("TLSv1.1"), "{}", error);
+        assert!(tls
+            .check_negotiated("TLSv1", "ECDHE-RSA-AES128-GCM-SHA256")
+            .is_err());
+
+        // So is a cipher outside the allowlist
+        assert!(tls
+            .check_negotiated("TLSv1.2", "ECDHE-RSA-AES256-SHA384")
+            .is_err());
+
+        // Without a policy anything goes
+        let open = PeerTls {
+            min_version: None,
+            ciphers: Vec::new(),
+            ..tls
+        };
+        assert!(open.check_negotiated("TLSv1.1", "AES128-SHA").is_ok());
+    }
+
+    #[test]
+    fn test_skip_verify_is_flagged() {
+        // Global setting applies to every TLS upstream without its own options
+        let insecure = UpstreamTlsConfig {
+            ca_cert_path: None,
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+        let manager = manager(
+            vec![
+                upstream("dev", true, None),
+                upstream("verified", true, Some(UpstreamTlsConfig::default())),
+                upstream("plain", false, None),
+            ],
+            insecure,
+        )
+        .unwrap();
+
+        assert_eq!(manager.insecure_upstreams(), vec!["dev"]);
+
+        let peer = manager.select_peer().unwrap();
+        assert!(peer.tls());
+        assert!(!peer.options.verify_cert);
+        assert!(!peer.options.verify_hostname);
+
+        let peer = manager.select_peer().unwrap();
+        assert!(peer.options.verify_cert);
+    }
+
+    #[test]
+    fn test_trust_on_first_use_skips_ca_verification() {
+        let tofu = UpstreamTlsConfig {
+            trust_on_first_use: true,
+            ..Default::default()
+        };
+        let manager = manager(
+            vec![upstream("self-signed", true, Some(tofu))],
+            UpstreamTlsConfig::default(),
+        )
+        .unwrap();
+
+        let peer = manager.select_peer().unwrap();
+        assert!(!peer.options.verify_cert);
+        assert!(!peer.options.verify_hostname);
+        assert_eq!(
+            manager.tls_for(&peer).unwrap().pin_key.as_deref(),
+            Some("127.0.0.1:8443")
+        );
+        // Pinned, so not reported as unverified
+        assert!(manager.insecure_upstreams().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_changed_certificate_is_rejected_after_pinning() {
+        let store = MemoryStore::new();
+        let original = [0xab; 32];
+
+        assert_eq!(
+            check_pin(&store, "127.0.0.1:8443", &original)
+                .await
+                .unwrap(),
+            PinCheck::Pinned("ab".repeat(32))
+        );
+        assert_eq!(
+            check_pin(&store, "127.0.0.1:8443", &original)
+                .await
+                .unwrap(),
+            PinCheck::Matched
+        );
+
+        assert_eq!(
+            check_pin(&store, "127.0.0.1:8443", &[0xcd; 32])
+                .await
+                .unwrap(),
+            PinCheck::Changed {
+                pinned: "ab".repeat(32),
+                presented: "cd".repeat(32),
+            }
+        );
+
+        // Each upstream has its own pin
+        assert!(matches!(
+            check_pin(&store, "127.0.0.1:9443", &[0xcd; 32])
+                .await
+                .unwrap(),
+            PinCheck::Pinned(_)
+        ));
+    }
+}
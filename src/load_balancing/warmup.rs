@@ -0,0 +1,153 @@
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use crate::load_balancing::manager::LoadBalancerManager;
+
+/// Longest a single warmup connection may take to establish
+const WARMUP_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Connections made by a warmup pass
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WarmupReport {
+    pub opened: usize,
+    pub failed: usize,
+}
+
+/// Open `connections` TCP connections to every healthy upstream
+///
+/// Pingora's pool only reuses connections it made for requests, so these
+/// are not handed to it. What warmup does buy is the first-contact cost
+/// (DNS, routing and conntrack entries, the backend's accept path) paid
+/// before traffic arrives, and an early warning for unreachable upstreams.
+///
+/// # Arguments
+/// * `manager` - Load balancer whose healthy upstreams are warmed
+/// * `connections` - Connections per upstream (`load_balancing.warmup_connections`)
+///
+/// # Returns
+/// * `WarmupReport` - How many connections were opened and how many failed
+pub async fn warmup(manager: &LoadBalancerManager, connections: usize) -> WarmupReport {
+    let mut report = WarmupReport::default();
+    let mut attempts = tokio::task::JoinSet::new();
+
+    for upstream in manager.healthy_upstreams() {
+        for _ in 0..connections {
+            let name = upstream.name.clone();
+            let address = (upstream.address.clone(), upstream.port);
+            attempts.spawn(async move {
+                let result =
+                    tokio::time::timeout(WARMUP_CONNECT_TIMEOUT, TcpStream::connect(address)).await;
+                (name, result)
+            });
+        }
+    }
+
+    while let Some(attempt) = attempts.join_next().await {
+        match attempt {
+            Ok((_, Ok(Ok(_)))) => report.opened += 1,
+            Ok((name, Ok(Err(e)))) => {
+                log::warn!("Warmup connection to {} failed: {}", name, e);
+                report.failed += 1;
+            }
+            Ok((name, Err(_))) => {
+                log::warn!("Warmup connection to {} timed out", name);
+                report.failed += 1;
+            }
+            Err(_) => report.failed += 1,
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{
+        LoadBalancingConfig, RetryConfig, UpstreamConfig, UpstreamTlsConfig,
+    };
+    use tokio::net::TcpListener;
+
+    fn upstream(name: &str, port: u16) -> UpstreamConfig {
+        UpstreamConfig {
+            name: name.to_string(),
+            address: "127.0.0.1".to_string(),
+            port,
+            weight: 1,
+            tls: false,
+            tls_options: None,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warmup_opens_connections_to_healthy_upstreams() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accepted = tokio::spawn(async move {
+            let mut count = 0;
+            while let Ok(Ok(_)) =
+                tokio::time::timeout(Duration::from_millis(500), listener.accept()).await
+            {
+                count += 1;
+            }
+            count
+        });
+
+        // The ejected upstream shares the port but must not be warmed
+        let manager = LoadBalancerManager::new(LoadBalancingConfig {
+            strategy: "round_robin".to_string(),
+            fallback: Vec::new(),
+            upstreams: vec![upstream("backend1", port), upstream("ejected", port)],
+            tls: UpstreamTlsConfig::default(),
+            warmup_connections: 3,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            retry: RetryConfig::default(),
+            passive_ejection: None,
+        })
+        .unwrap();
+        manager.eject("ejected");
+
+        let report = warmup(&manager, 3).await;
+
+        assert_eq!(
+            report,
+            WarmupReport {
+                opened: 3,
+                failed: 0
+            }
+        );
+        assert_eq!(accepted.await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_upstream_is_reported() {
+        // Bind then drop to get a port nothing listens on
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let manager = LoadBalancerManager::new(LoadBalancingConfig {
+            strategy: "round_robin".to_string(),
+            fallback: Vec::new(),
+            upstreams: vec![upstream("down", port)],
+            tls: UpstreamTlsConfig::default(),
+            warmup_connections: 2,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            retry: RetryConfig::default(),
+            passive_ejection: None,
+        })
+        .unwrap();
+
+        assert_eq!(
+            warmup(&manager, 2).await,
+            WarmupReport {
+                opened: 0,
+                failed: 2
+            }
+        );
+    }
+}
@@ -1,12 +1,14 @@
 use anyhow::{Context, Result};
-use pingora_core::server::Server;
-use pingora_proxy::http_proxy_service;
+use pingora_core::server::{RunArgs, Server};
+use pingora_core::services::listening::Service;
+use pingora_proxy::http_proxy;
 
 mod auth;
 mod cache;
 mod config;
 mod db;
 mod load_balancing;
+mod metrics;
 mod middleware;
 mod proxy;
 
@@ -28,13 +30,31 @@ fn main() -> Result<()> {
         .validate()
         .map_err(|e| anyhow::anyhow!("Configuration validation failed: {}", e))?;
 
+    // Before anything hashes a password
+    auth::PasswordManager::set_peppers(auth::Peppers::new(
+        settings.password.pepper.as_deref(),
+        settings.password.previous_pepper.as_deref(),
+    ));
+
+    // Fail fast on unreadable or malformed secret-encryption keys
+    let encryption = auth::encryption::EncryptionService::from_config(&settings.encryption)
+        .map_err(|e| anyhow::anyhow!("Failed to load encryption keys: {}", e))?;
+    if encryption.is_some() {
+        log::info!(
+            "✓ Secret encryption key version {} loaded",
+            settings.encryption.current_version
+        );
+    }
+
     log::info!("✓ Configuration loaded");
-    log::info!("  Listen port: {}", settings.server.listen_port);
-    log::info!("  Auth enabled: {}", settings.middleware.auth.enabled);
+    let summary = config::summary::StartupSummary::new(&settings);
     log::info!(
-        "  Rate limit enabled: {}",
-        settings.middleware.rate_limit.enabled
+        "Startup summary: {}",
+        serde_json::to_string(&summary).context("Failed to encode startup summary")?
     );
+    if settings.debug.pretty_json {
+        log::warn!("debug.pretty_json is on: JSON responses are indented; disable in production");
+    }
 
     // Create runtime for async initializations
     let rt = Runtime::new().context("Failed to create Tokio runtime")?;
@@ -60,6 +80,16 @@ fn main() -> Result<()> {
 
     log::info!("✓ Database connected");
 
+    // Make sure the configured admin account exists
+    if let Some((email, password)) = settings.admin.credentials() {
+        let outcome = rt.block_on(async {
+            auth::ensure_admin(&db::UserRepository::new(db_pool.inner()), &email, &password)
+                .await
+                .context("Failed to bootstrap admin user")
+        })?;
+        log::info!("✓ Admin user {} ({:?})", email, outcome);
+    }
+
     // Initialize Redis within async context
     log::info!("Initializing Redis...");
     let redis_client = rt.block_on(async {
@@ -83,7 +113,15 @@ fn main() -> Result<()> {
         settings.jwt.secret.clone(),
         settings.jwt.access_token_expiration,
         settings.jwt.refresh_token_expiration,
-    );
+    )
+    .with_absolute_expiry(settings.jwt.absolute_expiry)
+    .with_suspicious_access_token_expiration(settings.jwt.suspicious_access_token_expiration)
+    .with_suspicious_session_count(settings.jwt.suspicious_session_count)
+    .with_max_session_lifetime(settings.jwt.max_session_lifetime)
+    .with_single_session(settings.jwt.single_session)
+    .with_expired_grace(settings.jwt.expired_token_grace)
+    .with_clients(settings.jwt.clients.clone())
+    .with_leeway(settings.jwt.leeway_seconds);
     log::info!("✓ JWT manager initialized");
 
     // Initialize load balancer
@@ -94,41 +132,185 @@ fn main() -> Result<()> {
         "✓ Load balancer initialized with {} upstream(s)",
         settings.load_balancing.upstreams.len()
     );
+    let warmup_connections = settings.load_balancing.warmup_connections;
+    if warmup_connections > 0 {
+        let report = rt.block_on(load_balancing::warmup::warmup(
+            &load_balancer,
+            warmup_connections,
+        ));
+        log::info!(
+            "✓ Upstream warmup: {} connection(s) opened, {} failed",
+            report.opened,
+            report.failed
+        );
+    }
+    let insecure = load_balancer.insecure_upstreams();
+    if !insecure.is_empty() {
+        log::warn!(
+            "⚠ TLS certificate verification DISABLED for upstream(s): {}",
+            insecure.join(", ")
+        );
+    }
+
+    // Auth service group, when /auth/* is delegated to it
+    let auth = &settings.middleware.auth;
+    let auth_load_balancer = match auth.backend {
+        config::settings::AuthBackend::Builtin => None,
+        config::settings::AuthBackend::Upstream => {
+            let manager = load_balancing::manager::LoadBalancerManager::new(
+                config::settings::LoadBalancingConfig {
+                    strategy: "round_robin".to_string(),
+                    fallback: vec!["any".to_string()],
+                    upstreams: auth.upstreams.clone(),
+                    tls: settings.load_balancing.tls.clone(),
+                    warmup_connections: 0,
+                    connect_timeout_secs: settings.load_balancing.connect_timeout_secs,
+                    read_timeout_secs: settings.load_balancing.read_timeout_secs,
+                    retry: settings.load_balancing.retry.clone(),
+                    passive_ejection: settings.load_balancing.passive_ejection.clone(),
+                },
+            )?;
+            log::info!(
+                "✓ /auth/* proxied to {} auth upstream(s)",
+                auth.upstreams.len()
+            );
+            Some(manager)
+        }
+    };
+
+    // Build routing table
+    let router =
+        proxy::routing::Router::new(&settings.routing).context("Failed to build routing table")?;
+    log::info!("✓ {} route(s) configured", settings.routing.routes.len());
 
     // Create proxy service
-    let proxy_service = proxy::service::ProxyService::new(
+    let mut proxy_service = proxy::service::ProxyService::new(
         settings.clone(),
         db_pool.inner().clone(),
         redis_client,
         jwt_manager,
         load_balancer,
+        auth_load_balancer,
+        router,
     );
+    if let Some(encryption) = encryption {
+        proxy_service = proxy_service.with_encryption(encryption);
+    }
+    let readiness = proxy_service.readiness();
 
     // Create Pingora server
     let mut server = Server::new(None).context("Failed to create server")?;
     server.bootstrap();
 
     // Create HTTP proxy service
-    let mut proxy = http_proxy_service(&server.configuration, proxy_service);
-    proxy.add_tcp(&format!("0.0.0.0:{}", settings.server.listen_port));
+    let listen_ip = settings.server.listen_ip().map_err(anyhow::Error::msg)?;
+    let socket_options = proxy::listener::with_ipv6_only(
+        proxy::listener::tcp_socket_options(&settings.server.tcp),
+        listen_ip,
+        settings.server.ipv6_only,
+    );
+
+    // Slow header senders are cut off before Pingora hands the request to the proxy
+    let mut proxy = Service::new(
+        "Pingora HTTP Proxy Service".to_string(),
+        proxy::header_deadline::HeaderDeadline::new(
+            http_proxy(&server.configuration, proxy_service),
+            settings
+                .server
+                .header_read_timeout_secs
+                .map(std::time::Duration::from_secs),
+        ),
+    );
+    let proxy_address = proxy::listener::bind_address(listen_ip, settings.server.listen_port);
+    match &settings.server.tls {
+        Some(tls) => {
+            let tls_settings = proxy::listener::tls_settings(tls)
+                .map_err(|e| anyhow::anyhow!("Invalid server.tls: {}", e))?;
+            proxy.add_tls_with_settings(
+                &proxy_address,
+                Some(socket_options.clone()),
+                tls_settings,
+            );
+            log::info!(
+                "✓ TLS on {} (minimum {})",
+                proxy_address,
+                tls.min_tls_version
+            );
+        }
+        None => proxy.add_tcp_with_settings(&proxy_address, socket_options.clone()),
+    }
+
+    // Admin and metrics routes on their own port, if configured (over TLS
+    // too when the proxy listener uses it)
+    if let Some(admin) = &settings.server.admin_listener {
+        let address = proxy::listener::bind_address(listen_ip, admin.port);
+        match &settings.server.tls {
+            Some(tls) => {
+                let tls_settings = proxy::listener::tls_settings(tls)
+                    .map_err(|e| anyhow::anyhow!("Invalid server.tls: {}", e))?;
+                proxy.add_tls_with_settings(&address, Some(socket_options.clone()), tls_settings);
+                log::info!("✓ Admin listener on {} (TLS)", address);
+            }
+            None => {
+                proxy.add_tcp_with_settings(&address, socket_options.clone());
+                log::info!("✓ Admin listener on {}", address);
+            }
+        }
+    }
 
     // Add service to server
     server.add_service(proxy);
 
+    // Expose Prometheus metrics on a separate port
+    if let Some(metrics_port) = settings.server.metrics_port {
+        let address = proxy::listener::bind_address(listen_ip, metrics_port);
+        let mut prometheus_service = Service::prometheus_http_service();
+        prometheus_service.add_tcp_with_settings(
+            &address,
+            proxy::listener::with_ipv6_only(
+                Default::default(),
+                listen_ip,
+                settings.server.ipv6_only,
+            ),
+        );
+        server.add_service(prometheus_service);
+        log::info!("✓ Metrics available on {}/metrics", address);
+    }
+
     log::info!("\n========================================");
     log::info!(
-        "✓ Server starting on 0.0.0.0:{}",
-        settings.server.listen_port
+        "✓ Server starting on {}",
+        proxy::listener::bind_address(listen_ip, settings.server.listen_port)
     );
     log::info!("========================================\n");
 
     log::info!("Available endpoints:");
-    log::info!("  POST /auth/register  - Register new user");
-    log::info!("  POST /auth/login     - User login");
-    log::info!("  POST /auth/refresh   - Refresh access token");
-    log::info!("  POST /auth/logout    - User logout");
-    log::info!("  *                    - Proxied to backend (requires auth)\n");
-
-    // Run server
-    server.run_forever();
+    log::info!("  POST /auth/register          - Register new user");
+    log::info!("  POST /auth/login             - User login");
+    log::info!("  POST /auth/refresh           - Refresh access token");
+    log::info!("  POST /auth/logout            - User logout");
+    log::info!("  POST /auth/logout-all        - Sign out every session");
+    log::info!("  POST /auth/change-password   - Change password");
+    log::info!("  POST /auth/validate-password - Check a password against the policy");
+    if settings.server.openapi {
+        log::info!("  GET  /auth/openapi.json      - OpenAPI description");
+    }
+    log::info!("  GET  /health, /ready         - Liveness and readiness probes");
+    match &settings.server.admin_listener {
+        Some(admin) => log::info!(
+            "  *    /admin/*                - Admin API (port {} only)",
+            admin.port
+        ),
+        None => log::info!("  *    /admin/*                - Admin API (admin role)"),
+    }
+    log::info!("  *                            - Proxied to backend (requires auth)\n");
+
+    // Run server; SIGTERM fails /ready for drain_timeout_secs before shutting down
+    server.run(RunArgs {
+        shutdown_signal: Box::new(proxy::drain::DrainingShutdown::new(
+            readiness,
+            std::time::Duration::from_secs(settings.server.drain_timeout_secs),
+        )),
+    });
+    std::process::exit(0)
 }
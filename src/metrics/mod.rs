@@ -0,0 +1,286 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+};
+use std::time::Duration;
+
+/// Fraction of rate-limit checks that report their bucket fill
+pub const BUCKET_FILL_SAMPLE_RATE: f64 = 0.01;
+
+/// Latency histogram bucket upper bounds in seconds, 5ms to 10s
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Time from request arrival to the end of the response, by route prefix
+pub static REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "request_duration_seconds",
+        "Total request time, from arrival to the last byte sent",
+        &["route"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+/// Time the upstream took to send response headers, by route prefix
+pub static UPSTREAM_RESPONSE_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "upstream_response_duration_seconds",
+        "Time from sending the request upstream to its response headers",
+        &["route"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+/// Requests that failed authentication while auth ran in shadow mode
+pub static AUTH_WOULD_FAIL: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "auth_would_fail",
+        "Requests that would have been rejected by authentication in shadow mode"
+    )
+    .unwrap()
+});
+
+/// Finished requests by authentication decision
+pub static AUTH_DECISIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "auth_decisions_total",
+        "Requests by authentication outcome (skipped / authenticated / rejected / shadow)",
+        &["decision"]
+    )
+    .unwrap()
+});
+
+/// Logins that upgraded a password hash made with an outdated cost
+pub static PASSWORD_REHASHES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "password_rehashes_total",
+        "Password hashes upgraded to the current cost on login"
+    )
+    .unwrap()
+});
+
+/// Revoked refresh tokens presented again, each triggering a mass revocation
+pub static REFRESH_TOKEN_REUSE_DETECTED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "refresh_token_reuse_detected",
+        "Rotated-out refresh tokens replayed; all of the user's tokens were revoked"
+    )
+    .unwrap()
+});
+
+/// Requests abandoned by the client before a response was sent
+pub static CLIENT_DISCONNECTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "client_disconnect",
+        "Requests where the downstream client closed the connection early"
+    )
+    .unwrap()
+});
+
+/// Failed request body reads on locally handled endpoints, by reason
+/// (`client_abort` / `malformed` / `too_large`)
+pub static REQUEST_BODY_READ_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "request_body_read_errors_total",
+        "Request bodies that could not be read, by reason",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+/// Rate-limit rejections by route prefix and client type (user / ip / anonymous)
+pub static RATE_LIMIT_REJECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "rate_limit_rejections_total",
+        "Requests rejected by the rate limiter",
+        &["route", "client_type"]
+    )
+    .unwrap()
+});
+
+/// Remaining tokens in the bucket of the last sampled client
+pub static RATE_LIMIT_BUCKET_FILL: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "rate_limit_bucket_fill",
+        "Remaining rate-limit tokens for sampled clients",
+        &["route", "client_type"]
+    )
+    .unwrap()
+});
+
+/// Requests copied to a shadow upstream, by result (`ok` / `error`)
+pub static MIRROR_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "mirror_requests_total",
+        "Requests mirrored to a shadow upstream; errors are 5xx or failed sends",
+        &["result"]
+    )
+    .unwrap()
+});
+
+/// Upstream connections refused because the certificate differs from its
+/// trust-on-first-use pin, by upstream `address:port`
+pub static UPSTREAM_CERT_PIN_MISMATCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "upstream_cert_pin_mismatches_total",
+        "Upstream connections refused because the certificate changed since it was pinned",
+        &["upstream"]
+    )
+    .unwrap()
+});
+
+/// Duplicate `Idempotency-Key` requests answered from the recorded response
+pub static IDEMPOTENT_REPLAYS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "idempotent_replays_total",
+        "Duplicate Idempotency-Key requests answered without reaching the upstream"
+    )
+    .unwrap()
+});
+
+/// GETs answered with the response of an identical request in flight
+pub static COALESCED_REQUESTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "coalesced_requests_total",
+        "GETs answered with the response fetched for an identical concurrent request"
+    )
+    .unwrap()
+});
+
+/// Rate-limit service calls that failed or timed out
+pub static EXTERNAL_RATE_LIMIT_ERRORS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "external_rate_limit_errors_total",
+        "Rate-limit service calls that failed or timed out (decided by fail_open)"
+    )
+    .unwrap()
+});
+
+/// Responses served while a route's upstreams were all ejected, by route
+/// prefix and source (`last_good` / `static`)
+pub static FALLBACK_RESPONSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "fallback_responses_total",
+        "Requests answered with a fallback response while the breaker was open",
+        &["route", "source"]
+    )
+    .unwrap()
+});
+
+/// Requests currently being handled
+pub static ACTIVE_REQUESTS: Lazy<IntGauge> =
+    Lazy::new(|| register_int_gauge!("active_requests", "Requests currently in flight").unwrap());
+
+/// Most requests in flight at once since startup
+pub static ACTIVE_REQUESTS_PEAK: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "active_requests_peak",
+        "High-water mark of concurrent requests since startup"
+    )
+    .unwrap()
+});
+
+/// Count a rate-limit rejection
+///
+/// # Arguments
+/// * `route` - Matched route prefix (never the full path, to bound cardinality)
+/// * `client_type` - `user`, `ip` or `anonymous`
+pub fn record_rate_limit_rejection(route: &str, client_type: &str) {
+    RATE_LIMIT_REJECTIONS
+        .with_label_values(&[route, client_type])
+        .inc();
+}
+
+/// Observe a finished request's latencies
+///
+/// # Arguments
+/// * `route` - Matched route prefix (never the full path, to bound cardinality)
+/// * `total` - Whole request time
+/// * `upstream` - Upstream response time, if the request was proxied
+pub fn record_request_latency(route: &str, total: Duration, upstream: Option<Duration>) {
+    REQUEST_DURATION
+        .with_label_values(&[route])
+        .observe(total.as_secs_f64());
+    if let Some(upstream) = upstream {
+        UPSTREAM_RESPONSE_DURATION
+            .with_label_values(&[route])
+            .observe(upstream.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::core::Metric;
+
+    #[test]
+    fn test_rate_limit_rejection_is_labeled() {
+        let before_user = RATE_LIMIT_REJECTIONS
+            .with_label_values(&["/metrics-test", "user"])
+            .get();
+        let before_ip = RATE_LIMIT_REJECTIONS
+            .with_label_values(&["/metrics-test", "ip"])
+            .get();
+
+        record_rate_limit_rejection("/metrics-test", "user");
+
+        let rejections = |client_type| {
+            RATE_LIMIT_REJECTIONS
+                .with_label_values(&["/metrics-test", client_type])
+                .get()
+        };
+        assert_eq!(rejections("user"), before_user + 1);
+        assert_eq!(rejections("ip"), before_ip);
+    }
+
+    /// Cumulative observation count per bucket upper bound
+    fn buckets(histogram: &HistogramVec, route: &str) -> Vec<(f64, u64)> {
+        histogram
+            .with_label_values(&[route])
+            .metric()
+            .get_histogram()
+            .get_bucket()
+            .iter()
+            .map(|bucket| (bucket.get_upper_bound(), bucket.get_cumulative_count()))
+            .collect()
+    }
+
+    #[test]
+    fn test_request_latency_buckets() {
+        let route = "/latency-test";
+        for (total_ms, upstream_ms) in [(3, Some(2)), (40, Some(30)), (40, Some(35)), (2000, None)]
+        {
+            record_request_latency(
+                route,
+                Duration::from_millis(total_ms),
+                upstream_ms.map(Duration::from_millis),
+            );
+        }
+
+        let total = buckets(&REQUEST_DURATION, route);
+        let count_at = |buckets: &[(f64, u64)], bound: f64| {
+            buckets.iter().find(|(upper, _)| *upper == bound).unwrap().1
+        };
+        assert_eq!(count_at(&total, 0.005), 1);
+        assert_eq!(count_at(&total, 0.025), 1);
+        assert_eq!(count_at(&total, 0.05), 3);
+        assert_eq!(count_at(&total, 1.0), 3);
+        assert_eq!(count_at(&total, 2.5), 4);
+
+        // The unproxied request has no upstream time
+        let upstream = buckets(&UPSTREAM_RESPONSE_DURATION, route);
+        assert_eq!(count_at(&upstream, 0.005), 1);
+        assert_eq!(count_at(&upstream, 0.05), 3);
+        assert_eq!(count_at(&upstream, 10.0), 3);
+
+        // Other routes are labeled separately
+        assert_eq!(
+            count_at(&buckets(&REQUEST_DURATION, "/other-latency-test"), 10.0),
+            0
+        );
+    }
+}
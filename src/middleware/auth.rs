@@ -0,0 +1,159 @@
+use crate::auth::compare;
+use crate::config::settings::{AuthConfig, StaticAuthType};
+use base64::Engine;
+use pingora_http::RequestHeader;
+
+/// Static credential checks for machine clients
+///
+/// The credential scheme comes from `middleware.auth.auth_type`:
+/// * `api_key` - `X-API-Key: <token>`
+/// * `bearer` - `Authorization: Bearer <token>`
+/// * `basic` - `Authorization: Basic base64(user:password)`, with
+///   `valid_tokens` entries of the form `user:password`
+///
+/// Only SHA-256 digests of the tokens are kept, and presented tokens are
+/// compared against them in constant time.
+pub struct AuthMiddleware {
+    auth_type: StaticAuthType,
+    valid_token_digests: Vec<[u8; 32]>,
+}
+
+impl AuthMiddleware {
+    pub fn new(config: &AuthConfig) -> Self {
+        Self {
+            auth_type: config.auth_type,
+            valid_token_digests: config
+                .valid_tokens
+                .iter()
+                .map(|token| compare::secret_digest(token))
+                .collect(),
+        }
+    }
+
+    /// Verify the request carries one of the configured static tokens
+    pub fn verify_request(&self, req: &RequestHeader) -> Result<(), String> {
+        let token = match self.auth_type {
+            StaticAuthType::ApiKey => header(req, "X-API-Key")?.to_string(),
+            StaticAuthType::Bearer => header(req, "Authorization")?
+                .strip_prefix("Bearer ")
+                .ok_or_else(|| "Invalid authorization header format".to_string())?
+                .to_string(),
+            StaticAuthType::Basic => {
+                let encoded = header(req, "Authorization")?
+                    .strip_prefix("Basic ")
+                    .ok_or_else(|| "Invalid authorization header format".to_string())?;
+                let decoded = base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .map_err(|_| "Invalid basic credentials encoding".to_string())?;
+                String::from_utf8(decoded)
+                    .map_err(|_| "Invalid basic credentials encoding".to_string())?
+            }
+        };
+
+        if compare::matches_any_digest(&token, &self.valid_token_digests) {
+            Ok(())
+        } else {
+            Err(format!("Invalid {} credentials", self.auth_type.as_str()))
+        }
+    }
+}
+
+/// Required header value as a string
+fn header<'a>(req: &'a RequestHeader, name: &str) -> Result<&'a str, String> {
+    req.headers
+        .get(name)
+        .ok_or_else(|| format!("Missing {} header", name))?
+        .to_str()
+        .map_err(|_| format!("Invalid {} header", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build the middleware from a YAML `middleware.auth` section
+    fn middleware(yaml: &str) -> AuthMiddleware {
+        let config: AuthConfig = serde_yaml::from_str(yaml).unwrap();
+        AuthMiddleware::new(&config)
+    }
+
+    fn request(header: Option<(&'static str, String)>) -> RequestHeader {
+        let mut req = RequestHeader::build("GET", b"/internal/jobs", None).unwrap();
+        if let Some((name, value)) = header {
+            req.insert_header(name, value).unwrap();
+        }
+        req
+    }
+
+    fn basic(credentials: &str) -> Option<(&'static str, String)> {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        Some(("Authorization", format!("Basic {}", encoded)))
+    }
+
+    #[test]
+    fn test_api_key() {
+        let auth = middleware(
+            r#"
+            enabled: true
+            auth_type: api_key
+            valid_tokens: ["machine-key-1"]
+            "#,
+        );
+
+        let key = |value: &str| request(Some(("X-API-Key", value.to_string())));
+        assert!(auth.verify_request(&key("machine-key-1")).is_ok());
+        assert!(auth.verify_request(&key("wrong-key")).is_err());
+        assert!(auth.verify_request(&request(None)).is_err());
+    }
+
+    #[test]
+    fn test_only_token_digests_are_kept() {
+        let auth = middleware("valid_tokens: [\"machine-key-1\"]");
+        assert_eq!(
+            auth.valid_token_digests,
+            vec![compare::secret_digest("machine-key-1")]
+        );
+    }
+
+    #[test]
+    fn test_api_key_is_default_type() {
+        let auth = middleware("valid_tokens: [\"machine-key-1\"]");
+        let req = request(Some(("X-API-Key", "machine-key-1".to_string())));
+        assert!(auth.verify_request(&req).is_ok());
+    }
+
+    #[test]
+    fn test_basic() {
+        let auth = middleware(
+            r#"
+            enabled: true
+            auth_type: basic
+            valid_tokens: ["ci:s3cret"]
+            "#,
+        );
+
+        assert!(auth.verify_request(&request(basic("ci:s3cret"))).is_ok());
+        assert!(auth.verify_request(&request(basic("ci:wrong"))).is_err());
+        assert!(auth
+            .verify_request(&request(Some(("Authorization", "Basic !!!".to_string()))))
+            .is_err());
+
+        // An API key header is not a basic credential
+        let req = request(Some(("X-API-Key", "ci:s3cret".to_string())));
+        assert!(auth.verify_request(&req).is_err());
+    }
+
+    #[test]
+    fn test_bearer() {
+        let auth = middleware(
+            r#"
+            auth_type: bearer
+            valid_tokens: ["static-token"]
+            "#,
+        );
+
+        let bearer = |value: &str| request(Some(("Authorization", format!("Bearer {}", value))));
+        assert!(auth.verify_request(&bearer("static-token")).is_ok());
+        assert!(auth.verify_request(&bearer("other-token")).is_err());
+    }
+}
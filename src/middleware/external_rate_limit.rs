@@ -0,0 +1,293 @@
+use http::HeaderMap;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::settings::ExternalRateLimitConfig;
+use crate::metrics;
+use crate::proxy::http_client::{Endpoint, HttpClient, HttpClientError};
+
+/// Largest rate-limit service response read; anything longer is an error
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+/// What the rate-limit service decided, with the headers it wants passed on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalDecision {
+    /// Forward the request, adding the headers to the response
+    Allow(Vec<(String, String)>),
+    /// Answer 429 with the headers
+    Deny(Vec<(String, String)>),
+}
+
+/// Rate limiting delegated to a service speaking the Envoy ratelimit JSON API
+///
+/// Each request is described by its client, route and method and `POST`ed
+/// to the service, which answers 200 (allow) or 429 (over limit).
+/// `X-RateLimit-*` and `Retry-After` headers of its answer are passed to
+/// the client. Requests go over the shared [`HttpClient`], so connections
+/// to the service are kept alive.
+pub struct ExternalRateLimiter {
+    client: Arc<HttpClient>,
+    /// `None` when `address` is not `host:port`; every check then fails
+    endpoint: Option<Endpoint>,
+    address: String,
+    domain: String,
+    timeout: Duration,
+    fail_open: bool,
+}
+
+impl ExternalRateLimiter {
+    pub fn new(config: &ExternalRateLimitConfig, client: Arc<HttpClient>) -> Self {
+        let endpoint = config
+            .address
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+            .map(|(host, port)| Endpoint::new(host, port, false, &config.path));
+        Self {
+            client,
+            endpoint,
+            address: config.address.clone(),
+            domain: config.domain.clone(),
+            timeout: Duration::from_millis(config.timeout_ms),
+            fail_open: config.fail_open,
+        }
+    }
+
+    /// Ask the service whether a request may pass
+    ///
+    /// A service that is unreachable, slow or answers anything but 200 /
+    /// 429 lets the request through with `fail_open`, and rejects it
+    /// otherwise. Failures are counted in `external_rate_limit_errors_total`.
+    ///
+    /// # Arguments
+    /// * `client_id` - Rate-limit client (`user:<id>`, `ip:<addr>`, ...)
+    /// * `route` - Matched route prefix
+    /// * `method` - Request method
+    pub async fn check(&self, client_id: &str, route: &str, method: &str) -> ExternalDecision {
+        let body = self.encode_request(client_id, route, method);
+
+        let error = match self.send(body).await {
+            Ok(decision) => return decision,
+            Err(e) => e.to_string(),
+        };
+
+        metrics::EXTERNAL_RATE_LIMIT_ERRORS.inc();
+        log::warn!(
+            "Rate-limit service {} failed for {}: {}; {}",
+            self.address,
+            client_id,
+            error,
+            if self.fail_open {
+                "allowing"
+            } else {
+                "rejecting"
+            }
+        );
+        if self.fail_open {
+            ExternalDecision::Allow(Vec::new())
+        } else {
+            ExternalDecision::Deny(Vec::new())
+        }
+    }
+
+    /// `ShouldRateLimit` request body with one descriptor for the request
+    fn encode_request(&self, client_id: &str, route: &str, method: &str) -> Vec<u8> {
+        json!({
+            "domain": self.domain,
+            "descriptors": [{
+                "entries": [
+                    { "key": "client_id", "value": client_id },
+                    { "key": "route", "value": route },
+                    { "key": "method", "value": method },
+                ]
+            }]
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// Post the request to the service
+    async fn send(&self, body: Vec<u8>) -> Result<ExternalDecision, HttpClientError> {
+        let endpoint = self
+            .endpoint
+            .as_ref()
+            .ok_or_else(|| HttpClientError::InvalidUrl(self.address.clone()))?;
+        let response = self
+            .client
+            .post_json(endpoint, body, self.timeout, MAX_RESPONSE_SIZE)
+            .await?;
+        decision(response.status, &response.headers)
+    }
+}
+
+/// Decision from the service's response status and headers
+fn decision(status: u16, headers: &HeaderMap) -> Result<ExternalDecision, HttpClientError> {
+    let headers = headers
+        .iter()
+        .filter(|(name, _)| {
+            name.as_str().starts_with("x-ratelimit-") || name.as_str() == "retry-after"
+        })
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+
+    match status {
+        200 => Ok(ExternalDecision::Allow(headers)),
+        429 => Ok(ExternalDecision::Deny(headers)),
+        status => Err(HttpClientError::Request(format!(
+            "unexpected status {}",
+            status
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn limiter(address: String, fail_open: bool) -> ExternalRateLimiter {
+        ExternalRateLimiter::new(
+            &ExternalRateLimitConfig {
+                address,
+                path: "/json".to_string(),
+                domain: "proxy".to_string(),
+                timeout_ms: 200,
+                fail_open,
+            },
+            Arc::new(HttpClient::new()),
+        )
+    }
+
+    /// Rate-limit service answering one request with `response`
+    async fn mock_service(response: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let handle = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 1024];
+            // Request head and the whole JSON body
+            let complete = |received: &[u8]| {
+                let text = String::from_utf8_lossy(received);
+                text.split_once("\r\n\r\n").is_some_and(|(_, body)| {
+                    serde_json::from_str::<serde_json::Value>(body).is_ok()
+                })
+            };
+            while !complete(&received) {
+                let n = conn.read(&mut buf).await.unwrap();
+                assert!(n > 0, "connection closed early");
+                received.extend_from_slice(&buf[..n]);
+            }
+            conn.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(received).unwrap()
+        });
+
+        (address, handle)
+    }
+
+    #[tokio::test]
+    async fn test_service_allows() {
+        let (address, service) = mock_service(
+            "HTTP/1.1 200 OK\r\nX-RateLimit-Remaining: 41\r\nServer: rls\r\nContent-Length: 0\r\n\r\n",
+        )
+        .await;
+
+        let decision = limiter(address, true).check("user:42", "/api", "GET").await;
+        assert_eq!(
+            decision,
+            ExternalDecision::Allow(vec![(
+                "x-ratelimit-remaining".to_string(),
+                "41".to_string()
+            )])
+        );
+
+        let received = service.await.unwrap();
+        assert!(received.starts_with("POST /json HTTP/1.1\r\n"));
+        let body: serde_json::Value =
+            serde_json::from_str(received.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["domain"], "proxy");
+        assert_eq!(
+            body["descriptors"][0]["entries"][0],
+            json!({ "key": "client_id", "value": "user:42" })
+        );
+        assert_eq!(
+            body["descriptors"][0]["entries"][1],
+            json!({ "key": "route", "value": "/api" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_service_denies() {
+        let (address, _service) = mock_service(
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 30\r\nContent-Length: 0\r\n\r\n",
+        )
+        .await;
+
+        let decision = limiter(address, true)
+            .check("ip:203.0.113.7", "/api", "POST")
+            .await;
+        assert_eq!(
+            decision,
+            ExternalDecision::Deny(vec![("retry-after".to_string(), "30".to_string())])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_service_fails_open_or_closed() {
+        // Nothing listens on the port once the listener is dropped
+        let address = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let errors_before = metrics::EXTERNAL_RATE_LIMIT_ERRORS.get();
+
+        assert_eq!(
+            limiter(address.clone(), true)
+                .check("user:42", "/api", "GET")
+                .await,
+            ExternalDecision::Allow(Vec::new())
+        );
+        assert_eq!(
+            limiter(address, false)
+                .check("user:42", "/api", "GET")
+                .await,
+            ExternalDecision::Deny(Vec::new())
+        );
+        assert!(metrics::EXTERNAL_RATE_LIMIT_ERRORS.get() >= errors_before + 2);
+    }
+
+    #[tokio::test]
+    async fn test_slow_service_times_out() {
+        // Accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let decision = limiter(address, true).check("user:42", "/api", "GET").await;
+        assert_eq!(decision, ExternalDecision::Allow(Vec::new()));
+        drop(listener);
+    }
+
+    #[test]
+    fn test_unexpected_status_is_an_error() {
+        assert!(decision(500, &HeaderMap::new()).is_err());
+        assert!(decision(302, &HeaderMap::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_address_fails_open_or_closed() {
+        assert_eq!(
+            limiter("ratelimit".to_string(), true)
+                .check("user:42", "/api", "GET")
+                .await,
+            ExternalDecision::Allow(Vec::new())
+        );
+        assert_eq!(
+            limiter("ratelimit".to_string(), false)
+                .check("user:42", "/api", "GET")
+                .await,
+            ExternalDecision::Deny(Vec::new())
+        );
+    }
+}
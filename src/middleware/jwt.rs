@@ -1,21 +1,75 @@
+use crate::auth::jwks::IdpVerifier;
+use crate::auth::jwt::{peek_claims, Claims};
 use crate::auth::JwtManager;
 use pingora_http::{RequestHeader, ResponseHeader};
 
 pub struct JwtMiddleware {
     jwt_manager: JwtManager,
+    /// External IdP whose tokens are also accepted (`jwt.idp`)
+    idp: Option<IdpVerifier>,
+}
+
+/// Why a bearer token was not accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenRejection {
+    /// No `Authorization: Bearer` header
+    Missing,
+    /// Bad signature, expired or malformed
+    Invalid,
+    /// Genuine token that expired within the grace (`jwt.expired_token_grace`)
+    Expired,
+    /// Valid token of another type (e.g. a refresh token)
+    WrongType(String),
 }
 
 impl JwtMiddleware {
     pub fn new(jwt_manager: JwtManager) -> Self {
-        Self { jwt_manager }
+        Self {
+            jwt_manager,
+            idp: None,
+        }
+    }
+
+    /// Also accept access tokens signed by an external IdP
+    pub fn with_idp(mut self, idp: IdpVerifier) -> Self {
+        self.idp = Some(idp);
+        self
     }
-    pub fn verify_request(&self, req: &RequestHeader) -> Option<String> {
-        let auth_header = req.headers.get("Authorization")?;
-        let auth_str = auth_header.to_str().ok()?;
+
+    /// Verify the Bearer token as one issued by the external IdP
+    ///
+    /// # Returns
+    /// * `Option<String>` - The IdP's subject, or `None` if no IdP is
+    ///   configured or it did not issue this token
+    pub async fn verify_idp(&self, req: &RequestHeader) -> Option<String> {
+        let idp = self.idp.as_ref()?;
+        let token = req
+            .headers
+            .get("Authorization")?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?;
+
+        match idp.verify(token).await {
+            Ok(claims) => {
+                log::debug!("IdP token verified for subject: {}", claims.sub);
+                Some(claims.sub)
+            }
+            Err(e) => {
+                log::warn!("IdP token verification failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Verify the Bearer access token, saying why it was rejected
+    pub fn check_claims(&self, req: &RequestHeader) -> Result<Claims, TokenRejection> {
+        let auth_header = req.headers.get("Authorization").ok_or(TokenRejection::Missing)?;
+        let auth_str = auth_header.to_str().map_err(|_| TokenRejection::Missing)?;
 
         if !auth_str.starts_with("Bearer ") {
             log::warn!("Invalid authorization header format");
-            return None;
+            return Err(TokenRejection::Missing);
         }
 
         let token = &auth_str[7..];
@@ -24,15 +78,23 @@ impl JwtMiddleware {
             Ok(claims) => {
                 if claims.token_type != "access" {
                     log::warn!("Wrong token type: expected 'access', got '{}'", claims.token_type);
-                    return None;
+                    return Err(TokenRejection::WrongType(claims.token_type));
                 }
                 
                 log::debug!("Token verified for user: {}", claims.sub);
-                Some(claims.sub)
+                Ok(claims)
             }
             Err(e) => {
-                log::warn!("Token verification failed: {}", e);
-                None
+                // Unverified: who the token claims to be, for diagnostics only
+                let claimed = peek_claims(token).unwrap_or_default();
+                log::warn!(
+                    "Token verification failed: {} (claimed sub {:?}, jti {:?})",
+                    e, claimed.sub, claimed.jti
+                );
+                if self.jwt_manager.recently_expired(token) {
+                    return Err(TokenRejection::Expired);
+                }
+                Err(TokenRejection::Invalid)
             }
         }
     }
@@ -51,6 +113,7 @@ impl JwtMiddleware {
             "/auth/register",
             "/auth/login", 
             "/health",
+            "/ready",
         ];
 
         !public_paths.iter().any(|&p| path.starts_with(p))
@@ -64,6 +127,7 @@ mod tests {
     #[test]
     fn test_requires_auth() {
         assert!(!JwtMiddleware::requires_auth("/health"));
+        assert!(!JwtMiddleware::requires_auth("/ready"));
         assert!(!JwtMiddleware::requires_auth("/auth/register"));
         assert!(!JwtMiddleware::requires_auth("/auth/login"));
         
@@ -0,0 +1,197 @@
+use async_trait::async_trait;
+use dashmap::DashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::middleware::rate_limit::RateLimiter;
+
+/// Bucket count above which idle buckets are pruned
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Shortest time between two prunes, so a large map is not scanned on
+/// every request
+const PRUNE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// In-process token bucket limiter
+///
+/// Buckets live in a sharded `DashMap`, so each proxy instance enforces the
+/// limit on its own: with N instances a client can get up to N times the
+/// configured rate. Use the Redis backend when limits must be shared.
+///
+/// A bucket idle long enough to have refilled completely is the same as a
+/// new one, and is dropped.
+pub struct MemoryRateLimiter {
+    /// Client ID -> (remaining tokens, last refill)
+    buckets: DashMap<String, (u32, Instant)>,
+    requests_per_minute: u32,
+    burst_size: u32,
+    /// When idle buckets were last pruned
+    last_prune: Mutex<Instant>,
+}
+
+impl MemoryRateLimiter {
+    pub fn new(requests_per_minute: u32, burst_size: u32) -> Self {
+        Self {
+            buckets: DashMap::new(),
+            requests_per_minute,
+            burst_size,
+            last_prune: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Drop full buckets, at most once per `PRUNE_INTERVAL`
+    ///
+    /// A request arriving while another one prunes skips the prune.
+    fn prune(&self, now: Instant) {
+        // Without a refill rate a bucket never fills up again
+        if self.requests_per_minute == 0 {
+            return;
+        }
+        let Ok(mut last_prune) = self.last_prune.try_lock() else {
+            return;
+        };
+        if now.saturating_duration_since(*last_prune) < PRUNE_INTERVAL {
+            return;
+        }
+        *last_prune = now;
+
+        let refill_time = Duration::from_secs_f64(
+            self.burst_size as f64 * 60.0 / self.requests_per_minute as f64,
+        );
+        self.buckets.retain(|_, (_, last_refill)| {
+            now.saturating_duration_since(*last_refill) < refill_time
+        });
+    }
+
+    /// Token bucket check as of `now`
+    /// Returns (allowed, remaining_tokens)
+    fn check_at(&self, client_id: &str, now: Instant) -> (bool, u32) {
+        if self.buckets.len() > PRUNE_THRESHOLD {
+            self.prune(now);
+        }
+
+        let mut bucket = self
+            .buckets
+            .entry(client_id.to_string())
+            .or_insert((self.burst_size, now));
+        let (tokens, last_refill) = *bucket;
+
+        // Refill whole tokens only, keeping the remainder for the next check
+        let refill_rate = self.requests_per_minute as f64 / 60.0; // tokens per second
+        let elapsed = now.saturating_duration_since(last_refill).as_secs_f64();
+        let tokens_to_add = (elapsed * refill_rate) as u32;
+        let (tokens, last_refill) = if tokens_to_add > 0 {
+            ((tokens + tokens_to_add).min(self.burst_size), now)
+        } else {
+            (tokens, last_refill)
+        };
+
+        if tokens > 0 {
+            *bucket = (tokens - 1, last_refill);
+            (true, tokens - 1)
+        } else {
+            *bucket = (0, last_refill);
+            log::warn!("Rate limit exceeded for {}: 0 tokens remaining", client_id);
+            (false, 0)
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for MemoryRateLimiter {
+    async fn check_with_remaining(&self, client_id: &str) -> (bool, Option<u32>) {
+        let (allowed, remaining) = self.check_at(client_id, Instant::now());
+        (allowed, Some(remaining))
+    }
+
+    fn get_limit(&self) -> u32 {
+        self.requests_per_minute
+    }
+
+    fn get_burst_size(&self) -> u32 {
+        self.burst_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_enforces_burst_size() {
+        let limiter = MemoryRateLimiter::new(60, 3);
+        let now = Instant::now();
+
+        assert_eq!(limiter.check_at("ip:1", now), (true, 2));
+        assert_eq!(limiter.check_at("ip:1", now), (true, 1));
+        assert_eq!(limiter.check_at("ip:1", now), (true, 0));
+        assert_eq!(limiter.check_at("ip:1", now), (false, 0));
+
+        // Other clients have their own bucket
+        assert_eq!(limiter.check_at("ip:2", now), (true, 2));
+    }
+
+    #[test]
+    fn test_refills_over_time() {
+        let limiter = MemoryRateLimiter::new(60, 3); // 1 token per second
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            limiter.check_at("ip:1", start);
+        }
+        assert!(!limiter.check_at("ip:1", start).0);
+
+        // Half a second is not enough for a token
+        assert!(
+            !limiter
+                .check_at("ip:1", start + Duration::from_millis(500))
+                .0
+        );
+
+        // Two seconds refill two tokens
+        let later = start + Duration::from_secs(2);
+        assert_eq!(limiter.check_at("ip:1", later), (true, 1));
+
+        // Never above the burst size
+        let much_later = later + Duration::from_secs(600);
+        assert_eq!(limiter.check_at("ip:1", much_later), (true, 2));
+    }
+
+    #[test]
+    fn test_idle_buckets_pruned() {
+        let limiter = MemoryRateLimiter::new(60, 3); // full again after 3s
+        let start = Instant::now();
+        let pruned_at = start + PRUNE_INTERVAL;
+
+        for client in 0..=PRUNE_THRESHOLD {
+            limiter.check_at(&format!("ip:{}", client), start);
+        }
+
+        // Past the threshold, but not yet time to prune
+        limiter.check_at("ip:active", pruned_at - Duration::from_secs(2));
+        assert_eq!(limiter.buckets.len(), PRUNE_THRESHOLD + 2);
+
+        // Only the bucket still refilling is kept
+        limiter.check_at("ip:active", pruned_at);
+        assert_eq!(limiter.buckets.len(), 1);
+
+        // A pruned client starts again with a full bucket
+        assert_eq!(limiter.check_at("ip:0", pruned_at), (true, 2));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_contract() {
+        let limiter = MemoryRateLimiter::new(60, 1);
+
+        assert_eq!(
+            limiter.check_with_remaining("user:1").await,
+            (true, Some(0))
+        );
+        assert_eq!(
+            limiter.check_with_remaining("user:1").await,
+            (false, Some(0))
+        );
+        assert_eq!(limiter.get_limit(), 60);
+    }
+}
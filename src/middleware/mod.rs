@@ -1,5 +1,11 @@
+pub mod auth;
+pub mod external_rate_limit;
 pub mod jwt;
+pub mod memory_rate_limit;
 pub mod rate_limit;
 
+pub use auth::AuthMiddleware;
+pub use external_rate_limit::{ExternalDecision, ExternalRateLimiter};
 pub use jwt::JwtMiddleware;
-pub use rate_limit::RateLimitMiddleware;
\ No newline at end of file
+pub use memory_rate_limit::MemoryRateLimiter;
+pub use rate_limit::{ClientRateLimiters, RateLimitMiddleware, RateLimiter};
\ No newline at end of file
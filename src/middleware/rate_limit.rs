@@ -1,7 +1,82 @@
 use crate::cache::RedisClient;
+use crate::config::settings::RateLimitConfig;
+use async_trait::async_trait;
 use pingora_http::ResponseHeader;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Header set on allowed requests once the soft threshold is crossed
+pub const WARNING_HEADER: &str = "X-RateLimit-Warning";
+
+/// Warning for a request allowed with `remaining` tokens left, if any
+///
+/// The soft threshold is `warn_percent` of the bucket used up. Below it,
+/// without a threshold, or when the fill is unknown there is no warning.
+pub fn soft_limit_warning(
+    remaining: Option<u32>,
+    burst_size: u32,
+    warn_percent: Option<u8>,
+) -> Option<String> {
+    let (remaining, warn_percent) = (remaining?, warn_percent?);
+    let used = burst_size.saturating_sub(remaining) as u64;
+
+    if used * 100 >= burst_size as u64 * warn_percent as u64 {
+        Some(format!("{} of {} requests left", remaining, burst_size))
+    } else {
+        None
+    }
+}
+
+/// Token bucket rate limiter backend (Redis or in-memory)
+#[async_trait]
+pub trait RateLimiter: Send + Sync {
+    /// Check if request is allowed and report the bucket fill afterwards
+    /// Returns (allowed, remaining_tokens); remaining is None when unknown
+    async fn check_with_remaining(&self, client_id: &str) -> (bool, Option<u32>);
+
+    /// Get configured request limit
+    fn get_limit(&self) -> u32;
+
+    /// Get configured burst size
+    fn get_burst_size(&self) -> u32;
+}
+
+/// Separate limiters for authenticated and anonymous clients
+///
+/// Anonymous clients (`ip:` / `anonymous:` keys) usually get tighter
+/// limits than signed-in users (`user:` keys); either falls back to the
+/// global `requests_per_minute` / `burst_size` when not configured.
+pub struct ClientRateLimiters {
+    authenticated: Box<dyn RateLimiter>,
+    anonymous: Box<dyn RateLimiter>,
+}
+
+impl ClientRateLimiters {
+    /// Build both limiters with `build(requests_per_minute, burst_size)`
+    pub fn new(
+        config: &RateLimitConfig,
+        build: impl Fn(u32, u32) -> Box<dyn RateLimiter>,
+    ) -> Self {
+        let limiter = |authenticated| {
+            let limit = config.limit_for(authenticated);
+            build(limit.requests_per_minute, limit.burst_size)
+        };
+
+        Self {
+            authenticated: limiter(true),
+            anonymous: limiter(false),
+        }
+    }
+
+    /// Limiter for a client with or without a verified user
+    pub fn for_client(&self, authenticated: bool) -> &dyn RateLimiter {
+        if authenticated {
+            self.authenticated.as_ref()
+        } else {
+            self.anonymous.as_ref()
+        }
+    }
+}
+
 pub struct RateLimitMiddleware {
     redis_client: RedisClient,
     requests_per_minute: u32,
@@ -20,6 +95,12 @@ impl RateLimitMiddleware {
     /// Check if request is allowed (Token Bucket Algorithm)
     /// Returns true if allowed, false if rate limit exceeded
     pub async fn check_rate_limit(&self, client_id: &str) -> bool {
+        self.check_with_remaining(client_id).await.0
+    }
+
+    /// Check if request is allowed and report the bucket fill afterwards
+    /// Returns (allowed, remaining_tokens); remaining is None when Redis failed
+    pub async fn check_with_remaining(&self, client_id: &str) -> (bool, Option<u32>) {
         let key = format!("rate_limit:{}", client_id);
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -48,11 +129,11 @@ impl RateLimitMiddleware {
                         client_id, 
                         new_tokens
                     );
-                    true
+                    (true, Some(new_tokens))
                 } else {
                     // No tokens available, rate limited
                     log::warn!("Rate limit exceeded for {}: 0 tokens remaining", client_id);
-                    false
+                    (false, Some(0))
                 }
             }
             Ok(None) => {
@@ -62,15 +143,15 @@ impl RateLimitMiddleware {
                 if let Err(e) = self.set_token_bucket(&key, initial_tokens, now).await {
                     log::error!("Failed to initialize token bucket for {}: {}", client_id, e);
                     // Fallback: allow request on Redis failure
-                    return true;
+                    return (true, None);
                 }
                 log::debug!("Initialized token bucket for {} with {} tokens", client_id, initial_tokens);
-                true
+                (true, Some(initial_tokens))
             }
             Err(e) => {
                 // Redis error, fallback strategy: allow request
                 log::error!("Redis error during rate limit check for {}: {}", client_id, e);
-                true
+                (true, None)
             }
         }
     }
@@ -120,8 +201,100 @@ impl RateLimitMiddleware {
     }
 }
 
+#[async_trait]
+impl RateLimiter for RateLimitMiddleware {
+    async fn check_with_remaining(&self, client_id: &str) -> (bool, Option<u32>) {
+        RateLimitMiddleware::check_with_remaining(self, client_id).await
+    }
+
+    fn get_limit(&self) -> u32 {
+        RateLimitMiddleware::get_limit(self)
+    }
+
+    fn get_burst_size(&self) -> u32 {
+        RateLimitMiddleware::get_burst_size(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::middleware::MemoryRateLimiter;
+
+    #[tokio::test]
+    async fn test_soft_limit_warning_band() {
+        // 80% of a 5 request bucket: the 4th and 5th requests are warned
+        let limiter = MemoryRateLimiter::new(60, 5);
+        let mut warnings = Vec::new();
+
+        for _ in 0..5 {
+            let (allowed, remaining) = limiter.check_with_remaining("client").await;
+            assert!(allowed);
+            warnings.push(soft_limit_warning(remaining, 5, Some(80)));
+        }
+
+        assert_eq!(warnings[..3], [None, None, None]);
+        assert_eq!(warnings[3].as_deref(), Some("1 of 5 requests left"));
+        assert_eq!(warnings[4].as_deref(), Some("0 of 5 requests left"));
+
+        // The hard limit still rejects
+        assert!(!limiter.check_with_remaining("client").await.0);
+    }
+
+    fn config(yaml: &str) -> RateLimitConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn memory_limiters(config: &RateLimitConfig) -> ClientRateLimiters {
+        ClientRateLimiters::new(config, |requests_per_minute, burst_size| {
+            Box::new(MemoryRateLimiter::new(requests_per_minute, burst_size))
+        })
+    }
+
+    /// Requests allowed in a row before the first rejection
+    async fn allowed_in_a_row(limiter: &dyn RateLimiter, client_id: &str) -> u32 {
+        let mut allowed = 0;
+        while limiter.check_with_remaining(client_id).await.0 {
+            allowed += 1;
+        }
+        allowed
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_and_authenticated_limits() {
+        let limiters = memory_limiters(&config(
+            "enabled: true\n\
+             requests_per_minute: 60\n\
+             burst_size: 5\n\
+             authenticated: { requests_per_minute: 600, burst_size: 20 }\n\
+             anonymous: { requests_per_minute: 30, burst_size: 2 }",
+        ));
+
+        let anonymous = limiters.for_client(false);
+        assert_eq!(allowed_in_a_row(anonymous, "ip:203.0.113.7").await, 2);
+        assert_eq!(anonymous.get_limit(), 30);
+
+        let authenticated = limiters.for_client(true);
+        assert_eq!(allowed_in_a_row(authenticated, "user:42").await, 20);
+        assert_eq!(authenticated.get_burst_size(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_client_limits_default_to_global() {
+        let limiters = memory_limiters(&config(
+            "enabled: true\nrequests_per_minute: 60\nburst_size: 5",
+        ));
+
+        assert_eq!(allowed_in_a_row(limiters.for_client(false), "ip:203.0.113.7").await, 5);
+        assert_eq!(allowed_in_a_row(limiters.for_client(true), "user:42").await, 5);
+    }
+
+    #[test]
+    fn test_no_warning_without_threshold() {
+        assert_eq!(soft_limit_warning(Some(0), 5, None), None);
+        assert_eq!(soft_limit_warning(None, 5, Some(80)), None);
+    }
+
     #[test]
     fn test_token_bucket_format() {
         let value = "10:1234567890";
@@ -0,0 +1,88 @@
+use serde::Serialize;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::proxy::context::AuthDecision;
+
+/// One line of the JSON access log
+#[derive(Debug, Serialize)]
+pub struct AccessLogEntry<'a> {
+    pub request_id: &'a str,
+    pub method: &'a str,
+    pub path: &'a str,
+    /// Status sent to the client (absent if no response was written)
+    pub status: Option<u16>,
+    pub duration_ms: u128,
+    pub client_ip: Option<IpAddr>,
+    /// Matched route prefix (absent for unrouted paths)
+    pub route: Option<&'a str>,
+    pub auth: AuthDecision,
+}
+
+/// Level to log a finished request at
+///
+/// Without a threshold every request is logged at info. With one, only
+/// requests slower than it are logged at warn; the rest drop to debug.
+///
+/// # Arguments
+/// * `elapsed` - Time since the request arrived
+/// * `slow_threshold` - `server.slow_request_threshold_ms`
+pub fn level(elapsed: Duration, slow_threshold: Option<Duration>) -> log::Level {
+    match slow_threshold {
+        None => log::Level::Info,
+        Some(threshold) if elapsed > threshold => log::Level::Warn,
+        Some(_) => log::Level::Debug,
+    }
+}
+
+/// Write `entry` as a single JSON line at `level`
+pub fn record(entry: &AccessLogEntry, level: log::Level) {
+    if log::log_enabled!(level) {
+        match serde_json::to_string(entry) {
+            Ok(line) => log::log!(level, "{}", line),
+            Err(e) => log::error!("[{}] Access log encode error: {}", entry.request_id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_slow_requests_warn() {
+        let threshold = Some(Duration::from_millis(500));
+
+        assert_eq!(
+            level(Duration::from_millis(20), threshold),
+            log::Level::Debug
+        );
+        assert_eq!(
+            level(Duration::from_millis(750), threshold),
+            log::Level::Warn
+        );
+        // Every request is logged when no threshold is set
+        assert_eq!(level(Duration::from_millis(750), None), log::Level::Info);
+    }
+
+    #[test]
+    fn test_entry_is_json() {
+        let entry = AccessLogEntry {
+            request_id: "req-1",
+            method: "GET",
+            path: "/api/users",
+            status: Some(200),
+            duration_ms: 612,
+            client_ip: Some("10.0.0.1".parse().unwrap()),
+            route: Some("/api"),
+            auth: AuthDecision::Authenticated,
+        };
+
+        let json: serde_json::Value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["duration_ms"], 612);
+        assert_eq!(json["client_ip"], "10.0.0.1");
+        assert_eq!(json["route"], "/api");
+        assert_eq!(json["auth"], "authenticated");
+    }
+}
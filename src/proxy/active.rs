@@ -0,0 +1,126 @@
+use prometheus::IntGauge;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use crate::metrics;
+
+/// Requests in flight, and the most seen at once since startup
+///
+/// Exported as the `active_requests` and `active_requests_peak` gauges.
+/// A request counts from the start of `request_filter` until its
+/// [`ActiveRequestGuard`] is dropped with the request context, which
+/// happens on every exit path: short-circuited responses, upstream
+/// errors and client disconnects alike.
+#[derive(Debug)]
+pub struct ActiveRequests {
+    active: AtomicI64,
+    peak: AtomicI64,
+    active_gauge: IntGauge,
+    peak_gauge: IntGauge,
+}
+
+impl ActiveRequests {
+    /// Tracker reporting to the process-wide gauges
+    pub fn new() -> Arc<Self> {
+        Self::with_gauges(
+            metrics::ACTIVE_REQUESTS.clone(),
+            metrics::ACTIVE_REQUESTS_PEAK.clone(),
+        )
+    }
+
+    /// Tracker reporting to the given gauges
+    pub fn with_gauges(active_gauge: IntGauge, peak_gauge: IntGauge) -> Arc<Self> {
+        Arc::new(Self {
+            active: AtomicI64::new(0),
+            peak: AtomicI64::new(0),
+            active_gauge,
+            peak_gauge,
+        })
+    }
+
+    /// Count a request as in flight until the returned guard is dropped
+    pub fn enter(self: &Arc<Self>) -> ActiveRequestGuard {
+        let active = self.active.fetch_add(1, Ordering::Relaxed) + 1;
+        let peak = self.peak.fetch_max(active, Ordering::Relaxed).max(active);
+
+        self.active_gauge.set(active);
+        self.peak_gauge.set(peak);
+
+        ActiveRequestGuard {
+            requests: Arc::clone(self),
+        }
+    }
+}
+
+/// One in-flight request; the count drops when this does
+#[derive(Debug)]
+pub struct ActiveRequestGuard {
+    requests: Arc<ActiveRequests>,
+}
+
+impl Drop for ActiveRequestGuard {
+    fn drop(&mut self) {
+        let active = self.requests.active.fetch_sub(1, Ordering::Relaxed) - 1;
+        self.requests.active_gauge.set(active);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::context::ProxyContext;
+
+    fn tracker() -> Arc<ActiveRequests> {
+        ActiveRequests::with_gauges(
+            IntGauge::new("test_active_requests", "test").unwrap(),
+            IntGauge::new("test_active_requests_peak", "test").unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_gauge_reflects_in_flight_requests() {
+        let requests = tracker();
+        let (release, released) = tokio::sync::watch::channel(false);
+
+        // Three requests held open concurrently
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let guard = requests.enter();
+                let mut released = released.clone();
+                tokio::spawn(async move {
+                    released.wait_for(|done| *done).await.unwrap();
+                    drop(guard);
+                })
+            })
+            .collect();
+
+        assert_eq!(requests.active_gauge.get(), 3);
+        assert_eq!(requests.peak_gauge.get(), 3);
+
+        release.send(true).unwrap();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        // Back to zero; the high-water mark stays
+        assert_eq!(requests.active_gauge.get(), 0);
+        assert_eq!(requests.peak_gauge.get(), 3);
+
+        let _next = requests.enter();
+        assert_eq!(requests.active_gauge.get(), 1);
+        assert_eq!(requests.peak_gauge.get(), 3);
+    }
+
+    #[test]
+    fn test_short_circuited_request_is_released() {
+        let requests = tracker();
+
+        // A handler that answers early still drops its context
+        let mut ctx = ProxyContext::new();
+        ctx.active_request = Some(Arc::new(requests.enter()));
+        assert_eq!(requests.active_gauge.get(), 1);
+
+        drop(ctx);
+        assert_eq!(requests.active_gauge.get(), 0);
+    }
+}
@@ -0,0 +1,483 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::cache::BanStore;
+
+/// Longest ban accepted from the admin API (30 days)
+pub const MAX_BAN_TTL_SECONDS: u64 = 30 * 24 * 60 * 60;
+
+/// How long a [`BanCache`] uses its ban list before asking Redis again
+pub const BAN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An IP address or CIDR block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    /// Address with the host bits cleared
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    /// Whether `ip` is inside this block
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        ip.is_ipv4() == self.network.is_ipv4() && mask(ip, self.prefix_len) == self.network
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = ();
+
+    /// Parse `203.0.113.7`, `10.0.0.0/8` or `2001:db8::/32`
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+        let address = address.parse::<IpAddr>().map_err(|_| ())?.to_canonical();
+
+        let max_len = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len.parse::<u8>().map_err(|_| ())?,
+            None => max_len,
+        };
+        if prefix_len > max_len {
+            return Err(());
+        }
+
+        Ok(Self {
+            network: mask(address, prefix_len),
+            prefix_len,
+        })
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+/// Clear all but the first `prefix_len` bits of an address
+fn mask(ip: IpAddr, prefix_len: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V4((u32::from(ip) & mask).into())
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V6((u128::from(ip) & mask).into())
+        }
+    }
+}
+
+/// Who a ban applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BanTarget {
+    User(Uuid),
+    Network(IpNetwork),
+}
+
+impl fmt::Display for BanTarget {
+    /// Stored form: `user:<uuid>` or `ip:<network>/<prefix>`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BanTarget::User(user_id) => write!(f, "user:{}", user_id),
+            BanTarget::Network(network) => write!(f, "ip:{}", network),
+        }
+    }
+}
+
+impl FromStr for BanTarget {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.split_once(':') {
+            Some(("user", user_id)) => user_id.parse().map(BanTarget::User).map_err(|_| ()),
+            Some(("ip", network)) => network.parse().map(BanTarget::Network),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Serialize for BanTarget {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Body of `POST /admin/ban` and `DELETE /admin/ban`
+#[derive(Debug, Default, Deserialize)]
+pub struct BanRequest {
+    #[serde(default)]
+    pub user_id: Option<Uuid>,
+    /// Address or CIDR block
+    #[serde(default)]
+    pub ip: Option<String>,
+    /// Seconds until the ban lifts (`POST` only)
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+impl BanRequest {
+    /// The user or network named by the request
+    pub fn target(&self) -> Result<BanTarget, BanError> {
+        match (&self.user_id, &self.ip) {
+            (Some(user_id), None) => Ok(BanTarget::User(*user_id)),
+            (None, Some(ip)) => ip
+                .trim()
+                .parse()
+                .map(BanTarget::Network)
+                .map_err(|_| BanError::InvalidIp),
+            _ => Err(BanError::Target),
+        }
+    }
+}
+
+/// An active ban
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Ban {
+    pub target: BanTarget,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Ban {
+    /// Whether this ban covers the user or client address
+    pub fn applies_to(&self, user_id: Option<Uuid>, ip: Option<IpAddr>) -> bool {
+        match self.target {
+            BanTarget::User(banned) => user_id == Some(banned),
+            BanTarget::Network(network) => ip.is_some_and(|ip| network.contains(ip)),
+        }
+    }
+}
+
+/// Ban error types
+#[derive(Debug, Error)]
+pub enum BanError {
+    #[error("Exactly one of user_id and ip is required")]
+    Target,
+
+    #[error("Invalid IP address or CIDR block")]
+    InvalidIp,
+
+    #[error("ttl_secs must be between 1 and {}", MAX_BAN_TTL_SECONDS)]
+    InvalidTtl,
+
+    #[error("Cache error: {0}")]
+    CacheError(#[from] redis::RedisError),
+}
+
+/// Ban a user or network until its TTL runs out
+///
+/// Banning an already banned target replaces its expiry.
+///
+/// # Arguments
+/// * `store` - Ban storage (Redis in production)
+/// * `request` - Target and `ttl_secs`
+/// * `now` - Current time
+///
+/// # Returns
+/// * `Result<Ban, BanError>` - The ban now in effect
+pub async fn ban(
+    store: &dyn BanStore,
+    request: &BanRequest,
+    now: DateTime<Utc>,
+) -> Result<Ban, BanError> {
+    let target = request.target()?;
+    let ttl_secs = request
+        .ttl_secs
+        .filter(|ttl| (1..=MAX_BAN_TTL_SECONDS).contains(ttl))
+        .ok_or(BanError::InvalidTtl)?;
+
+    let expires_at = now + chrono::Duration::seconds(ttl_secs as i64);
+    store
+        .add_ban(&target.to_string(), expires_at.timestamp(), now.timestamp())
+        .await?;
+
+    Ok(Ban { target, expires_at })
+}
+
+/// Lift a ban before it expires
+///
+/// # Returns
+/// * `Result<bool, BanError>` - Whether the target was banned
+pub async fn unban(store: &dyn BanStore, request: &BanRequest) -> Result<bool, BanError> {
+    let target = request.target()?;
+    Ok(store.remove_ban(&target.to_string()).await?)
+}
+
+/// Bans in effect at `now`, soonest to expire first
+///
+/// # Arguments
+/// * `store` - Ban storage (Redis in production)
+/// * `now` - Current time
+///
+/// # Returns
+/// * `Result<Vec<Ban>, redis::RedisError>` - Active bans
+pub async fn active_bans(
+    store: &dyn BanStore,
+    now: DateTime<Utc>,
+) -> Result<Vec<Ban>, redis::RedisError> {
+    let bans = store.active_bans(now.timestamp()).await?;
+
+    Ok(bans
+        .into_iter()
+        .filter_map(|(target, expires_at)| {
+            let Ok(target) = target.parse() else {
+                log::warn!("Ignoring unrecognized ban target {:?}", target);
+                return None;
+            };
+            Some(Ban {
+                target,
+                expires_at: DateTime::from_timestamp(expires_at, 0)?,
+            })
+        })
+        .collect())
+}
+
+/// In-process copy of the active bans, checked on every request
+///
+/// The list is fetched again once it is older than the refresh interval,
+/// by one request while the others wait, so the ban set is read about
+/// once per interval instead of once per request. Bans set on another
+/// instance take effect here within that interval. When a fetch fails the
+/// previous list keeps being used until the next interval.
+pub struct BanCache {
+    refresh_interval: Duration,
+    /// Bans and when they were fetched; `None` until the first fetch
+    state: Mutex<Option<(Instant, Arc<Vec<Ban>>)>>,
+    /// Held while fetching, so concurrent requests make one fetch
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+impl BanCache {
+    pub fn new(refresh_interval: Duration) -> Self {
+        Self {
+            refresh_interval,
+            state: Mutex::new(None),
+            refreshing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Bans in effect at `now`, from the cache when it is fresh
+    ///
+    /// # Returns
+    /// * `Result<Vec<Ban>, redis::RedisError>` - Active bans, or the fetch
+    ///   error (the stale list is used until the next interval)
+    pub async fn bans(
+        &self,
+        store: &dyn BanStore,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Ban>, redis::RedisError> {
+        if let Some(bans) = self.fresh() {
+            return Ok(in_effect(&bans, now));
+        }
+
+        let _refreshing = self.refreshing.lock().await;
+        // Fetched by another request while this one waited
+        if let Some(bans) = self.fresh() {
+            return Ok(in_effect(&bans, now));
+        }
+
+        let fetched = active_bans(store, now).await;
+        let mut state = self.state.lock().unwrap();
+        let bans = match &fetched {
+            Ok(bans) => Arc::new(bans.clone()),
+            Err(_) => state
+                .as_ref()
+                .map_or_else(Default::default, |(_, bans)| Arc::clone(bans)),
+        };
+        *state = Some((Instant::now(), bans));
+        fetched
+    }
+
+    /// Drop the cached list, so a ban set through this instance applies
+    /// to its next request
+    pub fn invalidate(&self) {
+        *self.state.lock().unwrap() = None;
+    }
+
+    fn fresh(&self) -> Option<Arc<Vec<Ban>>> {
+        let state = self.state.lock().unwrap();
+        let (fetched_at, bans) = state.as_ref()?;
+        (fetched_at.elapsed() < self.refresh_interval).then(|| Arc::clone(bans))
+    }
+}
+
+/// Cached bans that have not expired by `now`
+fn in_effect(bans: &[Ban], now: DateTime<Utc>) -> Vec<Ban> {
+    bans.iter()
+        .filter(|ban| ban.expires_at > now)
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory::MemoryStore;
+
+    fn ip_ban(ip: &str, ttl_secs: u64) -> BanRequest {
+        BanRequest {
+            ip: Some(ip.to_string()),
+            ttl_secs: Some(ttl_secs),
+            ..Default::default()
+        }
+    }
+
+    async fn is_banned(store: &MemoryStore, ip: &str, now: DateTime<Utc>) -> bool {
+        let ip = Some(ip.parse().unwrap());
+        active_bans(store, now)
+            .await
+            .unwrap()
+            .iter()
+            .any(|ban| ban.applies_to(None, ip))
+    }
+
+    #[test]
+    fn test_network_parsing_and_matching() {
+        let network: IpNetwork = "10.1.2.3/8".parse().unwrap();
+        assert_eq!(network.to_string(), "10.0.0.0/8");
+        assert!(network.contains("10.200.0.1".parse().unwrap()));
+        assert!(!network.contains("11.0.0.1".parse().unwrap()));
+        // IPv4-mapped IPv6 clients match IPv4 blocks
+        assert!(network.contains("::ffff:10.0.0.1".parse().unwrap()));
+
+        let host: IpNetwork = "203.0.113.7".parse().unwrap();
+        assert_eq!(host.to_string(), "203.0.113.7/32");
+        assert!(!host.contains("203.0.113.8".parse().unwrap()));
+
+        let v6: IpNetwork = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!v6.contains("10.0.0.1".parse().unwrap()));
+
+        assert!("0.0.0.0/0".parse::<IpNetwork>().is_ok());
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("not-an-ip".parse::<IpNetwork>().is_err());
+    }
+
+    #[test]
+    fn test_target_round_trips() {
+        let user = BanTarget::User(Uuid::new_v4());
+        let network = BanTarget::Network("192.168.0.0/16".parse().unwrap());
+
+        for target in [user, network] {
+            assert_eq!(target.to_string().parse::<BanTarget>(), Ok(target));
+        }
+    }
+
+    #[test]
+    fn test_request_needs_exactly_one_target() {
+        assert!(matches!(
+            BanRequest::default().target(),
+            Err(BanError::Target)
+        ));
+        let both = BanRequest {
+            user_id: Some(Uuid::new_v4()),
+            ..ip_ban("10.0.0.1", 60)
+        };
+        assert!(matches!(both.target(), Err(BanError::Target)));
+        assert!(matches!(
+            ip_ban("10.0.0.1/99", 60).target(),
+            Err(BanError::InvalidIp)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_banned_ip_is_blocked_until_ttl_expires() {
+        let store = MemoryStore::new();
+        let now = Utc::now();
+
+        let ban = ban(&store, &ip_ban("203.0.113.0/24", 600), now)
+            .await
+            .unwrap();
+        assert_eq!(ban.expires_at, now + chrono::Duration::seconds(600));
+
+        assert!(is_banned(&store, "203.0.113.7", now).await);
+        assert!(!is_banned(&store, "198.51.100.1", now).await);
+        assert!(is_banned(&store, "203.0.113.7", now + chrono::Duration::seconds(599)).await);
+        assert!(!is_banned(&store, "203.0.113.7", now + chrono::Duration::seconds(600)).await);
+    }
+
+    #[tokio::test]
+    async fn test_unban_restores_access() {
+        let store = MemoryStore::new();
+        let now = Utc::now();
+        let user_id = Uuid::new_v4();
+        let user_ban = BanRequest {
+            user_id: Some(user_id),
+            ttl_secs: Some(600),
+            ..Default::default()
+        };
+
+        ban(&store, &ip_ban("203.0.113.7", 600), now).await.unwrap();
+        ban(&store, &user_ban, now).await.unwrap();
+        let bans = active_bans(&store, now).await.unwrap();
+        assert_eq!(bans.len(), 2);
+        assert!(bans.iter().any(|ban| ban.applies_to(Some(user_id), None)));
+
+        assert!(unban(&store, &ip_ban("203.0.113.7", 0)).await.unwrap());
+        assert!(!is_banned(&store, "203.0.113.7", now).await);
+        // Already lifted
+        assert!(!unban(&store, &ip_ban("203.0.113.7", 0)).await.unwrap());
+
+        let bans = active_bans(&store, now).await.unwrap();
+        assert_eq!(bans.len(), 1);
+        assert!(bans[0].applies_to(Some(user_id), None));
+    }
+
+    #[tokio::test]
+    async fn test_cache_reads_the_ban_set_once_per_interval() {
+        let store = MemoryStore::new();
+        let now = Utc::now();
+        let cache = BanCache::new(Duration::from_secs(60));
+        ban(&store, &ip_ban("203.0.113.7", 600), now).await.unwrap();
+        assert_eq!(cache.bans(&store, now).await.unwrap().len(), 1);
+
+        // Set elsewhere: not seen until the list is refreshed...
+        ban(&store, &ip_ban("198.51.100.1", 600), now)
+            .await
+            .unwrap();
+        assert_eq!(cache.bans(&store, now).await.unwrap().len(), 1);
+        // ...which an admin call on this instance forces
+        cache.invalidate();
+        assert_eq!(cache.bans(&store, now).await.unwrap().len(), 2);
+
+        // Cached bans still lift when they expire
+        let later = now + chrono::Duration::seconds(600);
+        assert!(cache.bans(&store, later).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ttl_is_bounded() {
+        let store = MemoryStore::new();
+        let now = Utc::now();
+
+        for ttl_secs in [0, MAX_BAN_TTL_SECONDS + 1] {
+            assert!(matches!(
+                ban(&store, &ip_ban("10.0.0.1", ttl_secs), now).await,
+                Err(BanError::InvalidTtl)
+            ));
+        }
+        assert!(matches!(
+            ban(
+                &store,
+                &BanRequest {
+                    ttl_secs: None,
+                    ..ip_ban("10.0.0.1", 0)
+                },
+                now
+            )
+            .await,
+            Err(BanError::InvalidTtl)
+        ));
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::proxy::fallback;
+use crate::proxy::idempotency::{CachedResponse, IdempotencyCapture};
+
+/// Response header marking a response shared from another request's fetch
+pub const COALESCED_HEADER: &str = "X-Coalesced-Response";
+
+/// Response of an upstream fetch, `None` until it is shared
+type Shared = watch::Receiver<Option<CachedResponse>>;
+
+/// Key of a GET for single-flight fetching
+///
+/// Scoped to the client, so one client is never sent a response fetched
+/// for another's credentials, and to the headers the response may vary on.
+///
+/// # Arguments
+/// * `path_and_query` - Request path and query
+/// * `scope` - Client, from [`client_scope`](crate::proxy::idempotency::client_scope)
+/// * `accept` - `Accept` header
+/// * `accept_encoding` - `Accept-Encoding` header
+pub fn fetch_key(
+    path_and_query: &str,
+    scope: &str,
+    accept: Option<&str>,
+    accept_encoding: Option<&str>,
+) -> String {
+    format!(
+        "{}:{}:{}:{}",
+        scope,
+        accept.unwrap_or_default(),
+        accept_encoding.unwrap_or_default(),
+        path_and_query
+    )
+}
+
+/// What to do with a GET on a route with `coalesce`
+#[derive(Debug)]
+pub enum Join {
+    /// No identical fetch in flight: forward it and share the response
+    Leader(FetchLeader),
+    /// Wait for the identical fetch in flight with [`wait`]
+    Follower(Shared),
+}
+
+/// Upstream fetches in flight, by [`fetch_key`]
+///
+/// Kept in memory: requests are only coalesced within one proxy instance.
+#[derive(Debug, Default)]
+pub struct InFlightFetches {
+    fetches: Arc<Mutex<HashMap<String, Shared>>>,
+}
+
+impl InFlightFetches {
+    /// Lead the fetch for a key, or follow the one already in flight
+    pub fn join(&self, key: &str) -> Join {
+        let mut fetches = self.fetches.lock().unwrap();
+        if let Some(shared) = fetches.get(key) {
+            return Join::Follower(shared.clone());
+        }
+
+        let (sender, shared) = watch::channel(None);
+        fetches.insert(key.to_string(), shared);
+        Join::Leader(FetchLeader {
+            fetches: Arc::clone(&self.fetches),
+            sender: Arc::new(sender),
+            capture: IdempotencyCapture::new(key.to_string(), 0),
+        })
+    }
+}
+
+/// The one request of a key sent upstream, recording its response
+///
+/// The fetch stops being in flight when the leader is dropped; followers
+/// still waiting then go upstream themselves unless it was shared.
+#[derive(Debug, Clone)]
+pub struct FetchLeader {
+    fetches: Arc<Mutex<HashMap<String, Shared>>>,
+    sender: Arc<watch::Sender<Option<CachedResponse>>>,
+    pub capture: IdempotencyCapture,
+}
+
+impl FetchLeader {
+    /// Hand the recorded response to the followers, if it may be shared
+    ///
+    /// # Returns
+    /// * `bool` - Whether a response was shared
+    pub fn share(&self) -> bool {
+        let Some(response) = self.capture.response().filter(fallback::is_shareable) else {
+            return false;
+        };
+        self.sender.send_replace(Some(response));
+        true
+    }
+}
+
+impl Drop for FetchLeader {
+    fn drop(&mut self) {
+        // Clones share the sender; the last one ends the fetch
+        if Arc::strong_count(&self.sender) > 1 {
+            return;
+        }
+        let mut fetches = self.fetches.lock().unwrap();
+        if fetches
+            .get(&self.capture.key)
+            .is_some_and(|shared| shared.same_channel(&self.sender.subscribe()))
+        {
+            fetches.remove(&self.capture.key);
+        }
+    }
+}
+
+/// Response of the fetch a follower joined
+///
+/// # Returns
+/// * `Option<CachedResponse>` - `None` if the leader ended without a
+///   shareable response or took longer than `timeout`
+pub async fn wait(mut shared: Shared, timeout: Duration) -> Option<CachedResponse> {
+    match tokio::time::timeout(timeout, shared.wait_for(Option::is_some)).await {
+        Ok(Ok(response)) => response.clone(),
+        Ok(Err(_)) | Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::idempotency::client_scope;
+    use pingora_http::ResponseHeader;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Leader's side of a fetch: the upstream answers after a short delay
+    async fn fetch_upstream(mut leader: FetchLeader, upstream_requests: &AtomicUsize, status: u16) {
+        upstream_requests.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut resp = ResponseHeader::build(status, None).unwrap();
+        resp.insert_header("Content-Type", "application/json")
+            .unwrap();
+        leader.capture.record_header(&resp);
+        leader.capture.record_body(br#"{"items":[]}"#);
+        leader.share();
+    }
+
+    /// A request as the proxy handles it: shared response or its own fetch
+    async fn get(
+        fetches: Arc<InFlightFetches>,
+        upstream_requests: Arc<AtomicUsize>,
+        status: u16,
+    ) -> Option<CachedResponse> {
+        let key = fetch_key("/api/items?page=1", "anonymous", None, None);
+        match fetches.join(&key) {
+            Join::Leader(leader) => {
+                fetch_upstream(leader, &upstream_requests, status).await;
+                None
+            }
+            Join::Follower(shared) => match wait(shared, Duration::from_secs(5)).await {
+                Some(response) => Some(response),
+                None => {
+                    upstream_requests.fetch_add(1, Ordering::SeqCst);
+                    None
+                }
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_make_one_upstream_request() {
+        let fetches = Arc::new(InFlightFetches::default());
+        let upstream_requests = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..10)
+            .map(|_| tokio::spawn(get(fetches.clone(), upstream_requests.clone(), 200)))
+            .collect();
+        let mut shared = 0;
+        for handle in handles {
+            if let Some(response) = handle.await.unwrap() {
+                assert_eq!(response.status, 200);
+                assert_eq!(response.body(), br#"{"items":[]}"#);
+                shared += 1;
+            }
+        }
+
+        assert_eq!(upstream_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(shared, 9);
+        assert!(fetches.fetches.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unshareable_response_sends_followers_upstream() {
+        let fetches = Arc::new(InFlightFetches::default());
+        let upstream_requests = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..3)
+            .map(|_| tokio::spawn(get(fetches.clone(), upstream_requests.clone(), 503)))
+            .collect();
+        for handle in handles {
+            assert!(handle.await.unwrap().is_none());
+        }
+
+        assert_eq!(upstream_requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_ends_with_its_leader() {
+        let fetches = InFlightFetches::default();
+        let key = fetch_key("/api/items", "anonymous", None, None);
+
+        let Join::Leader(leader) = fetches.join(&key) else {
+            panic!("first request should lead");
+        };
+        let Join::Follower(shared) = fetches.join(&key) else {
+            panic!("identical request should follow");
+        };
+        // Another client's request is fetched separately
+        let mut api_key = http::HeaderMap::new();
+        api_key.insert("X-API-Key", "key-a".parse().unwrap());
+        let scope = client_scope(None, &api_key);
+        assert!(matches!(
+            fetches.join(&fetch_key("/api/items", &scope, None, None)),
+            Join::Leader(_)
+        ));
+
+        // Dropped without a response, e.g. an upstream error
+        drop(leader);
+        assert!(wait(shared, Duration::from_secs(5)).await.is_none());
+        assert!(!fetches.fetches.lock().unwrap().contains_key(&key));
+        assert!(matches!(fetches.join(&key), Join::Leader(_)));
+    }
+}
@@ -0,0 +1,157 @@
+use dashmap::DashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Entries not seen for this long belong to connections that were closed
+/// without hitting the limit (client went away, idle timeout) and are dropped.
+const STALE_AFTER: Duration = Duration::from_secs(300);
+
+/// Map size above which stale entries are pruned
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Shortest time between two prunes, so a large map is not scanned on
+/// every request
+const PRUNE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Per-connection request counter enforcing `server.max_requests_per_connection`
+///
+/// A downstream connection is identified by the client socket address
+/// (IP and port), which is unique among open connections. Counts are
+/// forgotten once the limit closes the connection, or after the connection
+/// has been idle for `STALE_AFTER`.
+pub struct ConnectionTracker {
+    /// Client socket address -> (requests served, last request)
+    requests: DashMap<String, (u32, Instant)>,
+    max_requests: u32,
+    /// When stale entries were last pruned
+    last_prune: Mutex<Instant>,
+}
+
+impl ConnectionTracker {
+    pub fn new(max_requests: u32) -> Self {
+        Self {
+            requests: DashMap::new(),
+            max_requests,
+            last_prune: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Count a request on `connection`
+    ///
+    /// # Returns
+    /// * `true` if this is the last request allowed, so the connection must
+    ///   close after the response
+    pub fn record(&self, connection: &str) -> bool {
+        self.record_at(connection, Instant::now())
+    }
+
+    /// Count a request on `connection` as of `now`
+    fn record_at(&self, connection: &str, now: Instant) -> bool {
+        if self.requests.len() > PRUNE_THRESHOLD {
+            self.prune(now);
+        }
+
+        let mut entry = self
+            .requests
+            .entry(connection.to_string())
+            .or_insert((0, now));
+        *entry = (entry.0 + 1, now);
+        let served = entry.0;
+        drop(entry);
+
+        if served >= self.max_requests {
+            // The connection closes after this response; a new one starts fresh
+            self.requests.remove(connection);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drop stale entries, at most once per `PRUNE_INTERVAL`
+    ///
+    /// A request arriving while another one prunes skips the prune.
+    fn prune(&self, now: Instant) {
+        let Ok(mut last_prune) = self.last_prune.try_lock() else {
+            return;
+        };
+        if now.saturating_duration_since(*last_prune) < PRUNE_INTERVAL {
+            return;
+        }
+        *last_prune = now;
+
+        self.requests
+            .retain(|_, (_, last_seen)| now.saturating_duration_since(*last_seen) < STALE_AFTER);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_closed_after_max_requests() {
+        let tracker = ConnectionTracker::new(3);
+
+        assert!(!tracker.record("10.0.0.1:5000"));
+        assert!(!tracker.record("10.0.0.1:5000"));
+        assert!(tracker.record("10.0.0.1:5000"));
+
+        // The closed connection's count is dropped; a new one starts over
+        assert!(tracker.requests.is_empty());
+        assert!(!tracker.record("10.0.0.1:5000"));
+    }
+
+    #[test]
+    fn test_connections_counted_separately() {
+        let tracker = ConnectionTracker::new(2);
+
+        assert!(!tracker.record("10.0.0.1:5000"));
+        assert!(!tracker.record("10.0.0.1:5001"));
+        assert!(tracker.record("10.0.0.1:5000"));
+        assert!(tracker.record("10.0.0.1:5001"));
+    }
+
+    #[test]
+    fn test_single_request_per_connection() {
+        let tracker = ConnectionTracker::new(1);
+        assert!(tracker.record("10.0.0.1:5000"));
+    }
+
+    #[test]
+    fn test_stale_connections_pruned() {
+        let tracker = ConnectionTracker::new(100);
+        let start = Instant::now();
+
+        for port in 0..=PRUNE_THRESHOLD {
+            tracker.record_at(&format!("10.0.0.1:{}", port), start);
+        }
+        assert_eq!(tracker.requests.len(), PRUNE_THRESHOLD + 1);
+
+        tracker.record_at("10.0.0.2:5000", start + STALE_AFTER);
+        assert_eq!(tracker.requests.len(), 1);
+    }
+
+    #[test]
+    fn test_prunes_at_most_once_per_interval() {
+        let tracker = ConnectionTracker::new(100);
+        let start = Instant::now();
+
+        for port in 0..=PRUNE_THRESHOLD {
+            tracker.record_at(&format!("10.0.0.1:{}", port), start);
+        }
+        tracker.record_at("10.0.0.2:5000", start + STALE_AFTER);
+
+        // Past the threshold again, but the last prune is too recent
+        let soon = start + STALE_AFTER + Duration::from_secs(1);
+        for port in 0..=PRUNE_THRESHOLD {
+            tracker.record_at(&format!("10.0.0.3:{}", port), start);
+        }
+        tracker.record_at("10.0.0.2:5000", soon);
+        assert_eq!(tracker.requests.len(), PRUNE_THRESHOLD + 2);
+
+        // Once the interval has passed the stale entries go
+        tracker.record_at("10.0.0.2:5000", start + STALE_AFTER + PRUNE_INTERVAL);
+        assert_eq!(tracker.requests.len(), 1);
+    }
+}
@@ -1,5 +1,41 @@
+use pingora_core::protocols::l4::socket::SocketAddr;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::load_balancing::manager::UpstreamRequestGuard;
+use crate::proxy::active::ActiveRequestGuard;
+use crate::proxy::coalesce::FetchLeader;
+use crate::proxy::guards::UploadCounter;
+use crate::proxy::idempotency::IdempotencyCapture;
+use crate::proxy::transform::TransformBuffer;
 use uuid::Uuid;
 
+/// Outcome of authentication for a request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthDecision {
+    /// Not checked: public route, auth off, or handled before auth
+    #[default]
+    Skipped,
+    Authenticated,
+    Rejected,
+    /// Failed, but let through because auth runs in shadow mode
+    Shadow,
+}
+
+impl AuthDecision {
+    /// Label for logs and metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthDecision::Skipped => "skipped",
+            AuthDecision::Authenticated => "authenticated",
+            AuthDecision::Rejected => "rejected",
+            AuthDecision::Shadow => "shadow",
+        }
+    }
+}
+
 /// Request context that persists throughout the request lifecycle
 #[derive(Debug, Clone)]
 pub struct ProxyContext {
@@ -9,8 +45,80 @@ pub struct ProxyContext {
     /// Request ID for tracking
     pub request_id: String,
 
-    /// Client IP address
-    pub client_ip: Option<String>,
+    /// Client IP address (no port), parsed once from the peer address
+    pub client_ip: Option<IpAddr>,
+
+    /// Client socket address as reported by the listener (IP and port, or a unix path)
+    pub client_addr: Option<String>,
+
+    /// Last request allowed on this connection; it closes after the response
+    pub close_connection: bool,
+
+    /// Prefix of the routing table entry matching the path
+    pub matched_route: Option<String>,
+
+    /// What authentication decided, set once in `request_filter`
+    pub auth_decision: AuthDecision,
+
+    /// `X-RateLimit-Warning` value, once the soft rate limit is crossed
+    pub rate_limit_warning: Option<String>,
+
+    /// Response headers passed on from the external rate-limit service
+    pub rate_limit_headers: Vec<(String, String)>,
+
+    /// Why authentication would have failed (shadow mode only)
+    pub auth_shadow_failure: Option<String>,
+
+    /// Client sent `Expect: 100-continue` and awaits an interim response
+    pub expect_continue: bool,
+
+    /// Byte count for a multipart upload being streamed upstream
+    pub upload: Option<UploadCounter>,
+
+    /// Response body held back for JSON field injection
+    pub response_buffer: Option<TransformBuffer>,
+
+    /// Upstream peers selected so far (more than one after retries)
+    pub upstream_attempts: u32,
+
+    /// When the request was last sent upstream
+    pub upstream_start: Option<std::time::Instant>,
+
+    /// Time the upstream took to send response headers
+    pub upstream_response_time: Option<std::time::Duration>,
+
+    /// Public request path, set when it was rewritten for the upstream
+    pub original_path: Option<String>,
+
+    /// Path sent upstream after route rewrite rules
+    pub rewritten_path: Option<String>,
+
+    /// Response being recorded for replay to `Idempotency-Key` duplicates
+    pub idempotency: Option<IdempotencyCapture>,
+
+    /// Response being recorded as the route's last good one for its fallback
+    pub last_good: Option<IdempotencyCapture>,
+
+    /// GET sent upstream for the identical requests waiting on it
+    pub coalesce: Option<FetchLeader>,
+
+    /// Response is a Server-Sent Events stream, passed through unbuffered
+    pub event_stream: bool,
+
+    /// Shadow upstream this request is sampled for
+    pub mirror_to: Option<String>,
+
+    /// `Accept-Encoding` sent upstream instead of the client's (`response.encoding`)
+    pub upstream_accept_encoding: Option<String>,
+
+    /// `/auth/*` request proxied to the auth service group
+    pub auth_upstream: bool,
+
+    /// Counts this request as in flight until the context is dropped
+    pub active_request: Option<Arc<ActiveRequestGuard>>,
+
+    /// Counts this request as in flight to the selected upstream
+    pub upstream_request: Option<Arc<UpstreamRequestGuard>>,
 
     /// Request start time (for metrics)
     pub start_time: std::time::Instant,
@@ -19,10 +127,39 @@ pub struct ProxyContext {
 impl ProxyContext {
     /// Create a new context
     pub fn new() -> Self {
+        Self::with_request_id(uuid::Uuid::new_v4().to_string())
+    }
+
+    /// Create a new context with a pre-generated request ID
+    pub fn with_request_id(request_id: String) -> Self {
         Self {
             user_id: None,
-            request_id: uuid::Uuid::new_v4().to_string(),
+            request_id,
             client_ip: None,
+            client_addr: None,
+            close_connection: false,
+            matched_route: None,
+            auth_decision: AuthDecision::Skipped,
+            rate_limit_warning: None,
+            rate_limit_headers: Vec::new(),
+            auth_shadow_failure: None,
+            expect_continue: false,
+            upload: None,
+            response_buffer: None,
+            upstream_attempts: 0,
+            upstream_start: None,
+            upstream_response_time: None,
+            original_path: None,
+            rewritten_path: None,
+            idempotency: None,
+            last_good: None,
+            coalesce: None,
+            event_stream: false,
+            mirror_to: None,
+            upstream_accept_encoding: None,
+            auth_upstream: false,
+            active_request: None,
+            upstream_request: None,
             start_time: std::time::Instant::now(),
         }
     }
@@ -32,6 +169,16 @@ impl ProxyContext {
         self.user_id = Some(user_id);
     }
 
+    /// Record the downstream peer address
+    ///
+    /// IPv4 clients on a dual-stack listener arrive as IPv4-mapped IPv6
+    /// addresses (`::ffff:10.0.0.1`); they are stored as plain IPv4.
+    /// Unix socket peers have no IP, so `client_ip` stays `None`.
+    pub fn set_client_addr(&mut self, addr: &SocketAddr) {
+        self.client_ip = addr.as_inet().map(|inet| inet.ip().to_canonical());
+        self.client_addr = Some(addr.to_string());
+    }
+
     /// Get elapsed time since request started
     pub fn elapsed(&self) -> std::time::Duration {
         self.start_time.elapsed()
@@ -43,3 +190,41 @@ impl Default for ProxyContext {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn inet(addr: &str) -> SocketAddr {
+        SocketAddr::Inet(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn test_ipv4_client_addr() {
+        let mut ctx = ProxyContext::new();
+        ctx.set_client_addr(&inet("10.0.0.1:5000"));
+
+        assert_eq!(ctx.client_ip, Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+        assert_eq!(ctx.client_addr.as_deref(), Some("10.0.0.1:5000"));
+    }
+
+    #[test]
+    fn test_ipv6_client_addr() {
+        let mut ctx = ProxyContext::new();
+        ctx.set_client_addr(&inet("[2001:db8::1]:443"));
+
+        assert_eq!(
+            ctx.client_ip,
+            Some(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)))
+        );
+    }
+
+    #[test]
+    fn test_ipv4_mapped_client_addr_is_ipv4() {
+        let mut ctx = ProxyContext::new();
+        ctx.set_client_addr(&inet("[::ffff:10.0.0.1]:5000"));
+
+        assert_eq!(ctx.client_ip, Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+    }
+}
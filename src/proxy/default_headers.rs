@@ -0,0 +1,126 @@
+use pingora_http::ResponseHeader;
+use std::collections::BTreeMap;
+
+/// Only sent over TLS; browsers ignore it on plain HTTP
+const HSTS: &str = "strict-transport-security";
+
+/// `response.default_headers`, added to every response the proxy sends
+///
+/// Headers already on the response (e.g. set by the upstream) are left
+/// alone, so a backend can send a stricter value for its own pages.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultHeaders {
+    /// Lowercase name to the name as configured and its value
+    headers: BTreeMap<String, (String, String)>,
+}
+
+impl DefaultHeaders {
+    /// Headers from config (validated by `Settings::validate`)
+    pub fn new(headers: &BTreeMap<String, String>) -> Self {
+        Self {
+            headers: Self::by_lowercase_name(headers),
+        }
+    }
+
+    fn by_lowercase_name(headers: &BTreeMap<String, String>) -> BTreeMap<String, (String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), (name.clone(), value.clone())))
+            .collect()
+    }
+
+    /// Add the configured headers that `resp` does not have yet
+    ///
+    /// # Arguments
+    /// * `resp` - Response about to be sent
+    /// * `overrides` - The route's `response_headers`; an empty value drops
+    ///   that default
+    /// * `tls` - The client connection is TLS (required for HSTS)
+    pub fn apply(
+        &self,
+        resp: &mut ResponseHeader,
+        overrides: Option<&BTreeMap<String, String>>,
+        tls: bool,
+    ) {
+        let mut headers = self.headers.clone();
+        if let Some(overrides) = overrides {
+            headers.extend(Self::by_lowercase_name(overrides));
+        }
+
+        for (lowercase, (name, value)) in headers {
+            if value.is_empty()
+                || (lowercase == HSTS && !tls)
+                || resp.headers.contains_key(lowercase.as_str())
+            {
+                continue;
+            }
+            if let Err(e) = resp.insert_header(name.clone(), value.as_str()) {
+                log::warn!("Skipping default header {}: {}", name, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn security_headers() -> DefaultHeaders {
+        DefaultHeaders::new(&BTreeMap::from([
+            ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+            ("X-Frame-Options".to_string(), "DENY".to_string()),
+            ("Referrer-Policy".to_string(), "no-referrer".to_string()),
+            (
+                "Strict-Transport-Security".to_string(),
+                "max-age=31536000".to_string(),
+            ),
+        ]))
+    }
+
+    #[test]
+    fn test_defaults_added_to_proxied_response() {
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("X-Frame-Options", "SAMEORIGIN").unwrap();
+
+        security_headers().apply(&mut resp, None, true);
+
+        assert_eq!(resp.headers["X-Content-Type-Options"], "nosniff");
+        assert_eq!(resp.headers["Referrer-Policy"], "no-referrer");
+        assert_eq!(
+            resp.headers["Strict-Transport-Security"],
+            "max-age=31536000"
+        );
+        // The upstream's own value wins
+        assert_eq!(resp.headers["X-Frame-Options"], "SAMEORIGIN");
+        assert_eq!(resp.headers.get_all("X-Frame-Options").iter().count(), 1);
+    }
+
+    #[test]
+    fn test_hsts_only_over_tls() {
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+
+        security_headers().apply(&mut resp, None, false);
+
+        assert!(resp.headers.get("Strict-Transport-Security").is_none());
+        assert_eq!(resp.headers["X-Content-Type-Options"], "nosniff");
+    }
+
+    #[test]
+    fn test_route_overrides() {
+        let overrides = BTreeMap::from([
+            ("x-frame-options".to_string(), "SAMEORIGIN".to_string()),
+            ("Referrer-Policy".to_string(), String::new()),
+            (
+                "Cache-Control".to_string(),
+                "public, max-age=60".to_string(),
+            ),
+        ]);
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+
+        security_headers().apply(&mut resp, Some(&overrides), false);
+
+        assert_eq!(resp.headers["X-Frame-Options"], "SAMEORIGIN");
+        assert!(resp.headers.get("Referrer-Policy").is_none());
+        assert_eq!(resp.headers["Cache-Control"], "public, max-age=60");
+    }
+}
@@ -0,0 +1,106 @@
+use async_trait::async_trait;
+use pingora_core::server::{ShutdownSignal, ShutdownSignalWatch};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Whether the proxy wants new traffic, as reported by `GET /ready`
+///
+/// Only the readiness flips during a drain; `/health` keeps answering 200
+/// and requests keep being served, so the load balancer can move traffic
+/// away without cutting off requests in flight.
+#[derive(Debug, Default)]
+pub struct Readiness {
+    draining: AtomicBool,
+}
+
+impl Readiness {
+    /// `false` once draining has started
+    pub fn is_ready(&self) -> bool {
+        !self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Start failing `/ready`; there is no way back short of a restart
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Mark the proxy not ready, then wait out the drain period
+///
+/// # Arguments
+/// * `readiness` - Flag `/ready` reports
+/// * `drain_timeout` - How long the load balancer gets to stop sending
+///   new requests and the in-flight ones get to finish
+pub async fn drain(readiness: &Readiness, drain_timeout: Duration) {
+    readiness.start_draining();
+    log::warn!(
+        "Draining: /ready now fails, shutting down in {}s",
+        drain_timeout.as_secs()
+    );
+    tokio::time::sleep(drain_timeout).await;
+    log::warn!("Drain period over, shutting down");
+}
+
+/// Shutdown signals as Pingora handles them by default, except that
+/// `SIGTERM` drains (`server.drain_timeout_secs`) before the graceful exit
+///
+/// `SIGINT` still shuts down at once and `SIGQUIT` still hands the
+/// listeners over for a graceful upgrade.
+pub struct DrainingShutdown {
+    readiness: Arc<Readiness>,
+    drain_timeout: Duration,
+}
+
+impl DrainingShutdown {
+    pub fn new(readiness: Arc<Readiness>, drain_timeout: Duration) -> Self {
+        Self {
+            readiness,
+            drain_timeout,
+        }
+    }
+}
+
+#[async_trait]
+impl ShutdownSignalWatch for DrainingShutdown {
+    async fn recv(&self) -> ShutdownSignal {
+        let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler");
+        let mut interrupt = signal(SignalKind::interrupt()).expect("SIGINT handler");
+        let mut quit = signal(SignalKind::quit()).expect("SIGQUIT handler");
+
+        tokio::select! {
+            _ = terminate.recv() => {
+                drain(&self.readiness, self.drain_timeout).await;
+                ShutdownSignal::GracefulTerminate
+            }
+            _ = interrupt.recv() => ShutdownSignal::FastShutdown,
+            _ = quit.recv() => ShutdownSignal::GracefulUpgrade,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_drain_fails_readiness_for_the_drain_period() {
+        let readiness = Arc::new(Readiness::default());
+        assert!(readiness.is_ready());
+
+        let started = Instant::now();
+        let draining = tokio::spawn({
+            let readiness = Arc::clone(&readiness);
+            async move { drain(&readiness, Duration::from_millis(200)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!readiness.is_ready());
+        assert!(!draining.is_finished());
+
+        draining.await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(!readiness.is_ready());
+    }
+}
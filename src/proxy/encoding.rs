@@ -0,0 +1,261 @@
+use pingora_http::ResponseHeader;
+
+/// Result of negotiating a request's `Accept-Encoding`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Negotiation {
+    /// Ask the upstream for this content coding
+    Coding(String),
+    /// Ask the upstream for an uncompressed response
+    Identity,
+    /// The client refuses identity and every configured coding
+    Unsatisfiable,
+}
+
+impl Negotiation {
+    /// `Accept-Encoding` sent upstream, so it can only pick what was negotiated
+    ///
+    /// `Unsatisfiable` requests that are let through get identity, which a
+    /// server may send whatever the client asked for.
+    pub fn upstream_header(&self) -> &str {
+        match self {
+            Negotiation::Coding(coding) => coding,
+            Negotiation::Identity | Negotiation::Unsatisfiable => "identity",
+        }
+    }
+}
+
+/// Codings of an `Accept-Encoding` value with their quality values
+///
+/// Codings are lowercased. Elements with an unreadable `q` are dropped;
+/// `q` is clamped to 0..=1.
+fn parse(accept_encoding: &str) -> Vec<(String, f32)> {
+    accept_encoding
+        .split(',')
+        .filter_map(|element| {
+            let mut parts = element.split(';');
+            let coding = parts.next()?.trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+
+            let mut quality = 1.0;
+            for param in parts {
+                if let Some((name, value)) = param.split_once('=') {
+                    if name.trim().eq_ignore_ascii_case("q") {
+                        quality = value.trim().parse::<f32>().ok()?.clamp(0.0, 1.0);
+                    }
+                }
+            }
+            Some((coding, quality))
+        })
+        .collect()
+}
+
+/// Pick the content coding to ask the upstream for
+///
+/// Follows RFC 9110: a coding the client lists with `q=0` is never chosen,
+/// unlisted codings are only acceptable through `*`, and identity is
+/// acceptable unless excluded by `identity;q=0` or `*;q=0`. The coding
+/// with the highest `q` wins, ties going to the earlier one in `codings`;
+/// a coding is preferred over identity of the same `q` and over identity
+/// the client did not list.
+///
+/// # Arguments
+/// * `accept_encoding` - The request's `Accept-Encoding` (all values joined)
+/// * `codings` - Codings the upstreams can produce, most preferred first
+///
+/// # Returns
+/// * `Negotiation` - The coding, identity, or `Unsatisfiable`
+pub fn negotiate(accept_encoding: &str, codings: &[String]) -> Negotiation {
+    let accepted = parse(accept_encoding);
+    let quality = |coding: &str| {
+        accepted
+            .iter()
+            .find(|(name, _)| name == coding)
+            .or_else(|| accepted.iter().find(|(name, _)| name == "*"))
+            .map(|(_, quality)| *quality)
+    };
+
+    let mut best: Option<(&String, f32)> = None;
+    for coding in codings {
+        let q = quality(&coding.to_ascii_lowercase()).unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((coding, q));
+        }
+    }
+    // Unlisted identity is acceptable, but only as the last resort
+    let identity = quality("identity");
+
+    match best {
+        Some((coding, q)) if identity.is_none_or(|identity| q >= identity) => {
+            Negotiation::Coding(coding.clone())
+        }
+        _ if identity.unwrap_or(1.0) > 0.0 => Negotiation::Identity,
+        _ => Negotiation::Unsatisfiable,
+    }
+}
+
+/// Whether the proxy may compress a response
+///
+/// Responses that already carry a `Content-Encoding` other than identity
+/// pass through untouched, and so do media types in `exclude_types`
+/// (`type/*` covers a whole type), which are compressed already:
+/// compressing them again costs CPU and gains nothing. A `206 Partial
+/// Content` body is a byte range of the uncompressed representation and
+/// passes through too, so its `Content-Range` stays true.
+///
+/// # Arguments
+/// * `resp` - Upstream response header
+/// * `exclude_types` - `response.encoding.exclude_types`
+pub fn compressible(resp: &ResponseHeader, exclude_types: &[String]) -> bool {
+    if resp.status.as_u16() == 206 {
+        return false;
+    }
+    let header = |name| resp.headers.get(name).and_then(|v| v.to_str().ok());
+
+    let encoded = header("Content-Encoding").is_some_and(|e| !e.eq_ignore_ascii_case("identity"));
+    if encoded {
+        return false;
+    }
+
+    let media_type = header("Content-Type")
+        .and_then(|value| value.split(';').next())
+        .map(str::trim)
+        .unwrap_or_default();
+    let excluded = exclude_types
+        .iter()
+        .any(|excluded| match excluded.strip_suffix("/*") {
+            Some(type_) => media_type
+                .split_once('/')
+                .is_some_and(|(name, _)| name.eq_ignore_ascii_case(type_)),
+            None => excluded.eq_ignore_ascii_case(media_type),
+        });
+    !excluded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::EncodingConfig;
+
+    fn codings() -> Vec<String> {
+        vec!["br".to_string(), "gzip".to_string()]
+    }
+
+    #[test]
+    fn test_client_excluding_all_encodings() {
+        for header in ["identity;q=0", "*;q=0", "gzip;q=0, br;q=0, identity;q=0"] {
+            assert_eq!(
+                negotiate(header, &codings()),
+                Negotiation::Unsatisfiable,
+                "{}",
+                header
+            );
+        }
+        assert_eq!(Negotiation::Unsatisfiable.upstream_header(), "identity");
+
+        // Excluded codings are skipped even when another one is left
+        assert_eq!(
+            negotiate("br;q=0, gzip, identity;q=0", &codings()),
+            Negotiation::Coding("gzip".to_string())
+        );
+        assert_eq!(
+            negotiate("*;q=0, identity", &codings()),
+            Negotiation::Identity
+        );
+    }
+
+    #[test]
+    fn test_client_accepting_only_br() {
+        assert_eq!(
+            negotiate("br", &codings()),
+            Negotiation::Coding("br".to_string())
+        );
+        assert_eq!(negotiate("br", &codings()).upstream_header(), "br");
+
+        // Without br upstream, identity is still acceptable...
+        assert_eq!(
+            negotiate("br", &["gzip".to_string()]),
+            Negotiation::Identity
+        );
+        // ...unless the client excluded it too
+        assert_eq!(
+            negotiate("br, identity;q=0", &["gzip".to_string()]),
+            Negotiation::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn test_quality_values_decide() {
+        assert_eq!(
+            negotiate("gzip;q=0.9, br;q=0.5", &codings()),
+            Negotiation::Coding("gzip".to_string())
+        );
+        // Equal q: the configured order wins
+        assert_eq!(
+            negotiate("GZIP, br", &codings()),
+            Negotiation::Coding("br".to_string())
+        );
+        assert_eq!(
+            negotiate("*", &codings()),
+            Negotiation::Coding("br".to_string())
+        );
+        assert_eq!(
+            negotiate("gzip;q=0.1, identity;q=0.5", &codings()),
+            Negotiation::Identity
+        );
+        // Empty header: identity only; bad q: element ignored
+        assert_eq!(negotiate("", &codings()), Negotiation::Identity);
+        assert_eq!(negotiate("br;q=high", &codings()), Negotiation::Identity);
+    }
+
+    fn response(headers: &[(&'static str, &str)]) -> ResponseHeader {
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        for (name, value) in headers {
+            resp.insert_header(*name, *value).unwrap();
+        }
+        resp
+    }
+
+    #[test]
+    fn test_compressed_types_are_not_recompressed() {
+        let exclude_types = EncodingConfig::default().exclude_types;
+
+        let jpeg = response(&[("Content-Type", "image/jpeg")]);
+        assert!(!compressible(&jpeg, &exclude_types));
+        let video = response(&[("Content-Type", "video/mp4")]);
+        assert!(!compressible(&video, &exclude_types));
+
+        let json = response(&[("Content-Type", "application/json; charset=utf-8")]);
+        assert!(compressible(&json, &exclude_types));
+        let svg = response(&[("Content-Type", "image/svg+xml")]);
+        assert!(compressible(&svg, &exclude_types));
+
+        // The list is the operator's to change
+        assert!(compressible(&jpeg, &[]));
+    }
+
+    #[test]
+    fn test_encoded_responses_are_not_recompressed() {
+        let exclude_types = EncodingConfig::default().exclude_types;
+
+        let gzipped = response(&[("Content-Type", "text/html"), ("Content-Encoding", "gzip")]);
+        assert!(!compressible(&gzipped, &exclude_types));
+
+        let identity = response(&[
+            ("Content-Type", "text/html"),
+            ("Content-Encoding", "identity"),
+        ]);
+        assert!(compressible(&identity, &exclude_types));
+    }
+
+    #[test]
+    fn test_partial_content_is_not_compressed() {
+        let mut range = response(&[
+            ("Content-Type", "text/html"),
+            ("Content-Range", "bytes 0-9/100"),
+        ]);
+        range.set_status(206).unwrap();
+        assert!(!compressible(&range, &[]));
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::config::settings::FallbackConfig;
+use crate::proxy::idempotency::{CachedResponse, IdempotencyCapture};
+
+/// Response header marking a fallback response
+pub const FALLBACK_HEADER: &str = "X-Fallback-Response";
+
+/// Most distinct paths remembered; later ones are not recorded
+const MAX_ENTRIES: usize = 1024;
+
+/// Last good response per path, for routes with `fallback.last_good`
+///
+/// Kept in memory only: after a restart the static fallback is served
+/// until the route has proxied a response again.
+#[derive(Debug, Default)]
+pub struct LastGoodResponses {
+    responses: RwLock<HashMap<String, CachedResponse>>,
+}
+
+impl LastGoodResponses {
+    /// Remember a response sent in full, if it is a shareable 2xx
+    ///
+    /// Responses that set cookies or are marked `private` / `no-store`
+    /// belong to one client and are never served to another.
+    ///
+    /// # Arguments
+    /// * `capture` - Response recorded under the request's path and query
+    pub fn record(&self, capture: &IdempotencyCapture) {
+        let Some(response) = capture.response() else {
+            return;
+        };
+        if !(200..300).contains(&response.status) || !is_shareable(&response) {
+            return;
+        }
+
+        let mut responses = self.responses.write().unwrap();
+        if responses.len() >= MAX_ENTRIES && !responses.contains_key(&capture.key) {
+            return;
+        }
+        responses.insert(capture.key.clone(), response);
+    }
+
+    /// Last good response for a path and query, if any
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.responses.read().unwrap().get(key).cloned()
+    }
+}
+
+/// Whether a response may be served to clients other than the one it was for
+///
+/// Responses that set cookies or are marked `private` / `no-store` are not.
+pub fn is_shareable(response: &CachedResponse) -> bool {
+    !response.headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("set-cookie")
+            || (name.eq_ignore_ascii_case("cache-control")
+                && value.split(',').map(str::trim).any(|d| {
+                    d.eq_ignore_ascii_case("private") || d.eq_ignore_ascii_case("no-store")
+                }))
+    })
+}
+
+/// Response to serve while the route's breaker is open
+///
+/// # Arguments
+/// * `config` - Route's `fallback` settings
+/// * `last_good` - Recorded responses
+/// * `key` - Request path and query
+/// * `method` - Request method; only GETs get a recorded response
+///
+/// # Returns
+/// * `(CachedResponse, &str)` - Response and its source (`last_good` / `static`)
+pub fn response(
+    config: &FallbackConfig,
+    last_good: &LastGoodResponses,
+    key: &str,
+    method: &str,
+) -> (CachedResponse, &'static str) {
+    let recorded = (config.last_good && method == "GET")
+        .then(|| last_good.get(key))
+        .flatten();
+
+    match recorded {
+        Some(response) => (response, "last_good"),
+        None => (
+            CachedResponse::new(
+                config.status,
+                vec![("Content-Type".to_string(), config.content_type.clone())],
+                config.body.as_bytes(),
+            ),
+            "static",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pingora_http::ResponseHeader;
+
+    fn config(last_good: bool) -> FallbackConfig {
+        FallbackConfig {
+            last_good,
+            status: 503,
+            body: r#"{"error":"Temporarily unavailable"}"#.to_string(),
+            content_type: "application/json".to_string(),
+        }
+    }
+
+    fn capture(status: u16, headers: &[(&str, &str)], body: &[u8]) -> IdempotencyCapture {
+        let mut resp = ResponseHeader::build(status, None).unwrap();
+        for (name, value) in headers {
+            resp.insert_header(name.to_string(), *value).unwrap();
+        }
+
+        let mut capture = IdempotencyCapture::new("/api/items?page=1".to_string(), 0);
+        capture.record_header(&resp);
+        capture.record_body(body);
+        capture
+    }
+
+    #[test]
+    fn test_static_fallback_without_recorded_response() {
+        let last_good = LastGoodResponses::default();
+
+        let (resp, source) = response(&config(true), &last_good, "/api/items?page=1", "GET");
+        assert_eq!(source, "static");
+        assert_eq!(resp.status, 503);
+        assert_eq!(resp.body(), br#"{"error":"Temporarily unavailable"}"#);
+    }
+
+    #[test]
+    fn test_last_good_response_served_to_gets_only() {
+        let last_good = LastGoodResponses::default();
+        last_good.record(&capture(200, &[("Content-Type", "text/plain")], b"items"));
+
+        let (resp, source) = response(&config(true), &last_good, "/api/items?page=1", "GET");
+        assert_eq!(source, "last_good");
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.body(), b"items");
+
+        let (_, source) = response(&config(true), &last_good, "/api/items?page=1", "POST");
+        assert_eq!(source, "static");
+        let (_, source) = response(&config(false), &last_good, "/api/items?page=1", "GET");
+        assert_eq!(source, "static");
+    }
+
+    #[test]
+    fn test_only_shareable_successes_are_recorded() {
+        let last_good = LastGoodResponses::default();
+
+        last_good.record(&capture(404, &[], b"missing"));
+        last_good.record(&capture(200, &[("Set-Cookie", "session=abc")], b"mine"));
+        last_good.record(&capture(
+            200,
+            &[("Cache-Control", "max-age=0, private")],
+            b"mine",
+        ));
+        assert!(last_good.get("/api/items?page=1").is_none());
+
+        last_good.record(&capture(200, &[("Cache-Control", "max-age=60")], b"items"));
+        assert!(last_good.get("/api/items?page=1").is_some());
+    }
+}
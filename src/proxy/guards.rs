@@ -0,0 +1,434 @@
+use pingora_core::{Error, ErrorType};
+
+use crate::metrics;
+
+/// Non-standard status (nginx's) logged when the client went away mid-request
+pub const CLIENT_CLOSED_REQUEST: u16 = 499;
+
+/// Check the request target length (path + query) against the configured maximum
+///
+/// # Returns
+/// * `Some(414)` if the URI is too long, `None` otherwise
+pub fn check_uri_length(uri_length: usize, max_uri_length: usize) -> Option<u16> {
+    if uri_length > max_uri_length {
+        Some(414)
+    } else {
+        None
+    }
+}
+
+/// Check the request's header count and longest header value
+///
+/// # Arguments
+/// * `headers` - Request headers
+/// * `max_headers` - Maximum number of header fields (repeated names count each time)
+/// * `max_header_value_len` - Maximum length of any one value in bytes
+///
+/// # Returns
+/// * `Some(431)` if either limit is exceeded, `None` otherwise
+pub fn check_headers(
+    headers: &http::HeaderMap,
+    max_headers: usize,
+    max_header_value_len: usize,
+) -> Option<u16> {
+    if headers.len() > max_headers
+        || headers
+            .values()
+            .any(|value| value.len() > max_header_value_len)
+    {
+        Some(431)
+    } else {
+        None
+    }
+}
+
+/// How to answer a request's `Expect` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectDecision {
+    /// No expectation, nothing to do
+    None,
+    /// Send `100 Continue` before the body is read or forwarded
+    Continue,
+    /// Refuse the body upfront with this status (417 or 413)
+    Reject(u16),
+}
+
+/// Decide how to answer an `Expect` header before any body is sent
+///
+/// # Arguments
+/// * `expect` - Value of the `Expect` header, if present
+/// * `content_length` - Declared `Content-Length`, if present
+/// * `max_body_size` - Configured maximum body size in bytes
+///
+/// # Returns
+/// * `Reject(417)` for expectations other than `100-continue`
+/// * `Reject(413)` if the declared body exceeds `max_body_size`
+/// * `Continue` otherwise
+pub fn check_expect(
+    expect: Option<&str>,
+    content_length: Option<usize>,
+    max_body_size: usize,
+) -> ExpectDecision {
+    let Some(expect) = expect else {
+        return ExpectDecision::None;
+    };
+
+    if !expect.trim().eq_ignore_ascii_case("100-continue") {
+        return ExpectDecision::Reject(417);
+    }
+
+    match content_length {
+        Some(length) if length > max_body_size => ExpectDecision::Reject(413),
+        _ => ExpectDecision::Continue,
+    }
+}
+
+/// Whether a `Content-Type` header marks a multipart form upload
+pub fn is_multipart(content_type: Option<&str>) -> bool {
+    content_type
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("multipart/form-data"))
+}
+
+/// Check a request's media type against a route's allowlist
+///
+/// Parameters after `;` are ignored and matching is case-insensitive; an
+/// entry such as `text/*` allows a whole top-level type. A body sent
+/// without a `Content-Type` is refused, a request without a body passes.
+///
+/// # Arguments
+/// * `content_type` - Value of the `Content-Type` header, if present
+/// * `has_body` - Whether the request declares a body
+/// * `allowed` - The route's `allowed_content_types` (empty allows anything)
+///
+/// # Returns
+/// * `Some(415)` if the type is not allowed, `None` otherwise
+pub fn check_content_type(
+    content_type: Option<&str>,
+    has_body: bool,
+    allowed: &[String],
+) -> Option<u16> {
+    if allowed.is_empty() {
+        return None;
+    }
+
+    let Some(content_type) = content_type else {
+        return has_body.then_some(415);
+    };
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+
+    let permitted = allowed.iter().any(|entry| match entry.strip_suffix("/*") {
+        Some(top_level) => mime
+            .split_once('/')
+            .is_some_and(|(ty, _)| ty.eq_ignore_ascii_case(top_level)),
+        None => mime.eq_ignore_ascii_case(entry),
+    });
+
+    if permitted {
+        None
+    } else {
+        Some(415)
+    }
+}
+
+/// Check a declared upload size against the configured maximum
+///
+/// # Returns
+/// * `Some(413)` if `Content-Length` exceeds `max_upload_size`, `None` otherwise
+pub fn check_upload_size(content_length: Option<usize>, max_upload_size: usize) -> Option<u16> {
+    match content_length {
+        Some(length) if length > max_upload_size => Some(413),
+        _ => None,
+    }
+}
+
+/// Running byte count for an upload streamed to the upstream
+///
+/// Covers chunked uploads, whose size is only known as the body arrives.
+#[derive(Debug, Clone, Copy)]
+pub struct UploadCounter {
+    limit: usize,
+    received: usize,
+}
+
+impl UploadCounter {
+    /// Start counting an upload capped at `limit` bytes
+    pub fn new(limit: usize) -> Self {
+        Self { limit, received: 0 }
+    }
+
+    /// Account for the next body chunk
+    ///
+    /// # Returns
+    /// * `Err(413)` once the upload exceeds the limit
+    pub fn add(&mut self, chunk_len: usize) -> Result<(), u16> {
+        self.received += chunk_len;
+        if self.received > self.limit {
+            Err(413)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Why reading a request body failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyReadError {
+    /// The client closed the connection or stopped sending
+    ClientAbort,
+    /// The body did not follow HTTP framing (bad chunk, bad length, ...)
+    Malformed,
+    /// The body exceeded the size limit
+    TooLarge,
+}
+
+impl BodyReadError {
+    /// Classify an error from reading the request body
+    pub fn classify(e: &Error) -> Self {
+        match e.etype() {
+            ErrorType::HTTPStatus(413) => BodyReadError::TooLarge,
+            // A stalled client is treated like one that left
+            ErrorType::ConnectionClosed | ErrorType::ReadError | ErrorType::ReadTimedout => {
+                BodyReadError::ClientAbort
+            }
+            _ => BodyReadError::Malformed,
+        }
+    }
+
+    /// Status recorded for the request (499 is logged but never sent)
+    pub fn status(self) -> u16 {
+        match self {
+            BodyReadError::ClientAbort => CLIENT_CLOSED_REQUEST,
+            BodyReadError::Malformed => 400,
+            BodyReadError::TooLarge => 413,
+        }
+    }
+
+    /// Metric label
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BodyReadError::ClientAbort => "client_abort",
+            BodyReadError::Malformed => "malformed",
+            BodyReadError::TooLarge => "too_large",
+        }
+    }
+
+    /// Count the failure and turn it into a downstream error carrying its status
+    ///
+    /// # Arguments
+    /// * `e` - Error from reading the body
+    ///
+    /// # Returns
+    /// * `(BodyReadError, Box<Error>)` - Classification, and the error to fail the request with
+    pub fn record(e: Box<Error>) -> (Self, Box<Error>) {
+        let class = Self::classify(&e);
+        metrics::REQUEST_BODY_READ_ERRORS
+            .with_label_values(&[class.as_str()])
+            .inc();
+
+        let error = Error::because(
+            ErrorType::HTTPStatus(class.status()),
+            "Failed to read request body",
+            e,
+        )
+        .into_down();
+        (class, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_only() -> Vec<String> {
+        vec!["application/json".to_string()]
+    }
+
+    #[test]
+    fn test_disallowed_content_type_is_rejected() {
+        assert_eq!(
+            check_content_type(Some("application/xml"), true, &json_only()),
+            Some(415)
+        );
+        assert_eq!(
+            check_content_type(Some("multipart/form-data; boundary=x"), true, &json_only()),
+            Some(415)
+        );
+        // A body with no declared type is not let through either
+        assert_eq!(check_content_type(None, true, &json_only()), Some(415));
+    }
+
+    #[test]
+    fn test_allowed_content_type_proceeds() {
+        assert_eq!(
+            check_content_type(Some("Application/JSON; charset=utf-8"), true, &json_only()),
+            None
+        );
+        assert_eq!(
+            check_content_type(Some("text/csv"), true, &["text/*".to_string()]),
+            None
+        );
+        // Bodiless requests and routes without a rule
+        assert_eq!(check_content_type(None, false, &json_only()), None);
+        assert_eq!(check_content_type(Some("application/xml"), true, &[]), None);
+    }
+
+    #[test]
+    fn test_uri_over_limit_is_rejected() {
+        let uri = format!("/api/{}", "a".repeat(8192));
+        assert_eq!(check_uri_length(uri.len(), 8192), Some(414));
+    }
+
+    #[test]
+    fn test_normal_uri_proceeds() {
+        assert_eq!(check_uri_length("/api/users?page=2".len(), 8192), None);
+
+        // Exactly at the limit is still allowed
+        assert_eq!(check_uri_length(8192, 8192), None);
+    }
+
+    fn headers(count: usize, value_len: usize) -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("host", "api.example.com".parse().unwrap());
+        for i in 1..count {
+            headers.insert(
+                http::HeaderName::try_from(format!("x-custom-{}", i)).unwrap(),
+                "a".repeat(value_len).parse().unwrap(),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn test_too_many_headers_rejected() {
+        assert_eq!(check_headers(&headers(101, 10), 100, 8192), Some(431));
+
+        // Repeated names count once per field
+        let mut repeated = http::HeaderMap::new();
+        for _ in 0..101 {
+            repeated.append("cookie", "a=b".parse().unwrap());
+        }
+        assert_eq!(check_headers(&repeated, 100, 8192), Some(431));
+    }
+
+    #[test]
+    fn test_long_header_value_rejected() {
+        assert_eq!(check_headers(&headers(3, 8193), 100, 8192), Some(431));
+    }
+
+    #[test]
+    fn test_normal_headers_proceed() {
+        assert_eq!(check_headers(&headers(20, 100), 100, 8192), None);
+
+        // Exactly at both limits is still allowed
+        assert_eq!(check_headers(&headers(100, 8192), 100, 8192), None);
+    }
+
+    #[test]
+    fn test_expect_continue() {
+        assert_eq!(
+            check_expect(Some("100-continue"), Some(512), 1024),
+            ExpectDecision::Continue
+        );
+        assert_eq!(
+            check_expect(Some("100-Continue"), None, 1024),
+            ExpectDecision::Continue
+        );
+        assert_eq!(check_expect(None, Some(512), 1024), ExpectDecision::None);
+    }
+
+    #[test]
+    fn test_expect_rejected_upfront() {
+        assert_eq!(
+            check_expect(Some("100-continue"), Some(2048), 1024),
+            ExpectDecision::Reject(413)
+        );
+        assert_eq!(
+            check_expect(Some("something-else"), Some(512), 1024),
+            ExpectDecision::Reject(417)
+        );
+    }
+
+    #[test]
+    fn test_multipart_detection() {
+        assert!(is_multipart(Some("multipart/form-data; boundary=xyz")));
+        assert!(is_multipart(Some("Multipart/Form-Data")));
+        assert!(!is_multipart(Some("application/json")));
+        assert!(!is_multipart(None));
+    }
+
+    #[test]
+    fn test_oversized_upload_is_rejected() {
+        let max_upload_size = 10 * 1024 * 1024;
+        assert_eq!(
+            check_upload_size(Some(max_upload_size + 1), max_upload_size),
+            Some(413)
+        );
+
+        // Chunked upload without a declared length is cut off as it streams
+        let mut counter = UploadCounter::new(1024);
+        assert_eq!(counter.add(1000), Ok(()));
+        assert_eq!(counter.add(100), Err(413));
+    }
+
+    #[test]
+    fn test_upload_within_limit_streams_through() {
+        let max_upload_size = 10 * 1024 * 1024;
+        assert_eq!(check_upload_size(Some(4096), max_upload_size), None);
+        assert_eq!(check_upload_size(None, max_upload_size), None);
+
+        let mut counter = UploadCounter::new(max_upload_size);
+        for _ in 0..10 {
+            assert_eq!(counter.add(1024 * 1024), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_body_read_errors_are_classified() {
+        let cases = [
+            (ErrorType::ConnectionClosed, BodyReadError::ClientAbort, 499),
+            (ErrorType::ReadError, BodyReadError::ClientAbort, 499),
+            (ErrorType::ReadTimedout, BodyReadError::ClientAbort, 499),
+            (ErrorType::InvalidHTTPHeader, BodyReadError::Malformed, 400),
+            (ErrorType::H2Error, BodyReadError::Malformed, 400),
+            (
+                ErrorType::Custom("InvalidChunk"),
+                BodyReadError::Malformed,
+                400,
+            ),
+            (ErrorType::HTTPStatus(413), BodyReadError::TooLarge, 413),
+        ];
+
+        for (etype, class, status) in cases {
+            let e = Error::new(etype.clone());
+            assert_eq!(BodyReadError::classify(&e), class, "{:?}", etype);
+            assert_eq!(class.status(), status);
+        }
+    }
+
+    #[test]
+    fn test_aborted_body_read_is_metered_not_500() {
+        let counter = |class: BodyReadError| {
+            metrics::REQUEST_BODY_READ_ERRORS
+                .with_label_values(&[class.as_str()])
+                .get()
+        };
+        let aborts = counter(BodyReadError::ClientAbort);
+        let malformed = counter(BodyReadError::Malformed);
+
+        // Truncated body: the peer closed before Content-Length bytes arrived
+        let (class, error) = BodyReadError::record(Error::explain(
+            ErrorType::ConnectionClosed,
+            "premature body end",
+        ));
+        assert_eq!(class, BodyReadError::ClientAbort);
+        assert_eq!(error.etype(), &ErrorType::HTTPStatus(CLIENT_CLOSED_REQUEST));
+        assert_eq!(error.esource(), &pingora_core::ErrorSource::Downstream);
+        assert_eq!(counter(BodyReadError::ClientAbort), aborts + 1);
+
+        let (class, error) = BodyReadError::record(Error::new(ErrorType::InvalidHTTPHeader));
+        assert_eq!(class, BodyReadError::Malformed);
+        assert_eq!(error.etype(), &ErrorType::HTTPStatus(400));
+        assert_eq!(counter(BodyReadError::Malformed), malformed + 1);
+    }
+}
@@ -0,0 +1,367 @@
+use async_trait::async_trait;
+use pingora_core::apps::ServerApp;
+use pingora_core::protocols::raw_connect::ProxyDigest;
+use pingora_core::protocols::tls::{SslDigest, TlsRef, ALPN};
+use pingora_core::protocols::{
+    GetProxyDigest, GetSocketDigest, GetTimingDigest, Peek, Shutdown, SocketDigest, Ssl, Stream,
+    TimingDigest, UniqueID, UniqueIDType,
+};
+use pingora_core::server::ShutdownWatch;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+/// Deadline for a client to finish sending its request headers
+/// (`server.header_read_timeout_secs`)
+///
+/// Pingora reads the headers of a request before any `ProxyHttp` hook runs,
+/// without a timeout of its own, so a client trickling them a byte at a
+/// time (slowloris) could hold a connection open indefinitely. This wraps
+/// the proxy app and fails reads on a connection once the deadline has
+/// passed without a complete header block. The deadline starts when the
+/// connection is accepted, and for a kept-alive connection when the next
+/// request's first byte arrives; idle keep-alive connections are left to
+/// Pingora's keep-alive timeout. Bodies are not covered.
+///
+/// HTTP/2 (negotiated by ALPN) frames headers in binary and is passed
+/// through unchanged.
+pub struct HeaderDeadline<A> {
+    app: Arc<A>,
+    timeout: Option<Duration>,
+}
+
+impl<A> HeaderDeadline<A> {
+    /// Wrap `app`; `None` reads headers without a deadline
+    pub fn new(app: A, timeout: Option<Duration>) -> Self {
+        Self {
+            app: Arc::new(app),
+            timeout,
+        }
+    }
+}
+
+#[async_trait]
+impl<A: ServerApp + Send + Sync + 'static> ServerApp for HeaderDeadline<A> {
+    async fn process_new(
+        self: &Arc<Self>,
+        stream: Stream,
+        shutdown: &ShutdownWatch,
+    ) -> Option<Stream> {
+        let Some(timeout) = self.timeout else {
+            return self.app.process_new(stream, shutdown).await;
+        };
+        if stream.selected_alpn_proto() == Some(ALPN::H2) {
+            return self.app.process_new(stream, shutdown).await;
+        }
+
+        // A kept-alive connection comes back wrapped; start its next request over
+        let stream = if stream.as_any().is::<DeadlineStream>() {
+            let reused = stream
+                .into_any()
+                .downcast::<DeadlineStream>()
+                .expect("checked above");
+            DeadlineStream::new(reused.inner, timeout, false)
+        } else {
+            DeadlineStream::new(stream, timeout, true)
+        };
+
+        self.app.process_new(Box::new(stream), shutdown).await
+    }
+
+    async fn cleanup(&self) {
+        self.app.cleanup().await
+    }
+}
+
+/// Connection whose reads fail once the header deadline has passed
+#[derive(Debug)]
+struct DeadlineStream {
+    inner: Stream,
+    timeout: Duration,
+    /// Running once the request has started arriving
+    deadline: Option<Pin<Box<Sleep>>>,
+    /// Line ends seen in a row (a lone `\r` does not break the run); two
+    /// end the header block
+    line_ends: u8,
+}
+
+impl DeadlineStream {
+    /// # Arguments
+    /// * `inner` - The connection
+    /// * `timeout` - Time allowed for the header block
+    /// * `started` - Start the deadline now rather than on the first byte
+    fn new(inner: Stream, timeout: Duration, started: bool) -> Self {
+        Self {
+            inner,
+            timeout,
+            deadline: started.then(|| Box::pin(tokio::time::sleep(timeout))),
+            line_ends: 0,
+        }
+    }
+
+    fn headers_read(&self) -> bool {
+        self.line_ends >= 2
+    }
+
+    /// Look for the blank line ending the header block in newly read bytes
+    fn scan(&mut self, data: &[u8]) {
+        if !data.is_empty() && self.deadline.is_none() {
+            self.deadline = Some(Box::pin(tokio::time::sleep(self.timeout)));
+        }
+        for byte in data {
+            match byte {
+                b'\n' => self.line_ends += 1,
+                b'\r' => {}
+                _ => self.line_ends = 0,
+            }
+            if self.headers_read() {
+                self.deadline = None;
+                return;
+            }
+        }
+    }
+}
+
+impl AsyncRead for DeadlineStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if let Some(deadline) = this.deadline.as_mut() {
+            if deadline.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("request headers not received within {:?}", this.timeout),
+                )));
+            }
+        }
+
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if !this.headers_read() {
+            this.scan(&buf.filled()[filled..]);
+        }
+        result
+    }
+}
+
+impl AsyncWrite for DeadlineStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+// Everything else is the connection's own
+
+#[async_trait]
+impl Shutdown for DeadlineStream {
+    async fn shutdown(&mut self) {
+        self.inner.shutdown().await
+    }
+}
+
+impl UniqueID for DeadlineStream {
+    fn id(&self) -> UniqueIDType {
+        self.inner.id()
+    }
+}
+
+impl Ssl for DeadlineStream {
+    fn get_ssl(&self) -> Option<&TlsRef> {
+        self.inner.get_ssl()
+    }
+
+    fn get_ssl_digest(&self) -> Option<Arc<SslDigest>> {
+        self.inner.get_ssl_digest()
+    }
+
+    fn selected_alpn_proto(&self) -> Option<ALPN> {
+        self.inner.selected_alpn_proto()
+    }
+}
+
+impl GetTimingDigest for DeadlineStream {
+    fn get_timing_digest(&self) -> Vec<Option<TimingDigest>> {
+        self.inner.get_timing_digest()
+    }
+
+    fn get_read_pending_time(&self) -> Duration {
+        self.inner.get_read_pending_time()
+    }
+
+    fn get_write_pending_time(&self) -> Duration {
+        self.inner.get_write_pending_time()
+    }
+}
+
+impl GetProxyDigest for DeadlineStream {
+    fn get_proxy_digest(&self) -> Option<Arc<ProxyDigest>> {
+        self.inner.get_proxy_digest()
+    }
+
+    fn set_proxy_digest(&mut self, digest: ProxyDigest) {
+        self.inner.set_proxy_digest(digest)
+    }
+}
+
+impl GetSocketDigest for DeadlineStream {
+    fn get_socket_digest(&self) -> Option<Arc<SocketDigest>> {
+        self.inner.get_socket_digest()
+    }
+
+    fn set_socket_digest(&mut self, digest: SocketDigest) {
+        self.inner.set_socket_digest(digest)
+    }
+}
+
+#[async_trait]
+impl Peek for DeadlineStream {
+    async fn try_peek(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        self.inner.try_peek(buf).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    const TIMEOUT: Duration = Duration::from_millis(300);
+
+    /// Reads request headers the way Pingora does, then answers 200
+    struct HeaderReader;
+
+    #[async_trait]
+    impl ServerApp for HeaderReader {
+        async fn process_new(
+            self: &Arc<Self>,
+            mut stream: Stream,
+            _shutdown: &ShutdownWatch,
+        ) -> Option<Stream> {
+            let mut header = Vec::new();
+            let mut buf = [0u8; 64];
+            while !header.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => return None,
+                    Ok(n) => header.extend_from_slice(&buf[..n]),
+                }
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .ok()?;
+            Some(stream)
+        }
+    }
+
+    /// Serve one connection through the deadline; the client end is returned
+    fn serve(timeout: Option<Duration>) -> (DuplexStream, tokio::task::JoinHandle<()>) {
+        let (client, server) = tokio::io::duplex(1024);
+        let app = Arc::new(HeaderDeadline::new(HeaderReader, timeout));
+        let (_, shutdown) = tokio::sync::watch::channel(false);
+
+        let connection = tokio::spawn(async move {
+            let mut stream: Option<Stream> = Some(Box::new(server));
+            while let Some(reused) = stream {
+                stream = app.process_new(reused, &shutdown).await;
+            }
+        });
+        (client, connection)
+    }
+
+    /// Send `data` a byte at a time, `delay` apart, until the proxy hangs up
+    async fn trickle(client: &mut DuplexStream, data: &[u8], delay: Duration) {
+        for byte in data {
+            if client.write_all(&[*byte]).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trickled_headers_are_cut_off() {
+        let (mut client, connection) = serve(Some(TIMEOUT));
+        let start = Instant::now();
+
+        let request = b"GET / HTTP/1.1\r\nHost: example.com\r\nX-Padding: aaaaaaaaaaaa\r\n\r\n";
+        tokio::select! {
+            _ = trickle(&mut client, request, Duration::from_millis(20)) => {
+                panic!("the whole trickled header was read")
+            }
+            _ = connection => {}
+        }
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= TIMEOUT, "closed after {:?}", elapsed);
+        assert!(elapsed < TIMEOUT * 2, "closed after {:?}", elapsed);
+
+        // The client got no response
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_deadline_restarts_for_each_request() {
+        let (mut client, _connection) = serve(Some(TIMEOUT));
+        let mut buf = [0u8; 128];
+
+        for _ in 0..2 {
+            client
+                .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")
+                .await
+                .unwrap();
+            let n = client.read(&mut buf).await.unwrap();
+            assert!(buf[..n].starts_with(b"HTTP/1.1 200"));
+
+            // Idle longer than the deadline between requests
+            tokio::time::sleep(TIMEOUT + Duration::from_millis(100)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_deadline_when_unset() {
+        let (mut client, connection) = serve(None);
+
+        let request = b"GET / HTTP/1.1\r\n\r\n";
+        let mut buf = [0u8; 128];
+        trickle(&mut client, request, Duration::from_millis(30)).await;
+        let n = client.read(&mut buf).await.unwrap();
+        assert!(buf[..n].starts_with(b"HTTP/1.1 200"));
+        drop(client);
+        connection.await.unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use pingora_http::ResponseHeader;
+use std::collections::HashSet;
+
+use crate::config::settings::UpstreamHeadersConfig;
+
+/// Framing headers the proxy needs to relay the body; never stripped
+pub const FRAMING_HEADERS: &[&str] = &[
+    "connection",
+    "content-encoding",
+    "content-length",
+    "transfer-encoding",
+];
+
+/// `response.upstream_headers`, stripping internal headers from upstream responses
+///
+/// Denied headers are always removed. With an allowlist, every header not
+/// on it is removed too, except the framing headers.
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamHeaderFilter {
+    /// Lowercase names
+    deny: HashSet<String>,
+    /// Lowercase names; `None` passes every header not denied
+    allow: Option<HashSet<String>>,
+}
+
+impl UpstreamHeaderFilter {
+    /// Filter from config (validated by `Settings::validate`)
+    pub fn new(config: &UpstreamHeadersConfig) -> Self {
+        let lowercase =
+            |names: &[String]| names.iter().map(|name| name.to_ascii_lowercase()).collect();
+
+        Self {
+            deny: lowercase(&config.deny),
+            allow: config.allow.as_deref().map(lowercase),
+        }
+    }
+
+    /// Remove the headers the upstream may not pass to clients
+    ///
+    /// # Arguments
+    /// * `resp` - Upstream response, before the proxy adds its own headers
+    pub fn apply(&self, resp: &mut ResponseHeader) {
+        if self.deny.is_empty() && self.allow.is_none() {
+            return;
+        }
+
+        let stripped: Vec<String> = resp
+            .headers
+            .keys()
+            .map(|name| name.as_str())
+            .filter(|name| !self.passes(name))
+            .map(str::to_string)
+            .collect();
+
+        for name in &stripped {
+            resp.remove_header(name.as_str());
+        }
+        if !stripped.is_empty() {
+            log::debug!("Stripped upstream response headers: {:?}", stripped);
+        }
+    }
+
+    /// Whether a lowercase header name is passed on
+    fn passes(&self, name: &str) -> bool {
+        if FRAMING_HEADERS.contains(&name) {
+            return true;
+        }
+        if self.deny.contains(name) {
+            return false;
+        }
+        self.allow.as_ref().is_none_or(|allow| allow.contains(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(deny: &[&str], allow: Option<&[&str]>) -> UpstreamHeaderFilter {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        UpstreamHeaderFilter::new(&UpstreamHeadersConfig {
+            deny: names(deny),
+            allow: allow.map(names),
+        })
+    }
+
+    fn upstream_response() -> ResponseHeader {
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("Content-Type", "application/json")
+            .unwrap();
+        resp.insert_header("Content-Length", "2").unwrap();
+        resp.insert_header("Server", "internal-app/1.4.2").unwrap();
+        resp.insert_header("X-Debug-Token", "a1b2c3").unwrap();
+        resp.insert_header("Cache-Control", "no-cache").unwrap();
+        resp
+    }
+
+    #[test]
+    fn test_denied_header_is_removed() {
+        let mut resp = upstream_response();
+        filter(&["X-Debug-Token", "server"], None).apply(&mut resp);
+
+        assert!(resp.headers.get("x-debug-token").is_none());
+        assert!(resp.headers.get("server").is_none());
+        assert_eq!(
+            resp.headers.get("content-type").unwrap(),
+            "application/json"
+        );
+        assert_eq!(resp.headers.get("cache-control").unwrap(), "no-cache");
+    }
+
+    #[test]
+    fn test_allowlist_drops_unlisted_headers() {
+        let mut resp = upstream_response();
+        filter(&[], Some(&["Content-Type", "Cache-Control"])).apply(&mut resp);
+
+        assert_eq!(
+            resp.headers.get("content-type").unwrap(),
+            "application/json"
+        );
+        assert_eq!(resp.headers.get("cache-control").unwrap(), "no-cache");
+        assert!(resp.headers.get("server").is_none());
+        assert!(resp.headers.get("x-debug-token").is_none());
+        // Framing is kept without being listed
+        assert_eq!(resp.headers.get("content-length").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_denylist_wins_over_allowlist() {
+        let mut resp = upstream_response();
+        filter(&["Cache-Control"], Some(&["Content-Type", "Cache-Control"])).apply(&mut resp);
+
+        assert!(resp.headers.get("cache-control").is_none());
+        assert!(resp.headers.get("content-type").is_some());
+    }
+
+    #[test]
+    fn test_no_config_passes_everything() {
+        let mut resp = upstream_response();
+        UpstreamHeaderFilter::default().apply(&mut resp);
+        assert_eq!(resp.headers.len(), upstream_response().headers.len());
+    }
+}
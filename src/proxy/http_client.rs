@@ -0,0 +1,337 @@
+use bytes::Bytes;
+use http::HeaderMap;
+use pingora_core::connectors::http::Connector;
+use pingora_core::upstreams::peer::HttpPeer;
+use pingora_http::RequestHeader;
+use std::time::Duration;
+use thiserror::Error;
+
+/// How long a released connection may sit in the pool
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum HttpClientError {
+    #[error("Invalid URL {0}")]
+    InvalidUrl(String),
+
+    #[error("Cannot resolve {0}")]
+    Resolve(String),
+
+    #[error("Request failed: {0}")]
+    Request(String),
+
+    #[error("No answer within {0}ms")]
+    Timeout(u128),
+
+    #[error("Response larger than {0} bytes")]
+    TooLarge(usize),
+}
+
+/// Where requests of one side service go
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    host: String,
+    port: u16,
+    tls: bool,
+    path: String,
+}
+
+impl Endpoint {
+    /// Endpoint from `host`, `port` and `path`, over TLS when `tls`
+    pub fn new(host: &str, port: u16, tls: bool, path: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            tls,
+            path: path.to_string(),
+        }
+    }
+
+    /// Endpoint for an `http://` or `https://` URL
+    pub fn parse(url: &str) -> Result<Self, HttpClientError> {
+        let uri: http::Uri = url
+            .parse()
+            .map_err(|e| HttpClientError::InvalidUrl(format!("{}: {}", url, e)))?;
+        let tls = match uri.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err(HttpClientError::InvalidUrl(url.to_string())),
+        };
+        let host = uri
+            .host()
+            .ok_or_else(|| HttpClientError::InvalidUrl(format!("{} has no host", url)))?;
+        let path = uri.path_and_query().map_or("/", |pq| pq.as_str());
+
+        Ok(Self::new(
+            host,
+            uri.port_u16().unwrap_or(if tls { 443 } else { 80 }),
+            tls,
+            path,
+        ))
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+
+    /// `host:port`, for logs
+    pub fn authority(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// Peer for the endpoint, resolving its host
+    async fn peer(&self, timeout: Duration) -> Result<HttpPeer, HttpClientError> {
+        let address = tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or_else(|| HttpClientError::Resolve(self.authority()))?;
+
+        // The certificate is checked against the host, as for TLS upstreams
+        let mut peer = HttpPeer::new(address, self.tls, self.host.clone());
+        peer.options.connection_timeout = Some(timeout);
+        peer.options.read_timeout = Some(timeout);
+        Ok(peer)
+    }
+}
+
+/// Response of a side service, with its body read in full
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// HTTP client for the proxy's own calls to side services (IdP JWKS,
+/// rate-limit service)
+///
+/// Connections are made by Pingora's connector, so `https://` endpoints
+/// get the same certificate checks as TLS upstreams, and kept alive in its
+/// pool between requests. One client is shared by all callers.
+pub struct HttpClient {
+    connector: Connector,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpClient {
+    pub fn new() -> Self {
+        Self {
+            connector: Connector::new(None),
+        }
+    }
+
+    /// `GET` a document
+    ///
+    /// # Arguments
+    /// * `endpoint` - Where to send the request
+    /// * `accept` - `Accept` header
+    /// * `timeout` - Longest wait for the whole exchange
+    /// * `max_size` - Largest body read; longer ones are an error
+    pub async fn get(
+        &self,
+        endpoint: &Endpoint,
+        accept: &str,
+        timeout: Duration,
+        max_size: usize,
+    ) -> Result<HttpResponse, HttpClientError> {
+        let mut request = self.request_header("GET", endpoint)?;
+        insert(&mut request, "Accept", accept)?;
+        self.send(endpoint, request, None, timeout, max_size).await
+    }
+
+    /// `POST` a JSON body
+    ///
+    /// # Arguments
+    /// * `endpoint` - Where to send the request
+    /// * `body` - JSON document
+    /// * `timeout` - Longest wait for the whole exchange
+    /// * `max_size` - Largest body read; longer ones are an error
+    pub async fn post_json(
+        &self,
+        endpoint: &Endpoint,
+        body: Vec<u8>,
+        timeout: Duration,
+        max_size: usize,
+    ) -> Result<HttpResponse, HttpClientError> {
+        let mut request = self.request_header("POST", endpoint)?;
+        insert(&mut request, "Content-Type", "application/json")?;
+        insert(&mut request, "Content-Length", &body.len().to_string())?;
+        self.send(
+            endpoint,
+            request,
+            Some(Bytes::from(body)),
+            timeout,
+            max_size,
+        )
+        .await
+    }
+
+    fn request_header(
+        &self,
+        method: &str,
+        endpoint: &Endpoint,
+    ) -> Result<RequestHeader, HttpClientError> {
+        let mut request = RequestHeader::build(method, endpoint.path.as_bytes(), None)
+            .map_err(|e| HttpClientError::InvalidUrl(format!("{}: {}", endpoint.path, e)))?;
+        insert(&mut request, "Host", &endpoint.authority())?;
+        Ok(request)
+    }
+
+    async fn send(
+        &self,
+        endpoint: &Endpoint,
+        request: RequestHeader,
+        body: Option<Bytes>,
+        timeout: Duration,
+        max_size: usize,
+    ) -> Result<HttpResponse, HttpClientError> {
+        let exchange = async {
+            let peer = endpoint.peer(timeout).await?;
+            self.exchange(&peer, request, body, max_size).await
+        };
+        tokio::time::timeout(timeout, exchange)
+            .await
+            .map_err(|_| HttpClientError::Timeout(timeout.as_millis()))?
+    }
+
+    /// One request on a pooled (or new) connection
+    ///
+    /// The connection goes back to the pool only once its response was
+    /// read to the end; on any error it is dropped.
+    async fn exchange(
+        &self,
+        peer: &HttpPeer,
+        request: RequestHeader,
+        body: Option<Bytes>,
+        max_size: usize,
+    ) -> Result<HttpResponse, HttpClientError> {
+        let failed = |e: Box<pingora_core::Error>| HttpClientError::Request(e.to_string());
+
+        let (mut session, _reused) = self
+            .connector
+            .get_http_session(peer)
+            .await
+            .map_err(failed)?;
+        session
+            .write_request_header(Box::new(request))
+            .await
+            .map_err(failed)?;
+        if let Some(body) = body {
+            session
+                .write_request_body(body, true)
+                .await
+                .map_err(failed)?;
+        }
+        session.finish_request_body().await.map_err(failed)?;
+
+        session.read_response_header().await.map_err(failed)?;
+        let header = session
+            .response_header()
+            .ok_or_else(|| HttpClientError::Request("no response header".to_string()))?;
+        let status = header.status.as_u16();
+        let headers = header.headers.clone();
+
+        let mut body = Vec::new();
+        while let Some(chunk) = session.read_response_body().await.map_err(failed)? {
+            if body.len() + chunk.len() > max_size {
+                return Err(HttpClientError::TooLarge(max_size));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        self.connector
+            .release_http_session(session, peer, Some(IDLE_TIMEOUT))
+            .await;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+fn insert(request: &mut RequestHeader, name: &str, value: &str) -> Result<(), HttpClientError> {
+    request
+        .insert_header(name.to_string(), value)
+        .map_err(|e| HttpClientError::Request(format!("invalid {} header: {}", name, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_endpoint_parsing() {
+        let endpoint = Endpoint::parse("https://idp.example.com/.well-known/jwks.json").unwrap();
+        assert!(endpoint.is_tls());
+        assert_eq!(endpoint.authority(), "idp.example.com:443");
+        assert_eq!(endpoint.path, "/.well-known/jwks.json");
+
+        let endpoint = Endpoint::parse("http://127.0.0.1:8081").unwrap();
+        assert!(!endpoint.is_tls());
+        assert_eq!(endpoint.path, "/");
+
+        assert!(Endpoint::parse("ftp://idp.example.com/jwks").is_err());
+        assert!(Endpoint::parse("idp.example.com/jwks").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connections_are_kept_alive() {
+        // Accepts one connection and answers every request made on it
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let service = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            for _ in 0..2 {
+                let n = conn.read(&mut buf).await.unwrap();
+                assert!(n > 0, "connection closed early");
+                conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let client = HttpClient::new();
+        let endpoint = Endpoint::new("127.0.0.1", port, false, "/");
+        for _ in 0..2 {
+            let response = client
+                .get(&endpoint, "text/plain", Duration::from_secs(1), 16)
+                .await
+                .unwrap();
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body, b"ok");
+        }
+        service.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_is_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = conn.read(&mut buf).await;
+            let _ = conn
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n0123456789")
+                .await;
+        });
+
+        let endpoint = Endpoint::new("127.0.0.1", port, false, "/");
+        assert!(matches!(
+            HttpClient::new()
+                .get(&endpoint, "text/plain", Duration::from_secs(1), 4)
+                .await,
+            Err(HttpClientError::TooLarge(4))
+        ));
+    }
+}
@@ -0,0 +1,496 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use pingora_http::ResponseHeader;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::cache::IdempotencyStore;
+use crate::config::settings::IdempotencyConfig;
+
+/// Request header carrying the client's de-duplication key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Response header marking a replayed response
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// Longest accepted `Idempotency-Key`
+const MAX_KEY_LEN: usize = 255;
+
+/// Largest response body recorded for replay; bigger ones are not cached
+pub const MAX_CACHED_BODY: usize = 1024 * 1024;
+
+/// How often a duplicate checks whether the first request has finished
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Per-response headers not replayed (recomputed or hop-by-hop)
+const SKIPPED_HEADERS: &[&str] = &[
+    "content-length",
+    "transfer-encoding",
+    "connection",
+    "keep-alive",
+    "date",
+];
+
+/// Request headers carrying credentials
+const CREDENTIAL_HEADERS: &[&str] = &["authorization", "x-api-key", "cookie"];
+
+/// Who a recorded response may be handed to
+///
+/// The user id when the request has one. API-key and IdP clients have
+/// none, so they are told apart by a digest of the credentials they
+/// present; requests presenting none share `anonymous`.
+///
+/// # Arguments
+/// * `user_id` - Authenticated user, if any
+/// * `headers` - Request headers
+pub fn client_scope(user_id: Option<Uuid>, headers: &http::HeaderMap) -> String {
+    if let Some(user_id) = user_id {
+        return user_id.to_string();
+    }
+
+    let mut digest = Sha256::new();
+    let mut presented = false;
+    for name in CREDENTIAL_HEADERS {
+        for value in headers.get_all(*name) {
+            digest.update(name.as_bytes());
+            digest.update(b"\0");
+            digest.update(value.as_bytes());
+            digest.update(b"\0");
+            presented = true;
+        }
+    }
+    if !presented {
+        return "anonymous".to_string();
+    }
+
+    let hex: String = digest.finalize()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("credential-{}", hex)
+}
+
+/// Store key for an `Idempotency-Key` request
+///
+/// Keys are scoped to the path and the client (see [`client_scope`]), so
+/// one client can never be replayed another's response.
+///
+/// # Returns
+/// * `Option<String>` - `None` if the key is empty, too long or not visible ASCII
+pub fn dedup_key(path: &str, scope: &str, key: &str) -> Option<String> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN || !key.bytes().all(|b| b.is_ascii_graphic()) {
+        return None;
+    }
+
+    Some(format!("{}:{}:{}", scope, path, key))
+}
+
+/// Response recorded for replay
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// Base64, so binary bodies survive the JSON encoding
+    body: String,
+}
+
+impl CachedResponse {
+    /// Response with the given status, headers and body
+    pub fn new(status: u16, headers: Vec<(String, String)>, body: &[u8]) -> Self {
+        Self {
+            status,
+            headers,
+            body: STANDARD.encode(body),
+        }
+    }
+
+    /// Response body bytes
+    pub fn body(&self) -> Vec<u8> {
+        STANDARD.decode(&self.body).unwrap_or_default()
+    }
+
+    /// Response header to replay, marked with [`REPLAYED_HEADER`]
+    pub fn to_header(&self) -> pingora_core::Result<ResponseHeader> {
+        self.to_header_marked(REPLAYED_HEADER)
+    }
+
+    /// Response header with `marker: true` added
+    pub fn to_header_marked(&self, marker: &str) -> pingora_core::Result<ResponseHeader> {
+        let mut resp = ResponseHeader::build(self.status, Some(self.headers.len() + 2))?;
+        for (name, value) in &self.headers {
+            resp.append_header(name.clone(), value.as_str())?;
+        }
+        resp.insert_header("Content-Length", self.body().len().to_string())?;
+        resp.insert_header(marker.to_string(), "true")?;
+        Ok(resp)
+    }
+}
+
+/// What to do with a keyed request
+#[derive(Debug, PartialEq, Eq)]
+pub enum Claim {
+    /// First request with this key: forward it and record the response
+    First,
+    /// Duplicate: answer with the recorded response
+    Replay(CachedResponse),
+    /// The first request is still running after `lock_seconds`
+    InProgress,
+}
+
+/// Claim a key, or wait for the request that holds it
+///
+/// Concurrent duplicates are serialized: while the first request runs,
+/// the others poll until its response is recorded and replay it. If the
+/// first request fails without a cacheable response, one of them takes
+/// over and is forwarded instead.
+///
+/// # Arguments
+/// * `store` - Lock and response store
+/// * `key` - From [`dedup_key`]
+/// * `config` - Route's `idempotency` settings
+pub async fn claim(
+    store: &dyn IdempotencyStore,
+    key: &str,
+    config: &IdempotencyConfig,
+) -> Result<Claim, redis::RedisError> {
+    let deadline = Instant::now() + Duration::from_secs(config.lock_seconds);
+
+    loop {
+        if let Some(cached) = store.cached_response(key).await? {
+            match serde_json::from_str(&cached) {
+                Ok(response) => return Ok(Claim::Replay(response)),
+                Err(e) => log::warn!("Ignoring unreadable idempotent response {}: {}", key, e),
+            }
+        }
+
+        if store.try_lock(key, config.lock_seconds).await? {
+            return Ok(Claim::First);
+        }
+
+        if Instant::now() >= deadline {
+            return Ok(Claim::InProgress);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Response of a claimed request, recorded as it is sent
+#[derive(Debug, Clone)]
+pub struct IdempotencyCapture {
+    pub key: String,
+    pub ttl_seconds: u64,
+    status: Option<u16>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    overflow: bool,
+}
+
+impl IdempotencyCapture {
+    /// Start recording for a key claimed with [`Claim::First`]
+    pub fn new(key: String, ttl_seconds: u64) -> Self {
+        Self {
+            key,
+            ttl_seconds,
+            status: None,
+            headers: Vec::new(),
+            body: Vec::new(),
+            overflow: false,
+        }
+    }
+
+    /// Record the response status and headers
+    pub fn record_header(&mut self, resp: &ResponseHeader) {
+        self.status = Some(resp.status.as_u16());
+        self.headers = resp
+            .headers
+            .iter()
+            .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+    }
+
+    /// Record a chunk of the response body
+    pub fn record_body(&mut self, chunk: &[u8]) {
+        if self.body.len() + chunk.len() > MAX_CACHED_BODY {
+            self.overflow = true;
+            self.body = Vec::new();
+        } else if !self.overflow {
+            self.body.extend_from_slice(chunk);
+        }
+    }
+
+    /// Stop recording: the response is streamed and will never be replayed
+    ///
+    /// The key is still released when the request ends.
+    pub fn discard(&mut self) {
+        self.overflow = true;
+        self.body = Vec::new();
+    }
+
+    /// Recorded response, or `None` if it must not be replayed
+    ///
+    /// Nothing is replayed for a request that never got a response, a 5xx
+    /// (so the client can retry) or a body over [`MAX_CACHED_BODY`].
+    pub fn response(&self) -> Option<CachedResponse> {
+        let status = self.status.filter(|status| *status < 500)?;
+        if self.overflow {
+            return None;
+        }
+
+        Some(CachedResponse::new(
+            status,
+            self.headers.clone(),
+            &self.body,
+        ))
+    }
+}
+
+/// Record the claimed request's response, if replayable, and release the key
+///
+/// # Arguments
+/// * `store` - Lock and response store
+/// * `capture` - What was sent to the client
+/// * `completed` - The response was sent in full, without a proxy error
+pub async fn complete(
+    store: &dyn IdempotencyStore,
+    capture: &IdempotencyCapture,
+    completed: bool,
+) -> Result<(), redis::RedisError> {
+    if let Some(response) = capture.response().filter(|_| completed) {
+        let json = serde_json::to_string(&response).map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::TypeError,
+                "Idempotent response encode error",
+                e.to_string(),
+            ))
+        })?;
+        store
+            .cache_response(&capture.key, &json, capture.ttl_seconds)
+            .await?;
+    }
+
+    store.unlock(&capture.key).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::encryption::EncryptionService;
+    use crate::cache::idempotency::SealedResponses;
+    use crate::db::memory::MemoryStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn config() -> IdempotencyConfig {
+        IdempotencyConfig {
+            ttl_seconds: 300,
+            lock_seconds: 5,
+        }
+    }
+
+    fn upstream_response(status: u16, body: &[u8]) -> IdempotencyCapture {
+        let mut resp = ResponseHeader::build(status, None).unwrap();
+        resp.insert_header("Content-Type", "application/json")
+            .unwrap();
+        resp.insert_header("Content-Length", body.len().to_string())
+            .unwrap();
+        resp.insert_header("Connection", "keep-alive").unwrap();
+
+        let mut capture = IdempotencyCapture::new("key".to_string(), 300);
+        capture.record_header(&resp);
+        capture.record_body(body);
+        capture
+    }
+
+    /// One keyed request through claim / forward / complete
+    async fn handle(
+        store: Arc<MemoryStore>,
+        upstream_calls: Arc<AtomicUsize>,
+        key: &str,
+    ) -> CachedResponse {
+        match claim(store.as_ref(), key, &config()).await.unwrap() {
+            Claim::First => {
+                let n = upstream_calls.fetch_add(1, Ordering::SeqCst) + 1;
+                tokio::time::sleep(Duration::from_millis(200)).await;
+
+                let body = format!(r#"{{"order":{}}}"#, n);
+                let mut capture = upstream_response(201, body.as_bytes());
+                capture.key = key.to_string();
+                complete(store.as_ref(), &capture, true).await.unwrap();
+                capture.response().unwrap()
+            }
+            Claim::Replay(response) => response,
+            Claim::InProgress => panic!("duplicate gave up waiting"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_duplicates_reach_upstream_once() {
+        let store = Arc::new(MemoryStore::new());
+        let upstream_calls = Arc::new(AtomicUsize::new(0));
+        let key = dedup_key("/api/orders", "anonymous", "order-123").unwrap();
+
+        let (first, second) = tokio::join!(
+            handle(store.clone(), upstream_calls.clone(), &key),
+            handle(store.clone(), upstream_calls.clone(), &key),
+        );
+
+        assert_eq!(upstream_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(first, second);
+        assert_eq!(first.body(), br#"{"order":1}"#);
+
+        // Later repeats replay too; a new key goes upstream
+        let again = handle(store.clone(), upstream_calls.clone(), &key).await;
+        assert_eq!(again, first);
+        let other = dedup_key("/api/orders", "anonymous", "order-456").unwrap();
+        handle(store.clone(), upstream_calls.clone(), &other).await;
+        assert_eq!(upstream_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_not_replayed() {
+        let store = MemoryStore::new();
+        let capture = upstream_response(503, b"{}");
+
+        assert_eq!(claim(&store, "key", &config()).await.unwrap(), Claim::First);
+        complete(&store, &capture, true).await.unwrap();
+
+        // The retry is forwarded again
+        assert_eq!(claim(&store, "key", &config()).await.unwrap(), Claim::First);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_times_out_while_first_runs() {
+        let store = MemoryStore::new();
+        let config = IdempotencyConfig {
+            ttl_seconds: 300,
+            lock_seconds: 1,
+        };
+
+        assert_eq!(claim(&store, "key", &config).await.unwrap(), Claim::First);
+        assert_eq!(
+            claim(&store, "key", &config).await.unwrap(),
+            Claim::InProgress
+        );
+    }
+
+    #[test]
+    fn test_replayed_response() {
+        let response = upstream_response(201, br#"{"id":7}"#).response().unwrap();
+
+        // Hop-by-hop and length headers are recomputed, not stored
+        assert_eq!(
+            response.headers,
+            vec![("content-type".to_string(), "application/json".to_string())]
+        );
+
+        let header = response.to_header().unwrap();
+        assert_eq!(header.status.as_u16(), 201);
+        assert_eq!(header.headers["Content-Length"], "8");
+        assert_eq!(header.headers[REPLAYED_HEADER], "true");
+        assert!(header.headers.get("Connection").is_none());
+    }
+
+    #[test]
+    fn test_large_bodies_are_not_replayed() {
+        let mut capture = upstream_response(200, b"");
+        capture.record_body(&vec![b'x'; MAX_CACHED_BODY]);
+        capture.record_body(b"x");
+
+        assert!(capture.response().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_discarded_stream_releases_key() {
+        let store = MemoryStore::new();
+        let mut capture = upstream_response(200, b"data: 1\n\n");
+        capture.discard();
+        capture.record_body(b"data: 2\n\n");
+        assert!(capture.response().is_none());
+
+        assert_eq!(claim(&store, "key", &config()).await.unwrap(), Claim::First);
+        complete(&store, &capture, true).await.unwrap();
+        assert_eq!(claim(&store, "key", &config()).await.unwrap(), Claim::First);
+    }
+
+    #[tokio::test]
+    async fn test_recorded_responses_are_encrypted_at_rest() {
+        let store = MemoryStore::new();
+        let old = EncryptionService::from_keys(1, &[(1, [1u8; 32])]).unwrap();
+        let sealed = SealedResponses::new(&store, Some(&old));
+        let capture = upstream_response(201, br#"{"api_key":"ak_live_123"}"#);
+
+        assert_eq!(
+            claim(&sealed, "key", &config()).await.unwrap(),
+            Claim::First
+        );
+        complete(&sealed, &capture, true).await.unwrap();
+
+        let stored = store.cached_response("key").await.unwrap().unwrap();
+        assert!(stored.starts_with("v1:"));
+        assert!(!stored.contains("ak_live_123"));
+
+        // Still replayed after a rotation that keeps the old key
+        let rotated = EncryptionService::from_keys(2, &[(1, [1u8; 32]), (2, [2u8; 32])]).unwrap();
+        let sealed = SealedResponses::new(&store, Some(&rotated));
+        assert_eq!(
+            claim(&sealed, "key", &config()).await.unwrap(),
+            Claim::Replay(capture.response().unwrap())
+        );
+
+        // Unreadable once the key is gone: forwarded again
+        let retired = EncryptionService::from_keys(2, &[(2, [2u8; 32])]).unwrap();
+        let sealed = SealedResponses::new(&store, Some(&retired));
+        assert_eq!(
+            claim(&sealed, "key", &config()).await.unwrap(),
+            Claim::First
+        );
+    }
+
+    #[test]
+    fn test_dedup_key() {
+        let user = Uuid::new_v4();
+        assert_eq!(
+            dedup_key("/api/orders", &user.to_string(), "abc").unwrap(),
+            format!("{}:/api/orders:abc", user)
+        );
+        assert_ne!(
+            dedup_key("/api/orders", "anonymous", "abc"),
+            dedup_key("/api/payments", "anonymous", "abc")
+        );
+
+        assert!(dedup_key("/api/orders", "anonymous", "").is_none());
+        assert!(dedup_key("/api/orders", "anonymous", "has space").is_none());
+        assert!(dedup_key("/api/orders", "anonymous", &"k".repeat(MAX_KEY_LEN + 1)).is_none());
+    }
+
+    #[test]
+    fn test_clients_without_user_id_are_scoped_by_credentials() {
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut headers = http::HeaderMap::new();
+            for (name, value) in pairs {
+                headers.append(*name, value.parse().unwrap());
+            }
+            headers
+        };
+        let key_a = headers(&[("X-API-Key", "key-a")]);
+        let key_b = headers(&[("X-API-Key", "key-b")]);
+        let idp_token = headers(&[("Authorization", "Bearer idp-token")]);
+
+        // Two API-key clients no longer share a scope
+        assert_ne!(client_scope(None, &key_a), client_scope(None, &key_b));
+        assert_ne!(client_scope(None, &key_a), client_scope(None, &idp_token));
+        assert_eq!(
+            client_scope(None, &key_a),
+            client_scope(None, &key_a.clone())
+        );
+        assert!(client_scope(None, &key_a).starts_with("credential-"));
+        assert_eq!(client_scope(None, &headers(&[])), "anonymous");
+
+        // Users keep their scope across tokens
+        let user = Uuid::new_v4();
+        assert_eq!(client_scope(Some(user), &key_a), user.to_string());
+        assert_eq!(client_scope(Some(user), &idp_token), user.to_string());
+    }
+}
@@ -0,0 +1,338 @@
+use pingora_core::listeners::tls::TlsSettings;
+use pingora_core::listeners::TcpSocketOptions;
+use pingora_core::protocols::TcpKeepalive;
+use pingora_core::tls::ssl::SslVersion;
+use pingora_core::{ErrorType, OrErr};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use crate::config::settings::{AdminListenerConfig, ListenerTlsConfig, TcpConfig, TlsVersion};
+
+/// Socket options for the proxy TCP listener
+///
+/// # Arguments
+/// * `config` - `server.tcp` section
+///
+/// # Returns
+/// * `TcpSocketOptions` - Options for `Service::add_tcp_with_settings`
+pub fn tcp_socket_options(config: &TcpConfig) -> TcpSocketOptions {
+    let tcp_keepalive = config.keepalive_secs.map(|idle| TcpKeepalive {
+        idle: Duration::from_secs(idle),
+        interval: Duration::from_secs(config.keepalive_interval_secs),
+        count: config.keepalive_count,
+        #[cfg(target_os = "linux")]
+        user_timeout: Duration::ZERO,
+    });
+
+    TcpSocketOptions {
+        tcp_keepalive,
+        tcp_fastopen: config.fastopen_backlog,
+        ..Default::default()
+    }
+}
+
+/// `host:port` string for a listener, bracketing IPv6 addresses
+///
+/// # Example
+/// ```
+/// assert_eq!(bind_address("::".parse().unwrap(), 8080), "[::]:8080");
+/// ```
+pub fn bind_address(ip: IpAddr, port: u16) -> String {
+    SocketAddr::new(ip, port).to_string()
+}
+
+/// Set `IPV6_V6ONLY` on a listener bound to an IPv6 address
+///
+/// With `ipv6_only` off, `[::]` is dual-stack and IPv4 clients arrive as
+/// IPv4-mapped addresses. The option does not exist for IPv4 sockets.
+pub fn with_ipv6_only(
+    mut options: TcpSocketOptions,
+    ip: IpAddr,
+    ipv6_only: bool,
+) -> TcpSocketOptions {
+    if ip.is_ipv6() {
+        options.ipv6_only = Some(ipv6_only);
+    }
+    options
+}
+
+/// TLS settings for the proxy listener
+///
+/// # Arguments
+/// * `config` - `server.tls` section
+///
+/// # Returns
+/// * `pingora_core::Result<TlsSettings>` - Settings for `Service::add_tls_with_settings`
+pub fn tls_settings(config: &ListenerTlsConfig) -> pingora_core::Result<TlsSettings> {
+    let mut settings = TlsSettings::intermediate(&config.cert_path, &config.key_path)?;
+
+    settings
+        .set_min_proto_version(Some(ssl_version(config.min_tls_version)))
+        .or_err(
+            ErrorType::InternalError,
+            "failed to set minimum TLS version",
+        )?;
+
+    let (cipher_list, ciphersuites) = split_ciphers(&config.ciphers);
+    if !cipher_list.is_empty() {
+        settings.set_cipher_list(&cipher_list).or_err(
+            ErrorType::InternalError,
+            "no usable TLS 1.2 cipher in server.tls.ciphers",
+        )?;
+    }
+    if !ciphersuites.is_empty() {
+        settings.set_ciphersuites(&ciphersuites).or_err(
+            ErrorType::InternalError,
+            "no usable TLS 1.3 suite in server.tls.ciphers",
+        )?;
+    }
+
+    Ok(settings)
+}
+
+fn ssl_version(version: TlsVersion) -> SslVersion {
+    match version {
+        TlsVersion::Tls12 => SslVersion::TLS1_2,
+        TlsVersion::Tls13 => SslVersion::TLS1_3,
+    }
+}
+
+/// Split a cipher allowlist into the TLS 1.2 cipher list and TLS 1.3 suites
+///
+/// OpenSSL configures the two separately; TLS 1.3 suite names start with `TLS_`.
+///
+/// # Example
+/// ```
+/// let (list, suites) = split_ciphers(&["ECDHE-RSA-AES128-GCM-SHA256".into(), "TLS_AES_128_GCM_SHA256".into()]);
+/// assert_eq!(list, "ECDHE-RSA-AES128-GCM-SHA256");
+/// assert_eq!(suites, "TLS_AES_128_GCM_SHA256");
+/// ```
+pub fn split_ciphers(ciphers: &[String]) -> (String, String) {
+    let (suites, list): (Vec<&str>, Vec<&str>) = ciphers
+        .iter()
+        .map(String::as_str)
+        .partition(|cipher| cipher.starts_with("TLS_"));
+
+    (list.join(":"), suites.join(":"))
+}
+
+/// How a request is handled given the listener it arrived on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerRoute {
+    /// `/admin/*` endpoints
+    Admin,
+    /// Prometheus text exposition
+    Metrics,
+    /// Auth endpoints and proxying
+    Public,
+    /// Path that does not exist on this listener
+    NotFound,
+}
+
+/// Listener dedicated to admin and metrics routes (`server.admin_listener`)
+#[derive(Debug, Clone)]
+pub struct AdminListener {
+    port: u16,
+    allowlist: Vec<IpAddr>,
+}
+
+impl AdminListener {
+    pub fn new(config: &AdminListenerConfig) -> Self {
+        Self {
+            port: config.port,
+            // Compared against canonical client IPs (IPv4-mapped as IPv4)
+            allowlist: config.allowlist.iter().map(IpAddr::to_canonical).collect(),
+        }
+    }
+
+    /// Whether a connection accepted on `local_port` came in on this listener
+    pub fn is_local_port(&self, local_port: Option<u16>) -> bool {
+        local_port == Some(self.port)
+    }
+
+    /// Whether `client_ip` may use this listener
+    pub fn allows(&self, client_ip: Option<IpAddr>) -> bool {
+        self.allowlist.is_empty() || client_ip.is_some_and(|ip| self.allowlist.contains(&ip))
+    }
+}
+
+/// Route a path according to the listener it arrived on
+///
+/// Without an admin listener, `/admin/*` is served on the public port and
+/// `/metrics` is proxied like any other path. With one, both exist only on
+/// the admin listener, which serves nothing else.
+///
+/// # Arguments
+/// * `admin` - Configured admin listener, if any
+/// * `on_admin_listener` - Request arrived on the admin listener
+/// * `path` - Request path
+pub fn route(admin: Option<&AdminListener>, on_admin_listener: bool, path: &str) -> ListenerRoute {
+    let is_admin = path.starts_with("/admin/");
+    let is_metrics = path == "/metrics";
+
+    match (admin.is_some(), on_admin_listener) {
+        (false, _) if is_admin => ListenerRoute::Admin,
+        (false, _) => ListenerRoute::Public,
+        (true, true) if is_admin => ListenerRoute::Admin,
+        (true, true) if is_metrics => ListenerRoute::Metrics,
+        (true, true) => ListenerRoute::NotFound,
+        (true, false) if is_admin || is_metrics => ListenerRoute::NotFound,
+        (true, false) => ListenerRoute::Public,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admin_listener(allowlist: Vec<IpAddr>) -> AdminListener {
+        AdminListener::new(&AdminListenerConfig {
+            port: 9000,
+            allowlist,
+        })
+    }
+
+    #[test]
+    fn test_metrics_only_on_admin_listener() {
+        let admin = admin_listener(vec![]);
+
+        assert_eq!(
+            route(Some(&admin), true, "/metrics"),
+            ListenerRoute::Metrics
+        );
+        assert_eq!(
+            route(Some(&admin), false, "/metrics"),
+            ListenerRoute::NotFound
+        );
+    }
+
+    #[test]
+    fn test_admin_paths_only_on_admin_listener() {
+        let admin = admin_listener(vec![]);
+        let revoke = "/admin/users/42/revoke-tokens";
+
+        assert_eq!(route(Some(&admin), true, revoke), ListenerRoute::Admin);
+        assert_eq!(route(Some(&admin), false, revoke), ListenerRoute::NotFound);
+
+        // The admin listener serves nothing else
+        assert_eq!(
+            route(Some(&admin), true, "/api/users"),
+            ListenerRoute::NotFound
+        );
+        assert_eq!(
+            route(Some(&admin), false, "/api/users"),
+            ListenerRoute::Public
+        );
+    }
+
+    #[test]
+    fn test_without_admin_listener() {
+        assert_eq!(
+            route(None, false, "/admin/users/42/revoke-tokens"),
+            ListenerRoute::Admin
+        );
+        assert_eq!(route(None, false, "/metrics"), ListenerRoute::Public);
+    }
+
+    #[test]
+    fn test_admin_listener_allowlist() {
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let admin = admin_listener(vec![local]);
+        assert!(admin.is_local_port(Some(9000)));
+        assert!(!admin.is_local_port(Some(8080)));
+        assert!(admin.allows(Some(local)));
+        assert!(!admin.allows(Some(other)));
+        assert!(!admin.allows(None));
+
+        assert!(admin_listener(vec![]).allows(Some(other)));
+    }
+
+    #[test]
+    fn test_keepalive_applied() {
+        let config: TcpConfig = serde_yaml::from_str(
+            r#"
+            keepalive_secs: 60
+            keepalive_interval_secs: 5
+            keepalive_count: 4
+            fastopen_backlog: 1024
+            "#,
+        )
+        .unwrap();
+
+        let options = tcp_socket_options(&config);
+
+        let keepalive = options.tcp_keepalive.unwrap();
+        assert_eq!(keepalive.idle, Duration::from_secs(60));
+        assert_eq!(keepalive.interval, Duration::from_secs(5));
+        assert_eq!(keepalive.count, 4);
+        assert_eq!(options.tcp_fastopen, Some(1024));
+    }
+
+    #[test]
+    fn test_defaults_leave_socket_untouched() {
+        let options = tcp_socket_options(&TcpConfig::default());
+
+        assert!(options.tcp_keepalive.is_none());
+        assert!(options.tcp_fastopen.is_none());
+    }
+
+    #[test]
+    fn test_ipv6_bind() {
+        let ip: IpAddr = "::".parse().unwrap();
+        assert_eq!(bind_address(ip, 8080), "[::]:8080");
+
+        // Dual-stack by default; IPv6-only on request
+        let options = with_ipv6_only(tcp_socket_options(&TcpConfig::default()), ip, false);
+        assert_eq!(options.ipv6_only, Some(false));
+        let options = with_ipv6_only(tcp_socket_options(&TcpConfig::default()), ip, true);
+        assert_eq!(options.ipv6_only, Some(true));
+
+        let ip: IpAddr = "0.0.0.0".parse().unwrap();
+        assert_eq!(bind_address(ip, 8080), "0.0.0.0:8080");
+        let options = with_ipv6_only(tcp_socket_options(&TcpConfig::default()), ip, false);
+        assert_eq!(options.ipv6_only, None);
+    }
+
+    #[test]
+    fn test_allowlist_matches_ipv6_and_mapped_clients() {
+        let admin = admin_listener(vec![
+            "2001:db8::1".parse().unwrap(),
+            "::ffff:10.0.0.1".parse().unwrap(),
+        ]);
+
+        assert!(admin.allows(Some("2001:db8::1".parse().unwrap())));
+        assert!(!admin.allows(Some("2001:db8::2".parse().unwrap())));
+        // Client IPs from a dual-stack listener are stored as plain IPv4
+        assert!(admin.allows(Some("10.0.0.1".parse().unwrap())));
+    }
+
+    #[test]
+    fn test_split_ciphers() {
+        let ciphers = vec![
+            "ECDHE-ECDSA-AES128-GCM-SHA256".to_string(),
+            "TLS_AES_128_GCM_SHA256".to_string(),
+            "ECDHE-RSA-AES128-GCM-SHA256".to_string(),
+            "TLS_CHACHA20_POLY1305_SHA256".to_string(),
+        ];
+
+        let (list, suites) = split_ciphers(&ciphers);
+        assert_eq!(
+            list,
+            "ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256"
+        );
+        assert_eq!(
+            suites,
+            "TLS_AES_128_GCM_SHA256:TLS_CHACHA20_POLY1305_SHA256"
+        );
+
+        assert_eq!(split_ciphers(&[]), (String::new(), String::new()));
+    }
+
+    #[test]
+    fn test_min_tls_version_maps_to_openssl() {
+        assert_eq!(ssl_version(TlsVersion::Tls12), SslVersion::TLS1_2);
+        assert_eq!(ssl_version(TlsVersion::Tls13), SslVersion::TLS1_3);
+    }
+}
@@ -0,0 +1,184 @@
+use pingora_http::RequestHeader;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+use crate::metrics;
+
+/// Give up on a shadow upstream after this long
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether requests with `method` are safe to replay against a shadow upstream
+pub fn is_mirrorable(method: &http::Method) -> bool {
+    matches!(
+        *method,
+        http::Method::GET | http::Method::HEAD | http::Method::OPTIONS
+    )
+}
+
+/// Whether a request falls in the mirrored `percent`, given a uniform draw in `[0, 1)`
+pub fn is_sampled(percent: f64, draw: f64) -> bool {
+    draw * 100.0 < percent
+}
+
+/// Send a copy of `req` to the shadow upstream in the background
+///
+/// Returns immediately; the shadow's response is read only for its status
+/// and then discarded. Results are counted in `mirror_requests_total`.
+///
+/// # Arguments
+/// * `address` - Shadow upstream `host:port`
+/// * `req` - Request header as sent to the primary upstream
+/// * `request_id` - For log correlation
+pub fn spawn(address: String, req: &RequestHeader, request_id: String) -> JoinHandle<()> {
+    let head = encode_request(req);
+
+    tokio::spawn(async move {
+        let result = tokio::time::timeout(MIRROR_TIMEOUT, send(&address, &head)).await;
+
+        match result {
+            Ok(Ok(status)) if status < 500 => {
+                metrics::MIRROR_REQUESTS.with_label_values(&["ok"]).inc();
+            }
+            Ok(Ok(status)) => {
+                log::debug!("[{}] Mirror {} returned {}", request_id, address, status);
+                metrics::MIRROR_REQUESTS.with_label_values(&["error"]).inc();
+            }
+            Ok(Err(e)) => {
+                log::debug!("[{}] Mirror {} failed: {}", request_id, address, e);
+                metrics::MIRROR_REQUESTS.with_label_values(&["error"]).inc();
+            }
+            Err(_) => {
+                log::debug!("[{}] Mirror {} timed out", request_id, address);
+                metrics::MIRROR_REQUESTS.with_label_values(&["error"]).inc();
+            }
+        }
+    })
+}
+
+/// Serialize the request head as HTTP/1.1 for a one-shot connection
+///
+/// The body is not mirrored, so framing headers are dropped.
+fn encode_request(req: &RequestHeader) -> Vec<u8> {
+    let target = req
+        .uri
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+
+    let mut head = format!("{} {} HTTP/1.1\r\n", req.method, target).into_bytes();
+    for (name, value) in req.headers.iter() {
+        if matches!(
+            name.as_str(),
+            "content-length" | "transfer-encoding" | "connection" | "expect"
+        ) {
+            continue;
+        }
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"Connection: close\r\n\r\n");
+
+    head
+}
+
+/// Send the request head and return the response status
+async fn send(address: &str, head: &[u8]) -> std::io::Result<u16> {
+    let mut stream = TcpStream::connect(address).await?;
+    stream.write_all(head).await?;
+
+    let mut status_line = String::new();
+    BufReader::new(&mut stream)
+        .read_line(&mut status_line)
+        .await?;
+
+    // "HTTP/1.1 200 OK"
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "Malformed status line")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn mirror_count(result: &str) -> u64 {
+        metrics::MIRROR_REQUESTS.with_label_values(&[result]).get()
+    }
+
+    #[test]
+    fn test_only_idempotent_methods_mirrored() {
+        assert!(is_mirrorable(&http::Method::GET));
+        assert!(is_mirrorable(&http::Method::HEAD));
+        assert!(!is_mirrorable(&http::Method::POST));
+        assert!(!is_mirrorable(&http::Method::DELETE));
+    }
+
+    #[test]
+    fn test_sampling() {
+        assert!(is_sampled(10.0, 0.05));
+        assert!(!is_sampled(10.0, 0.1));
+        assert!(!is_sampled(0.0, 0.0));
+        assert!(is_sampled(100.0, 0.999));
+    }
+
+    #[tokio::test]
+    async fn test_mirrored_request_reaches_shadow() {
+        let shadow = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = shadow.local_addr().unwrap().to_string();
+
+        let mut req = RequestHeader::build("GET", b"/users?page=2", None).unwrap();
+        req.insert_header("Host", "api.example.com").unwrap();
+        req.insert_header("X-Request-ID", "req-1").unwrap();
+        req.insert_header("Content-Length", "0").unwrap();
+
+        let ok_before = mirror_count("ok");
+        let handle = spawn(address, &req, "req-1".to_string());
+
+        // The shadow gets the request and its response goes nowhere
+        let (mut conn, _) = shadow.accept().await.unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !received.ends_with(b"\r\n\r\n") {
+            let n = conn.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed early");
+            received.extend_from_slice(&buf[..n]);
+        }
+        conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nshadow")
+            .await
+            .unwrap();
+
+        handle.await.unwrap();
+
+        let received = String::from_utf8(received).unwrap();
+        assert!(received.starts_with("GET /users?page=2 HTTP/1.1\r\n"));
+        assert!(received.contains("x-request-id: req-1\r\n"));
+        assert!(received.contains("Connection: close\r\n"));
+        assert!(!received.contains("content-length"));
+        assert!(mirror_count("ok") > ok_before);
+    }
+
+    #[tokio::test]
+    async fn test_shadow_errors_are_counted() {
+        // Nothing listens on the port once the listener is dropped
+        let address = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+
+        let req = RequestHeader::build("GET", b"/users", None).unwrap();
+        let errors_before = mirror_count("error");
+        spawn(address, &req, "req-2".to_string()).await.unwrap();
+
+        assert!(mirror_count("error") > errors_before);
+    }
+}
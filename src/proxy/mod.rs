@@ -1,2 +1,24 @@
+pub mod access_log;
+pub mod active;
+pub mod ban;
+pub mod coalesce;
+pub mod connection;
 pub mod context;
+pub mod default_headers;
+pub mod drain;
+pub mod encoding;
+pub mod fallback;
+pub mod guards;
+pub mod header_deadline;
+pub mod header_filter;
+pub mod http_client;
+pub mod idempotency;
+pub mod listener;
+pub mod mirror;
+pub mod protocol;
+pub mod redirect;
+pub mod request_id;
+pub mod retry;
+pub mod routing;
 pub mod service;
+pub mod transform;
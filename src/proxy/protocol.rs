@@ -0,0 +1,136 @@
+use pingora_http::ResponseHeader;
+
+/// Connection-specific headers, which HTTP/2 forbids (RFC 9113 §8.2.2)
+///
+/// An HTTP/1.1 upstream may send any of these; they describe that hop only.
+const CONNECTION_SPECIFIC_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Whether a response is a Server-Sent Events stream (`text/event-stream`)
+///
+/// Such a body never ends on its own; it has to reach the client chunk by
+/// chunk, so nothing may hold it back to buffer, record or rewrite it.
+pub fn is_event_stream(resp: &ResponseHeader) -> bool {
+    resp.headers
+        .get("Content-Type")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("text/event-stream"))
+}
+
+/// Ask proxies in front of this one not to buffer a streamed response
+pub fn disable_downstream_buffering(resp: &mut ResponseHeader) {
+    resp.insert_header("X-Accel-Buffering", "no").ok();
+}
+
+/// Drop `Content-Length` from a response whose body length will change
+///
+/// HTTP/1.1 clients then need chunked framing; HTTP/2 frames the body
+/// itself and must not see `Transfer-Encoding`.
+pub fn make_length_unknown(resp: &mut ResponseHeader, http2: bool) {
+    resp.remove_header("Content-Length");
+    if !http2 {
+        resp.insert_header("Transfer-Encoding", "Chunked").ok();
+    }
+}
+
+/// Ask the client to close its connection after this response
+///
+/// Only meaningful for HTTP/1.1; an HTTP/2 connection carries other
+/// streams and is never closed per response.
+pub fn close_after_response(resp: &mut ResponseHeader, http2: bool) {
+    if !http2 {
+        resp.insert_header("Connection", "close").ok();
+    }
+}
+
+/// Make a finished response header legal for the downstream protocol
+///
+/// Called last in `response_filter`, after every header insertion.
+pub fn finalize_response(resp: &mut ResponseHeader, http2: bool) {
+    if http2 {
+        for name in CONNECTION_SPECIFIC_HEADERS {
+            resp.remove_header(*name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Upstream HTTP/1.1 response as the proxy receives it
+    fn upstream_response() -> ResponseHeader {
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("Content-Type", "application/json")
+            .unwrap();
+        resp.insert_header("Content-Length", "42").unwrap();
+        resp.insert_header("Connection", "keep-alive").unwrap();
+        resp.insert_header("Keep-Alive", "timeout=5").unwrap();
+        resp
+    }
+
+    /// Run the proxy's header handling for one downstream protocol
+    fn filter(http2: bool, transformed: bool, close: bool) -> ResponseHeader {
+        let mut resp = upstream_response();
+        if transformed {
+            make_length_unknown(&mut resp, http2);
+        }
+        if close {
+            close_after_response(&mut resp, http2);
+        }
+        resp.insert_header("X-Proxy-By", "Pingora-Custom-Proxy")
+            .unwrap();
+        finalize_response(&mut resp, http2);
+        resp
+    }
+
+    #[test]
+    fn test_event_stream_detection() {
+        let with_type = |content_type: &str| {
+            let mut resp = ResponseHeader::build(200, None).unwrap();
+            resp.insert_header("Content-Type", content_type).unwrap();
+            resp
+        };
+
+        assert!(is_event_stream(&with_type("text/event-stream")));
+        assert!(is_event_stream(&with_type(
+            "Text/Event-Stream; charset=utf-8"
+        )));
+        assert!(!is_event_stream(&with_type("application/json")));
+        assert!(!is_event_stream(&upstream_response()));
+        assert!(!is_event_stream(&ResponseHeader::build(200, None).unwrap()));
+    }
+
+    #[test]
+    fn test_http1_framing() {
+        let resp = filter(false, false, true);
+        assert_eq!(resp.headers.get("content-length").unwrap(), "42");
+        assert_eq!(resp.headers.get("connection").unwrap(), "close");
+
+        // Body length changes: chunked instead of a stale length
+        let resp = filter(false, true, false);
+        assert!(resp.headers.get("content-length").is_none());
+        assert_eq!(resp.headers.get("transfer-encoding").unwrap(), "Chunked");
+    }
+
+    #[test]
+    fn test_http2_framing() {
+        for transformed in [false, true] {
+            let resp = filter(true, transformed, true);
+            for name in CONNECTION_SPECIFIC_HEADERS {
+                assert!(resp.headers.get(*name).is_none(), "{} sent over h2", name);
+            }
+            assert_eq!(
+                resp.headers.get("x-proxy-by").unwrap(),
+                "Pingora-Custom-Proxy"
+            );
+            assert_eq!(resp.headers.get("content-length").is_some(), !transformed);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use pingora_http::{RequestHeader, ResponseHeader};
+
+/// `scheme://host` the client addressed, from `Host` (or the HTTP/2 authority)
+///
+/// # Arguments
+/// * `req` - Client request
+/// * `tls` - The listener terminates TLS
+pub fn public_origin(req: &RequestHeader, tls: bool) -> Option<String> {
+    let host = req
+        .headers
+        .get(http::header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| req.uri.authority().map(|authority| authority.as_str()))
+        .filter(|host| !host.is_empty() && !host.contains(['/', '\\', '@']))?;
+
+    let scheme = if tls { "https" } else { "http" };
+    Some(format!("{}://{}", scheme, host))
+}
+
+/// Rewrite the `Location` of a 3xx response, if it points at the backend
+///
+/// # Arguments
+/// * `resp` - Upstream response
+/// * `backend_hosts` - Names of the backend, as `host` (any port) or `host:port`
+/// * `public_origin` - From [`public_origin`]; the result is a relative
+///   redirect when unknown
+/// * `prefix` - Route prefix stripped from the upstream path, restored here
+///
+/// # Returns
+/// * `Option<String>` - The new `Location`, or `None` when it was left alone
+pub fn rewrite_redirect(
+    resp: &mut ResponseHeader,
+    backend_hosts: &[String],
+    public_origin: Option<&str>,
+    prefix: Option<&str>,
+) -> Option<String> {
+    if !resp.status.is_redirection() {
+        return None;
+    }
+
+    let location = resp.headers.get(http::header::LOCATION)?.to_str().ok()?;
+    let rewritten = rewrite_location(location, backend_hosts, public_origin, prefix)?;
+
+    resp.insert_header(http::header::LOCATION, rewritten.as_str())
+        .ok()?;
+    Some(rewritten)
+}
+
+/// Public form of a redirect target
+///
+/// Absolute URLs are rewritten only when their host is one of
+/// `backend_hosts`; redirects to other sites pass through. Relative
+/// redirects stay relative and only get `prefix` restored.
+///
+/// # Example
+/// ```
+/// let backend = vec!["10.0.0.5:3000".to_string()];
+/// assert_eq!(
+///     rewrite_location("http://10.0.0.5:3000/login", &backend, Some("https://example.com"), None),
+///     Some("https://example.com/login".to_string())
+/// );
+/// ```
+pub fn rewrite_location(
+    location: &str,
+    backend_hosts: &[String],
+    public_origin: Option<&str>,
+    prefix: Option<&str>,
+) -> Option<String> {
+    let with_prefix = |path: &str| format!("{}{}", prefix.unwrap_or(""), path);
+
+    let Some((scheme, rest)) = location.split_once("://") else {
+        // `//host/path` is absolute too and is left alone
+        if location.starts_with('/') && !location.starts_with("//") && prefix.is_some() {
+            return Some(with_prefix(location));
+        }
+        return None;
+    };
+
+    let scheme = scheme.to_ascii_lowercase();
+    let default_port = match scheme.as_str() {
+        "http" => 80,
+        "https" => 443,
+        _ => return None,
+    };
+
+    let split = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(split);
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+
+    let (host, port) = split_authority(authority);
+    let port = port.unwrap_or(default_port);
+    let is_backend = backend_hosts.iter().any(|backend| {
+        let (backend_host, backend_port) = split_authority(backend);
+        backend_host.eq_ignore_ascii_case(host) && backend_port.is_none_or(|p| p == port)
+    });
+    if !is_backend {
+        return None;
+    }
+
+    Some(format!(
+        "{}{}",
+        public_origin.unwrap_or(""),
+        with_prefix(&path)
+    ))
+}
+
+/// Split `host[:port]`, keeping IPv6 brackets on the host
+fn split_authority(authority: &str) -> (&str, Option<u16>) {
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+
+    match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()),
+        _ => (authority, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend() -> Vec<String> {
+        vec!["10.0.0.5:3000".to_string(), "backend.internal".to_string()]
+    }
+
+    fn redirect(status: u16, location: &str) -> ResponseHeader {
+        let mut resp = ResponseHeader::build(status, None).unwrap();
+        resp.insert_header("Location", location).unwrap();
+        resp
+    }
+
+    #[test]
+    fn test_backend_location_is_rewritten_to_public_host() {
+        let mut resp = redirect(302, "http://10.0.0.5:3000/login?next=%2F");
+
+        let rewritten =
+            rewrite_redirect(&mut resp, &backend(), Some("https://api.example.com"), None);
+
+        assert_eq!(
+            rewritten.as_deref(),
+            Some("https://api.example.com/login?next=%2F")
+        );
+        assert_eq!(
+            resp.headers["Location"],
+            "https://api.example.com/login?next=%2F"
+        );
+    }
+
+    #[test]
+    fn test_backend_names_and_ports() {
+        let public = Some("https://example.com");
+
+        // A host listed without a port matches any port
+        assert_eq!(
+            rewrite_location("http://BACKEND.internal:8080/a", &backend(), public, None),
+            Some("https://example.com/a".to_string())
+        );
+        // Default port
+        assert_eq!(
+            rewrite_location("http://backend.internal", &backend(), public, None),
+            Some("https://example.com/".to_string())
+        );
+        // Right host, wrong port
+        assert_eq!(
+            rewrite_location("http://10.0.0.5:4000/a", &backend(), public, None),
+            None
+        );
+        // IPv6 backend
+        let v6 = vec!["[fd00::5]:3000".to_string()];
+        assert_eq!(
+            rewrite_location("http://[fd00::5]:3000/a", &v6, public, None),
+            Some("https://example.com/a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_other_redirects_pass_through() {
+        let public = Some("https://example.com");
+
+        assert_eq!(
+            rewrite_location(
+                "https://accounts.google.com/o/oauth2",
+                &backend(),
+                public,
+                None
+            ),
+            None
+        );
+        assert_eq!(rewrite_location("/login", &backend(), public, None), None);
+        assert_eq!(
+            rewrite_location("//10.0.0.5:3000/a", &backend(), public, Some("/api")),
+            None
+        );
+
+        // Not a redirect
+        let mut resp = redirect(200, "http://10.0.0.5:3000/");
+        assert_eq!(rewrite_redirect(&mut resp, &backend(), public, None), None);
+        assert_eq!(resp.headers["Location"], "http://10.0.0.5:3000/");
+    }
+
+    #[test]
+    fn test_stripped_prefix_is_restored() {
+        let public = Some("https://example.com");
+
+        assert_eq!(
+            rewrite_location(
+                "http://10.0.0.5:3000/users/1",
+                &backend(),
+                public,
+                Some("/api")
+            ),
+            Some("https://example.com/api/users/1".to_string())
+        );
+        assert_eq!(
+            rewrite_location("/users/1", &backend(), public, Some("/api")),
+            Some("/api/users/1".to_string())
+        );
+
+        // Unknown public host: relative redirect
+        assert_eq!(
+            rewrite_location(
+                "http://10.0.0.5:3000/users/1",
+                &backend(),
+                None,
+                Some("/api")
+            ),
+            Some("/api/users/1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_public_origin() {
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        assert_eq!(public_origin(&req, false), None);
+
+        req.insert_header("Host", "example.com:8443").unwrap();
+        assert_eq!(
+            public_origin(&req, true).as_deref(),
+            Some("https://example.com:8443")
+        );
+
+        req.insert_header("Host", "evil.com/path").unwrap();
+        assert_eq!(public_origin(&req, false), None);
+    }
+}
@@ -0,0 +1,155 @@
+use pingora_http::{RequestHeader, ResponseHeader};
+use uuid::Uuid;
+
+use crate::config::settings::{RequestIdConfig, RequestIdFormat};
+
+/// Generate a request ID in the configured format
+pub fn generate(format: RequestIdFormat) -> String {
+    let id = Uuid::new_v4();
+    match format {
+        RequestIdFormat::Uuid => id.hyphenated().to_string(),
+        RequestIdFormat::Hex => id.simple().to_string(),
+    }
+}
+
+/// Whether a `traceparent` value is a well-formed W3C trace context (version 00)
+///
+/// `00-<32 hex trace-id>-<16 hex parent-id>-<2 hex flags>`, where the trace
+/// and parent IDs must not be all zeros.
+pub fn is_valid_traceparent(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let [version, trace_id, parent_id, flags] = parts.as_slice() else {
+        return false;
+    };
+
+    let is_hex = |s: &str, len: usize| {
+        s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    let non_zero = |s: &str| s.bytes().any(|b| b != b'0');
+
+    *version == "00"
+        && is_hex(trace_id, 32)
+        && non_zero(trace_id)
+        && is_hex(parent_id, 16)
+        && non_zero(parent_id)
+        && is_hex(flags, 2)
+}
+
+/// New sampled `traceparent` for a request that arrived without one
+pub fn new_traceparent() -> String {
+    let trace_id = Uuid::new_v4().simple().to_string();
+    let parent_id = &Uuid::new_v4().simple().to_string()[..16];
+    format!("00-{}-{}-01", trace_id, parent_id)
+}
+
+/// Add the request ID (and `traceparent`, if enabled) to the upstream request
+pub fn tag_upstream_request(
+    req: &mut RequestHeader,
+    config: &RequestIdConfig,
+    request_id: &str,
+) -> pingora_core::Result<()> {
+    req.insert_header(config.header.clone(), request_id)?;
+
+    if config.traceparent {
+        let incoming = req
+            .headers
+            .get("traceparent")
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| is_valid_traceparent(v));
+        if incoming.is_none() {
+            req.insert_header("traceparent", new_traceparent())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Echo the request ID back to the client
+pub fn tag_response(
+    resp: &mut ResponseHeader,
+    config: &RequestIdConfig,
+    request_id: &str,
+) -> pingora_core::Result<()> {
+    resp.insert_header(config.header.clone(), request_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(header: &str, traceparent: bool) -> RequestIdConfig {
+        RequestIdConfig {
+            header: header.to_string(),
+            format: RequestIdFormat::Uuid,
+            traceparent,
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let id = generate(RequestIdFormat::Uuid);
+        assert_eq!(id.len(), 36);
+        assert!(Uuid::parse_str(&id).is_ok());
+
+        let id = generate(RequestIdFormat::Hex);
+        assert_eq!(id.len(), 32);
+        assert!(id.bytes().all(|b| b.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_configured_header_carries_request_id() {
+        let config = config("X-Correlation-ID", false);
+
+        let mut req = RequestHeader::build("GET", b"/api/users", None).unwrap();
+        tag_upstream_request(&mut req, &config, "req-123").unwrap();
+        assert_eq!(req.headers.get("x-correlation-id").unwrap(), "req-123");
+        assert!(req.headers.get("x-request-id").is_none());
+        assert!(req.headers.get("traceparent").is_none());
+
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        tag_response(&mut resp, &config, "req-123").unwrap();
+        assert_eq!(resp.headers.get("x-correlation-id").unwrap(), "req-123");
+        assert!(resp.headers.get("x-request-id").is_none());
+    }
+
+    #[test]
+    fn test_traceparent_forwarded() {
+        let config = config("X-Request-ID", true);
+
+        // A valid incoming trace context is kept
+        let incoming = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let mut req = RequestHeader::build("GET", b"/api/users", None).unwrap();
+        req.insert_header("traceparent", incoming).unwrap();
+        tag_upstream_request(&mut req, &config, "req-123").unwrap();
+        assert_eq!(req.headers.get("traceparent").unwrap(), incoming);
+
+        // Missing or malformed ones are replaced
+        for existing in [None, Some("garbage")] {
+            let mut req = RequestHeader::build("GET", b"/api/users", None).unwrap();
+            if let Some(value) = existing {
+                req.insert_header("traceparent", value).unwrap();
+            }
+            tag_upstream_request(&mut req, &config, "req-123").unwrap();
+
+            let traceparent = req.headers.get("traceparent").unwrap().to_str().unwrap();
+            assert!(is_valid_traceparent(traceparent));
+        }
+    }
+
+    #[test]
+    fn test_traceparent_validation() {
+        assert!(is_valid_traceparent(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        ));
+        assert!(!is_valid_traceparent(
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01"
+        ));
+        assert!(!is_valid_traceparent(
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        ));
+        assert!(!is_valid_traceparent(
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"
+        ));
+        assert!(!is_valid_traceparent("00-abc-def-01"));
+    }
+}
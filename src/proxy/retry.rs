@@ -0,0 +1,97 @@
+use pingora_core::{Error, ErrorSource, ErrorType};
+
+use crate::config::settings::{RetryConfig, RetryOn};
+
+/// How an upstream attempt failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamFailure {
+    /// No connection was made, so no backend saw the request
+    Connect,
+    /// A backend answered with this status
+    Status(u16),
+}
+
+/// Whether to try another upstream after a failed attempt
+///
+/// Connection failures are always safe to retry. A 5xx is only retried
+/// with `on: connect_or_5xx`, since the backend may have acted on the
+/// request before failing; otherwise it is passed through to the client.
+///
+/// # Arguments
+/// * `config` - `load_balancing.retry`
+/// * `failure` - What went wrong
+/// * `attempts` - Upstream attempts made so far, including the failed one
+pub fn should_retry(config: &RetryConfig, failure: UpstreamFailure, attempts: u32) -> bool {
+    if attempts > config.max_retries {
+        return false;
+    }
+
+    match failure {
+        UpstreamFailure::Connect => true,
+        UpstreamFailure::Status(status) => config.on == RetryOn::ConnectOr5xx && status >= 500,
+    }
+}
+
+/// Error that discards a 5xx response so the request is retried
+pub fn status_error(status: u16) -> Box<Error> {
+    let mut e = Error::create(
+        ErrorType::HTTPStatus(status),
+        ErrorSource::Upstream,
+        Some("upstream 5xx, retrying".into()),
+        None,
+    );
+    e.set_retry(true);
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(on: RetryOn) -> RetryConfig {
+        RetryConfig { max_retries: 2, on }
+    }
+
+    #[test]
+    fn test_connect_only_passes_5xx_through() {
+        let config = config(RetryOn::ConnectError);
+
+        // A refused connection is retried on another upstream...
+        assert!(should_retry(&config, UpstreamFailure::Connect, 1));
+        // ...but a backend 500 goes back to the client as is
+        assert!(!should_retry(&config, UpstreamFailure::Status(500), 1));
+        assert!(!should_retry(&config, UpstreamFailure::Status(503), 1));
+    }
+
+    #[test]
+    fn test_connect_or_5xx_retries_server_errors() {
+        let config = config(RetryOn::ConnectOr5xx);
+
+        assert!(should_retry(&config, UpstreamFailure::Connect, 1));
+        assert!(should_retry(&config, UpstreamFailure::Status(502), 1));
+        assert!(!should_retry(&config, UpstreamFailure::Status(404), 1));
+        assert!(!should_retry(&config, UpstreamFailure::Status(200), 1));
+    }
+
+    #[test]
+    fn test_retries_are_capped() {
+        let config = config(RetryOn::ConnectOr5xx);
+
+        // First attempt plus two retries, then the failure is final
+        assert!(should_retry(&config, UpstreamFailure::Connect, 2));
+        assert!(!should_retry(&config, UpstreamFailure::Connect, 3));
+        assert!(!should_retry(
+            &RetryConfig::default(),
+            UpstreamFailure::Connect,
+            1
+        ));
+    }
+
+    #[test]
+    fn test_status_error_is_retryable() {
+        let e = status_error(500);
+
+        assert!(e.retry());
+        assert_eq!(e.etype(), &ErrorType::HTTPStatus(500));
+    }
+}
@@ -0,0 +1,449 @@
+use http::Uri;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+use crate::config::settings::{
+    CoalesceConfig, FallbackConfig, IdempotencyConfig, MirrorConfig, RedirectConfig, RouteAuthType,
+    RoutingConfig,
+};
+
+/// Route with its rewrite rules compiled
+#[derive(Debug, Clone)]
+struct CompiledRoute {
+    prefix: String,
+    strip_prefix: bool,
+    rewrite: Option<(Regex, String)>,
+    auth: Option<RouteAuthType>,
+    rate_limit: bool,
+    mirror: Option<MirrorConfig>,
+    allowed_content_types: Vec<String>,
+    idempotency: Option<IdempotencyConfig>,
+    redirects: Option<RedirectConfig>,
+    response_headers: BTreeMap<String, String>,
+    fallback: Option<FallbackConfig>,
+    coalesce: Option<CoalesceConfig>,
+    audience: Option<String>,
+}
+
+impl CompiledRoute {
+    /// Match on whole path segments: `/api` matches `/api` and `/api/users`, not `/apix`
+    fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(self.prefix.trim_end_matches('/')) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+/// Prefix-matched routing table built from `routing.routes`
+#[derive(Debug, Clone, Default)]
+pub struct Router {
+    /// Sorted by prefix length, longest first
+    routes: Vec<CompiledRoute>,
+}
+
+impl Router {
+    /// Build the routing table, compiling rewrite patterns
+    ///
+    /// # Arguments
+    /// * `config` - Routing configuration
+    ///
+    /// # Returns
+    /// * `Result<Router, regex::Error>` - Router or the first invalid pattern
+    pub fn new(config: &RoutingConfig) -> Result<Self, regex::Error> {
+        let mut routes = config
+            .routes
+            .iter()
+            .map(|route| {
+                let rewrite = match &route.rewrite {
+                    Some(rule) => Some((Regex::new(&rule.pattern)?, rule.replacement.clone())),
+                    None => None,
+                };
+
+                Ok(CompiledRoute {
+                    prefix: route.prefix.clone(),
+                    strip_prefix: route.strip_prefix,
+                    rewrite,
+                    auth: route.auth,
+                    rate_limit: route.rate_limit,
+                    mirror: route.mirror.clone(),
+                    allowed_content_types: route.allowed_content_types.clone(),
+                    idempotency: route.idempotency.clone(),
+                    redirects: route.redirects.clone(),
+                    response_headers: route.response_headers.clone(),
+                    fallback: route.fallback.clone(),
+                    coalesce: route.coalesce.clone(),
+                    audience: route.audience.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, regex::Error>>()?;
+
+        routes.sort_by_key(|route| std::cmp::Reverse(route.prefix.len()));
+
+        Ok(Self { routes })
+    }
+
+    /// Prefix of the longest route matching `path`
+    ///
+    /// Used as a bounded-cardinality label for per-route metrics.
+    pub fn match_prefix(&self, path: &str) -> Option<&str> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .map(|route| route.prefix.as_str())
+    }
+
+    /// Credential required for `path`
+    ///
+    /// Routes without an explicit `auth` setting (and unrouted paths) need a JWT.
+    pub fn auth_type(&self, path: &str) -> RouteAuthType {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .and_then(|route| route.auth)
+            .unwrap_or(RouteAuthType::Jwt)
+    }
+
+    /// Whether requests to `path` are rate limited
+    ///
+    /// Only routes with `rate_limit: false` are exempt; unrouted paths are not.
+    pub fn rate_limited(&self, path: &str) -> bool {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .is_none_or(|route| route.rate_limit)
+    }
+
+    /// Shadow upstream that `path` is mirrored to, if any
+    pub fn mirror(&self, path: &str) -> Option<&MirrorConfig> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .and_then(|route| route.mirror.as_ref())
+    }
+
+    /// Request body media types accepted on `path` (empty: any)
+    pub fn allowed_content_types(&self, path: &str) -> &[String] {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .map_or(&[], |route| route.allowed_content_types.as_slice())
+    }
+
+    /// Request de-duplication settings for `path`, if enabled
+    pub fn idempotency(&self, path: &str) -> Option<&IdempotencyConfig> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .and_then(|route| route.idempotency.as_ref())
+    }
+
+    /// Redirect rewriting settings for `path`, if enabled
+    pub fn redirects(&self, path: &str) -> Option<&RedirectConfig> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .and_then(|route| route.redirects.as_ref())
+            .filter(|redirects| redirects.rewrite_location)
+    }
+
+    /// Overrides of the default response headers on `path`, if any
+    pub fn response_headers(&self, path: &str) -> Option<&BTreeMap<String, String>> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .map(|route| &route.response_headers)
+            .filter(|headers| !headers.is_empty())
+    }
+
+    /// Response served on `path` while the breaker is open, if any
+    pub fn fallback(&self, path: &str) -> Option<&FallbackConfig> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .and_then(|route| route.fallback.as_ref())
+    }
+
+    /// `aud` required of JWTs on `path`, if any
+    pub fn audience(&self, path: &str) -> Option<&str> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .and_then(|route| route.audience.as_deref())
+    }
+
+    /// Single-flight settings of the route matching `path`, if enabled
+    pub fn coalesce(&self, path: &str) -> Option<&CoalesceConfig> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .and_then(|route| route.coalesce.as_ref())
+    }
+
+    /// Prefix removed from `path` before it was sent upstream, if any
+    pub fn stripped_prefix(&self, path: &str) -> Option<&str> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(path))
+            .filter(|route| route.strip_prefix)
+            .map(|route| route.prefix.trim_end_matches('/'))
+    }
+
+    /// Path to send upstream for a public request path
+    ///
+    /// The longest matching route applies: its prefix is stripped first (if
+    /// configured), then the regex rewrite runs on the result.
+    ///
+    /// # Returns
+    /// * `Some(path)` if the path changed, `None` if it is forwarded as-is
+    ///
+    /// # Example
+    /// ```
+    /// // routes: [{ prefix: "/api", strip_prefix: true }]
+    /// assert_eq!(router.rewrite_path("/api/users"), Some("/users".to_string()));
+    /// ```
+    pub fn rewrite_path(&self, path: &str) -> Option<String> {
+        let route = self.routes.iter().find(|route| route.matches(path))?;
+
+        let mut rewritten = path.to_string();
+
+        if route.strip_prefix {
+            let rest = &path[route.prefix.trim_end_matches('/').len()..];
+            rewritten = if rest.starts_with('/') {
+                rest.to_string()
+            } else {
+                format!("/{}", rest)
+            };
+        }
+
+        if let Some((pattern, replacement)) = &route.rewrite {
+            rewritten = pattern
+                .replace(&rewritten, replacement.as_str())
+                .into_owned();
+        }
+
+        if rewritten == path {
+            None
+        } else {
+            Some(rewritten)
+        }
+    }
+}
+
+/// Replace the path of a request URI, keeping its query string
+pub fn rewrite_uri(uri: &Uri, path: &str) -> Result<Uri, http::uri::InvalidUri> {
+    match uri.query() {
+        Some(query) => format!("{}?{}", path, query).parse(),
+        None => path.parse(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{RewriteRule, RouteConfig};
+
+    fn router(routes: Vec<RouteConfig>) -> Router {
+        Router::new(&RoutingConfig { routes }).unwrap()
+    }
+
+    fn route(prefix: &str) -> RouteConfig {
+        RouteConfig {
+            prefix: prefix.to_string(),
+            strip_prefix: false,
+            rewrite: None,
+            auth: None,
+            rate_limit: true,
+            mirror: None,
+            allowed_content_types: Vec::new(),
+            idempotency: None,
+            redirects: None,
+            response_headers: BTreeMap::new(),
+            fallback: None,
+            coalesce: None,
+            audience: None,
+        }
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let router = router(vec![RouteConfig {
+            strip_prefix: true,
+            ..route("/api")
+        }]);
+
+        assert_eq!(
+            router.rewrite_path("/api/users"),
+            Some("/users".to_string())
+        );
+        assert_eq!(router.rewrite_path("/api"), Some("/".to_string()));
+
+        // Only whole segments match
+        assert_eq!(router.rewrite_path("/apix/users"), None);
+    }
+
+    #[test]
+    fn test_path_left_intact_without_rule() {
+        let router = router(vec![route("/api")]);
+        assert_eq!(router.rewrite_path("/api/users"), None);
+
+        let router = Router::default();
+        assert_eq!(router.rewrite_path("/api/users"), None);
+    }
+
+    #[test]
+    fn test_regex_rewrite() {
+        let router = router(vec![RouteConfig {
+            rewrite: Some(RewriteRule {
+                pattern: r"^/v1/(\w+)".to_string(),
+                replacement: "/api/$1".to_string(),
+            }),
+            ..route("/v1")
+        }]);
+
+        assert_eq!(
+            router.rewrite_path("/v1/users/42"),
+            Some("/api/users/42".to_string())
+        );
+    }
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let router = router(vec![
+            RouteConfig {
+                strip_prefix: true,
+                ..route("/api")
+            },
+            route("/api/internal"),
+        ]);
+
+        assert_eq!(router.rewrite_path("/api/internal/stats"), None);
+        assert_eq!(
+            router.rewrite_path("/api/users"),
+            Some("/users".to_string())
+        );
+    }
+
+    #[test]
+    fn test_route_auth_type() {
+        let router = router(vec![
+            RouteConfig {
+                auth: Some(RouteAuthType::None),
+                ..route("/docs")
+            },
+            RouteConfig {
+                auth: Some(RouteAuthType::ApiKey),
+                ..route("/internal")
+            },
+            route("/api"),
+        ]);
+
+        assert_eq!(router.auth_type("/docs/index.html"), RouteAuthType::None);
+        assert_eq!(router.auth_type("/internal/jobs"), RouteAuthType::ApiKey);
+        assert_eq!(router.auth_type("/api/users"), RouteAuthType::Jwt);
+        assert_eq!(router.auth_type("/unrouted"), RouteAuthType::Jwt);
+    }
+
+    #[test]
+    fn test_route_rate_limit_override() {
+        let router = router(vec![
+            RouteConfig {
+                rate_limit: false,
+                ..route("/webhooks")
+            },
+            route("/api"),
+        ]);
+
+        assert!(!router.rate_limited("/webhooks/stripe"));
+        assert!(router.rate_limited("/api/users"));
+        assert!(router.rate_limited("/unrouted"));
+    }
+
+    #[test]
+    fn test_route_mirror() {
+        let router = router(vec![
+            RouteConfig {
+                mirror: Some(MirrorConfig {
+                    address: "10.0.0.9:3000".to_string(),
+                    percent: 10.0,
+                }),
+                ..route("/api")
+            },
+            route("/api/internal"),
+        ]);
+
+        assert_eq!(
+            router.mirror("/api/users").map(|m| m.address.as_str()),
+            Some("10.0.0.9:3000")
+        );
+        assert!(router.mirror("/api/internal/jobs").is_none());
+        assert!(router.mirror("/unrouted").is_none());
+    }
+
+    #[test]
+    fn test_route_allowed_content_types() {
+        let router = router(vec![
+            RouteConfig {
+                allowed_content_types: vec!["application/json".to_string()],
+                ..route("/api")
+            },
+            route("/api/uploads"),
+        ]);
+
+        assert_eq!(
+            router.allowed_content_types("/api/users"),
+            ["application/json".to_string()]
+        );
+        assert!(router.allowed_content_types("/api/uploads/1").is_empty());
+        assert!(router.allowed_content_types("/unrouted").is_empty());
+    }
+
+    #[test]
+    fn test_route_redirects() {
+        let redirects = RedirectConfig {
+            rewrite_location: true,
+            internal_hosts: Vec::new(),
+        };
+        let router = router(vec![
+            RouteConfig {
+                strip_prefix: true,
+                redirects: Some(redirects.clone()),
+                ..route("/app/")
+            },
+            RouteConfig {
+                redirects: Some(RedirectConfig {
+                    rewrite_location: false,
+                    ..redirects
+                }),
+                ..route("/legacy")
+            },
+        ]);
+
+        assert!(router.redirects("/app/login").is_some());
+        assert_eq!(router.stripped_prefix("/app/login"), Some("/app"));
+        assert!(router.redirects("/legacy").is_none());
+        assert_eq!(router.stripped_prefix("/legacy"), None);
+        assert!(router.redirects("/unrouted").is_none());
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_rejected() {
+        let result = Router::new(&RoutingConfig {
+            routes: vec![RouteConfig {
+                rewrite: Some(RewriteRule {
+                    pattern: "(".to_string(),
+                    replacement: String::new(),
+                }),
+                ..route("/api")
+            }],
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_rewrite_uri_keeps_query() {
+        let uri: Uri = "/api/users?page=2".parse().unwrap();
+        assert_eq!(rewrite_uri(&uri, "/users").unwrap(), "/users?page=2");
+    }
+}
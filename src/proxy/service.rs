@@ -1,20 +1,80 @@
 use async_trait::async_trait;
 use bytes::Bytes;
+use pingora_core::modules::http::compression::ResponseCompression;
 use pingora_core::upstreams::peer::HttpPeer;
 use pingora_core::Error;
+use pingora_core::ErrorSource;
 use pingora_core::ErrorType;
 use pingora_core::Result;
 use pingora_http::{RequestHeader, ResponseHeader};
-use pingora_proxy::{ProxyHttp, Session};
+use pingora_proxy::{FailToProxy, ProxyHttp, Session};
 use sqlx::PgPool;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::auth::{login_user, logout_user, refresh_token, register_user, JwtManager};
+use crate::auth::admin_nonce::{
+    consume_nonce, issue_nonce, NonceError, ADMIN_NONCE_HEADER, ADMIN_NONCE_TTL_SECONDS,
+};
+use crate::auth::change_password::ChangePasswordError;
+use crate::auth::cookie::{get_cookie, refresh_cookie};
+use crate::auth::encryption::EncryptionService;
+use crate::auth::import::ImportError;
+use crate::auth::jwks::IdpVerifier;
+use crate::auth::login::LoginError;
+use crate::auth::logout::{LogoutAllResponse, LogoutError};
+use crate::auth::refresh::RefreshError;
+use crate::auth::register::RegisterError;
+use crate::auth::schema::{self, SchemaError, ValidationErrorResponse};
+use crate::auth::user_data::UserDataError;
+use crate::auth::{
+    change_password, erase_user, export_user, import_users, login_user, logout_all_devices,
+    logout_user, refresh_token, register_user, validate_password, ImportUserRequest, JwtManager,
+    LogAudit, LoginStores, ValidatePasswordRequest,
+};
+use crate::cache::idempotency::SealedResponses;
 use crate::cache::RedisClient;
+use crate::cache::TokenBlacklist;
+use crate::config::settings::{
+    AuthMode, RateLimitBackend, RateLimitConfig, RateLimitDimension, RouteAuthType,
+    UnsatisfiableEncoding,
+};
+use crate::config::summary;
 use crate::config::Settings;
+use crate::db::error::{DbErrorKind, DbFailure};
+use crate::db::user::{UserError, Usernames};
+use crate::db::version_cache::{TokenVersionCache, VersionCachedUsers, TOKEN_VERSION_TTL};
+use crate::db::{TokenRepository, UserRepository, UserStore};
 use crate::load_balancing::manager::LoadBalancerManager;
-use crate::middleware::{JwtMiddleware, RateLimitMiddleware};
-use crate::proxy::context::ProxyContext;
+use crate::load_balancing::tls::{check_pin, PinCheck};
+use crate::metrics;
+use crate::middleware::jwt::TokenRejection;
+use crate::middleware::rate_limit::{soft_limit_warning, WARNING_HEADER};
+use crate::middleware::{
+    AuthMiddleware, ClientRateLimiters, ExternalDecision, ExternalRateLimiter, JwtMiddleware,
+    MemoryRateLimiter, RateLimitMiddleware, RateLimiter,
+};
+use crate::proxy::access_log::{self, AccessLogEntry};
+use crate::proxy::active::ActiveRequests;
+use crate::proxy::ban::{self, Ban, BanCache, BanError, BanRequest};
+use crate::proxy::coalesce::{self, InFlightFetches, Join};
+use crate::proxy::connection::ConnectionTracker;
+use crate::proxy::context::{AuthDecision, ProxyContext};
+use crate::proxy::default_headers::DefaultHeaders;
+use crate::proxy::drain::Readiness;
+use crate::proxy::encoding::{self, Negotiation};
+use crate::proxy::fallback::{self, LastGoodResponses};
+use crate::proxy::guards;
+use crate::proxy::header_filter::UpstreamHeaderFilter;
+use crate::proxy::http_client::HttpClient;
+use crate::proxy::idempotency::{self, CachedResponse, Claim, IdempotencyCapture};
+use crate::proxy::listener::{self, AdminListener, ListenerRoute};
+use crate::proxy::mirror;
+use crate::proxy::protocol;
+use crate::proxy::redirect;
+use crate::proxy::request_id;
+use crate::proxy::retry::{self, UpstreamFailure};
+use crate::proxy::routing::{self, Router};
+use crate::proxy::transform::{BufferState, ResponseTransformer};
 use pingora_core::upstreams::peer::Peer;
 
 /// Proxy service with authentication and rate limiting
@@ -24,9 +84,34 @@ pub struct ProxyService {
     pub redis_client: Arc<RedisClient>,
     pub jwt_manager: Arc<JwtManager>,
     pub load_balancer: Arc<LoadBalancerManager>,
+    /// Auth service group for `/auth/*` (`auth.backend: upstream` only)
+    pub auth_load_balancer: Option<Arc<LoadBalancerManager>>,
+    pub router: Arc<Router>,
     // Middleware components
     jwt_middleware: JwtMiddleware,
-    rate_limit_middleware: Option<RateLimitMiddleware>,
+    auth_middleware: AuthMiddleware,
+    rate_limit_middleware: Option<ClientRateLimiters>,
+    external_rate_limiter: Option<ExternalRateLimiter>,
+    connection_tracker: Option<ConnectionTracker>,
+    response_transformer: Option<ResponseTransformer>,
+    admin_listener: Option<AdminListener>,
+    default_headers: DefaultHeaders,
+    upstream_header_filter: UpstreamHeaderFilter,
+    active_requests: Arc<ActiveRequests>,
+    /// Flipped to not-ready when a drain starts on shutdown
+    readiness: Arc<Readiness>,
+    /// Served by routes with `fallback.last_good` while their breaker is open
+    last_good: LastGoodResponses,
+    /// GETs in flight on routes with `coalesce`, awaited by identical ones
+    in_flight_fetches: InFlightFetches,
+    /// Active bans, refetched from Redis at most once per second
+    ban_cache: BanCache,
+    /// Users' token versions, read from Postgres at most once per TTL
+    token_versions: TokenVersionCache,
+    /// Reported by `GET /health?detailed=true`
+    config_fingerprint: String,
+    /// Seals responses kept for idempotent replay (`encryption` keys)
+    encryption: Option<EncryptionService>,
 }
 
 impl ProxyService {
@@ -37,20 +122,74 @@ impl ProxyService {
         redis_client: RedisClient,
         jwt_manager: JwtManager,
         load_balancer: LoadBalancerManager,
+        auth_load_balancer: Option<LoadBalancerManager>,
+        router: Router,
     ) -> Self {
-        // Initialize JWT middleware
-        let jwt_middleware = JwtMiddleware::new(jwt_manager.clone());
+        // Shared by the proxy's own calls to side services
+        let http_client = Arc::new(HttpClient::new());
+
+        // Initialize JWT middleware, also accepting external IdP tokens if configured
+        let mut jwt_middleware = JwtMiddleware::new(jwt_manager.clone());
+        if let Some(idp) = &settings.jwt.idp {
+            match IdpVerifier::new(idp, Arc::clone(&http_client)) {
+                Ok(verifier) => jwt_middleware = jwt_middleware.with_idp(verifier),
+                // IdP tokens are then rejected like any unknown token
+                Err(e) => log::error!("External IdP disabled: {}", e),
+            }
+        }
+
+        // Initialize static token (api_key / bearer / basic) middleware
+        let auth_middleware = AuthMiddleware::new(&settings.middleware.auth);
 
         // Initialize rate limit middleware if enabled
-        let rate_limit_middleware = if settings.middleware.rate_limit.enabled {
-            Some(RateLimitMiddleware::new(
-                redis_client.clone(),
-                settings.middleware.rate_limit.requests_per_minute,
-                settings.middleware.rate_limit.burst_size,
-            ))
-        } else {
-            None
-        };
+        let rate_limit = &settings.middleware.rate_limit;
+        let external = rate_limit.backend == RateLimitBackend::External;
+        let rate_limit_middleware = (rate_limit.enabled && !external).then(|| {
+            ClientRateLimiters::new(
+                rate_limit,
+                |requests_per_minute, burst_size| -> Box<dyn RateLimiter> {
+                    if rate_limit.backend == RateLimitBackend::Memory {
+                        Box::new(MemoryRateLimiter::new(requests_per_minute, burst_size))
+                    } else {
+                        Box::new(RateLimitMiddleware::new(
+                            redis_client.clone(),
+                            requests_per_minute,
+                            burst_size,
+                        ))
+                    }
+                },
+            )
+        });
+
+        // Decisions delegated to a rate-limit service, if configured
+        let external_rate_limiter = rate_limit
+            .external
+            .as_ref()
+            .filter(|_| rate_limit.enabled && external)
+            .map(|config| ExternalRateLimiter::new(config, Arc::clone(&http_client)));
+
+        // Keep-alive request cap, if configured
+        let connection_tracker = settings
+            .server
+            .max_requests_per_connection
+            .map(ConnectionTracker::new);
+
+        // JSON response field injection, if enabled
+        let transform = &settings.middleware.response_transform;
+        let response_transformer = transform
+            .enabled
+            .then(|| ResponseTransformer::new(transform));
+
+        // Separate port for admin and metrics routes, if configured
+        let admin_listener = settings
+            .server
+            .admin_listener
+            .as_ref()
+            .map(AdminListener::new);
+
+        let default_headers = DefaultHeaders::new(&settings.response.default_headers);
+        let upstream_header_filter = UpstreamHeaderFilter::new(&settings.response.upstream_headers);
+        let config_fingerprint = summary::fingerprint(&settings);
 
         Self {
             settings: Arc::new(settings),
@@ -58,10 +197,48 @@ impl ProxyService {
             redis_client: Arc::new(redis_client),
             jwt_manager: Arc::new(jwt_manager),
             load_balancer: Arc::new(load_balancer),
+            auth_load_balancer: auth_load_balancer.map(Arc::new),
+            router: Arc::new(router),
             jwt_middleware,
+            auth_middleware,
             rate_limit_middleware,
+            external_rate_limiter,
+            connection_tracker,
+            response_transformer,
+            admin_listener,
+            default_headers,
+            upstream_header_filter,
+            active_requests: ActiveRequests::new(),
+            readiness: Arc::new(Readiness::default()),
+            last_good: LastGoodResponses::default(),
+            in_flight_fetches: InFlightFetches::default(),
+            ban_cache: BanCache::new(ban::BAN_REFRESH_INTERVAL),
+            token_versions: TokenVersionCache::new(TOKEN_VERSION_TTL),
+            config_fingerprint,
+            encryption: None,
         }
     }
+
+    /// Encrypt responses recorded for idempotent replay with these keys
+    pub fn with_encryption(mut self, encryption: EncryptionService) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// User store for a request, with token versions cached
+    fn users(&self) -> VersionCachedUsers<'_, UserRepository<'_>> {
+        VersionCachedUsers::new(UserRepository::new(&self.db_pool), &self.token_versions)
+    }
+
+    /// Idempotency store for a request, sealing responses when keys are set
+    fn idempotency_store(&self) -> SealedResponses<'_> {
+        SealedResponses::new(self.redis_client.as_ref(), self.encryption.as_ref())
+    }
+
+    /// Readiness `/ready` reports, for the shutdown handler to drain with
+    pub fn readiness(&self) -> Arc<Readiness> {
+        Arc::clone(&self.readiness)
+    }
 }
 
 #[async_trait]
@@ -69,11 +246,100 @@ impl ProxyHttp for ProxyService {
     type CTX = ProxyContext;
 
     fn new_ctx(&self) -> Self::CTX {
-        ProxyContext::new()
+        ProxyContext::with_request_id(request_id::generate(self.settings.request_id.format))
+    }
+
+    /// Turn on Pingora's response compression when the proxy compresses
+    ///
+    /// It reads `Accept-Encoding` before `request_filter` runs, so the level
+    /// has to be set here.
+    async fn early_request_filter(
+        &self,
+        session: &mut Session,
+        _ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        let level = self.settings.response.encoding.compression_level;
+        if level > 0 {
+            set_compression_level(session, level);
+        }
+        Ok(())
     }
 
     /// Handle incoming requests - routing and authentication
     async fn request_filter(&self, session: &mut Session, ctx: &mut Self::CTX) -> Result<bool> {
+        // In flight until the context is dropped, whichever way the request ends
+        ctx.active_request = Some(Arc::new(self.active_requests.enter()));
+
+        // ============================================================
+        // Request size guards - before any routing or auth work
+        // ============================================================
+        let uri_length = session.req_header().raw_path().len();
+        let max_uri_length = self.settings.server.max_uri_length;
+        if let Some(status) = guards::check_uri_length(uri_length, max_uri_length) {
+            log::warn!(
+                "[{}] URI too long: {} bytes (max {})",
+                ctx.request_id,
+                uri_length,
+                max_uri_length
+            );
+            let json = r#"{"error":"URI too long"}"#.to_string();
+            self.send_json_response(session, status, json).await?;
+            return Ok(true); // Stop processing
+        }
+
+        let max_headers = self.settings.server.max_headers;
+        let max_header_value_len = self.settings.server.max_header_value_len;
+        if let Some(status) = guards::check_headers(
+            &session.req_header().headers,
+            max_headers,
+            max_header_value_len,
+        ) {
+            log::warn!(
+                "[{}] Request headers too large: {} fields (max {}, values up to {} bytes)",
+                ctx.request_id,
+                session.req_header().headers.len(),
+                max_headers,
+                max_header_value_len
+            );
+            let json = r#"{"error":"Request header fields too large"}"#.to_string();
+            self.send_json_response(session, status, json).await?;
+            return Ok(true); // Stop processing
+        }
+
+        // Multipart uploads have their own, larger cap
+        let content_length: Option<usize> =
+            header_str(session.req_header(), "Content-Length").and_then(|v| v.parse().ok());
+        let multipart = guards::is_multipart(header_str(session.req_header(), "Content-Type"));
+        let max_body_size = if multipart {
+            self.settings.server.max_upload_size
+        } else {
+            self.settings.server.max_body_size
+        };
+        match guards::check_expect(
+            header_str(session.req_header(), "Expect"),
+            content_length,
+            max_body_size,
+        ) {
+            guards::ExpectDecision::None => {}
+            guards::ExpectDecision::Continue => ctx.expect_continue = true,
+            guards::ExpectDecision::Reject(status) => {
+                log::warn!(
+                    "[{}] Rejected Expect request with {} (max body {} bytes)",
+                    ctx.request_id,
+                    status,
+                    max_body_size
+                );
+                let json = if status == 413 {
+                    r#"{"error":"Payload too large"}"#
+                } else {
+                    r#"{"error":"Expectation failed"}"#
+                };
+                self.send_json_response(session, status, json.to_string())
+                    .await?;
+                return Ok(true); // Stop processing
+            }
+        }
+
         let req = session.req_header_mut();
         let path = req.uri.path().to_string();
         let method = req.method.as_str().to_string();
@@ -88,389 +354,3978 @@ impl ProxyHttp for ProxyService {
 
         // Store client IP
         if let Some(addr) = session.client_addr() {
-            ctx.client_ip = Some(addr.to_string());
+            ctx.set_client_addr(addr);
+        }
+
+        // Close HTTP/1 keep-alive connections after their last allowed request
+        if let Some(tracker) = &self.connection_tracker {
+            if count_connection_request(tracker, session.is_http2(), ctx) {
+                session.set_keepalive(None);
+            }
         }
 
         // ============================================================
-        // Health check endpoint - no authentication required
+        // Health and readiness probes - no authentication required
         // ============================================================
-        if path == "/health" {
-            let json = r#"{"status":"ok","service":"pingora-proxy"}"#.to_string();
-            self.send_json_response(session, 200, json).await?;
+        if let Some((status, json)) = probe_response(
+            session.req_header(),
+            &path,
+            &self.readiness,
+            &self.config_fingerprint,
+            || self.redis_client.is_healthy(),
+        ) {
+            self.send_json_response(session, status, json).await?;
             return Ok(true); // Stop processing
         }
 
         // ============================================================
-        // Authentication Endpoints
+        // Admin Endpoints - admin user required; with an admin listener,
+        // only served there (with /metrics) to allowlisted clients
         // ============================================================
-        if path.starts_with("/auth/") {
-            return self
-                .handle_auth_endpoint(session, ctx, &path, &method)
-                .await;
+        let local_port = session
+            .server_addr()
+            .and_then(|addr| addr.as_inet())
+            .map(|addr| addr.port());
+        let on_admin_listener = self
+            .admin_listener
+            .as_ref()
+            .is_some_and(|admin| admin.is_local_port(local_port));
+
+        match listener::route(self.admin_listener.as_ref(), on_admin_listener, &path) {
+            ListenerRoute::Public => {}
+            ListenerRoute::NotFound => {
+                self.send_not_found_response(session).await?;
+                return Ok(true);
+            }
+            ListenerRoute::Admin | ListenerRoute::Metrics
+                if on_admin_listener
+                    && !self
+                        .admin_listener
+                        .as_ref()
+                        .is_some_and(|admin| admin.allows(ctx.client_ip)) =>
+            {
+                log::warn!(
+                    "[{}] Admin listener request from {:?} not in allowlist",
+                    ctx.request_id,
+                    ctx.client_ip
+                );
+                let json = r#"{"error":"Forbidden"}"#.to_string();
+                self.send_json_response(session, 403, json).await?;
+                return Ok(true);
+            }
+            ListenerRoute::Metrics => {
+                self.send_metrics_response(session).await?;
+                return Ok(true);
+            }
+            ListenerRoute::Admin => {
+                return self
+                    .handle_admin_endpoint(session, ctx, &path, &method)
+                    .await;
+            }
+        }
+
+        // ============================================================
+        // Bans - set through the admin API, so admin endpoints are
+        // exempt and a ban can always be lifted. IPs are checked here,
+        // users once authenticated.
+        // ============================================================
+        let bans = match self
+            .ban_cache
+            .bans(self.redis_client.as_ref(), chrono::Utc::now())
+            .await
+        {
+            Ok(bans) => bans,
+            Err(e) => {
+                // Fail open: bans are incident tooling, not an auth boundary
+                log::error!("[{}] Ban lookup failed: {}", ctx.request_id, e);
+                Vec::new()
+            }
+        };
+        if self.reject_banned(session, ctx, &bans).await? {
+            return Ok(true); // Stop processing
+        }
+
+        // ============================================================
+        // Authentication Endpoints - handled here, or proxied to the
+        // auth service (rate limited but not authenticated)
+        // ============================================================
+        match auth_endpoint_target(self.auth_load_balancer.is_some(), &path) {
+            Some(AuthTarget::Local) => {
+                return self
+                    .handle_auth_endpoint(session, ctx, &path, &method)
+                    .await;
+            }
+            Some(AuthTarget::Upstream) => ctx.auth_upstream = true,
+            None => {}
         }
 
+        // Matched once here; rate limiting and logging read it from ctx
+        ctx.matched_route = self.router.match_prefix(&path).map(str::to_string);
+
         // ============================================================
-        // JWT Authentication (for protected routes)
+        // Authentication (credential type chosen per route)
         // ============================================================
-        if self.settings.middleware.auth.enabled {
-            match self.authenticate_request(session.req_header(), ctx).await {
-                Ok(()) => {
+        let auth_mode = self.settings.middleware.auth.mode();
+        let auth_type = self.router.auth_type(&path);
+        if auth_applies(auth_mode, auth_type, ctx.auth_upstream) {
+            let audience = self.router.audience(&path);
+            let result = self
+                .authenticate_request(session.req_header(), auth_type, audience)
+                .await;
+            let reject = record_auth_result(auth_mode, &result, ctx);
+            match result {
+                Ok(_) => {
                     log::info!("[{}] Authenticated user: {:?}", ctx.request_id, ctx.user_id);
                 }
-                Err(e) => {
+                Err(e) if reject => {
                     log::warn!("[{}] Authentication failed: {}", ctx.request_id, e);
-                    self.send_unauthorized_response(session).await?;
+                    self.send_auth_error(session, &e).await?;
                     return Ok(true); // Stop processing
                 }
+                Err(e) => {
+                    log::warn!(
+                        "[{}] Authentication would fail (shadow mode): {}",
+                        ctx.request_id,
+                        e
+                    );
+                }
+            }
+        }
+
+        if ctx.user_id.is_some() && self.reject_banned(session, ctx, &bans).await? {
+            return Ok(true); // Stop processing
+        }
+
+        // ============================================================
+        // Rate Limiting (unless the route opts out)
+        // ============================================================
+        let route = ctx.matched_route.clone();
+        let route = route.as_deref().unwrap_or("default");
+        if self
+            .enforce_rate_limit(session, ctx, Some(&path), route, &method)
+            .await?
+        {
+            return Ok(true); // Stop processing
+        }
+
+        // ============================================================
+        // Content-Type Allowlist - per route, before anything is forwarded
+        // ============================================================
+        let has_body = content_length.is_some_and(|length| length > 0)
+            || session
+                .req_header()
+                .headers
+                .contains_key("Transfer-Encoding");
+        if let Some(status) = guards::check_content_type(
+            header_str(session.req_header(), "Content-Type"),
+            has_body,
+            self.router.allowed_content_types(&path),
+        ) {
+            log::warn!(
+                "[{}] Content type {:?} not allowed on {}",
+                ctx.request_id,
+                header_str(session.req_header(), "Content-Type"),
+                path
+            );
+            let json = r#"{"error":"Unsupported media type"}"#.to_string();
+            self.send_json_response(session, status, json).await?;
+            return Ok(true); // Stop processing
+        }
+
+        // ============================================================
+        // Content-Coding Negotiation - only the chosen coding goes upstream
+        // ============================================================
+        let encoding_config = &self.settings.response.encoding;
+        let accept_encoding: Vec<&str> = session
+            .req_header()
+            .headers
+            .get_all("Accept-Encoding")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        if !encoding_config.codings.is_empty() && !accept_encoding.is_empty() {
+            let negotiation =
+                encoding::negotiate(&accept_encoding.join(","), &encoding_config.codings);
+            if negotiation == Negotiation::Unsatisfiable
+                && encoding_config.unsatisfiable == UnsatisfiableEncoding::Reject
+            {
+                log::warn!(
+                    "[{}] No acceptable content coding in {:?}",
+                    ctx.request_id,
+                    accept_encoding
+                );
+                let json = r#"{"error":"Not acceptable"}"#.to_string();
+                self.send_json_response(session, 406, json).await?;
+                return Ok(true); // Stop processing
+            }
+            ctx.upstream_accept_encoding = Some(negotiation.upstream_header().to_string());
+        }
+
+        // ============================================================
+        // Upload Size Guard - reject before anything is forwarded
+        // ============================================================
+        if multipart {
+            let max_upload_size = self.settings.server.max_upload_size;
+            if let Some(status) = guards::check_upload_size(content_length, max_upload_size) {
+                log::warn!(
+                    "[{}] Upload too large: {:?} bytes (max {})",
+                    ctx.request_id,
+                    content_length,
+                    max_upload_size
+                );
+                let json = r#"{"error":"Payload too large"}"#.to_string();
+                self.send_json_response(session, status, json).await?;
+                return Ok(true); // Stop processing
+            }
+
+            // Streamed to the upstream as it arrives, counted in request_body_filter
+            ctx.upload = Some(guards::UploadCounter::new(max_upload_size));
+        }
+
+        // ============================================================
+        // Circuit Breaker - answer from the route's fallback while every
+        // upstream of its group is ejected
+        // ============================================================
+        if let Some(config) = self.router.fallback(&path) {
+            let key = session
+                .req_header()
+                .uri
+                .path_and_query()
+                .map_or_else(|| path.clone(), |pq| pq.to_string());
+            let balancer =
+                balancer_for(ctx, &self.load_balancer, self.auth_load_balancer.as_deref());
+            if balancer.breaker_open() {
+                let (response, source) = fallback::response(config, &self.last_good, &key, &method);
+                log::warn!(
+                    "[{}] All upstreams ejected, serving {} fallback for {}",
+                    ctx.request_id,
+                    source,
+                    path
+                );
+                let route = ctx.matched_route.as_deref().unwrap_or("default");
+                metrics::FALLBACK_RESPONSES
+                    .with_label_values(&[route, source])
+                    .inc();
+                self.send_recorded_response(session, ctx, &response, fallback::FALLBACK_HEADER)
+                    .await?;
+                return Ok(true); // Stop processing
+            }
+
+            if config.last_good && method == "GET" {
+                ctx.last_good = Some(IdempotencyCapture::new(key, 0));
             }
         }
 
         // ============================================================
-        // Rate Limiting
+        // Request De-duplication - a repeated Idempotency-Key POST gets
+        // the first request's response instead of reaching the upstream
         // ============================================================
-        if let Some(rate_limiter) = &self.rate_limit_middleware {
-            if let Err(e) = self.check_rate_limit(ctx, rate_limiter).await {
-                log::warn!("[{}] Rate limit exceeded: {}", ctx.request_id, e);
-                self.send_rate_limit_response(session).await?;
+        let dedup = self
+            .router
+            .idempotency(&path)
+            .filter(|_| method == "POST")
+            .zip(header_str(
+                session.req_header(),
+                idempotency::IDEMPOTENCY_KEY_HEADER,
+            ));
+        if let Some((config, key)) = dedup {
+            let scope = idempotency::client_scope(ctx.user_id, &session.req_header().headers);
+            let Some(key) = idempotency::dedup_key(&path, &scope, key) else {
+                let json = r#"{"error":"Invalid Idempotency-Key"}"#.to_string();
+                self.send_json_response(session, 400, json).await?;
                 return Ok(true); // Stop processing
+            };
+
+            match idempotency::claim(&self.idempotency_store(), &key, config).await {
+                Ok(Claim::First) => {
+                    ctx.idempotency = Some(IdempotencyCapture::new(key, config.ttl_seconds));
+                }
+                Ok(Claim::Replay(response)) => {
+                    log::info!(
+                        "[{}] Replaying response for duplicate {}",
+                        ctx.request_id,
+                        key
+                    );
+                    metrics::IDEMPOTENT_REPLAYS.inc();
+                    self.send_replayed_response(session, ctx, &response).await?;
+                    return Ok(true); // Stop processing
+                }
+                Ok(Claim::InProgress) => {
+                    let json =
+                        r#"{"error":"A request with this Idempotency-Key is still in progress"}"#;
+                    self.send_json_response(session, 409, json.to_string())
+                        .await?;
+                    return Ok(true); // Stop processing
+                }
+                Err(e) => {
+                    log::warn!(
+                        "[{}] De-duplication unavailable, forwarding {}: {}",
+                        ctx.request_id,
+                        key,
+                        e
+                    );
+                }
+            }
+        }
+
+        // ============================================================
+        // Request Coalescing - identical concurrent GETs wait for the one
+        // already in flight upstream and share its response
+        // ============================================================
+        let coalesced = self
+            .router
+            .coalesce(&path)
+            .filter(|_| method == "GET" && session.req_header().headers.get("Range").is_none());
+        if let Some(config) = coalesced {
+            let req = session.req_header();
+            let key = coalesce::fetch_key(
+                &req.uri
+                    .path_and_query()
+                    .map_or_else(|| path.clone(), |pq| pq.to_string()),
+                &idempotency::client_scope(ctx.user_id, &req.headers),
+                header_str(req, "Accept"),
+                header_str(req, "Accept-Encoding"),
+            );
+
+            match self.in_flight_fetches.join(&key) {
+                Join::Leader(leader) => ctx.coalesce = Some(leader),
+                Join::Follower(shared) => {
+                    let wait = Duration::from_millis(config.wait_ms);
+                    match coalesce::wait(shared, wait).await {
+                        Some(response) => {
+                            log::info!(
+                                "[{}] Sharing the response of an identical request for {}",
+                                ctx.request_id,
+                                path
+                            );
+                            metrics::COALESCED_REQUESTS.inc();
+                            self.send_recorded_response(
+                                session,
+                                ctx,
+                                &response,
+                                coalesce::COALESCED_HEADER,
+                            )
+                            .await?;
+                            return Ok(true); // Stop processing
+                        }
+                        None => log::debug!(
+                            "[{}] No shared response for {}, forwarding",
+                            ctx.request_id,
+                            path
+                        ),
+                    }
+                }
+            }
+        }
+
+        // Sample idempotent requests for the route's shadow upstream
+        if let Some(mirror) = self.router.mirror(&path) {
+            if mirror::is_mirrorable(&session.req_header().method)
+                && mirror::is_sampled(mirror.percent, rand::random())
+            {
+                ctx.mirror_to = Some(mirror.address.clone());
             }
         }
 
+        // ============================================================
+        // Path Rewriting
+        // ============================================================
+        if let Some(rewritten) = self.router.rewrite_path(&path) {
+            let uri = routing::rewrite_uri(&session.req_header().uri, &rewritten)
+                .map_err(|e| Error::because(ErrorType::InternalError, "Path rewrite error", e))?;
+            session.req_header_mut().set_uri(uri);
+
+            log::info!(
+                "[{}] Rewrote path {} -> {}",
+                ctx.request_id,
+                path,
+                rewritten
+            );
+            ctx.original_path = Some(path);
+            ctx.rewritten_path = Some(rewritten);
+        }
+
+        // The request will be forwarded: let the client send its body now.
+        // Expect is dropped so the upstream doesn't send a second 100.
+        if ctx.expect_continue {
+            self.send_continue(session).await?;
+            session.req_header_mut().remove_header("Expect");
+        }
+
         // Continue to upstream
         Ok(false)
     }
 
+    /// Enforce the upload cap on multipart bodies while they stream upstream
+    async fn request_body_filter(
+        &self,
+        _session: &mut Session,
+        body: &mut Option<Bytes>,
+        _end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        if let (Some(upload), Some(chunk)) = (ctx.upload.as_mut(), body.as_ref()) {
+            if let Err(status) = upload.add(chunk.len()) {
+                log::warn!("[{}] Upload exceeded size limit mid-stream", ctx.request_id);
+                return Err(Error::explain(
+                    ErrorType::HTTPStatus(status),
+                    "Upload too large",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Select upstream server for load balancing
     async fn upstream_peer(
         &self,
         _session: &mut Session,
         ctx: &mut Self::CTX,
     ) -> Result<Box<HttpPeer>> {
-        let peer = self
-            .load_balancer
+        ctx.upstream_attempts += 1;
+        let balancer = balancer_for(ctx, &self.load_balancer, self.auth_load_balancer.as_deref());
+        let peer = balancer
             .select_peer()
             .map_err(|e| Error::because(ErrorType::InternalError, "Load balancer error", e))?;
+        // Replacing the guard releases the upstream of a failed attempt
+        ctx.upstream_request = balancer.start_request(&peer).map(Arc::new);
 
         log::info!("[{}] Selected upstream: {}", ctx.request_id, peer.address());
 
         Ok(peer)
     }
 
-    /// Add custom headers to response
-    async fn response_filter(
+    /// Retry a failed connection on the next upstream, within `load_balancing.retry`
+    fn fail_to_connect(
         &self,
         _session: &mut Session,
-        upstream_response: &mut ResponseHeader,
+        peer: &HttpPeer,
         ctx: &mut Self::CTX,
-    ) -> Result<()> {
-        // Add custom proxy headers
-        upstream_response
-            .insert_header("X-Proxy-By", "Pingora-Custom-Proxy")
-            .ok();
-        upstream_response
-            .insert_header("X-Request-ID", &ctx.request_id)
-            .ok();
-        upstream_response
-            .insert_header("X-Response-Time", format!("{}ms", ctx.elapsed().as_millis()))
-            .ok();
-
-        log::info!(
-            "[{}] Response: {} (took {:?})",
-            ctx.request_id,
-            upstream_response.status,
-            ctx.elapsed()
-        );
+        mut e: Box<Error>,
+    ) -> Box<Error> {
+        balancer_for(ctx, &self.load_balancer, self.auth_load_balancer.as_deref())
+            .record_connect_failure(peer);
 
-        Ok(())
+        let retry = &self.settings.load_balancing.retry;
+        if retry::should_retry(retry, UpstreamFailure::Connect, ctx.upstream_attempts) {
+            log::warn!(
+                "[{}] Connecting to {} failed, retrying: {}",
+                ctx.request_id,
+                peer.address(),
+                e
+            );
+            e.set_retry(true);
+        }
+        e
     }
-}
 
-impl ProxyService {
-    /// Handle authentication endpoints
-    async fn handle_auth_endpoint(
+    /// Reset the upstream's connect failure count, and enforce its
+    /// `min_tls_version`, `ciphers` and certificate pin on new TLS connections
+    async fn connected_to_upstream(
         &self,
-        session: &mut Session,
-        ctx: &mut ProxyContext,
-        path: &str,
-        method: &str,
-    ) -> Result<bool> {
-        match (method, path) {
-            ("POST", "/auth/register") => {
-                self.handle_register(session, ctx).await?;
-            }
-            ("POST", "/auth/login") => {
-                self.handle_login(session, ctx).await?;
-            }
-            ("POST", "/auth/refresh") => {
-                self.handle_refresh(session, ctx).await?;
-            }
-            ("POST", "/auth/logout") => {
-                self.handle_logout(session, ctx).await?;
+        _session: &mut Session,
+        reused: bool,
+        peer: &HttpPeer,
+        #[cfg(unix)] _fd: std::os::unix::io::RawFd,
+        digest: Option<&pingora_core::protocols::Digest>,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        let balancer = balancer_for(ctx, &self.load_balancer, self.auth_load_balancer.as_deref());
+        balancer.record_success(peer);
+
+        // Pooled connections were checked when they were opened
+        let Some(ssl) = digest
+            .and_then(|digest| digest.ssl_digest.as_ref())
+            .filter(|_| !reused)
+        else {
+            return Ok(());
+        };
+
+        if let Some(tls) = balancer.tls_for(peer) {
+            if let Err(reason) = tls.check_negotiated(ssl.version, ssl.cipher) {
+                log::warn!(
+                    "[{}] Refusing upstream {}: {}",
+                    ctx.request_id,
+                    peer.address(),
+                    reason
+                );
+                return Error::e_explain(ErrorType::TLSHandshakeFailure, reason);
             }
-            _ => {
-                self.send_not_found_response(session).await?;
+
+            if let Some(pin_key) = &tls.pin_key {
+                // Fail closed: an unverified certificate is never trusted
+                match check_pin(self.redis_client.as_ref(), pin_key, &ssl.cert_digest).await {
+                    Ok(PinCheck::Pinned(fingerprint)) => log::warn!(
+                        target: "audit",
+                        "Pinned certificate {} for upstream {} on first use",
+                        fingerprint,
+                        pin_key
+                    ),
+                    Ok(PinCheck::Matched) => {}
+                    Ok(PinCheck::Changed { pinned, presented }) => {
+                        metrics::UPSTREAM_CERT_PIN_MISMATCHES
+                            .with_label_values(&[pin_key])
+                            .inc();
+                        log::error!(
+                            target: "audit",
+                            "[{}] Upstream {} presented certificate {}, pinned is {}; refusing connection",
+                            ctx.request_id,
+                            pin_key,
+                            presented,
+                            pinned
+                        );
+                        return Error::e_explain(
+                            ErrorType::TLSHandshakeFailure,
+                            "upstream certificate does not match its pin",
+                        );
+                    }
+                    Err(e) => {
+                        log::error!(
+                            "[{}] Cannot check certificate pin of upstream {}: {}",
+                            ctx.request_id,
+                            pin_key,
+                            e
+                        );
+                        return Error::e_explain(
+                            ErrorType::TLSHandshakeFailure,
+                            "upstream certificate pin unavailable",
+                        );
+                    }
+                }
             }
         }
 
-        Ok(true) // Stop processing, we handled it
+        Ok(())
     }
 
-    /// Handle user registration
-    async fn handle_register(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
-        log::info!("[{}] Handling registration", ctx.request_id);
-
-        let body = self.read_request_body(session).await?;
+    /// Propagate the request ID (and trace context) to the upstream and
+    /// start timing its response
+    async fn upstream_request_filter(
+        &self,
+        _session: &mut Session,
+        upstream_request: &mut RequestHeader,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        request_id::tag_upstream_request(
+            upstream_request,
+            &self.settings.request_id,
+            &ctx.request_id,
+        )?;
 
-        let request: crate::auth::RegisterRequest = serde_json::from_slice(&body)
-            .map_err(|e| Error::because(ErrorType::InternalError, "Invalid JSON", e))?;
+        if let Some(accept_encoding) = &ctx.upstream_accept_encoding {
+            upstream_request.insert_header("Accept-Encoding", accept_encoding.as_str())?;
+        }
 
-        match register_user(
-            &self.db_pool,
-            &self.jwt_manager,
-            request,
-            self.settings.jwt.refresh_token_expiration,
-        )
-        .await
-        {
-            Ok(response) => {
-                let json = serde_json::to_string(&response)
-                    .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
-                self.send_json_response(session, 201, json).await?;
-            }
-            Err(e) => {
-                log::error!("[{}] Registration failed: {}", ctx.request_id, e);
-                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
-                self.send_json_response(session, 400, error_msg).await?;
-            }
+        // Fire and forget: the client only ever sees the primary's response.
+        // Taken so that retries to another primary don't mirror again.
+        if let Some(address) = ctx.mirror_to.take() {
+            mirror::spawn(address, upstream_request, ctx.request_id.clone());
         }
 
+        ctx.upstream_start = Some(Instant::now());
         Ok(())
     }
 
-    /// Handle user login
-    async fn handle_login(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
-        log::info!("[{}] Handling login", ctx.request_id);
+    /// Time the upstream response; with `retry.on: connect_or_5xx`, drop a
+    /// 5xx and retry on the next upstream
+    fn upstream_response_filter(
+        &self,
+        _session: &mut Session,
+        upstream_response: &mut ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        ctx.upstream_response_time = ctx.upstream_start.map(|start| start.elapsed());
 
-        let body = self.read_request_body(session).await?;
+        let status = upstream_response.status.as_u16();
+        let retry = &self.settings.load_balancing.retry;
+        if retry::should_retry(
+            retry,
+            UpstreamFailure::Status(status),
+            ctx.upstream_attempts,
+        ) {
+            log::warn!(
+                "[{}] Upstream answered {}, retrying",
+                ctx.request_id,
+                status
+            );
+            return Err(retry::status_error(status));
+        }
+        Ok(())
+    }
 
-        let request: crate::auth::LoginRequest = serde_json::from_slice(&body)
-            .map_err(|e| Error::because(ErrorType::InternalError, "Invalid JSON", e))?;
+    /// Add custom headers to response
+    async fn response_filter(
+        &self,
+        session: &mut Session,
+        upstream_response: &mut ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        let http2 = session.is_http2();
+        let event_stream = protocol::is_event_stream(upstream_response);
 
-        match login_user(
-            &self.db_pool,
-            &self.jwt_manager,
-            request,
-            self.settings.jwt.refresh_token_expiration,
-        )
-        .await
-        {
-            Ok(response) => {
-                let json = serde_json::to_string(&response)
-                    .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
-                self.send_json_response(session, 200, json).await?;
-            }
-            Err(e) => {
-                log::error!("[{}] Login failed: {}", ctx.request_id, e);
-                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
-                self.send_json_response(session, 401, error_msg).await?;
+        // Strip internal headers first, so neither clients nor recorded
+        // replays see them, and the proxy's own headers are never touched
+        self.upstream_header_filter.apply(upstream_response);
+
+        // Server-Sent Events: never buffered, recorded or transformed, so
+        // each event reaches the client as the upstream sends it
+        if event_stream {
+            ctx.event_stream = true;
+            ctx.last_good = None;
+            ctx.coalesce = None;
+            if let Some(capture) = ctx.idempotency.as_mut() {
+                capture.discard();
             }
+            protocol::disable_downstream_buffering(upstream_response);
         }
 
-        Ok(())
-    }
+        // Recorded as the upstream sent it; proxy headers are added on replay
+        if let Some(capture) = ctx.idempotency.as_mut() {
+            capture.record_header(upstream_response);
+        }
+        if let Some(capture) = ctx.last_good.as_mut() {
+            capture.record_header(upstream_response);
+        }
+        if let Some(leader) = ctx.coalesce.as_mut() {
+            leader.capture.record_header(upstream_response);
+        }
 
-    /// Handle token refresh
-    async fn handle_refresh(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
-        log::info!("[{}] Handling token refresh", ctx.request_id);
+        let path = ctx
+            .original_path
+            .clone()
+            .unwrap_or_else(|| session.req_header().uri.path().to_string());
+
+        // Don't leak the backend's host in redirects
+        if let Some(redirects) = self.router.redirects(&path) {
+            let mut backend_hosts: Vec<String> =
+                balancer_for(ctx, &self.load_balancer, self.auth_load_balancer.as_deref())
+                    .upstreams()
+                    .iter()
+                    .map(|upstream| format!("{}:{}", upstream.address, upstream.port))
+                    .collect();
+            backend_hosts.extend(redirects.internal_hosts.iter().cloned());
 
-        let body = self.read_request_body(session).await?;
+            let origin =
+                redirect::public_origin(session.req_header(), self.settings.server.tls.is_some());
+            if let Some(location) = redirect::rewrite_redirect(
+                upstream_response,
+                &backend_hosts,
+                origin.as_deref(),
+                self.router.stripped_prefix(&path),
+            ) {
+                log::debug!("[{}] Rewrote redirect to {}", ctx.request_id, location);
+            }
+        }
 
-        let request: crate::auth::RefreshRequest = serde_json::from_slice(&body)
-            .map_err(|e| Error::because(ErrorType::InternalError, "Invalid JSON", e))?;
+        // Hold back small JSON bodies for field injection; the length changes
+        if let Some(transformer) = self
+            .response_transformer
+            .as_ref()
+            .filter(|_| !ctx.event_stream)
+        {
+            let req = session.req_header();
+            let path = ctx.original_path.as_deref().unwrap_or(req.uri.path());
 
-        match refresh_token(
-            &self.db_pool,
-            &self.redis_client,
-            &self.jwt_manager,
-            request,
+            if transformer.applies_to_response(path, &req.method, upstream_response) {
+                protocol::make_length_unknown(upstream_response, http2);
+                ctx.response_buffer = Some(transformer.buffer());
+            }
+        }
+
+        // Already compressed bodies pass through as the upstream sent them
+        let encoding_config = &self.settings.response.encoding;
+        if encoding_config.compression_level > 0
+            && !encoding::compressible(upstream_response, &encoding_config.exclude_types)
+        {
+            set_compression_level(session, 0);
+        }
+
+        // Responses of the auth service carry tokens, like the built-in ones
+        if ctx.auth_upstream {
+            forbid_caching(upstream_response).ok();
+        }
+
+        // Tell the client not to reuse a connection at its request limit
+        if ctx.close_connection {
+            protocol::close_after_response(upstream_response, http2);
+        }
+
+        // Add custom proxy headers
+        upstream_response
+            .insert_header("X-Proxy-By", "Pingora-Custom-Proxy")
+            .ok();
+        request_id::tag_response(
+            upstream_response,
+            &self.settings.request_id,
+            &ctx.request_id,
         )
-        .await
+        .ok();
+        upstream_response
+            .insert_header("X-Response-Time", format!("{}ms", ctx.elapsed().as_millis()))
+            .ok();
+        if let Some(warning) = &ctx.rate_limit_warning {
+            upstream_response
+                .insert_header(WARNING_HEADER, warning.as_str())
+                .ok();
+        }
+        for (name, value) in &ctx.rate_limit_headers {
+            upstream_response
+                .insert_header(name.clone(), value.as_str())
+                .ok();
+        }
+        self.add_default_headers(session, &path, upstream_response);
+
+        // No hop-by-hop headers from an HTTP/1.1 upstream on an HTTP/2 client
+        protocol::finalize_response(upstream_response, http2);
+
+        Ok(())
+    }
+
+    /// Inject configured fields into buffered JSON response bodies, and
+    /// record bodies for idempotent replay and route fallbacks
+    fn response_body_filter(
+        &self,
+        _session: &mut Session,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> Result<Option<std::time::Duration>> {
+        if let (Some(transformer), Some(buffer)) =
+            (&self.response_transformer, ctx.response_buffer.as_mut())
         {
-            Ok(response) => {
-                let json = serde_json::to_string(&response)
-                    .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
-                self.send_json_response(session, 200, json).await?;
+            match buffer.push(body.take(), end_of_stream) {
+                BufferState::Buffering => {}
+                BufferState::Complete(original) => {
+                    *body = Some(match transformer.inject(&original, &ctx.request_id) {
+                        Some(injected) => Bytes::from(injected),
+                        None => original,
+                    });
+                }
+                BufferState::Overflow(held_back) => {
+                    log::debug!(
+                        "[{}] Response too large to transform, passing through",
+                        ctx.request_id
+                    );
+                    *body = Some(held_back);
+                    ctx.response_buffer = None;
+                }
             }
-            Err(e) => {
-                log::error!("[{}] Token refresh failed: {}", ctx.request_id, e);
-                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
-                self.send_json_response(session, 401, error_msg).await?;
+        }
+
+        // What the client receives, after any injection
+        if let (Some(capture), Some(chunk)) = (ctx.idempotency.as_mut(), body.as_ref()) {
+            capture.record_body(chunk);
+        }
+        if let (Some(capture), Some(chunk)) = (ctx.last_good.as_mut(), body.as_ref()) {
+            capture.record_body(chunk);
+        }
+        if let (Some(leader), Some(chunk)) = (ctx.coalesce.as_mut(), body.as_ref()) {
+            leader.capture.record_body(chunk);
+        }
+
+        Ok(None)
+    }
+
+    /// Answer a request that failed before a response was sent, with the
+    /// same status Pingora would use and the default headers added
+    async fn fail_to_proxy(
+        &self,
+        session: &mut Session,
+        e: &Error,
+        ctx: &mut Self::CTX,
+    ) -> FailToProxy {
+        let code = error_status(e);
+
+        // 0 or 499: the client is gone, there is no one to answer
+        if code > 0 && code != guards::CLIENT_CLOSED_REQUEST {
+            let path = ctx
+                .original_path
+                .clone()
+                .unwrap_or_else(|| session.req_header().uri.path().to_string());
+            if let Err(write_error) = self.send_error_response(session, &path, code).await {
+                log::error!(
+                    "[{}] Failed to send error response: {}",
+                    ctx.request_id,
+                    write_error
+                );
             }
         }
 
-        Ok(())
+        FailToProxy {
+            error_code: code,
+            can_reuse_downstream: false,
+        }
     }
 
-    /// Handle user logout
-    async fn handle_logout(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
-        log::info!("[{}] Handling logout", ctx.request_id);
+    /// Write the access log line and count client disconnects; no error
+    /// response is sent for those. Records the response
+    /// of a claimed `Idempotency-Key` request and releases its key, and
+    /// keeps the route's last good response for its fallback, and shares
+    /// a coalesced GET's response with the identical requests waiting on it.
+    async fn logging(&self, session: &mut Session, e: Option<&Error>, ctx: &mut Self::CTX) {
+        if let Some(capture) = ctx.last_good.take() {
+            if e.is_none() && session.response_written().is_some() {
+                self.last_good.record(&capture);
+            }
+        }
 
-        let access_token = self.extract_token_from_header(session.req_header())?;
+        if let Some(leader) = ctx.coalesce.take() {
+            if e.is_none() && session.response_written().is_some() {
+                leader.share();
+            }
+        }
 
-        let body = self.read_request_body(session).await?;
+        if let Some(capture) = ctx.idempotency.take() {
+            let completed = e.is_none() && session.response_written().is_some();
+            if let Err(err) =
+                idempotency::complete(&self.idempotency_store(), &capture, completed).await
+            {
+                log::warn!(
+                    "[{}] Failed to record idempotent response {}: {}",
+                    ctx.request_id,
+                    capture.key,
+                    err
+                );
+            }
+        }
 
-        let request: crate::auth::LogoutRequest = serde_json::from_slice(&body)
-            .map_err(|e| Error::because(ErrorType::InternalError, "Invalid JSON", e))?;
+        let elapsed = ctx.elapsed();
+        let slow_threshold = self
+            .settings
+            .server
+            .slow_request_threshold_ms
+            .map(Duration::from_millis);
+        let req = session.req_header();
+        access_log::record(
+            &AccessLogEntry {
+                request_id: &ctx.request_id,
+                method: req.method.as_str(),
+                path: ctx.original_path.as_deref().unwrap_or(req.uri.path()),
+                status: session.response_written().map(|resp| resp.status.as_u16()),
+                duration_ms: elapsed.as_millis(),
+                client_ip: ctx.client_ip,
+                route: ctx.matched_route.as_deref(),
+                auth: ctx.auth_decision,
+            },
+            access_log::level(elapsed, slow_threshold),
+        );
+        metrics::AUTH_DECISIONS
+            .with_label_values(&[ctx.auth_decision.as_str()])
+            .inc();
+        metrics::record_request_latency(
+            ctx.matched_route.as_deref().unwrap_or("default"),
+            elapsed,
+            ctx.upstream_response_time,
+        );
 
-        match logout_user(
-            &self.db_pool,
-            &self.redis_client,
-            &self.jwt_manager,
-            &access_token,
-            request,
-        )
-        .await
-        {
-            Ok(()) => {
-                let json = r#"{"message":"Logged out successfully"}"#.to_string();
-                self.send_json_response(session, 200, json).await?;
+        if let Some(e) = e.filter(|e| is_client_disconnect(e)) {
+            metrics::CLIENT_DISCONNECTS.inc();
+            log::debug!(
+                "[{}] Client disconnected after {:?}: {}",
+                ctx.request_id,
+                ctx.elapsed(),
+                e
+            );
+        }
+    }
+
+    /// A client going away is not a proxy error
+    fn suppress_error_log(&self, _session: &Session, _ctx: &Self::CTX, error: &Error) -> bool {
+        is_client_disconnect(error)
+    }
+}
+
+impl ProxyService {
+    /// Handle authentication endpoints
+    async fn handle_auth_endpoint(
+        &self,
+        session: &mut Session,
+        ctx: &mut ProxyContext,
+        path: &str,
+        method: &str,
+    ) -> Result<bool> {
+        if self.settings.server.read_only && writes_database(method, path) {
+            self.send_read_only_response(session, ctx).await?;
+            return Ok(true);
+        }
+
+        match (method, path) {
+            ("POST", "/auth/register") => {
+                self.handle_register(session, ctx).await?;
             }
-            Err(e) => {
-                log::error!("[{}] Logout failed: {}", ctx.request_id, e);
-                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
-                self.send_json_response(session, 400, error_msg).await?;
+            ("POST", "/auth/login") => {
+                self.handle_login(session, ctx).await?;
+            }
+            ("POST", "/auth/refresh") => {
+                self.handle_refresh(session, ctx).await?;
+            }
+            ("POST", "/auth/logout") => {
+                self.handle_logout(session, ctx).await?;
+            }
+            ("POST", "/auth/logout-all") => {
+                self.handle_logout_all(session, ctx).await?;
             }
+            ("POST", "/auth/change-password") => {
+                self.handle_change_password(session, ctx).await?;
+            }
+            ("POST", "/auth/validate-password") => {
+                self.handle_validate_password(session, ctx).await?;
+            }
+            ("GET", "/auth/openapi.json") if self.settings.server.openapi => {
+                let json = crate::auth::openapi::spec().to_string();
+                self.send_json_response(session, 200, json).await?;
+            }
+            _ => match auth_allowed_methods(path, self.settings.server.openapi) {
+                Some(allow) => {
+                    log::warn!(
+                        "[{}] Method {} not allowed on {}",
+                        ctx.request_id,
+                        method,
+                        path
+                    );
+                    self.send_method_not_allowed_response(session, &allow)
+                        .await?;
+                }
+                None => {
+                    self.send_not_found_response(session).await?;
+                }
+            },
         }
 
-        Ok(())
+        Ok(true) // Stop processing, we handled it
     }
 
-    /// Authenticate request using JWT middleware
-    async fn authenticate_request(
+    /// Handle admin endpoints
+    async fn handle_admin_endpoint(
         &self,
-        req: &RequestHeader,
+        session: &mut Session,
         ctx: &mut ProxyContext,
-    ) -> std::result::Result<(), String> {
-        // Use JWT middleware to verify token
-        let user_id_str = self
-            .jwt_middleware
-            .verify_request(req)
-            .ok_or_else(|| "Invalid or missing token".to_string())?;
+        path: &str,
+        method: &str,
+    ) -> Result<bool> {
+        let admin = authorize_admin(
+            session.req_header(),
+            &self.jwt_middleware,
+            self.redis_client.as_ref(),
+            &self.users(),
+        )
+        .await;
+
+        match admin {
+            Ok(user_id) => ctx.set_user_id(user_id),
+            Err((status, e)) => {
+                log::warn!("[{}] Admin authorization failed: {}", ctx.request_id, e);
+                if status == 403 {
+                    let json = r#"{"error":"Forbidden"}"#.to_string();
+                    self.send_json_response(session, 403, json).await?;
+                } else if status == 503 {
+                    let json = r#"{"error":"Service temporarily unavailable"}"#.to_string();
+                    self.send_json_response(session, 503, json).await?;
+                } else {
+                    self.send_unauthorized_response(session).await?;
+                }
+                return Ok(true);
+            }
+        }
 
-        // Extract token for blacklist check
-        let token = self
-            .extract_token_from_header(req)
-            .map_err(|e| format!("Token extraction failed: {}", e))?;
+        if self.settings.server.read_only && writes_database(method, path) {
+            self.send_read_only_response(session, ctx).await?;
+            return Ok(true);
+        }
 
-        // Check if token is blacklisted (additional security layer)
-        let is_blacklisted = self
-            .redis_client
-            .is_token_blacklisted(&token)
-            .await
-            .map_err(|e| format!("Redis error: {}", e))?;
+        match (method, revoke_tokens_target(path)) {
+            ("GET", None) if path == "/admin/nonce" => {
+                self.handle_admin_nonce(session, ctx).await?;
+            }
+            ("POST", Some(user_id)) => {
+                if self.consume_admin_nonce(session, ctx).await? {
+                    self.handle_revoke_tokens(session, ctx, user_id).await?;
+                }
+            }
+            ("POST", None) if path == "/admin/users/import" => {
+                if self.consume_admin_nonce(session, ctx).await? {
+                    self.handle_import_users(session, ctx).await?;
+                }
+            }
+            ("POST", None) if path == "/admin/ban" => {
+                if self.consume_admin_nonce(session, ctx).await? {
+                    self.handle_ban(session, ctx).await?;
+                }
+            }
+            ("DELETE", None) if path == "/admin/ban" => {
+                if self.consume_admin_nonce(session, ctx).await? {
+                    self.handle_unban(session, ctx).await?;
+                }
+            }
+            ("GET", None) if path == "/admin/bans" => {
+                self.handle_list_bans(session, ctx).await?;
+            }
+            ("POST", None) if upstream_ejection_target(path).is_some() => {
+                if self.consume_admin_nonce(session, ctx).await? {
+                    let (name, eject) = upstream_ejection_target(path).unwrap_or_default();
+                    self.handle_upstream_ejection(session, ctx, name, eject)
+                        .await?;
+                }
+            }
+            _ => match (method, user_export_target(path), user_target(path)) {
+                ("GET", Some(user_id), _) => {
+                    self.handle_export_user(session, ctx, user_id).await?;
+                }
+                ("DELETE", _, Some(user_id)) => {
+                    if self.consume_admin_nonce(session, ctx).await? {
+                        self.handle_erase_user(session, ctx, user_id).await?;
+                    }
+                }
+                _ => {
+                    self.send_not_found_response(session).await?;
+                }
+            },
+        }
 
-        if is_blacklisted {
-            return Err("Token has been revoked".to_string());
+        Ok(true) // Stop processing, we handled it
+    }
+
+    /// Handle `GET /admin/nonce` by issuing a single-use nonce to the admin
+    async fn handle_admin_nonce(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
+        let Some(user_id) = ctx.user_id else {
+            return self.send_unauthorized_response(session).await;
+        };
+
+        match issue_nonce(
+            self.redis_client.as_ref(),
+            &user_id,
+            ADMIN_NONCE_TTL_SECONDS,
+        )
+        .await
+        {
+            Ok(response) => {
+                let json = serde_json::to_string(&response)
+                    .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
+                self.send_json_response(session, 200, json).await
+            }
+            Err(e) => {
+                log::error!("[{}] Issuing admin nonce failed: {}", ctx.request_id, e);
+                let json = r#"{"error":"Service unavailable"}"#.to_string();
+                self.send_json_response(session, 503, json).await
+            }
         }
+    }
+
+    /// Consume the `X-Admin-Nonce` of a mutating admin request
+    ///
+    /// # Returns
+    /// * `Result<bool>` - `true` to go ahead, `false` if a rejection was sent
+    async fn consume_admin_nonce(&self, session: &mut Session, ctx: &ProxyContext) -> Result<bool> {
+        let Some(user_id) = ctx.user_id else {
+            self.send_unauthorized_response(session).await?;
+            return Ok(false);
+        };
 
-        // Parse user ID
-        let user_id = uuid::Uuid::parse_str(&user_id_str)
-            .map_err(|_| "Invalid user ID in token".to_string())?;
+        let nonce = session
+            .req_header()
+            .headers
+            .get(ADMIN_NONCE_HEADER)
+            .and_then(|value| value.to_str().ok());
 
-        ctx.set_user_id(user_id);
+        let (status, message) =
+            match consume_nonce(self.redis_client.as_ref(), &user_id, nonce).await {
+                Ok(()) => return Ok(true),
+                Err(NonceError::Missing) => (428, "Admin nonce required"),
+                Err(NonceError::Invalid) => (403, "Invalid admin nonce"),
+                Err(NonceError::CacheError(e)) => {
+                    log::error!("[{}] Admin nonce check failed: {}", ctx.request_id, e);
+                    (503, "Service unavailable")
+                }
+            };
 
-        Ok(())
+        log::warn!(
+            "[{}] Rejected admin request from {}: {}",
+            ctx.request_id,
+            user_id,
+            message
+        );
+        let json = format!(r#"{{"error":"{}"}}"#, message);
+        self.send_json_response(session, status, json).await?;
+        Ok(false)
     }
 
-    /// Check rate limit using middleware
-    async fn check_rate_limit(
+    /// Handle `POST /admin/users/{id}/revoke-tokens` by bumping the token version
+    async fn handle_revoke_tokens(
         &self,
+        session: &mut Session,
         ctx: &ProxyContext,
-        rate_limiter: &RateLimitMiddleware,
-    ) -> std::result::Result<(), String> {
-        // Determine client identifier (user_id > client_ip > request_id)
-        let client_id = if let Some(user_id) = &ctx.user_id {
-            format!("user:{}", user_id)
-        } else if let Some(ip) = &ctx.client_ip {
-            format!("ip:{}", ip)
-        } else {
-            format!("anonymous:{}", ctx.request_id)
+        user_id: &str,
+    ) -> Result<()> {
+        let Ok(user_id) = uuid::Uuid::parse_str(user_id) else {
+            let json = r#"{"error":"Invalid user ID"}"#.to_string();
+            return self.send_json_response(session, 400, json).await;
         };
 
-        // Check rate limit using token bucket algorithm
-        if !rate_limiter.check_rate_limit(&client_id).await {
-            return Err(format!(
-                "Rate limit exceeded: {} requests per minute allowed",
-                rate_limiter.get_limit()
-            ));
+        match self.users().bump_token_version(&user_id).await {
+            Ok(token_version) => {
+                log::info!(
+                    "[{}] Revoked all tokens for user {} (version {})",
+                    ctx.request_id,
+                    user_id,
+                    token_version
+                );
+                let json = format!(
+                    r#"{{"user_id":"{}","token_version":{}}}"#,
+                    user_id, token_version
+                );
+                self.send_json_response(session, 200, json).await?;
+            }
+            Err(UserError::NotFound) => {
+                let json = r#"{"error":"User not found"}"#.to_string();
+                self.send_json_response(session, 404, json).await?;
+            }
+            Err(e @ (UserError::Transient(_) | UserError::Constraint(_))) => {
+                self.send_database_error(session, ctx, &e.into()).await?;
+            }
+            Err(e) => {
+                log::error!("[{}] Token revocation failed: {}", ctx.request_id, e);
+                let json = r#"{"error":"Internal server error"}"#.to_string();
+                self.send_json_response(session, 500, json).await?;
+            }
         }
 
         Ok(())
     }
 
-    /// Extract JWT token from Authorization header
-    fn extract_token_from_header(&self, req: &RequestHeader) -> Result<String> {
-        let auth_header = req
-            .headers
-            .get("Authorization")
-            .ok_or_else(|| Error::new_str("Missing Authorization header"))?
-            .to_str()
-            .map_err(|_| Error::new_str("Invalid Authorization header"))?;
+    /// Handle `GET /admin/users/{id}/export` with everything stored about the user
+    async fn handle_export_user(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+        user_id: &str,
+    ) -> Result<()> {
+        let Ok(user_id) = uuid::Uuid::parse_str(user_id) else {
+            let json = r#"{"error":"Invalid user ID"}"#.to_string();
+            return self.send_json_response(session, 400, json).await;
+        };
 
-        if !auth_header.starts_with("Bearer ") {
-            return Err(Error::new_str("Invalid Authorization format"));
+        match export_user(
+            &self.users(),
+            &TokenRepository::new(&self.db_pool),
+            &LogAudit,
+            &user_id,
+        )
+        .await
+        {
+            Ok(export) => {
+                log::warn!(
+                    "[{}] Admin {:?} exported data of user {}",
+                    ctx.request_id,
+                    ctx.user_id,
+                    user_id
+                );
+                let json = serde_json::to_string(&export).map_err(|e| {
+                    Error::because(ErrorType::InternalError, "JSON serialize error", e)
+                })?;
+                self.send_json_response(session, 200, json).await
+            }
+            Err(e) => self.send_user_data_error(session, ctx, e).await,
         }
-
-        Ok(auth_header[7..].to_string())
     }
 
-    /// Read request body
-    async fn read_request_body(&self, session: &mut Session) -> Result<Vec<u8>> {
-        use bytes::Buf;
-
-        let mut body = Vec::new();
-
-        while let Some(chunk) = session.read_request_body().await? {
-            body.extend_from_slice(chunk.chunk());
+    /// Handle `DELETE /admin/users/{id}?hard=true` by erasing the user
+    ///
+    /// Only hard deletion exists; without `hard=true` nothing is deleted.
+    async fn handle_erase_user(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+        user_id: &str,
+    ) -> Result<()> {
+        let Ok(user_id) = uuid::Uuid::parse_str(user_id) else {
+            let json = r#"{"error":"Invalid user ID"}"#.to_string();
+            return self.send_json_response(session, 400, json).await;
+        };
+        if !is_hard_delete(session.req_header()) {
+            let json = r#"{"error":"Erasing a user requires hard=true"}"#.to_string();
+            return self.send_json_response(session, 400, json).await;
         }
 
-        Ok(body)
+        match erase_user(
+            &self.users(),
+            &TokenRepository::new(&self.db_pool),
+            &LogAudit,
+            &user_id,
+        )
+        .await
+        {
+            Ok(report) => {
+                log::warn!(
+                    "[{}] Admin {:?} erased user {}",
+                    ctx.request_id,
+                    ctx.user_id,
+                    user_id
+                );
+                let json = serde_json::to_string(&report).map_err(|e| {
+                    Error::because(ErrorType::InternalError, "JSON serialize error", e)
+                })?;
+                self.send_json_response(session, 200, json).await
+            }
+            Err(e) => self.send_user_data_error(session, ctx, e).await,
+        }
     }
 
-    /// Send JSON response
-    async fn send_json_response(
+    /// Respond to a failed export or erasure: 404 for an unknown user
+    async fn send_user_data_error(
         &self,
         session: &mut Session,
-        status: u16,
-        json: String,
+        ctx: &ProxyContext,
+        e: UserDataError,
     ) -> Result<()> {
-        let mut resp = ResponseHeader::build(status, Some(4))?;
-        resp.insert_header("Content-Type", "application/json")?;
-        resp.insert_header("Content-Length", json.len().to_string())?;
+        match e {
+            UserDataError::NotFound => {
+                let json = r#"{"error":"User not found"}"#.to_string();
+                self.send_json_response(session, 404, json).await
+            }
+            UserDataError::DatabaseError(e) => self.send_database_error(session, ctx, &e).await,
+        }
+    }
 
-        session.write_response_header(Box::new(resp), false).await?;
+    /// Handle `POST /admin/users/import` with a JSON array of users
+    async fn handle_import_users(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
+        let body = self.read_request_body(session, ctx).await?;
 
-        let body = Bytes::from(json);
-        session.write_response_body(Some(body), true).await?;
+        let Ok(rows) = serde_json::from_slice::<Vec<ImportUserRequest>>(&body) else {
+            let json = r#"{"error":"Expected a JSON array of users"}"#.to_string();
+            return self.send_json_response(session, 400, json).await;
+        };
 
-        Ok(())
+        match import_users(&self.users(), rows).await {
+            Ok(response) => {
+                log::info!(
+                    "[{}] User import: {} created, {} failed",
+                    ctx.request_id,
+                    response.created,
+                    response.failed
+                );
+                let json = serde_json::to_string(&response)
+                    .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
+                self.send_json_response(session, 200, json).await
+            }
+            Err(ImportError::DatabaseError(e)) => self.send_database_error(session, ctx, &e).await,
+            Err(e) => {
+                let json = format!(r#"{{"error":"{}"}}"#, e);
+                self.send_json_response(session, 400, json).await
+            }
+        }
     }
 
-    /// Send 401 Unauthorized response
-    async fn send_unauthorized_response(&self, session: &mut Session) -> Result<()> {
-        let json = r#"{"error":"Unauthorized"}"#.to_string();
-        self.send_json_response(session, 401, json).await
+    /// Handle `POST /admin/ban`, banning a user or IP/CIDR block for `ttl_secs`
+    async fn handle_ban(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
+        let body = self.read_request_body(session, ctx).await?;
+        let Ok(request) = serde_json::from_slice::<BanRequest>(&body) else {
+            let json = r#"{"error":"Invalid JSON"}"#.to_string();
+            return self.send_json_response(session, 400, json).await;
+        };
+
+        match ban::ban(self.redis_client.as_ref(), &request, chrono::Utc::now()).await {
+            Ok(ban) => {
+                self.ban_cache.invalidate();
+                log::warn!(
+                    "[{}] Admin {:?} banned {} until {}",
+                    ctx.request_id,
+                    ctx.user_id,
+                    ban.target,
+                    ban.expires_at
+                );
+                let json = serde_json::to_string(&ban).map_err(|e| {
+                    Error::because(ErrorType::InternalError, "JSON serialize error", e)
+                })?;
+                self.send_json_response(session, 200, json).await
+            }
+            Err(e) => self.send_ban_error(session, ctx, e).await,
+        }
     }
 
-    /// Send 429 Rate Limit response
-    async fn send_rate_limit_response(&self, session: &mut Session) -> Result<()> {
-        let json = r#"{"error":"Too many requests"}"#.to_string();
-        self.send_json_response(session, 429, json).await
+    /// Handle `DELETE /admin/ban`, lifting a ban before it expires
+    async fn handle_unban(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
+        let body = self.read_request_body(session, ctx).await?;
+        let Ok(request) = serde_json::from_slice::<BanRequest>(&body) else {
+            let json = r#"{"error":"Invalid JSON"}"#.to_string();
+            return self.send_json_response(session, 400, json).await;
+        };
+
+        match ban::unban(self.redis_client.as_ref(), &request).await {
+            Ok(true) => {
+                self.ban_cache.invalidate();
+                let target = request.target().ok();
+                log::warn!(
+                    "[{}] Admin {:?} lifted ban on {:?}",
+                    ctx.request_id,
+                    ctx.user_id,
+                    target
+                );
+                let json = serde_json::json!({ "unbanned": target }).to_string();
+                self.send_json_response(session, 200, json).await
+            }
+            Ok(false) => {
+                let json = r#"{"error":"Ban not found"}"#.to_string();
+                self.send_json_response(session, 404, json).await
+            }
+            Err(e) => self.send_ban_error(session, ctx, e).await,
+        }
     }
 
-    /// Send 404 Not Found response
-    async fn send_not_found_response(&self, session: &mut Session) -> Result<()> {
+    /// Handle `GET /admin/bans`
+    async fn handle_list_bans(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
+        match ban::active_bans(self.redis_client.as_ref(), chrono::Utc::now()).await {
+            Ok(bans) => {
+                let json = serde_json::json!({ "bans": bans }).to_string();
+                self.send_json_response(session, 200, json).await
+            }
+            Err(e) => self.send_ban_error(session, ctx, e.into()).await,
+        }
+    }
+
+    /// Handle `POST /admin/upstreams/{name}/eject` and `.../restore`
+    ///
+    /// Ejection is kept in memory, so it only applies to this proxy instance.
+    async fn handle_upstream_ejection(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+        name: &str,
+        eject: bool,
+    ) -> Result<()> {
+        let mut found = false;
+        for balancer in std::iter::once(&self.load_balancer).chain(&self.auth_load_balancer) {
+            found |= if eject {
+                balancer.eject(name)
+            } else {
+                balancer.restore(name)
+            };
+        }
+
+        if !found {
+            let json = r#"{"error":"Upstream not found"}"#.to_string();
+            return self.send_json_response(session, 404, json).await;
+        }
+
+        log::warn!(
+            "[{}] Admin {:?} {} upstream {}",
+            ctx.request_id,
+            ctx.user_id,
+            if eject { "ejected" } else { "restored" },
+            name
+        );
+        let json = serde_json::json!({ "upstream": name, "ejected": eject }).to_string();
+        self.send_json_response(session, 200, json).await
+    }
+
+    /// Respond to a failed ban request: 400 for bad input, 503 if Redis is down
+    async fn send_ban_error(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+        e: BanError,
+    ) -> Result<()> {
+        if let BanError::CacheError(e) = &e {
+            log::error!("[{}] Ban store error: {}", ctx.request_id, e);
+            let json = r#"{"error":"Service unavailable"}"#.to_string();
+            return self.send_json_response(session, 503, json).await;
+        }
+        let json = format!(r#"{{"error":"{}"}}"#, e);
+        self.send_json_response(session, 400, json).await
+    }
+
+    /// Send 403 if a ban covers the client's IP or user
+    ///
+    /// # Returns
+    /// * `Result<bool>` - `true` if the request was rejected
+    async fn reject_banned(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+        bans: &[Ban],
+    ) -> Result<bool> {
+        let Some(ban) = bans
+            .iter()
+            .find(|ban| ban.applies_to(ctx.user_id, ctx.client_ip))
+        else {
+            return Ok(false);
+        };
+
+        log::warn!(
+            "[{}] Rejected banned client {:?} / {:?} ({} until {})",
+            ctx.request_id,
+            ctx.client_ip,
+            ctx.user_id,
+            ban.target,
+            ban.expires_at
+        );
+        let json = r#"{"error":"Forbidden"}"#.to_string();
+        self.send_json_response(session, 403, json).await?;
+        Ok(true)
+    }
+
+    /// What login identifiers match (`middleware.auth.usernames`)
+    fn usernames(&self) -> Usernames {
+        if self.settings.middleware.auth.usernames {
+            Usernames::Enabled
+        } else {
+            Usernames::Disabled
+        }
+    }
+
+    /// Handle user registration
+    async fn handle_register(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
+        log::info!("[{}] Handling registration", ctx.request_id);
+
+        let body = self.read_request_body(session, ctx).await?;
+
+        let parsed = schema::parse::<crate::auth::RegisterRequest>(&body);
+        let Some(request) = self.accept_request(session, ctx, parsed).await? else {
+            return Ok(());
+        };
+
+        match register_user(
+            &self.users(),
+            &TokenRepository::new(&self.db_pool),
+            &self.jwt_manager,
+            request,
+            self.usernames(),
+        )
+        .await
+        {
+            Ok(response) => {
+                let json = serde_json::to_string(&response)
+                    .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
+                let cookie = self.refresh_cookie_for(&response.refresh_token);
+                self.send_json_response_with_cookie(session, 201, json, cookie)
+                    .await?;
+            }
+            Err(RegisterError::DatabaseError(e)) => {
+                self.send_database_error(session, ctx, &e).await?;
+            }
+            Err(e) => {
+                log::error!("[{}] Registration failed: {}", ctx.request_id, e);
+                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
+                self.send_json_response(session, 400, error_msg).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle user login
+    async fn handle_login(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
+        log::info!("[{}] Handling login", ctx.request_id);
+
+        let body = self.read_request_body(session, ctx).await?;
+
+        let parsed = schema::parse::<crate::auth::LoginRequest>(&body);
+        let Some(request) = self.accept_request(session, ctx, parsed).await? else {
+            return Ok(());
+        };
+
+        let users = self.users();
+        let tokens = TokenRepository::new(&self.db_pool);
+        let stores = LoginStores::new(&users, &tokens, &LogAudit)
+            .with_read_only(self.settings.server.read_only);
+        match login_user(
+            stores,
+            &self.jwt_manager,
+            request,
+            self.usernames(),
+            ctx.client_ip,
+        )
+        .await
+        {
+            Ok(response) => {
+                let json = serde_json::to_string(&response)
+                    .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
+                let cookie = self.refresh_cookie_for(&response.refresh_token);
+                self.send_json_response_with_cookie(session, 200, json, cookie)
+                    .await?;
+            }
+            Err(LoginError::InvalidCredentials) => {
+                log::warn!("[{}] Login failed: invalid credentials", ctx.request_id);
+                let error_msg = format!(r#"{{"error":"{}"}}"#, LoginError::InvalidCredentials);
+                self.send_json_response(session, 401, error_msg).await?;
+            }
+            Err(e @ LoginError::AudienceNotAllowed) => {
+                log::warn!("[{}] Login refused: {}", ctx.request_id, e);
+                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
+                self.send_json_response(session, 403, error_msg).await?;
+            }
+            Err(LoginError::DatabaseError(e)) => {
+                self.send_database_error(session, ctx, &e).await?;
+            }
+            Err(e) => {
+                // Hashing and token failures are server-side
+                log::error!("[{}] Login failed: {}", ctx.request_id, e);
+                let error_msg = r#"{"error":"Internal server error"}"#.to_string();
+                self.send_json_response(session, 500, error_msg).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle token refresh
+    async fn handle_refresh(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
+        log::info!("[{}] Handling token refresh", ctx.request_id);
+
+        let body = self.read_request_body(session, ctx).await?;
+
+        // Cookie-mode clients may send no body at all
+        let parsed = if body.is_empty() {
+            Ok(crate::auth::RefreshRequest::default())
+        } else {
+            schema::parse(&body)
+        };
+        let Some(mut request) = self.accept_request(session, ctx, parsed).await? else {
+            return Ok(());
+        };
+
+        // A token in the body wins; otherwise fall back to the cookie
+        let cookie_config = &self.settings.jwt.refresh_cookie;
+        let cookie_mode = cookie_config.enabled && request.refresh_token.is_empty();
+        if cookie_mode {
+            let cookie_token = session
+                .req_header()
+                .headers
+                .get_all("Cookie")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .find_map(|header| get_cookie(header, &cookie_config.name));
+
+            match cookie_token {
+                Some(token) => request.refresh_token = token.to_string(),
+                None => {
+                    log::warn!("[{}] Token refresh without a refresh token", ctx.request_id);
+                    let json = r#"{"error":"Missing refresh token"}"#.to_string();
+                    self.send_json_response(session, 401, json).await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        match refresh_token(
+            &self.users(),
+            &TokenRepository::new(&self.db_pool),
+            self.redis_client.as_ref(),
+            &self.jwt_manager,
+            request,
+            &LogAudit,
+            ctx.client_ip,
+        )
+        .await
+        {
+            Ok(response) => {
+                let json = serde_json::to_string(&response)
+                    .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
+                let cookie = self.refresh_cookie_for(&response.refresh_token);
+                self.send_json_response_with_cookie(session, 200, json, cookie)
+                    .await?;
+            }
+            Err(RefreshError::DatabaseError(e)) => {
+                self.send_database_error(session, ctx, &e).await?;
+            }
+            Err(e) => {
+                log::error!("[{}] Token refresh failed: {}", ctx.request_id, e);
+                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
+                self.send_json_response(session, 401, error_msg).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle user logout
+    async fn handle_logout(&self, session: &mut Session, ctx: &ProxyContext) -> Result<()> {
+        log::info!("[{}] Handling logout", ctx.request_id);
+
+        let access_token = extract_token_from_header(session.req_header())?;
+
+        let body = self.read_request_body(session, ctx).await?;
+
+        // Clients without a refresh token may send no body at all
+        let parsed = crate::auth::LogoutRequest::from_body(&body);
+        let Some(request) = self.accept_request(session, ctx, parsed).await? else {
+            return Ok(());
+        };
+
+        match logout_user(
+            &TokenRepository::new(&self.db_pool),
+            self.redis_client.as_ref(),
+            &self.jwt_manager,
+            &access_token,
+            request,
+            self.settings.server.read_only,
+        )
+        .await
+        {
+            Ok(()) => {
+                let json = r#"{"message":"Logged out successfully"}"#.to_string();
+                self.send_json_response(session, 200, json).await?;
+            }
+            Err(LogoutError::DatabaseError(e)) => {
+                self.send_database_error(session, ctx, &e).await?;
+            }
+            Err(e) => {
+                log::error!("[{}] Logout failed: {}", ctx.request_id, e);
+                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
+                self.send_json_response(session, 400, error_msg).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle logout from every device
+    ///
+    /// Needs a valid, unrevoked access token; it is blacklisted along with
+    /// revoking all of the user's refresh tokens.
+    async fn handle_logout_all(&self, session: &mut Session, ctx: &mut ProxyContext) -> Result<()> {
+        log::info!("[{}] Handling logout from all devices", ctx.request_id);
+
+        let user_id = match verify_jwt(
+            session.req_header(),
+            None,
+            &self.jwt_middleware,
+            self.redis_client.as_ref(),
+            &self.users(),
+        )
+        .await
+        {
+            Ok(user_id) => user_id,
+            Err(e) => {
+                log::warn!("[{}] Logout-all unauthorized: {}", ctx.request_id, e);
+                return self.send_auth_error(session, &e).await;
+            }
+        };
+        ctx.set_user_id(user_id);
+
+        let access_token = extract_token_from_header(session.req_header())?;
+
+        match logout_all_devices(
+            &TokenRepository::new(&self.db_pool),
+            self.redis_client.as_ref(),
+            &self.jwt_manager,
+            &access_token,
+        )
+        .await
+        {
+            Ok(revoked) => {
+                let json = serde_json::to_string(&LogoutAllResponse { revoked }).map_err(|e| {
+                    Error::because(ErrorType::InternalError, "JSON serialize error", e)
+                })?;
+                self.send_json_response(session, 200, json).await?;
+            }
+            Err(LogoutError::DatabaseError(e)) => {
+                self.send_database_error(session, ctx, &e).await?;
+            }
+            Err(e) => {
+                log::error!("[{}] Logout-all failed: {}", ctx.request_id, e);
+                let json = r#"{"error":"Internal server error"}"#.to_string();
+                self.send_json_response(session, 500, json).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle password change for the user of the access token
+    async fn handle_change_password(
+        &self,
+        session: &mut Session,
+        ctx: &mut ProxyContext,
+    ) -> Result<()> {
+        log::info!("[{}] Handling password change", ctx.request_id);
+
+        let users = self.users();
+        let user_id = match verify_jwt(
+            session.req_header(),
+            None,
+            &self.jwt_middleware,
+            self.redis_client.as_ref(),
+            &users,
+        )
+        .await
+        {
+            Ok(user_id) => user_id,
+            Err(e) => {
+                log::warn!("[{}] Password change unauthorized: {}", ctx.request_id, e);
+                return self.send_auth_error(session, &e).await;
+            }
+        };
+        ctx.set_user_id(user_id);
+
+        let body = self.read_request_body(session, ctx).await?;
+
+        let parsed = schema::parse::<crate::auth::ChangePasswordRequest>(&body);
+        let Some(request) = self.accept_request(session, ctx, parsed).await? else {
+            return Ok(());
+        };
+
+        match change_password(
+            &users,
+            &TokenRepository::new(&self.db_pool),
+            &self.jwt_manager,
+            &user_id,
+            request,
+        )
+        .await
+        {
+            Ok(response) => {
+                let json = serde_json::to_string(&response)
+                    .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
+                self.send_json_response(session, 200, json).await?;
+            }
+            Err(e @ ChangePasswordError::InvalidCredentials) => {
+                log::warn!("[{}] Password change failed: {}", ctx.request_id, e);
+                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
+                self.send_json_response(session, 401, error_msg).await?;
+            }
+            Err(e @ ChangePasswordError::PasswordValidationFailed(_)) => {
+                let error_msg = format!(r#"{{"error":"{}"}}"#, e);
+                self.send_json_response(session, 400, error_msg).await?;
+            }
+            Err(ChangePasswordError::DatabaseError(e)) => {
+                self.send_database_error(session, ctx, &e).await?;
+            }
+            Err(e) => {
+                log::error!("[{}] Password change failed: {}", ctx.request_id, e);
+                let json = r#"{"error":"Internal server error"}"#.to_string();
+                self.send_json_response(session, 500, json).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Authenticate request using JWT middleware
+    async fn authenticate_request(
+        &self,
+        req: &RequestHeader,
+        auth_type: RouteAuthType,
+        audience: Option<&str>,
+    ) -> std::result::Result<Option<uuid::Uuid>, AuthError> {
+        authenticate(
+            auth_type,
+            audience,
+            req,
+            &self.jwt_middleware,
+            &self.auth_middleware,
+            self.redis_client.as_ref(),
+            &self.users(),
+        )
+        .await
+    }
+
+    /// Handle a dry-run password policy check
+    ///
+    /// Unlike the other auth endpoints this one is rate limited: it needs
+    /// no credentials, so nothing else stops it being hammered.
+    async fn handle_validate_password(
+        &self,
+        session: &mut Session,
+        ctx: &mut ProxyContext,
+    ) -> Result<()> {
+        if self
+            .enforce_rate_limit(session, ctx, None, "/auth/validate-password", "POST")
+            .await?
+        {
+            return Ok(());
+        }
+
+        let body = self.read_request_body(session, ctx).await?;
+
+        let parsed = schema::parse::<ValidatePasswordRequest>(&body);
+        let Some(request) = self.accept_request(session, ctx, parsed).await? else {
+            return Ok(());
+        };
+
+        let json = serde_json::to_string(&validate_password(request))
+            .map_err(|e| Error::because(ErrorType::InternalError, "JSON serialize error", e))?;
+        self.send_json_response(session, 200, json).await
+    }
+
+    /// Apply the client's rate limit with the configured backend, answering
+    /// 429 when it is exceeded
+    ///
+    /// # Arguments
+    /// * `path` - Request path, whose route may opt out of rate limiting;
+    ///   `None` always counts the request
+    ///
+    /// # Returns
+    /// * `Result<bool>` - Whether the request was rejected (and answered)
+    async fn enforce_rate_limit(
+        &self,
+        session: &mut Session,
+        ctx: &mut ProxyContext,
+        path: Option<&str>,
+        route: &str,
+        method: &str,
+    ) -> Result<bool> {
+        if let Some(rate_limiters) = &self.rate_limit_middleware {
+            let config = &self.settings.middleware.rate_limit;
+            match check_rate_limit(
+                &self.router,
+                path,
+                ctx,
+                rate_limiters,
+                config,
+                route,
+                method,
+            )
+            .await
+            {
+                Ok(warning) => ctx.rate_limit_warning = warning,
+                Err(e) => {
+                    log::warn!("[{}] Rate limit exceeded: {}", ctx.request_id, e);
+                    self.send_rate_limit_response(session, &[]).await?;
+                    return Ok(true);
+                }
+            }
+        }
+
+        let counted = path.is_none_or(|path| self.router.rate_limited(path));
+        if let Some(external) = self.external_rate_limiter.as_ref().filter(|_| counted) {
+            let (client_type, client_id) = rate_limit_client(ctx);
+            match external.check(&client_id, route, method).await {
+                ExternalDecision::Allow(headers) => ctx.rate_limit_headers = headers,
+                ExternalDecision::Deny(headers) => {
+                    metrics::record_rate_limit_rejection(route, client_type);
+                    log::warn!(
+                        "[{}] Rate limit exceeded: denied by rate-limit service",
+                        ctx.request_id
+                    );
+                    self.send_rate_limit_response(session, &headers).await?;
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Read request body
+    async fn read_request_body(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+    ) -> Result<Vec<u8>> {
+        use bytes::Buf;
+
+        // The client is holding its body until we answer the expectation
+        if ctx.expect_continue {
+            self.send_continue(session).await?;
+        }
+
+        let max_body_size = self.settings.server.max_body_size;
+        let mut body = Vec::new();
+
+        loop {
+            let chunk = match session.read_request_body().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => return Ok(body),
+                Err(e) => return Err(body_read_failed(ctx, e)),
+            };
+            if body.len() + chunk.len() > max_body_size {
+                let e = Error::explain(ErrorType::HTTPStatus(413), "Request body too large");
+                return Err(body_read_failed(ctx, e));
+            }
+            body.extend_from_slice(chunk.chunk());
+        }
+    }
+
+    /// Send the `100 Continue` interim response
+    async fn send_continue(&self, session: &mut Session) -> Result<()> {
+        let resp = ResponseHeader::build(100, Some(0))?;
+        session.write_response_header(Box::new(resp), false).await
+    }
+
+    /// Send JSON response
+    async fn send_json_response(
+        &self,
+        session: &mut Session,
+        status: u16,
+        json: String,
+    ) -> Result<()> {
+        self.send_json_response_with_cookie(session, status, json, None)
+            .await
+    }
+
+    /// Send JSON response, optionally setting a cookie
+    async fn send_json_response_with_cookie(
+        &self,
+        session: &mut Session,
+        status: u16,
+        json: String,
+        cookie: Option<String>,
+    ) -> Result<()> {
+        let path = session.req_header().uri.path().to_string();
+        let json = format_json(json, self.settings.debug.pretty_json);
+        let mut resp = build_handler_response_header(&path, status, json.len(), cookie)?;
+        self.add_default_headers(session, &path, &mut resp);
+
+        session.write_response_header(Box::new(resp), false).await?;
+
+        let body = Bytes::from(json);
+        session.write_response_body(Some(body), true).await?;
+
+        Ok(())
+    }
+
+    /// `Set-Cookie` value for a refresh token, if `jwt.refresh_cookie` is
+    /// enabled and a token was issued
+    fn refresh_cookie_for(&self, token: &str) -> Option<String> {
+        let config = &self.settings.jwt.refresh_cookie;
+        (config.enabled && !token.is_empty())
+            .then(|| refresh_cookie(config, token, self.jwt_manager.refresh_token_expiration()))
+    }
+
+    /// Answer a database failure: 503 if transient, 409 for a constraint
+    /// violation, 500 otherwise. Details are logged, never sent.
+    async fn send_database_error(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+        e: &DbFailure,
+    ) -> Result<()> {
+        let status = e.kind.status();
+        log::error!("[{}] Database error ({}): {}", ctx.request_id, status, e);
+
+        let json = match e.kind {
+            DbErrorKind::Transient => r#"{"error":"Service temporarily unavailable"}"#,
+            DbErrorKind::Constraint => r#"{"error":"Conflict"}"#,
+            DbErrorKind::Other => r#"{"error":"Internal server error"}"#,
+        };
+        self.send_json_response(session, status, json.to_string())
+            .await
+    }
+
+    /// Answer a duplicate `Idempotency-Key` request with the recorded response
+    async fn send_replayed_response(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+        response: &CachedResponse,
+    ) -> Result<()> {
+        let mut resp = response.to_header()?;
+        request_id::tag_response(&mut resp, &self.settings.request_id, &ctx.request_id)?;
+        let path = session.req_header().uri.path().to_string();
+        self.add_default_headers(session, &path, &mut resp);
+
+        session.write_response_header(Box::new(resp), false).await?;
+        session
+            .write_response_body(Some(Bytes::from(response.body())), true)
+            .await
+    }
+
+    /// Answer with a response recorded from another request, marked with
+    /// `marker: true` (a route's fallback or a coalesced fetch)
+    async fn send_recorded_response(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+        response: &CachedResponse,
+        marker: &str,
+    ) -> Result<()> {
+        let mut resp = response.to_header_marked(marker)?;
+        request_id::tag_response(&mut resp, &self.settings.request_id, &ctx.request_id)?;
+        let path = session.req_header().uri.path().to_string();
+        self.add_default_headers(session, &path, &mut resp);
+
+        session.write_response_header(Box::new(resp), false).await?;
+        session
+            .write_response_body(Some(Bytes::from(response.body())), true)
+            .await
+    }
+
+    /// Send the Prometheus metrics of this process
+    async fn send_metrics_response(&self, session: &mut Session) -> Result<()> {
+        let body = prometheus::TextEncoder::new()
+            .encode_to_string(&prometheus::gather())
+            .map_err(|e| Error::because(ErrorType::InternalError, "Metrics encode error", e))?;
+
+        let mut resp = ResponseHeader::build(200, Some(2))?;
+        resp.insert_header("Content-Type", prometheus::TEXT_FORMAT)?;
+        resp.insert_header("Content-Length", body.len().to_string())?;
+        self.add_default_headers(session, "/metrics", &mut resp);
+
+        session.write_response_header(Box::new(resp), false).await?;
+        session
+            .write_response_body(Some(Bytes::from(body)), true)
+            .await
+    }
+
+    /// Send an empty-bodied error response, as Pingora's `respond_error` does
+    async fn send_error_response(
+        &self,
+        session: &mut Session,
+        path: &str,
+        status: u16,
+    ) -> Result<()> {
+        let mut resp = ResponseHeader::build(status, Some(4))?;
+        resp.insert_header("Cache-Control", "private, no-store")?;
+        resp.insert_header("Content-Length", "0")?;
+        self.add_default_headers(session, path, &mut resp);
+
+        session.write_response_header(Box::new(resp), false).await?;
+        session.write_response_body(None, true).await
+    }
+
+    /// Add `response.default_headers`, with the route's overrides for `path`
+    fn add_default_headers(&self, session: &Session, path: &str, resp: &mut ResponseHeader) {
+        let tls = session
+            .digest()
+            .is_some_and(|digest| digest.ssl_digest.is_some());
+        self.default_headers
+            .apply(resp, self.router.response_headers(path), tls);
+    }
+
+    /// Send 401 Unauthorized response
+    async fn send_unauthorized_response(&self, session: &mut Session) -> Result<()> {
+        self.send_json_response(session, 401, unauthorized_json(None))
+            .await
+    }
+
+    /// Send 401 for a failed authentication, describing it when that helps
+    /// the client fix its request
+    ///
+    /// A recently expired token also gets a `WWW-Authenticate` challenge
+    /// naming the expiry, the hint for the client to refresh and retry.
+    /// A store outage gets 503 instead, as the token was not judged.
+    async fn send_auth_error(&self, session: &mut Session, e: &AuthError) -> Result<()> {
+        if let AuthError::Unavailable(_) = e {
+            let json = r#"{"error":"Service temporarily unavailable"}"#.to_string();
+            return self.send_json_response(session, 503, json).await;
+        }
+        let Some(challenge) = e.challenge() else {
+            return self
+                .send_json_response(session, 401, unauthorized_json(Some(e)))
+                .await;
+        };
+
+        let json = format_json(unauthorized_json(Some(e)), self.settings.debug.pretty_json);
+        let path = session.req_header().uri.path().to_string();
+        let mut resp = build_json_response_header(401, json.len(), path.starts_with("/auth/"))?;
+        resp.insert_header("WWW-Authenticate", challenge)?;
+        self.add_default_headers(session, &path, &mut resp);
+
+        session.write_response_header(Box::new(resp), false).await?;
+        session
+            .write_response_body(Some(Bytes::from(json)), true)
+            .await
+    }
+
+    /// Take a parsed auth request body, answering for a rejected one
+    ///
+    /// Invalid JSON gets 400; fields breaking the payload's schema get 422
+    /// with one entry per field.
+    ///
+    /// # Returns
+    /// * `Ok(Some(T))` - The request, to be handled
+    /// * `Ok(None)` - The body was rejected and the response sent
+    async fn accept_request<T>(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+        parsed: std::result::Result<T, SchemaError>,
+    ) -> Result<Option<T>> {
+        match parsed {
+            Ok(request) => Ok(Some(request)),
+            Err(SchemaError::InvalidJson(e)) => {
+                log::debug!("[{}] Rejected request body: {}", ctx.request_id, e);
+                let json = r#"{"error":"Invalid JSON"}"#.to_string();
+                self.send_json_response(session, 400, json).await?;
+                Ok(None)
+            }
+            Err(SchemaError::Invalid(fields)) => {
+                log::debug!("[{}] Rejected request fields: {:?}", ctx.request_id, fields);
+                let json =
+                    serde_json::to_string(&ValidationErrorResponse::new(fields)).map_err(|e| {
+                        Error::because(ErrorType::InternalError, "JSON serialize error", e)
+                    })?;
+                self.send_json_response(session, 422, json).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Send 503 for a request that would write to the database in read-only mode
+    async fn send_read_only_response(
+        &self,
+        session: &mut Session,
+        ctx: &ProxyContext,
+    ) -> Result<()> {
+        log::info!(
+            "[{}] Rejected {} in read-only mode",
+            ctx.request_id,
+            session.req_header().uri.path()
+        );
+        let json = r#"{"error":"Service is in read-only mode"}"#.to_string();
+        self.send_json_response(session, 503, json).await
+    }
+
+    /// Send 429 Rate Limit response
+    ///
+    /// # Arguments
+    /// * `headers` - Extra headers, e.g. `Retry-After` from a rate-limit service
+    async fn send_rate_limit_response(
+        &self,
+        session: &mut Session,
+        headers: &[(String, String)],
+    ) -> Result<()> {
+        let json = format_json(
+            r#"{"error":"Too many requests"}"#.to_string(),
+            self.settings.debug.pretty_json,
+        );
+        let path = session.req_header().uri.path().to_string();
+        let mut resp = build_json_response_header(429, json.len(), path.starts_with("/auth/"))?;
+        for (name, value) in headers {
+            resp.insert_header(name.clone(), value.as_str())?;
+        }
+        self.add_default_headers(session, &path, &mut resp);
+
+        session.write_response_header(Box::new(resp), false).await?;
+        session
+            .write_response_body(Some(Bytes::from(json)), true)
+            .await
+    }
+
+    /// Send 404 Not Found response
+    async fn send_not_found_response(&self, session: &mut Session) -> Result<()> {
         let json = r#"{"error":"Not found"}"#.to_string();
         self.send_json_response(session, 404, json).await
     }
-}
\ No newline at end of file
+
+    /// Send 405 for a known path requested with the wrong method
+    async fn send_method_not_allowed_response(
+        &self,
+        session: &mut Session,
+        allow: &str,
+    ) -> Result<()> {
+        let json = format_json(
+            r#"{"error":"Method not allowed"}"#.to_string(),
+            self.settings.debug.pretty_json,
+        );
+        let mut resp = build_method_not_allowed_header(allow, json.len())?;
+        let path = session.req_header().uri.path().to_string();
+        self.add_default_headers(session, &path, &mut resp);
+        session.write_response_header(Box::new(resp), false).await?;
+        session
+            .write_response_body(Some(Bytes::from(json)), true)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Indent a JSON body in `debug.pretty_json` mode
+///
+/// Runs before `Content-Length` is computed. A body that does not parse
+/// is sent unchanged.
+fn format_json(json: String, pretty: bool) -> String {
+    if !pretty {
+        return json;
+    }
+    serde_json::from_str::<serde_json::Value>(&json)
+        .ok()
+        .and_then(|value| serde_json::to_string_pretty(&value).ok())
+        .unwrap_or(json)
+}
+
+/// Build the response header for a JSON body
+///
+/// `no_store` adds `Cache-Control: no-store` and `Pragma: no-cache` so that
+/// intermediaries never cache the response.
+fn build_json_response_header(
+    status: u16,
+    content_length: usize,
+    no_store: bool,
+) -> Result<ResponseHeader> {
+    let mut resp = ResponseHeader::build(status, Some(6))?;
+    resp.insert_header("Content-Type", "application/json")?;
+    resp.insert_header("Content-Length", content_length.to_string())?;
+
+    if no_store {
+        forbid_caching(&mut resp)?;
+    }
+
+    Ok(resp)
+}
+
+/// Build the response header for a JSON body a handler sends on `path`
+///
+/// Auth responses carry tokens and must never be cached, so `/auth/*`
+/// responses are built `no_store`.
+fn build_handler_response_header(
+    path: &str,
+    status: u16,
+    content_length: usize,
+    cookie: Option<String>,
+) -> Result<ResponseHeader> {
+    let no_store = path.starts_with("/auth/");
+    let mut resp = build_json_response_header(status, content_length, no_store)?;
+    if let Some(cookie) = cookie {
+        resp.insert_header("Set-Cookie", cookie)?;
+    }
+    Ok(resp)
+}
+
+/// Mark a response `Cache-Control: no-store` and `Pragma: no-cache`,
+/// replacing whatever caching it allowed
+fn forbid_caching(resp: &mut ResponseHeader) -> Result<()> {
+    resp.insert_header("Cache-Control", "no-store")?;
+    resp.insert_header("Pragma", "no-cache")?;
+    Ok(())
+}
+
+/// 405 response header listing the methods a path accepts
+fn build_method_not_allowed_header(allow: &str, content_length: usize) -> Result<ResponseHeader> {
+    let mut resp = build_json_response_header(405, content_length, true)?;
+    resp.insert_header("Allow", allow)?;
+    Ok(resp)
+}
+
+/// Built-in auth endpoints and the method each accepts
+const AUTH_ENDPOINTS: &[(&str, &str)] = &[
+    ("/auth/register", "POST"),
+    ("/auth/login", "POST"),
+    ("/auth/refresh", "POST"),
+    ("/auth/logout", "POST"),
+    ("/auth/logout-all", "POST"),
+    ("/auth/change-password", "POST"),
+    ("/auth/validate-password", "POST"),
+    ("/auth/openapi.json", "GET"),
+];
+
+/// `Allow` value for a built-in auth endpoint, or `None` if `path` is not one
+///
+/// `/auth/openapi.json` only exists with `server.openapi`.
+fn auth_allowed_methods(path: &str, openapi: bool) -> Option<String> {
+    let methods: Vec<&str> = AUTH_ENDPOINTS
+        .iter()
+        .filter(|(endpoint, _)| *endpoint == path)
+        .filter(|(endpoint, _)| openapi || *endpoint != "/auth/openapi.json")
+        .map(|(_, method)| *method)
+        .collect();
+
+    (!methods.is_empty()).then(|| methods.join(", "))
+}
+
+/// Whether a built-in auth or admin endpoint has to write to the database
+///
+/// Login and logout are not listed: in read-only mode they skip their
+/// writes (see [`LoginStores::with_read_only`] and [`logout_user`]).
+fn writes_database(method: &str, path: &str) -> bool {
+    match (method, path) {
+        (
+            "POST",
+            "/auth/register"
+            | "/auth/refresh"
+            | "/auth/logout-all"
+            | "/auth/change-password"
+            | "/admin/users/import",
+        ) => true,
+        ("POST", path) => revoke_tokens_target(path).is_some(),
+        ("DELETE", path) => user_target(path).is_some(),
+        _ => false,
+    }
+}
+
+/// Who answers an `/auth/*` request
+#[derive(Debug, PartialEq, Eq)]
+enum AuthTarget {
+    /// The built-in handlers
+    Local,
+    /// The auth service group
+    Upstream,
+}
+
+/// Target for `path`, or `None` if it is not an auth endpoint
+fn auth_endpoint_target(auth_upstream: bool, path: &str) -> Option<AuthTarget> {
+    if !path.starts_with("/auth/") {
+        None
+    } else if auth_upstream {
+        Some(AuthTarget::Upstream)
+    } else {
+        Some(AuthTarget::Local)
+    }
+}
+
+/// Load balancer to pick this request's upstream from
+fn balancer_for<'a>(
+    ctx: &ProxyContext,
+    primary: &'a LoadBalancerManager,
+    auth: Option<&'a LoadBalancerManager>,
+) -> &'a LoadBalancerManager {
+    match auth {
+        Some(auth) if ctx.auth_upstream => auth,
+        _ => primary,
+    }
+}
+
+/// Extract JWT token from Authorization header
+fn extract_token_from_header(req: &RequestHeader) -> Result<String> {
+    let auth_header = req
+        .headers
+        .get("Authorization")
+        .ok_or_else(|| Error::new_str("Missing Authorization header"))?
+        .to_str()
+        .map_err(|_| Error::new_str("Invalid Authorization header"))?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(Error::new_str("Invalid Authorization format"));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+/// Why a request failed authentication
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum AuthError {
+    /// A valid JWT of another type (e.g. a refresh token) sent as the bearer
+    WrongTokenType,
+    /// A valid access token issued for another app than the route's `audience`
+    WrongAudience,
+    /// A genuine access token that expired within `jwt.expired_token_grace`
+    TokenExpired,
+    /// A bearer token that is forged, malformed or long expired
+    InvalidToken,
+    /// Any other failure; the reason is logged, not sent
+    Rejected(String),
+    /// The token blacklist or user store could not be asked; answered
+    /// with 503, since the token itself may be fine
+    Unavailable(String),
+}
+
+impl AuthError {
+    /// `error_description` for the 401 body, for failures the client can fix
+    fn description(&self) -> Option<&'static str> {
+        match self {
+            AuthError::WrongTokenType => Some("wrong token type: an access token is required"),
+            AuthError::WrongAudience => Some("the access token was not issued for this app"),
+            AuthError::TokenExpired => Some("the access token has expired; refresh it and retry"),
+            AuthError::InvalidToken | AuthError::Rejected(_) | AuthError::Unavailable(_) => None,
+        }
+    }
+
+    /// Machine-readable `code` for the 401 body
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            AuthError::TokenExpired => Some("token-expired"),
+            AuthError::InvalidToken => Some("invalid-token"),
+            AuthError::WrongTokenType
+            | AuthError::WrongAudience
+            | AuthError::Rejected(_)
+            | AuthError::Unavailable(_) => None,
+        }
+    }
+
+    /// `WWW-Authenticate` challenge telling the client how to recover
+    fn challenge(&self) -> Option<&'static str> {
+        match self {
+            AuthError::TokenExpired => Some(
+                r#"Bearer error="invalid_token", error_description="The access token expired""#,
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::WrongTokenType => f.write_str("Wrong token type"),
+            AuthError::WrongAudience => f.write_str("Token issued for another audience"),
+            AuthError::TokenExpired => f.write_str("Access token expired"),
+            AuthError::InvalidToken => f.write_str("Invalid token"),
+            AuthError::Rejected(reason) | AuthError::Unavailable(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl From<String> for AuthError {
+    fn from(reason: String) -> Self {
+        AuthError::Rejected(reason)
+    }
+}
+
+/// JSON body of a 401 response
+///
+/// Only failures with a description or code say more than
+/// `{"error":"Unauthorized"}`.
+fn unauthorized_json(error: Option<&AuthError>) -> String {
+    let mut body = serde_json::json!({ "error": "Unauthorized" });
+    if let Some(code) = error.and_then(AuthError::code) {
+        body["code"] = code.into();
+    }
+    if let Some(description) = error.and_then(AuthError::description) {
+        body["error_description"] = description.into();
+    }
+    body.to_string()
+}
+
+/// Verify the credential a route requires
+///
+/// # Returns
+/// * `Ok(Some(user_id))` for a valid JWT, `Ok(None)` for public routes and API keys
+async fn authenticate(
+    auth_type: RouteAuthType,
+    audience: Option<&str>,
+    req: &RequestHeader,
+    jwt_middleware: &JwtMiddleware,
+    auth_middleware: &AuthMiddleware,
+    blacklist: &dyn TokenBlacklist,
+    users: &dyn UserStore,
+) -> std::result::Result<Option<uuid::Uuid>, AuthError> {
+    match auth_type {
+        RouteAuthType::None => Ok(None),
+        RouteAuthType::ApiKey => auth_middleware
+            .verify_request(req)
+            .map(|()| None)
+            .map_err(AuthError::from),
+        RouteAuthType::Jwt => verify_bearer(req, audience, jwt_middleware, blacklist, users).await,
+        RouteAuthType::Any => {
+            if auth_middleware.verify_request(req).is_ok() {
+                return Ok(None);
+            }
+            verify_bearer(req, audience, jwt_middleware, blacklist, users).await
+        }
+    }
+}
+
+/// Verify a Bearer token issued by the proxy or, failing that, by the
+/// external IdP (`jwt.idp`)
+///
+/// IdP users have no id here, so like API keys they authenticate without
+/// one. Routes with an `audience` only take the proxy's own tokens.
+async fn verify_bearer(
+    req: &RequestHeader,
+    audience: Option<&str>,
+    jwt_middleware: &JwtMiddleware,
+    blacklist: &dyn TokenBlacklist,
+    users: &dyn UserStore,
+) -> std::result::Result<Option<uuid::Uuid>, AuthError> {
+    let result = verify_jwt(req, audience, jwt_middleware, blacklist, users).await;
+    if matches!(result, Err(AuthError::InvalidToken))
+        && audience.is_none()
+        && jwt_middleware.verify_idp(req).await.is_some()
+    {
+        return Ok(None);
+    }
+    result.map(Some)
+}
+
+/// Verify a Bearer access token and check it has not been revoked
+///
+/// With an `audience`, the token must have been issued for it.
+async fn verify_jwt(
+    req: &RequestHeader,
+    audience: Option<&str>,
+    jwt_middleware: &JwtMiddleware,
+    blacklist: &dyn TokenBlacklist,
+    users: &dyn UserStore,
+) -> std::result::Result<uuid::Uuid, AuthError> {
+    // Use JWT middleware to verify token
+    let claims = jwt_middleware.check_claims(req).map_err(|e| match e {
+        TokenRejection::WrongType(_) => AuthError::WrongTokenType,
+        TokenRejection::Expired => AuthError::TokenExpired,
+        TokenRejection::Invalid => AuthError::InvalidToken,
+        TokenRejection::Missing => AuthError::Rejected("Missing token".to_string()),
+    })?;
+
+    if let Some(audience) = audience.filter(|audience| claims.aud.as_deref() != Some(*audience)) {
+        log::warn!(
+            "Token for audience {:?} rejected on a route for {}",
+            claims.aud,
+            audience
+        );
+        return Err(AuthError::WrongAudience);
+    }
+
+    // Extract token for blacklist check
+    let token =
+        extract_token_from_header(req).map_err(|e| format!("Token extraction failed: {}", e))?;
+
+    // Check if token is blacklisted (additional security layer)
+    let is_blacklisted = blacklist
+        .is_token_blacklisted(&token)
+        .await
+        .map_err(|e| AuthError::Unavailable(format!("Redis error: {}", e)))?;
+
+    if is_blacklisted {
+        return Err(AuthError::Rejected("Token has been revoked".to_string()));
+    }
+
+    // Parse user ID
+    let user_id =
+        uuid::Uuid::parse_str(&claims.sub).map_err(|_| "Invalid user ID in token".to_string())?;
+
+    // Tokens issued before the user's version was bumped are revoked
+    let token_version = users.token_version(&user_id).await.map_err(|e| match e {
+        UserError::NotFound => AuthError::Rejected("Unknown user".to_string()),
+        e => AuthError::Unavailable(format!("Database error: {}", e)),
+    })?;
+
+    if claims.token_version != token_version {
+        return Err(AuthError::Rejected("Token has been revoked".to_string()));
+    }
+
+    Ok(user_id)
+}
+
+/// Require a JWT belonging to a user with the admin role
+///
+/// # Returns
+/// * `Ok(user_id)` for an admin, `Err((401, reason))` without a valid token,
+///   `Err((403, reason))` for a valid token of a non-admin user,
+///   `Err((503, reason))` if a store could not be asked
+async fn authorize_admin(
+    req: &RequestHeader,
+    jwt_middleware: &JwtMiddleware,
+    blacklist: &dyn TokenBlacklist,
+    users: &dyn UserStore,
+) -> std::result::Result<uuid::Uuid, (u16, String)> {
+    let user_id = verify_jwt(req, None, jwt_middleware, blacklist, users)
+        .await
+        .map_err(|e| match e {
+            AuthError::Unavailable(_) => (503, e.to_string()),
+            e => (401, e.to_string()),
+        })?;
+
+    let user = users.find_by_id(&user_id).await.map_err(|e| match e {
+        UserError::NotFound => (401, format!("User lookup failed: {}", e)),
+        e => (503, format!("User lookup failed: {}", e)),
+    })?;
+
+    if !user.is_admin() {
+        return Err((403, format!("User {} is not an admin", user_id)));
+    }
+
+    Ok(user_id)
+}
+
+/// User ID segment of a `/admin/users/{id}/revoke-tokens` path
+fn revoke_tokens_target(path: &str) -> Option<&str> {
+    path.strip_prefix("/admin/users/")?
+        .strip_suffix("/revoke-tokens")
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+}
+
+/// User ID segment of a `/admin/users/{id}/export` path
+fn user_export_target(path: &str) -> Option<&str> {
+    path.strip_prefix("/admin/users/")?
+        .strip_suffix("/export")
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+}
+
+/// User ID segment of a `/admin/users/{id}` path
+fn user_target(path: &str) -> Option<&str> {
+    path.strip_prefix("/admin/users/")
+        .filter(|id| !id.is_empty() && !id.contains('/') && *id != "import")
+}
+
+/// Upstream name of a `/admin/upstreams/{name}/eject` or `.../restore`
+/// path, and whether it is to be ejected
+fn upstream_ejection_target(path: &str) -> Option<(&str, bool)> {
+    let (name, action) = path.strip_prefix("/admin/upstreams/")?.split_once('/')?;
+    let eject = match action {
+        "eject" => true,
+        "restore" => false,
+        _ => return None,
+    };
+    (!name.is_empty()).then_some((name, eject))
+}
+
+/// Whether an erasure asked for hard deletion (`?hard=true`)
+fn is_hard_delete(req: &RequestHeader) -> bool {
+    req.uri
+        .query()
+        .is_some_and(|query| query.split('&').any(|pair| pair == "hard=true"))
+}
+
+/// Status of the error response for a failed request (0: send none)
+///
+/// Matches Pingora's default `fail_to_proxy`: an explicit HTTP status, 502
+/// for upstream failures, nothing when the client connection broke, 400 for
+/// other client errors and 500 otherwise.
+fn error_status(e: &Error) -> u16 {
+    match e.etype() {
+        ErrorType::HTTPStatus(code) => *code,
+        _ => match e.esource() {
+            ErrorSource::Upstream => 502,
+            ErrorSource::Downstream => match e.etype() {
+                ErrorType::WriteError | ErrorType::ReadError | ErrorType::ConnectionClosed => 0,
+                _ => 400,
+            },
+            ErrorSource::Internal | ErrorSource::Unset => 500,
+        },
+    }
+}
+
+/// Status and body of a `/health` or `/ready` probe, `None` for other paths
+///
+/// `/health` does not look at readiness, so it keeps answering 200 while
+/// `/ready` fails during a drain.
+///
+/// # Arguments
+/// * `req` - The request, for `/health?detailed=true`
+/// * `path` - Request path
+/// * `readiness` - Flag `/ready` reports
+/// * `config_fingerprint` - Reported by detailed `/health`
+/// * `redis_healthy` - Redis state, only asked for by detailed `/health`
+fn probe_response(
+    req: &RequestHeader,
+    path: &str,
+    readiness: &Readiness,
+    config_fingerprint: &str,
+    redis_healthy: impl FnOnce() -> bool,
+) -> Option<(u16, String)> {
+    match path {
+        "/health" if is_detailed_health(req) => {
+            let json = serde_json::json!({
+                "status": "ok",
+                "service": "pingora-proxy",
+                "config_fingerprint": config_fingerprint,
+                "redis": if redis_healthy() { "up" } else { "down" },
+            });
+            Some((200, json.to_string()))
+        }
+        "/health" => Some((
+            200,
+            r#"{"status":"ok","service":"pingora-proxy"}"#.to_string(),
+        )),
+        // Readiness for the load balancer: fails while draining for shutdown
+        "/ready" => {
+            let (status, json) = ready_response(readiness);
+            Some((status, json.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Status and body of `GET /ready`
+fn ready_response(readiness: &Readiness) -> (u16, &'static str) {
+    if readiness.is_ready() {
+        (200, r#"{"status":"ready"}"#)
+    } else {
+        (503, r#"{"status":"draining"}"#)
+    }
+}
+
+/// Whether `/health` was asked for details (`?detailed=true`)
+fn is_detailed_health(req: &RequestHeader) -> bool {
+    req.uri
+        .query()
+        .is_some_and(|query| query.split('&').any(|pair| pair == "detailed=true"))
+}
+
+/// Classify, count and log a failed body read; the returned error carries
+/// 499, 400 or 413 instead of surfacing as a 500
+fn body_read_failed(ctx: &ProxyContext, e: Box<Error>) -> Box<Error> {
+    let (class, error) = guards::BodyReadError::record(e);
+    if class == guards::BodyReadError::ClientAbort {
+        log::debug!(
+            "[{}] Client aborted request body: {}",
+            ctx.request_id,
+            error
+        );
+    } else {
+        log::warn!("[{}] Unreadable request body: {}", ctx.request_id, error);
+    }
+    error
+}
+
+/// Whether an error means the downstream client closed the connection
+fn is_client_disconnect(e: &Error) -> bool {
+    matches!(e.esource(), ErrorSource::Downstream)
+        && matches!(
+            e.etype(),
+            ErrorType::ConnectionClosed
+                | ErrorType::ReadError
+                | ErrorType::WriteError
+                | ErrorType::HTTPStatus(guards::CLIENT_CLOSED_REQUEST)
+        )
+}
+
+/// Header value as a string, if present and valid UTF-8
+fn header_str<'a>(req: &'a RequestHeader, name: &str) -> Option<&'a str> {
+    req.headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Check the local rate limit of a request
+///
+/// Clients with a verified user get the `authenticated` limits, the
+/// rest the `anonymous` ones.
+///
+/// # Arguments
+/// * `path` - Request path; requests on routes with `rate_limit: false`
+///   are not counted, and `None` always counts the request
+///
+/// # Returns
+/// * `Ok(warning)` if allowed, with an `X-RateLimit-Warning` value past
+///   the soft threshold; `Err(reason)` over the hard limit
+async fn check_rate_limit(
+    router: &Router,
+    path: Option<&str>,
+    ctx: &ProxyContext,
+    rate_limiters: &ClientRateLimiters,
+    config: &RateLimitConfig,
+    route: &str,
+    method: &str,
+) -> std::result::Result<Option<String>, String> {
+    if path.is_some_and(|path| !router.rate_limited(path)) {
+        return Ok(None);
+    }
+
+    let rate_limiter = rate_limiters.for_client(ctx.user_id.is_some());
+    let (client_type, client_id) = rate_limit_client(ctx);
+    let key = rate_limit_key(client_id, &config.key_dimensions, route, method);
+
+    // Check rate limit using token bucket algorithm
+    let (allowed, remaining) = rate_limiter.check_with_remaining(&key).await;
+
+    if let Some(remaining) = remaining {
+        if rand::random::<f64>() < metrics::BUCKET_FILL_SAMPLE_RATE {
+            metrics::RATE_LIMIT_BUCKET_FILL
+                .with_label_values(&[route, client_type])
+                .set(remaining as i64);
+        }
+    }
+
+    if !allowed {
+        metrics::record_rate_limit_rejection(route, client_type);
+        return Err(format!(
+            "Rate limit exceeded: {} requests per minute allowed",
+            rate_limiter.get_limit()
+        ));
+    }
+
+    Ok(soft_limit_warning(
+        remaining,
+        rate_limiter.get_burst_size(),
+        config.warn_threshold_percent,
+    ))
+}
+
+/// Rate-limit client type label and bucket key (user_id > client_ip > request_id)
+fn rate_limit_client(ctx: &ProxyContext) -> (&'static str, String) {
+    if let Some(user_id) = &ctx.user_id {
+        ("user", format!("user:{}", user_id))
+    } else if let Some(ip) = &ctx.client_ip {
+        ("ip", format!("ip:{}", ip))
+    } else {
+        ("anonymous", format!("anonymous:{}", ctx.request_id))
+    }
+}
+
+/// Bucket key for a client, extended with the configured dimensions
+///
+/// e.g. `user:<id>:route=/api/orders:method=POST`
+fn rate_limit_key(
+    client_id: String,
+    dimensions: &[RateLimitDimension],
+    route: &str,
+    method: &str,
+) -> String {
+    dimensions
+        .iter()
+        .fold(client_id, |key, dimension| match dimension {
+            RateLimitDimension::Method => format!("{}:method={}", key, method),
+            RateLimitDimension::Route => format!("{}:route={}", key, route),
+        })
+}
+
+/// Whether a request to a route is authenticated at all
+///
+/// Public routes, auth turned off and `/auth/*` requests proxied to an
+/// auth service are left with [`AuthDecision::Skipped`].
+fn auth_applies(mode: AuthMode, auth_type: RouteAuthType, auth_upstream: bool) -> bool {
+    mode != AuthMode::Off && auth_type != RouteAuthType::None && !auth_upstream
+}
+
+/// Record the outcome of authentication on the context
+///
+/// # Returns
+/// * `bool` - Whether the request must be rejected
+fn record_auth_result(
+    mode: AuthMode,
+    result: &std::result::Result<Option<uuid::Uuid>, AuthError>,
+    ctx: &mut ProxyContext,
+) -> bool {
+    match result {
+        Ok(user_id) => {
+            ctx.auth_decision = AuthDecision::Authenticated;
+            if let Some(user_id) = user_id {
+                ctx.set_user_id(*user_id);
+            }
+            false
+        }
+        Err(e) => reject_auth_failure(mode, &e.to_string(), ctx),
+    }
+}
+
+/// Adjust Pingora's compression of this response; 0 turns it off
+fn set_compression_level(session: &mut Session, level: u32) {
+    if let Some(compression) = session
+        .downstream_modules_ctx
+        .get_mut::<ResponseCompression>()
+    {
+        compression.adjust_level(level);
+    }
+}
+
+/// Count the request against its connection's request limit
+///
+/// # Returns
+/// * `true` if the limit is reached: `ctx.close_connection` is set and the
+///   connection must not be kept alive after the response
+fn count_connection_request(
+    tracker: &ConnectionTracker,
+    http2: bool,
+    ctx: &mut ProxyContext,
+) -> bool {
+    let Some(addr) = &ctx.client_addr else {
+        return false;
+    };
+    if http2 || !tracker.record(addr) {
+        return false;
+    }
+
+    log::debug!(
+        "[{}] Connection {} reached its request limit, closing",
+        ctx.request_id,
+        addr
+    );
+    ctx.close_connection = true;
+    true
+}
+
+/// Decide whether an authentication failure rejects the request
+///
+/// In shadow mode the failure is counted and recorded on the context, but
+/// the request is allowed through so enforcement impact can be measured.
+fn reject_auth_failure(mode: AuthMode, reason: &str, ctx: &mut ProxyContext) -> bool {
+    match mode {
+        AuthMode::Enforce => {
+            ctx.auth_decision = AuthDecision::Rejected;
+            true
+        }
+        AuthMode::Shadow => {
+            metrics::AUTH_WOULD_FAIL.inc();
+            ctx.auth_decision = AuthDecision::Shadow;
+            ctx.auth_shadow_failure = Some(reason.to_string());
+            false
+        }
+        AuthMode::Off => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{
+        AuthBackend, AuthConfig, LoadBalancingConfig, RetryConfig, StaticAuthType, UpstreamConfig,
+        UpstreamTlsConfig,
+    };
+    use crate::db::memory::MemoryStore;
+    use crate::db::user::ROLE_ADMIN;
+    use crate::proxy::drain::drain;
+
+    #[test]
+    fn test_shadow_mode_lets_request_through_and_records_metric() {
+        let mut ctx = ProxyContext::new();
+        let before = metrics::AUTH_WOULD_FAIL.get();
+
+        let rejected = reject_auth_failure(AuthMode::Shadow, "Invalid or missing token", &mut ctx);
+
+        assert!(!rejected);
+        assert!(metrics::AUTH_WOULD_FAIL.get() > before);
+        assert_eq!(
+            ctx.auth_shadow_failure.as_deref(),
+            Some("Invalid or missing token")
+        );
+        assert_eq!(ctx.auth_decision, AuthDecision::Shadow);
+    }
+
+    #[test]
+    fn test_auth_responses_carry_no_store() {
+        let cookie = || Some("refresh_token=abc; HttpOnly".to_string());
+
+        // As handle_login and handle_refresh answer, with and without the
+        // refresh cookie, on success and failure
+        for (path, status, cookie) in [
+            ("/auth/login", 200, cookie()),
+            ("/auth/login", 401, None),
+            ("/auth/refresh", 200, cookie()),
+            ("/auth/refresh", 401, None),
+        ] {
+            let set_cookie = cookie.clone();
+            let resp = build_handler_response_header(path, status, 42, cookie).unwrap();
+            assert_eq!(resp.status.as_u16(), status);
+            assert_eq!(resp.headers.get("cache-control").unwrap(), "no-store");
+            assert_eq!(resp.headers.get("pragma").unwrap(), "no-cache");
+            assert_eq!(resp.headers.get("content-length").unwrap(), "42");
+            assert_eq!(
+                resp.headers
+                    .get("set-cookie")
+                    .map(|v| v.to_str().unwrap().to_string()),
+                set_cookie
+            );
+        }
+
+        // Only the /auth/ prefix selects no-store
+        for path in ["/health", "/authz/check", "/api/auth/login"] {
+            let resp = build_handler_response_header(path, 200, 42, None).unwrap();
+            assert!(resp.headers.get("cache-control").is_none(), "{}", path);
+            assert!(resp.headers.get("pragma").is_none(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_proxied_auth_responses_carry_no_store() {
+        // With `auth.backend: upstream`, /auth/* requests go to the auth service
+        assert_eq!(
+            auth_endpoint_target(true, "/auth/refresh"),
+            Some(AuthTarget::Upstream)
+        );
+
+        // and response_filter overrides whatever caching it allowed
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("Cache-Control", "public, max-age=60")
+            .unwrap();
+        forbid_caching(&mut resp).unwrap();
+        assert_eq!(resp.headers["Cache-Control"], "no-store");
+        assert_eq!(resp.headers["Pragma"], "no-cache");
+        assert_eq!(resp.headers.get_all("Cache-Control").iter().count(), 1);
+    }
+
+    #[test]
+    fn test_pretty_json_keeps_value() {
+        let json = r#"{"error":"Unauthorized","error_description":"Token expired"}"#;
+        assert_eq!(format_json(json.to_string(), false), json);
+
+        let pretty = format_json(json.to_string(), true);
+        assert!(
+            pretty.contains("\n  \"error\": \"Unauthorized\""),
+            "{}",
+            pretty
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&pretty).unwrap(),
+            serde_json::from_str::<serde_json::Value>(json).unwrap()
+        );
+
+        // Content-Length is taken from the indented body
+        let resp = build_json_response_header(401, pretty.len(), true).unwrap();
+        assert_eq!(
+            resp.headers.get("content-length").unwrap(),
+            pretty.len().to_string().as_str()
+        );
+
+        assert_eq!(format_json("not json".to_string(), true), "not json");
+    }
+
+    #[test]
+    fn test_other_responses_have_no_cache_directives() {
+        let resp = build_json_response_header(200, 42, false).unwrap();
+        assert!(resp.headers.get("cache-control").is_none());
+        assert!(resp.headers.get("pragma").is_none());
+    }
+
+    #[test]
+    fn test_rate_limit_client_type() {
+        let mut ctx = ProxyContext::new();
+        assert_eq!(rate_limit_client(&ctx).0, "anonymous");
+
+        ctx.client_ip = Some("10.0.0.1".parse().unwrap());
+        assert_eq!(rate_limit_client(&ctx), ("ip", "ip:10.0.0.1".to_string()));
+
+        let user_id = uuid::Uuid::new_v4();
+        ctx.set_user_id(user_id);
+        assert_eq!(
+            rate_limit_client(&ctx),
+            ("user", format!("user:{}", user_id))
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_key_dimensions() {
+        let dimensions = [RateLimitDimension::Route, RateLimitDimension::Method];
+        let key = |method| rate_limit_key("user:1".to_string(), &dimensions, "/api/orders", method);
+
+        assert_eq!(key("POST"), "user:1:route=/api/orders:method=POST");
+
+        // GETs and POSTs draw from separate buckets
+        assert_ne!(key("GET"), key("POST"));
+
+        // Without dimensions every request shares the client bucket
+        assert_eq!(
+            rate_limit_key("user:1".to_string(), &[], "/api/orders", "GET"),
+            rate_limit_key("user:1".to_string(), &[], "/api/orders", "POST")
+        );
+    }
+
+    fn route_auth_fixtures() -> (JwtManager, JwtMiddleware, AuthMiddleware, MemoryStore) {
+        let jwt_manager = JwtManager::new("test_secret".to_string(), 900, 604800);
+        let jwt_middleware = JwtMiddleware::new(jwt_manager.clone());
+        let auth_middleware = AuthMiddleware::new(&AuthConfig {
+            enabled: true,
+            mode: None,
+            auth_type: StaticAuthType::ApiKey,
+            valid_tokens: vec!["machine-key-1".to_string()],
+            backend: AuthBackend::Builtin,
+            upstreams: Vec::new(),
+            usernames: false,
+        });
+        (
+            jwt_manager,
+            jwt_middleware,
+            auth_middleware,
+            MemoryStore::new(),
+        )
+    }
+
+    /// Register a user in the store, returning its ID
+    async fn registered_user(store: &MemoryStore) -> uuid::Uuid {
+        store
+            .create(crate::db::user::CreateUser {
+                email: format!("{}@example.com", uuid::Uuid::new_v4()),
+                username: None,
+                password_hash: "unused".to_string(),
+            })
+            .await
+            .unwrap()
+            .id
+    }
+
+    fn request(header: Option<(&'static str, String)>) -> RequestHeader {
+        let mut req = RequestHeader::build("GET", b"/api/users", None).unwrap();
+        if let Some((name, value)) = header {
+            req.insert_header(name, value).unwrap();
+        }
+        req
+    }
+
+    #[tokio::test]
+    async fn test_route_auth_types_enforce_their_credential() {
+        let (jwt_manager, jwt, keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        let token = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+
+        let bearer = || request(Some(("Authorization", format!("Bearer {}", token))));
+        let api_key = || request(Some(("X-API-Key", "machine-key-1".to_string())));
+        let bad_key = || request(Some(("X-API-Key", "wrong-key".to_string())));
+        let none = || request(None);
+
+        let check = |auth_type, req: RequestHeader| {
+            let (jwt, keys, store) = (&jwt, &keys, &store);
+            async move { authenticate(auth_type, None, &req, jwt, keys, store, store).await }
+        };
+
+        // none: anything passes
+        assert_eq!(check(RouteAuthType::None, none()).await, Ok(None));
+
+        // jwt: only a bearer token
+        assert_eq!(check(RouteAuthType::Jwt, bearer()).await, Ok(Some(user_id)));
+        assert!(check(RouteAuthType::Jwt, api_key()).await.is_err());
+        assert!(check(RouteAuthType::Jwt, none()).await.is_err());
+
+        // api_key: only a configured key
+        assert_eq!(check(RouteAuthType::ApiKey, api_key()).await, Ok(None));
+        assert!(check(RouteAuthType::ApiKey, bearer()).await.is_err());
+        assert!(check(RouteAuthType::ApiKey, bad_key()).await.is_err());
+
+        // any: either credential
+        assert_eq!(check(RouteAuthType::Any, api_key()).await, Ok(None));
+        assert_eq!(check(RouteAuthType::Any, bearer()).await, Ok(Some(user_id)));
+        assert!(check(RouteAuthType::Any, bad_key()).await.is_err());
+        assert!(check(RouteAuthType::Any, none()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_revoked_jwt_is_rejected() {
+        let (jwt_manager, jwt, keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        let token = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        store.blacklist_token(&token, 900).await.unwrap();
+
+        let req = request(Some(("Authorization", format!("Bearer {}", token))));
+        assert_eq!(
+            authenticate(RouteAuthType::Jwt, None, &req, &jwt, &keys, &store, &store).await,
+            Err(AuthError::Rejected("Token has been revoked".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_outage_is_unavailable_not_unauthorized() {
+        /// Blacklist whose Redis is down
+        struct Down;
+
+        #[async_trait]
+        impl TokenBlacklist for Down {
+            async fn blacklist_token(
+                &self,
+                _: &str,
+                _: u64,
+            ) -> std::result::Result<(), redis::RedisError> {
+                Err(redis::RedisError::from((redis::ErrorKind::IoError, "down")))
+            }
+
+            async fn is_token_blacklisted(
+                &self,
+                _: &str,
+            ) -> std::result::Result<bool, redis::RedisError> {
+                Err(redis::RedisError::from((redis::ErrorKind::IoError, "down")))
+            }
+        }
+
+        let (jwt_manager, jwt, keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        store.set_role(&user_id, ROLE_ADMIN).await.unwrap();
+        let token = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        let req = request(Some(("Authorization", format!("Bearer {}", token))));
+
+        let result = authenticate(RouteAuthType::Jwt, None, &req, &jwt, &keys, &Down, &store).await;
+        assert!(matches!(result, Err(AuthError::Unavailable(_))));
+
+        let result = authorize_admin(&req, &jwt, &Down, &store).await;
+        assert_eq!(result.map_err(|(status, _)| status), Err(503));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_as_bearer_is_wrong_token_type() {
+        let (jwt_manager, jwt, keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        let (refresh_token, _, _) = jwt_manager.generate_refresh_token(&user_id, 0).unwrap();
+
+        let req = request(Some(("Authorization", format!("Bearer {}", refresh_token))));
+        let e = authenticate(RouteAuthType::Jwt, None, &req, &jwt, &keys, &store, &store)
+            .await
+            .unwrap_err();
+        assert_eq!(e, AuthError::WrongTokenType);
+
+        // The 401 says why
+        let body: serde_json::Value = serde_json::from_str(&unauthorized_json(Some(&e))).unwrap();
+        assert_eq!(body["error"], "Unauthorized");
+        assert_eq!(
+            body["error_description"],
+            "wrong token type: an access token is required"
+        );
+
+        // Other failures stay generic
+        let e = authenticate(
+            RouteAuthType::Jwt,
+            None,
+            &request(None),
+            &jwt,
+            &keys,
+            &store,
+            &store,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(unauthorized_json(Some(&e)), r#"{"error":"Unauthorized"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_recently_expired_token_is_token_expired() {
+        let (_, _, keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        let jwt_manager =
+            JwtManager::new("test_secret".to_string(), 900, 604800).with_expired_grace(Some(300));
+        let jwt = JwtMiddleware::new(jwt_manager);
+        // Same secret, issuing tokens that expired 30 seconds ago
+        let issuer = JwtManager::new("test_secret".to_string(), -30, 604800);
+        let token = issuer.generate_access_token(&user_id, 0).unwrap();
+
+        let req = request(Some(("Authorization", format!("Bearer {}", token))));
+        let e = authenticate(RouteAuthType::Jwt, None, &req, &jwt, &keys, &store, &store)
+            .await
+            .unwrap_err();
+        assert_eq!(e, AuthError::TokenExpired);
+        assert!(e.challenge().is_some());
+        let body: serde_json::Value = serde_json::from_str(&unauthorized_json(Some(&e))).unwrap();
+        assert_eq!(body["code"], "token-expired");
+
+        // Expired for longer than the grace: just invalid
+        let issuer = JwtManager::new("test_secret".to_string(), -600, 604800);
+        let token = issuer.generate_access_token(&user_id, 0).unwrap();
+        let req = request(Some(("Authorization", format!("Bearer {}", token))));
+        assert_eq!(
+            authenticate(RouteAuthType::Jwt, None, &req, &jwt, &keys, &store, &store).await,
+            Err(AuthError::InvalidToken)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_garbage_token_is_invalid_token() {
+        let (_, _, keys, store) = route_auth_fixtures();
+        let jwt = JwtMiddleware::new(
+            JwtManager::new("test_secret".to_string(), 900, 604800).with_expired_grace(Some(300)),
+        );
+
+        let req = request(Some(("Authorization", "Bearer not.a.jwt".to_string())));
+        let e = authenticate(RouteAuthType::Jwt, None, &req, &jwt, &keys, &store, &store)
+            .await
+            .unwrap_err();
+        assert_eq!(e, AuthError::InvalidToken);
+        assert!(e.challenge().is_none());
+        let body: serde_json::Value = serde_json::from_str(&unauthorized_json(Some(&e))).unwrap();
+        assert_eq!(body["code"], "invalid-token");
+        assert!(body.get("error_description").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_token_version_bump_rejects_issued_tokens() {
+        let (jwt_manager, jwt, keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        let old_token = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        let old_req = request(Some(("Authorization", format!("Bearer {}", old_token))));
+
+        assert_eq!(
+            authenticate(
+                RouteAuthType::Jwt,
+                None,
+                &old_req,
+                &jwt,
+                &keys,
+                &store,
+                &store
+            )
+            .await,
+            Ok(Some(user_id))
+        );
+
+        let version = store.bump_token_version(&user_id).await.unwrap();
+        assert_eq!(version, 1);
+
+        assert_eq!(
+            authenticate(
+                RouteAuthType::Jwt,
+                None,
+                &old_req,
+                &jwt,
+                &keys,
+                &store,
+                &store
+            )
+            .await,
+            Err(AuthError::Rejected("Token has been revoked".to_string()))
+        );
+
+        // Tokens issued at the new version are accepted
+        let new_token = jwt_manager
+            .generate_access_token(&user_id, version)
+            .unwrap();
+        let new_req = request(Some(("Authorization", format!("Bearer {}", new_token))));
+        assert_eq!(
+            authenticate(
+                RouteAuthType::Jwt,
+                None,
+                &new_req,
+                &jwt,
+                &keys,
+                &store,
+                &store
+            )
+            .await,
+            Ok(Some(user_id))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_endpoints_require_admin_role() {
+        let (jwt_manager, jwt, _keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        let token = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        let req = request(Some(("Authorization", format!("Bearer {}", token))));
+
+        // No token, or a static API key, is not enough
+        let api_key = request(Some(("X-API-Key", "machine-key-1".to_string())));
+        for req in [request(None), api_key] {
+            let result = authorize_admin(&req, &jwt, &store, &store).await;
+            assert_eq!(result.map_err(|(status, _)| status), Err(401));
+        }
+
+        // A regular user is authenticated but forbidden
+        let result = authorize_admin(&req, &jwt, &store, &store).await;
+        assert_eq!(result.map_err(|(status, _)| status), Err(403));
+
+        store.set_role(&user_id, ROLE_ADMIN).await.unwrap();
+        assert_eq!(
+            authorize_admin(&req, &jwt, &store, &store).await,
+            Ok(user_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_range_request_passes_through() {
+        use crate::config::settings::{EncodingConfig, RequestIdConfig, ResponseTransformConfig};
+        use pingora_core::connectors::http::Connector;
+        use pingora_core::upstreams::peer::HttpPeer;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // An upstream that answers the range it was asked for
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let upstream = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let n = conn.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+            conn.write_all(
+                b"HTTP/1.1 206 Partial Content\r\n\
+                  Content-Type: application/json\r\n\
+                  Content-Range: bytes 0-9/100\r\n\
+                  Content-Length: 10\r\n\r\n\
+                  {\"items\":[",
+            )
+            .await
+            .unwrap();
+            request
+        });
+
+        // The Range header reaches the upstream untouched
+        let mut req = RequestHeader::build("GET", b"/api/items", None).unwrap();
+        req.insert_header("Host", address.to_string()).unwrap();
+        req.insert_header("Range", "bytes=0-9").unwrap();
+        request_id::tag_upstream_request(&mut req, &RequestIdConfig::default(), "req-1").unwrap();
+
+        let peer = HttpPeer::new(address, false, String::new());
+        let connector = Connector::new(None);
+        let (mut session, _) = connector.get_http_session(&peer).await.unwrap();
+        session
+            .write_request_header(Box::new(req.clone()))
+            .await
+            .unwrap();
+        session.finish_request_body().await.unwrap();
+        session.read_response_header().await.unwrap();
+        let resp = session.response_header().unwrap().clone();
+        let mut body = Vec::new();
+        while let Some(chunk) = session.read_response_body().await.unwrap() {
+            body.extend_from_slice(&chunk);
+        }
+        assert!(upstream.await.unwrap().contains("range: bytes=0-9\r\n"));
+
+        // Transforms would take this JSON, far below max_size, if it were whole
+        let transformer = ResponseTransformer::new(&ResponseTransformConfig {
+            enabled: true,
+            max_size: 65536,
+            ..Default::default()
+        });
+        assert_eq!(resp.status.as_u16(), 206);
+        assert!(!transformer.applies_to_response("/api/items", &req.method, &resp));
+
+        // and compression would take it too
+        assert!(!encoding::compressible(
+            &resp,
+            &EncodingConfig::default().exclude_types
+        ));
+
+        // so the range keeps its length and body as the upstream sent them
+        assert_eq!(resp.headers.get("content-length").unwrap(), "10");
+        assert_eq!(resp.headers.get("content-range").unwrap(), "bytes 0-9/100");
+        assert_eq!(body, b"{\"items\":[");
+    }
+
+    fn balancer(address: &str, port: u16) -> LoadBalancerManager {
+        LoadBalancerManager::new(LoadBalancingConfig {
+            strategy: "round_robin".to_string(),
+            fallback: Vec::new(),
+            upstreams: vec![UpstreamConfig {
+                name: address.to_string(),
+                address: address.to_string(),
+                port,
+                weight: 1,
+                tls: false,
+                tls_options: None,
+                connect_timeout_secs: None,
+                read_timeout_secs: None,
+            }],
+            tls: UpstreamTlsConfig::default(),
+            warmup_connections: 0,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            retry: RetryConfig::default(),
+            passive_ejection: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_upstream_auth_backend_proxies_login() {
+        let primary = balancer("127.0.0.1", 3000);
+        let auth = balancer("10.0.0.5", 9000);
+
+        let mut ctx = ProxyContext::new();
+        assert_eq!(
+            auth_endpoint_target(true, "/auth/login"),
+            Some(AuthTarget::Upstream)
+        );
+        ctx.auth_upstream = true;
+
+        let peer = balancer_for(&ctx, &primary, Some(&auth))
+            .select_peer()
+            .unwrap();
+        assert_eq!(peer.address().to_string(), "10.0.0.5:9000");
+
+        // Other traffic still goes to the primary group
+        let ctx = ProxyContext::new();
+        let peer = balancer_for(&ctx, &primary, Some(&auth))
+            .select_peer()
+            .unwrap();
+        assert_eq!(peer.address().to_string(), "127.0.0.1:3000");
+    }
+
+    #[test]
+    fn test_builtin_auth_backend_runs_local_handler() {
+        assert_eq!(
+            auth_endpoint_target(false, "/auth/login"),
+            Some(AuthTarget::Local)
+        );
+        assert_eq!(auth_endpoint_target(false, "/api/users"), None);
+        assert_eq!(auth_endpoint_target(true, "/api/users"), None);
+    }
+
+    #[test]
+    fn test_default_headers_on_auth_response() {
+        let defaults = DefaultHeaders::new(&std::collections::BTreeMap::from([(
+            "X-Content-Type-Options".to_string(),
+            "nosniff".to_string(),
+        )]));
+
+        // As send_json_response builds a 401 from /auth/login
+        let mut resp = build_json_response_header(401, 25, true).unwrap();
+        defaults.apply(
+            &mut resp,
+            Router::default().response_headers("/auth/login"),
+            false,
+        );
+
+        assert_eq!(resp.headers["X-Content-Type-Options"], "nosniff");
+        assert_eq!(resp.headers["Cache-Control"], "no-store");
+    }
+
+    #[test]
+    fn test_error_status() {
+        let status = |etype, esource| error_status(&Error::create(etype, esource, None, None));
+
+        assert_eq!(status(ErrorType::HTTPStatus(413), ErrorSource::Unset), 413);
+        assert_eq!(
+            status(ErrorType::ConnectRefused, ErrorSource::Upstream),
+            502
+        );
+        assert_eq!(status(ErrorType::ReadError, ErrorSource::Downstream), 0);
+        assert_eq!(
+            status(ErrorType::InvalidHTTPHeader, ErrorSource::Downstream),
+            400
+        );
+        assert_eq!(status(ErrorType::InternalError, ErrorSource::Internal), 500);
+    }
+
+    #[test]
+    fn test_detailed_health_query() {
+        let request = |uri: &str| RequestHeader::build("GET", uri.as_bytes(), None).unwrap();
+
+        assert!(is_detailed_health(&request("/health?detailed=true")));
+        assert!(is_detailed_health(&request("/health?x=1&detailed=true")));
+        assert!(!is_detailed_health(&request("/health")));
+        assert!(!is_detailed_health(&request("/health?detailed=false")));
+    }
+
+    #[test]
+    fn test_wrong_method_on_auth_endpoint_is_405() {
+        // GET /auth/login: known path, wrong method
+        let allow = auth_allowed_methods("/auth/login", false).unwrap();
+        assert_eq!(allow, "POST");
+
+        let resp = build_method_not_allowed_header(&allow, 32).unwrap();
+        assert_eq!(resp.status.as_u16(), 405);
+        assert_eq!(resp.headers.get("allow").unwrap(), "POST");
+
+        assert_eq!(
+            auth_allowed_methods("/auth/openapi.json", true).as_deref(),
+            Some("GET")
+        );
+        assert_eq!(
+            auth_allowed_methods("/auth/logout-all", false).as_deref(),
+            Some("POST")
+        );
+    }
+
+    #[test]
+    fn test_unknown_auth_path_is_404() {
+        // POST /auth/unknown: no such endpoint for any method
+        assert_eq!(auth_allowed_methods("/auth/unknown", false), None);
+        assert_eq!(auth_allowed_methods("/auth/login/extra", false), None);
+        // The OpenAPI document does not exist unless enabled
+        assert_eq!(auth_allowed_methods("/auth/openapi.json", false), None);
+    }
+
+    #[test]
+    fn test_revoke_tokens_target() {
+        assert_eq!(
+            revoke_tokens_target("/admin/users/42/revoke-tokens"),
+            Some("42")
+        );
+        assert_eq!(revoke_tokens_target("/admin/users//revoke-tokens"), None);
+        assert_eq!(revoke_tokens_target("/admin/users/a/b/revoke-tokens"), None);
+        assert_eq!(revoke_tokens_target("/admin/users/42"), None);
+    }
+
+    #[tokio::test]
+    async fn test_probes_while_draining() {
+        let readiness = Arc::new(Readiness::default());
+        let probe = |uri: &str| {
+            let req = RequestHeader::build("GET", uri.as_bytes(), None).unwrap();
+            probe_response(&req, req.uri.path(), &readiness, "abc123", || false)
+        };
+        let health = Some((
+            200,
+            r#"{"status":"ok","service":"pingora-proxy"}"#.to_string(),
+        ));
+
+        assert_eq!(
+            probe("/ready"),
+            Some((200, r#"{"status":"ready"}"#.to_string()))
+        );
+        assert_eq!(probe("/health"), health);
+
+        let draining = tokio::spawn({
+            let readiness = Arc::clone(&readiness);
+            async move { drain(&readiness, Duration::from_millis(200)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // Only /ready fails; /health and other requests are unaffected
+        assert_eq!(
+            probe("/ready"),
+            Some((503, r#"{"status":"draining"}"#.to_string()))
+        );
+        assert_eq!(probe("/health"), health);
+        assert_eq!(probe("/api/users"), None);
+
+        let (status, json) = probe("/health?detailed=true").unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(json["config_fingerprint"], "abc123");
+        assert_eq!(json["redis"], "down");
+
+        assert!(!draining.is_finished());
+        draining.await.unwrap();
+    }
+
+    #[test]
+    fn test_user_data_targets() {
+        assert_eq!(user_export_target("/admin/users/42/export"), Some("42"));
+        assert_eq!(user_export_target("/admin/users//export"), None);
+        assert_eq!(user_export_target("/admin/users/42"), None);
+
+        assert_eq!(user_target("/admin/users/42"), Some("42"));
+        assert_eq!(user_target("/admin/users/"), None);
+        assert_eq!(user_target("/admin/users/42/export"), None);
+        assert_eq!(user_target("/admin/users/import"), None);
+    }
+
+    #[test]
+    fn test_upstream_ejection_target() {
+        assert_eq!(
+            upstream_ejection_target("/admin/upstreams/backend1/eject"),
+            Some(("backend1", true))
+        );
+        assert_eq!(
+            upstream_ejection_target("/admin/upstreams/backend1/restore"),
+            Some(("backend1", false))
+        );
+        assert_eq!(upstream_ejection_target("/admin/upstreams//eject"), None);
+        assert_eq!(upstream_ejection_target("/admin/upstreams/backend1"), None);
+        assert_eq!(upstream_ejection_target("/admin/upstreams/a/b/eject"), None);
+    }
+
+    #[test]
+    fn test_read_only_mode_blocks_writes_only() {
+        // Blocked: registration and everything else that writes
+        for (method, path) in [
+            ("POST", "/auth/register"),
+            ("POST", "/auth/refresh"),
+            ("POST", "/auth/logout-all"),
+            ("POST", "/auth/change-password"),
+            ("POST", "/admin/users/import"),
+            ("POST", "/admin/users/42/revoke-tokens"),
+            ("DELETE", "/admin/users/42"),
+        ] {
+            assert!(writes_database(method, path), "{} {}", method, path);
+        }
+
+        // Still served: reads, Redis-backed bans, and logins and logouts,
+        // which skip their writes
+        for (method, path) in [
+            ("POST", "/auth/login"),
+            ("POST", "/auth/logout"),
+            ("GET", "/auth/me"),
+            ("POST", "/auth/validate-password"),
+            ("GET", "/auth/openapi.json"),
+            ("GET", "/admin/nonce"),
+            ("GET", "/admin/bans"),
+            ("POST", "/admin/ban"),
+            ("GET", "/admin/users/42/export"),
+            ("GET", "/auth/register"),
+        ] {
+            assert!(!writes_database(method, path), "{} {}", method, path);
+        }
+    }
+
+    #[test]
+    fn test_client_disconnect_detection() {
+        assert!(is_client_disconnect(&Error::new_down(
+            ErrorType::ConnectionClosed
+        )));
+        assert!(is_client_disconnect(&Error::new_down(
+            ErrorType::WriteError
+        )));
+        // A request body the client abandoned
+        assert!(is_client_disconnect(&Error::new_down(
+            ErrorType::HTTPStatus(guards::CLIENT_CLOSED_REQUEST)
+        )));
+        assert!(!is_client_disconnect(&Error::new_down(
+            ErrorType::HTTPStatus(400)
+        )));
+
+        // Upstream failures and other downstream errors are still real errors
+        assert!(!is_client_disconnect(&Error::new_up(
+            ErrorType::ConnectionClosed
+        )));
+        assert!(!is_client_disconnect(&Error::new_down(
+            ErrorType::InvalidHTTPHeader
+        )));
+    }
+
+    #[test]
+    fn test_enforce_mode_rejects() {
+        let mut ctx = ProxyContext::new();
+
+        assert!(reject_auth_failure(
+            AuthMode::Enforce,
+            "Token has been revoked",
+            &mut ctx
+        ));
+        assert!(ctx.auth_shadow_failure.is_none());
+        assert_eq!(ctx.auth_decision, AuthDecision::Rejected);
+    }
+
+    /// The routing and authentication steps of `request_filter`
+    async fn route_and_authenticate(
+        router: &Router,
+        mode: AuthMode,
+        req: &RequestHeader,
+        fixtures: (&JwtMiddleware, &AuthMiddleware, &MemoryStore),
+    ) -> (ProxyContext, bool) {
+        let (jwt, keys, store) = fixtures;
+        let path = req.uri.path();
+        let mut ctx = ProxyContext::new();
+
+        ctx.matched_route = router.match_prefix(path).map(str::to_string);
+        let auth_type = router.auth_type(path);
+        let mut rejected = false;
+        if auth_applies(mode, auth_type, ctx.auth_upstream) {
+            let audience = router.audience(path);
+            let result = authenticate(auth_type, audience, req, jwt, keys, store, store).await;
+            rejected = record_auth_result(mode, &result, &mut ctx);
+        }
+
+        (ctx, rejected)
+    }
+
+    #[tokio::test]
+    async fn test_route_and_auth_decision_are_recorded() {
+        let (jwt_manager, jwt, keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        let token = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        let fixtures = (&jwt, &keys, &store);
+
+        let public = |prefix: &str| crate::config::settings::RouteConfig {
+            prefix: prefix.to_string(),
+            strip_prefix: false,
+            rewrite: None,
+            auth: Some(RouteAuthType::None),
+            rate_limit: true,
+            mirror: None,
+            allowed_content_types: Vec::new(),
+            idempotency: None,
+            redirects: None,
+            response_headers: Default::default(),
+            fallback: None,
+            coalesce: None,
+            audience: None,
+        };
+        let router = Router::new(&crate::config::settings::RoutingConfig {
+            routes: vec![
+                public("/public"),
+                crate::config::settings::RouteConfig {
+                    auth: None,
+                    ..public("/api")
+                },
+            ],
+        })
+        .unwrap();
+
+        let get = |path: &str, token: Option<&str>| {
+            let mut req = RequestHeader::build("GET", path.as_bytes(), None).unwrap();
+            if let Some(token) = token {
+                req.insert_header("Authorization", format!("Bearer {}", token))
+                    .unwrap();
+            }
+            req
+        };
+
+        // Allowlisted route: matched, never authenticated
+        let (ctx, rejected) = route_and_authenticate(
+            &router,
+            AuthMode::Enforce,
+            &get("/public/docs", None),
+            fixtures,
+        )
+        .await;
+        assert!(!rejected);
+        assert_eq!(ctx.matched_route.as_deref(), Some("/public"));
+        assert_eq!(ctx.auth_decision, AuthDecision::Skipped);
+
+        // Protected route with a valid token
+        let req = get("/api/users", Some(&token));
+        let (ctx, rejected) =
+            route_and_authenticate(&router, AuthMode::Enforce, &req, fixtures).await;
+        assert!(!rejected);
+        assert_eq!(ctx.matched_route.as_deref(), Some("/api"));
+        assert_eq!(ctx.auth_decision, AuthDecision::Authenticated);
+        assert_eq!(ctx.user_id, Some(user_id));
+
+        // Protected route without a token, enforced and in shadow mode
+        let (ctx, rejected) = route_and_authenticate(
+            &router,
+            AuthMode::Enforce,
+            &get("/api/users", None),
+            fixtures,
+        )
+        .await;
+        assert!(rejected);
+        assert_eq!(ctx.matched_route.as_deref(), Some("/api"));
+        assert_eq!(ctx.auth_decision, AuthDecision::Rejected);
+
+        let (ctx, rejected) = route_and_authenticate(
+            &router,
+            AuthMode::Shadow,
+            &get("/api/users", None),
+            fixtures,
+        )
+        .await;
+        assert!(!rejected);
+        assert_eq!(ctx.auth_decision, AuthDecision::Shadow);
+        assert!(ctx.auth_shadow_failure.is_some());
+
+        // Unrouted paths need a JWT but have no route label
+        let (ctx, _) =
+            route_and_authenticate(&router, AuthMode::Off, &get("/other", None), fixtures).await;
+        assert_eq!(ctx.matched_route, None);
+        assert_eq!(ctx.auth_decision, AuthDecision::Skipped);
+    }
+
+    #[test]
+    fn test_connection_closed_at_request_limit() {
+        let tracker = ConnectionTracker::new(2);
+        let request_on = |addr: &str, http2: bool| {
+            let mut ctx = ProxyContext::new();
+            ctx.client_addr = Some(addr.to_string());
+            let closing = count_connection_request(&tracker, http2, &mut ctx);
+
+            // As response_filter answers the request
+            let mut resp = ResponseHeader::build(200, None).unwrap();
+            if ctx.close_connection {
+                protocol::close_after_response(&mut resp, http2);
+            }
+            let connection = resp
+                .headers
+                .get("Connection")
+                .map(|v| v.to_str().unwrap().to_string());
+            (closing, connection)
+        };
+
+        assert_eq!(request_on("10.0.0.1:5000", false), (false, None));
+        assert_eq!(
+            request_on("10.0.0.1:5000", false),
+            (true, Some("close".to_string()))
+        );
+
+        // The next connection from the same port starts over
+        assert_eq!(request_on("10.0.0.1:5000", false), (false, None));
+
+        // HTTP/2 connections are never counted
+        for _ in 0..3 {
+            assert_eq!(request_on("10.0.0.2:5000", true), (false, None));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bypass_route_skips_auth_and_rate_limit() {
+        let (_, jwt, keys, store) = route_auth_fixtures();
+        let fixtures = (&jwt, &keys, &store);
+
+        let routes: crate::config::settings::RoutingConfig = serde_yaml::from_str(
+            "routes:\n  - prefix: /webhooks\n    auth: false\n    rate_limit: false\n  - prefix: /api",
+        )
+        .unwrap();
+        let router = Router::new(&routes).unwrap();
+
+        // Authentication, then rate limiting as request_filter does it
+        let config: RateLimitConfig =
+            serde_yaml::from_str("enabled: true\nrequests_per_minute: 60\nburst_size: 1").unwrap();
+        let limiters = ClientRateLimiters::new(&config, |requests_per_minute, burst_size| {
+            Box::new(MemoryRateLimiter::new(requests_per_minute, burst_size))
+        });
+        let handle = |path: &'static str| {
+            let (router, limiters, config) = (&router, &limiters, &config);
+            async move {
+                let req = RequestHeader::build("POST", path.as_bytes(), None).unwrap();
+                let (mut ctx, rejected) =
+                    route_and_authenticate(router, AuthMode::Enforce, &req, fixtures).await;
+                ctx.client_ip = Some("10.0.0.1".parse().unwrap());
+                let route = ctx.matched_route.clone().unwrap_or_default();
+                let throttled =
+                    check_rate_limit(router, Some(path), &ctx, limiters, config, &route, "POST")
+                        .await
+                        .is_err();
+                (ctx, rejected, throttled)
+            }
+        };
+
+        // Unauthenticated and past the burst: still let through
+        for _ in 0..3 {
+            let (ctx, rejected, throttled) = handle("/webhooks/stripe").await;
+            assert!(!rejected && !throttled);
+            assert_eq!(ctx.auth_decision, AuthDecision::Skipped);
+        }
+
+        // Other routes keep both middlewares
+        let (ctx, rejected, _) = handle("/api/orders").await;
+        assert!(rejected);
+        assert_eq!(ctx.auth_decision, AuthDecision::Rejected);
+        assert!(handle("/api/orders").await.2);
+    }
+
+    #[tokio::test]
+    async fn test_throttled_user_increments_labeled_counter() {
+        let (jwt_manager, jwt, keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        let token = jwt_manager.generate_access_token(&user_id, 0).unwrap();
+        let fixtures = (&jwt, &keys, &store);
+
+        let routes: crate::config::settings::RoutingConfig =
+            serde_yaml::from_str("routes:\n  - prefix: /throttled").unwrap();
+        let router = Router::new(&routes).unwrap();
+        let config: RateLimitConfig =
+            serde_yaml::from_str("enabled: true\nrequests_per_minute: 1\nburst_size: 1").unwrap();
+        let limiters = ClientRateLimiters::new(&config, |requests_per_minute, burst_size| {
+            Box::new(MemoryRateLimiter::new(requests_per_minute, burst_size))
+        });
+
+        let path = "/throttled/orders/42";
+        let mut req = RequestHeader::build("GET", path.as_bytes(), None).unwrap();
+        req.insert_header("Authorization", format!("Bearer {}", token))
+            .unwrap();
+        let (ctx, rejected) =
+            route_and_authenticate(&router, AuthMode::Enforce, &req, fixtures).await;
+        assert!(!rejected);
+        let route = ctx.matched_route.clone().unwrap();
+        assert_eq!(route, "/throttled");
+
+        let counter = metrics::RATE_LIMIT_REJECTIONS.with_label_values(&["/throttled", "user"]);
+        let by_path = metrics::RATE_LIMIT_REJECTIONS.with_label_values(&[path, "user"]);
+        let before = counter.get();
+
+        // The first request empties the bucket, the second is throttled
+        let check =
+            || check_rate_limit(&router, Some(path), &ctx, &limiters, &config, &route, "GET");
+        assert!(check().await.is_ok());
+        assert!(check().await.is_err());
+
+        // Counted under the route prefix, not the full path
+        assert_eq!(counter.get(), before + 1);
+        assert_eq!(by_path.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_token_is_only_accepted_on_its_apps_routes() {
+        let (jwt_manager, jwt, keys, store) = route_auth_fixtures();
+        let user_id = registered_user(&store).await;
+        let fixtures = (&jwt, &keys, &store);
+
+        let app = |prefix: &str, audience: &str| crate::config::settings::RouteConfig {
+            prefix: prefix.to_string(),
+            strip_prefix: false,
+            rewrite: None,
+            auth: None,
+            rate_limit: true,
+            mirror: None,
+            allowed_content_types: Vec::new(),
+            idempotency: None,
+            redirects: None,
+            response_headers: Default::default(),
+            fallback: None,
+            coalesce: None,
+            audience: Some(audience.to_string()),
+        };
+        let router = Router::new(&crate::config::settings::RoutingConfig {
+            routes: vec![app("/app-a", "app-a"), app("/app-b", "app-b")],
+        })
+        .unwrap();
+
+        let token_for = |audience: Option<&str>| {
+            jwt_manager
+                .clone()
+                .with_audience(audience.map(str::to_string))
+                .generate_access_token(&user_id, 0)
+                .unwrap()
+        };
+        let check = |path: &'static str, token: String| {
+            let router = &router;
+            async move {
+                let mut req = RequestHeader::build("GET", path.as_bytes(), None).unwrap();
+                req.insert_header("Authorization", format!("Bearer {}", token))
+                    .unwrap();
+                route_and_authenticate(router, AuthMode::Enforce, &req, fixtures).await
+            }
+        };
+
+        // Minted for app A: accepted there only
+        let (ctx, rejected) = check("/app-a/items", token_for(Some("app-a"))).await;
+        assert!(!rejected);
+        assert_eq!(ctx.user_id, Some(user_id));
+
+        let (ctx, rejected) = check("/app-b/items", token_for(Some("app-a"))).await;
+        assert!(rejected);
+        assert_eq!(ctx.auth_decision, AuthDecision::Rejected);
+
+        // Unscoped tokens are not accepted where an audience is required
+        let (_, rejected) = check("/app-b/items", token_for(None)).await;
+        assert!(rejected);
+
+        let req = request(Some((
+            "Authorization",
+            format!("Bearer {}", token_for(Some("app-a"))),
+        )));
+        let e = authenticate(
+            RouteAuthType::Jwt,
+            Some("app-b"),
+            &req,
+            &jwt,
+            &keys,
+            &store,
+            &store,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(e, AuthError::WrongAudience);
+    }
+}
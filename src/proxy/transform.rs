@@ -0,0 +1,258 @@
+use bytes::Bytes;
+use pingora_http::ResponseHeader;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::config::settings::ResponseTransformConfig;
+
+/// Injects configured fields into small JSON upstream responses
+///
+/// Eligible responses are buffered in full (up to `max_size`), parsed,
+/// extended and re-serialized. Anything else streams through untouched.
+#[derive(Debug, Clone)]
+pub struct ResponseTransformer {
+    routes: Vec<String>,
+    content_types: Vec<String>,
+    max_size: usize,
+    inject: BTreeMap<String, String>,
+}
+
+impl ResponseTransformer {
+    pub fn new(config: &ResponseTransformConfig) -> Self {
+        Self {
+            routes: config.routes.clone(),
+            content_types: config.content_types.clone(),
+            max_size: config.max_size,
+            inject: config.inject.clone(),
+        }
+    }
+
+    /// Whether a response should be buffered for transformation
+    ///
+    /// # Arguments
+    /// * `path` - Public request path
+    /// * `content_type` - Response `Content-Type`, if any
+    /// * `content_encoding` - Response `Content-Encoding`, if any
+    /// * `content_length` - Response `Content-Length`, if declared
+    pub fn applies(
+        &self,
+        path: &str,
+        content_type: Option<&str>,
+        content_encoding: Option<&str>,
+        content_length: Option<usize>,
+    ) -> bool {
+        let route_matches = self.routes.is_empty()
+            || self.routes.iter().any(|prefix| {
+                match path.strip_prefix(prefix.trim_end_matches('/')) {
+                    Some(rest) => rest.is_empty() || rest.starts_with('/'),
+                    None => false,
+                }
+            });
+
+        let media_type = content_type
+            .and_then(|value| value.split(';').next())
+            .map(str::trim);
+        let type_matches = media_type.is_some_and(|media_type| {
+            self.content_types
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(media_type))
+        });
+
+        // Compressed bodies can't be parsed without decoding them first
+        let encoded = content_encoding.is_some_and(|e| !e.eq_ignore_ascii_case("identity"));
+
+        let fits = content_length.is_none_or(|length| length <= self.max_size);
+
+        route_matches && type_matches && !encoded && fits
+    }
+
+    /// Whether an upstream response should be buffered for transformation
+    ///
+    /// Only complete bodies qualify: a `206 Partial Content` body is a byte
+    /// range of the resource, and rewriting it would break `Content-Range`.
+    ///
+    /// # Arguments
+    /// * `path` - Public request path
+    /// * `method` - Request method
+    /// * `resp` - Upstream response header
+    pub fn applies_to_response(
+        &self,
+        path: &str,
+        method: &http::Method,
+        resp: &ResponseHeader,
+    ) -> bool {
+        let has_complete_body =
+            method != http::Method::HEAD && !matches!(resp.status.as_u16(), 204 | 206 | 304);
+        let header = |name| resp.headers.get(name).and_then(|v| v.to_str().ok());
+
+        has_complete_body
+            && self.applies(
+                path,
+                header("Content-Type"),
+                header("Content-Encoding"),
+                header("Content-Length").and_then(|v| v.parse().ok()),
+            )
+    }
+
+    /// Empty buffer for one response body
+    pub fn buffer(&self) -> TransformBuffer {
+        TransformBuffer {
+            body: Vec::new(),
+            max_size: self.max_size,
+        }
+    }
+
+    /// Set the configured fields on a JSON object body
+    ///
+    /// # Returns
+    /// * `Some(body)` with the fields injected, `None` if the body is not a JSON object
+    pub fn inject(&self, body: &[u8], request_id: &str) -> Option<Vec<u8>> {
+        let Ok(Value::Object(mut object)) = serde_json::from_slice::<Value>(body) else {
+            return None;
+        };
+
+        for (field, value) in &self.inject {
+            object.insert(
+                field.clone(),
+                Value::String(value.replace("{request_id}", request_id)),
+            );
+        }
+
+        serde_json::to_vec(&object).ok()
+    }
+}
+
+/// What to emit after buffering a body chunk
+#[derive(Debug, PartialEq, Eq)]
+pub enum BufferState {
+    /// Still collecting, emit nothing yet
+    Buffering,
+    /// Whole body collected within the limit
+    Complete(Bytes),
+    /// Limit exceeded: emit what was held back and pass the rest through
+    Overflow(Bytes),
+}
+
+/// Response body held back until it can be transformed
+#[derive(Debug, Clone)]
+pub struct TransformBuffer {
+    body: Vec<u8>,
+    max_size: usize,
+}
+
+impl TransformBuffer {
+    /// Add the next body chunk
+    pub fn push(&mut self, chunk: Option<Bytes>, end_of_stream: bool) -> BufferState {
+        if let Some(chunk) = chunk {
+            self.body.extend_from_slice(&chunk);
+        }
+
+        if self.body.len() > self.max_size {
+            BufferState::Overflow(Bytes::from(std::mem::take(&mut self.body)))
+        } else if end_of_stream {
+            BufferState::Complete(Bytes::from(std::mem::take(&mut self.body)))
+        } else {
+            BufferState::Buffering
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transformer(routes: Vec<&str>) -> ResponseTransformer {
+        ResponseTransformer::new(&ResponseTransformConfig {
+            enabled: true,
+            routes: routes.into_iter().map(String::from).collect(),
+            inject: BTreeMap::from([
+                ("served_by".to_string(), "proxy-1".to_string()),
+                ("correlation_id".to_string(), "{request_id}".to_string()),
+            ]),
+            max_size: 1024,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_small_json_gets_injected_fields() {
+        let transformer = transformer(vec![]);
+        let mut buffer = transformer.buffer();
+
+        assert_eq!(
+            buffer.push(Some(Bytes::from_static(br#"{"id":1,"#)), false),
+            BufferState::Buffering
+        );
+        let BufferState::Complete(body) =
+            buffer.push(Some(Bytes::from_static(br#""name":"a"}"#)), true)
+        else {
+            panic!("expected a complete body");
+        };
+
+        let injected = transformer.inject(&body, "req-123").unwrap();
+        let json: Value = serde_json::from_slice(&injected).unwrap();
+        assert_eq!(json["id"], 1);
+        assert_eq!(json["name"], "a");
+        assert_eq!(json["served_by"], "proxy-1");
+        assert_eq!(json["correlation_id"], "req-123");
+    }
+
+    #[test]
+    fn test_non_json_body_unchanged() {
+        let transformer = transformer(vec![]);
+
+        assert!(!transformer.applies("/api/page", Some("text/html"), None, Some(100)));
+
+        // Declared JSON that doesn't parse into an object is left as-is
+        assert_eq!(transformer.inject(b"not json", "req-123"), None);
+        assert_eq!(transformer.inject(b"[1,2,3]", "req-123"), None);
+    }
+
+    #[test]
+    fn test_eligibility() {
+        let transformer = transformer(vec!["/api"]);
+        let json = Some("application/json; charset=utf-8");
+
+        assert!(transformer.applies("/api/users", json, None, Some(100)));
+        assert!(transformer.applies("/api/users", json, None, None));
+        assert!(!transformer.applies("/apix/users", json, None, Some(100)));
+        assert!(!transformer.applies("/other", json, None, Some(100)));
+        assert!(!transformer.applies("/api/users", json, Some("gzip"), Some(100)));
+        assert!(!transformer.applies("/api/users", json, None, Some(4096)));
+    }
+
+    #[test]
+    fn test_partial_content_not_transformed() {
+        let transformer = transformer(vec![]);
+        let response = |status| {
+            let mut resp = ResponseHeader::build(status, None).unwrap();
+            resp.insert_header("Content-Type", "application/json")
+                .unwrap();
+            resp.insert_header("Content-Length", "100").unwrap();
+            resp
+        };
+
+        assert!(transformer.applies_to_response("/api/users", &http::Method::GET, &response(200)));
+        assert!(!transformer.applies_to_response("/api/users", &http::Method::GET, &response(206)));
+        assert!(!transformer.applies_to_response(
+            "/api/users",
+            &http::Method::HEAD,
+            &response(200)
+        ));
+    }
+
+    #[test]
+    fn test_oversized_stream_passes_through() {
+        let transformer = transformer(vec![]);
+        let mut buffer = transformer.buffer();
+
+        assert_eq!(
+            buffer.push(Some(Bytes::from(vec![b'a'; 1000])), false),
+            BufferState::Buffering
+        );
+        assert_eq!(
+            buffer.push(Some(Bytes::from(vec![b'b'; 100])), false),
+            BufferState::Overflow(Bytes::from([vec![b'a'; 1000], vec![b'b'; 100]].concat()))
+        );
+    }
+}